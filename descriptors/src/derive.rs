@@ -50,16 +50,19 @@ pub trait Descriptor<Key> {
     /// participating descriptor
     fn derive_pattern_len(&self) -> Result<usize, DeriveError>;
 
-    /// Detects bitcoin network which should be used with the provided
-    /// descriptor
-    fn network(&self, regtest: bool) -> Result<Network, DeriveError>;
+    /// Detects bitcoin network of the extended keys used in the descriptor.
+    /// Since extended keys for testnets and regtest share the same encoding,
+    /// the network is detected either as bitcoin mainnet or as testnet.
+    fn network(&self) -> Result<Network, DeriveError>;
 
     /// Generates address from the descriptor for specific derive pattern
+    /// using address encoding of the provided network, which must match the
+    /// network of the descriptor keys (i.e. any test network for testnet keys).
     fn address<C: Verification>(
         &self,
         secp: &Secp256k1<C>,
         pat: impl AsRef<[UnhardenedIndex]>,
-        regtest: bool,
+        network: Network,
     ) -> Result<AddressCompat, DeriveError>;
 
     /// Creates scriptPubkey for specific derive pattern in pre-taproot
@@ -78,6 +81,26 @@ pub trait Descriptor<Key> {
     ) -> Result<Script, DeriveError>;
 }
 
+/// Methods for working with BIP-389 multipath descriptors (using `<a;b;...>`
+/// key derivation segments).
+pub trait MultipathDescriptor: Sized {
+    /// Counts number of multipath branches in the descriptor. All multipath
+    /// keys must have the same number of branches; single-path descriptors
+    /// always have a single branch.
+    fn multipath_len(&self) -> Result<usize, DeriveError>;
+
+    /// Constructs single-path descriptor for the multipath branch `no`.
+    fn branch(&self, no: usize) -> Result<Self, DeriveError>;
+
+    /// Expands multipath descriptor into the list of single-path descriptors,
+    /// one per branch.
+    fn branches(&self) -> Result<Vec<Self>, DeriveError> {
+        (0..self.multipath_len()?)
+            .map(|no| self.branch(no))
+            .collect()
+    }
+}
+
 #[cfg(feature = "miniscript")]
 mod ms {
     use std::cell::Cell;
@@ -121,6 +144,43 @@ mod ms {
         translate_hash_fail!(DerivationAccount, XOnlyPublicKey, DerivePatternError);
     }
 
+    struct BranchTranslator(usize);
+
+    impl Translator<DerivationAccount, DerivationAccount, DeriveError> for BranchTranslator {
+        fn pk(&mut self, pk: &DerivationAccount) -> Result<DerivationAccount, DeriveError> {
+            if pk.multipath_len() == 1 {
+                return Ok(pk.clone());
+            }
+            pk.branch(self.0)
+                .ok_or(DeriveError::NoMultipathBranch(self.0))
+        }
+
+        fn sha256(&mut self, sha256: &DerivationAccount) -> Result<DerivationAccount, DeriveError> {
+            Ok(sha256.clone())
+        }
+
+        fn hash256(
+            &mut self,
+            hash256: &DerivationAccount,
+        ) -> Result<DerivationAccount, DeriveError> {
+            Ok(hash256.clone())
+        }
+
+        fn ripemd160(
+            &mut self,
+            ripemd160: &DerivationAccount,
+        ) -> Result<DerivationAccount, DeriveError> {
+            Ok(ripemd160.clone())
+        }
+
+        fn hash160(
+            &mut self,
+            hash160: &DerivationAccount,
+        ) -> Result<DerivationAccount, DeriveError> {
+            Ok(hash160.clone())
+        }
+    }
+
     impl DeriveDescriptor<bitcoin::PublicKey> for miniscript::Descriptor<DerivationAccount>
     where
         Self: TranslatePk<DerivationAccount, bitcoin::PublicKey>,
@@ -167,7 +227,7 @@ mod ms {
         #[inline]
         fn check_sanity(&self) -> Result<(), DeriveError> {
             self.derive_pattern_len()?;
-            self.network(false)?;
+            self.network()?;
             Ok(())
         }
 
@@ -191,7 +251,7 @@ mod ms {
             len.get().ok_or(DeriveError::NoKeys)
         }
 
-        fn network(&self, regtest: bool) -> Result<Network, DeriveError> {
+        fn network(&self) -> Result<Network, DeriveError> {
             let network = Cell::new(None);
             self.for_each_key(|key| match (network.get(), key.account_xpub.network) {
                 (None, net) => {
@@ -201,14 +261,7 @@ mod ms {
                 (Some(net1), net2) if net1 != net2 => false,
                 _ => true,
             });
-            let network = network.get().ok_or(DeriveError::NoKeys)?;
-            match (network, regtest) {
-                (network, false) => Ok(network),
-                (Network::Testnet | Network::Signet | Network::Regtest, true) if regtest => {
-                    Ok(Network::Regtest)
-                }
-                _ => Err(DeriveError::InconsistentKeyNetwork),
-            }
+            network.get().ok_or(DeriveError::NoKeys)
         }
 
         #[inline]
@@ -216,9 +269,12 @@ mod ms {
             &self,
             secp: &Secp256k1<C>,
             pat: impl AsRef<[UnhardenedIndex]>,
-            regtest: bool,
+            network: Network,
         ) -> Result<AddressCompat, DeriveError> {
-            let network = AddressNetwork::from(self.network(regtest)?);
+            if (self.network()? == Network::Bitcoin) != (network == Network::Bitcoin) {
+                return Err(DeriveError::InconsistentKeyNetwork);
+            }
+            let network = AddressNetwork::from(network);
             let spk = Descriptor::script_pubkey_pretr(self, secp, pat)?;
             AddressCompat::from_script(&spk.into(), network)
                 .ok_or(DeriveError::NoAddressForDescriptor)
@@ -245,4 +301,67 @@ mod ms {
             Ok(d.script_pubkey())
         }
     }
+
+    impl MultipathDescriptor for miniscript::Descriptor<DerivationAccount> {
+        fn multipath_len(&self) -> Result<usize, DeriveError> {
+            let len = Cell::new(None);
+            let consistent = self.for_each_key(|key| match (len.get(), key.multipath_len()) {
+                (_, 1) => true,
+                (None, c) => {
+                    len.set(Some(c));
+                    true
+                }
+                (Some(c1), c2) => c1 == c2,
+            });
+            if !consistent {
+                return Err(DeriveError::InconsistentKeyMultipath);
+            }
+            Ok(len.get().unwrap_or(1))
+        }
+
+        fn branch(&self, no: usize) -> Result<Self, DeriveError> {
+            if no >= self.multipath_len()? {
+                return Err(DeriveError::NoMultipathBranch(no));
+            }
+            self.translate_pk(&mut BranchTranslator(no))
+        }
+    }
+
+    #[cfg(test)]
+    mod test {
+        use std::str::FromStr;
+
+        use super::*;
+
+        #[test]
+        fn multipath_expansion() {
+            let descriptor = miniscript::Descriptor::<DerivationAccount>::from_str(
+                "wsh(multi(1,[00000000/48h/0h/0h/2h]xpub69PnGxAGwEBNtGPnxd71p2QbHRZvjDG1BEza1sZdRbd7uWkjHqfGxMburhdEocC5ud2NpkbhwnM29c2zdqWS36wJue1BuJgMnLTpxpxzJe1/<0;1>/*,xpub661MyMwAqRbcFtXgS5sYJABqqG9YLmC4Q1Rdap9gSE8NqtwybGhePY2gZ29ESFjqJoCu1Rupje8YtGqsefD265TMg7usUDFdp6W1EGMcet8/5/*))",
+            )
+            .unwrap();
+            assert_eq!(descriptor.multipath_len().unwrap(), 2);
+            let branches = descriptor.branches().unwrap();
+            assert_eq!(branches.len(), 2);
+            assert_eq!(
+                format!("{:#}", branches[1]),
+                format!("{:#}", descriptor).replace("<0;1>", "1")
+            );
+            assert_eq!(branches[1].multipath_len().unwrap(), 1);
+            assert_eq!(descriptor.derive_pattern_len().unwrap(), 2);
+            assert_eq!(branches[0].derive_pattern_len().unwrap(), 1);
+            assert!(matches!(
+                descriptor.branch(2),
+                Err(DeriveError::NoMultipathBranch(2))
+            ));
+
+            let inconsistent = miniscript::Descriptor::<DerivationAccount>::from_str(
+                "wsh(multi(1,[00000000/48h/0h/0h/2h]xpub69PnGxAGwEBNtGPnxd71p2QbHRZvjDG1BEza1sZdRbd7uWkjHqfGxMburhdEocC5ud2NpkbhwnM29c2zdqWS36wJue1BuJgMnLTpxpxzJe1/<0;1>/*,xpub661MyMwAqRbcFtXgS5sYJABqqG9YLmC4Q1Rdap9gSE8NqtwybGhePY2gZ29ESFjqJoCu1Rupje8YtGqsefD265TMg7usUDFdp6W1EGMcet8/<0;1;2>/*))",
+            )
+            .unwrap();
+            assert!(matches!(
+                inconsistent.multipath_len(),
+                Err(DeriveError::InconsistentKeyMultipath)
+            ));
+        }
+    }
 }
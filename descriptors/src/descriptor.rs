@@ -178,7 +178,7 @@ impl From<ConvertInfo> for SpkClass {
             ConvertInfo::Bare => SpkClass::Bare,
             ConvertInfo::Hashed => SpkClass::Hashed,
             ConvertInfo::NestedV0 | ConvertInfo::SegWitV0 => SpkClass::SegWit,
-            ConvertInfo::Taproot { .. } => SpkClass::Taproot,
+            ConvertInfo::Taproot => SpkClass::Taproot,
         }
     }
 }
@@ -560,7 +560,7 @@ impl DescrVariants {
             ConvertInfo::Hashed => self.hashed,
             ConvertInfo::NestedV0 => self.nested,
             ConvertInfo::SegWitV0 => self.segwit,
-            ConvertInfo::Taproot { .. } => self.taproot,
+            ConvertInfo::Taproot => self.taproot,
         }
     }
 }
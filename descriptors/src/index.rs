@@ -0,0 +1,308 @@
+// Wallet-level libraries for bitcoin protocol by LNP/BP Association
+//
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// This software is distributed without any warranty.
+//
+// You should have received a copy of the Apache-2.0 License
+// along with this software.
+// If not, see <https://opensource.org/licenses/Apache-2.0>.
+
+//! Reverse index of the scriptPubkeys derived from a descriptor, allowing
+//! fast detection of the wallet-owned outputs.
+
+use std::collections::BTreeMap;
+use std::fmt::{self, Display, Formatter};
+use std::ops::Range;
+
+use bitcoin::secp256k1::{self, Secp256k1, Verification};
+use bitcoin::util::bip32::KeySource;
+use bitcoin::Script;
+use bitcoin_hd::{DerivationAccount, DeriveError, SegmentIndexes, UnhardenedIndex};
+use miniscript::ForEachKey;
+#[cfg(feature = "serde")]
+use serde_with::{As, DisplayFromStr};
+
+use crate::derive::Descriptor as _;
+
+/// Origin of the scriptPubkey derived from a descriptor
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_crate")
+)]
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct ScriptOrigin {
+    /// Derivation branch: the first index of the two-segment terminal
+    /// derivation (`0` for receive and `1` for change addresses), or `None`
+    /// for descriptors with a single derivation segment
+    pub branch: Option<UnhardenedIndex>,
+
+    /// Index of the scriptPubkey within its derivation branch
+    pub index: UnhardenedIndex,
+
+    /// Public keys used by the scriptPubkey together with their origins
+    pub keys: BTreeMap<secp256k1::PublicKey, KeySource>,
+}
+
+impl ScriptOrigin {
+    /// Returns full terminal derivation of the scriptPubkey
+    pub fn terminal(&self) -> Vec<UnhardenedIndex> {
+        self.branch.into_iter().chain([self.index]).collect()
+    }
+}
+
+impl Display for ScriptOrigin {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if let Some(branch) = self.branch {
+            Display::fmt(&branch, f)?;
+            f.write_str("/")?;
+        }
+        Display::fmt(&self.index, f)
+    }
+}
+
+/// Index of scriptPubkeys derived from a descriptor within a window of
+/// derivation indexes, mapping each script to its origin.
+///
+/// The window is maintained separately for each of the derivation branches
+/// and may be moved or extended without re-deriving already known scripts.
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_crate")
+)]
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ScriptIndex {
+    #[cfg_attr(feature = "serde", serde(with = "As::<DisplayFromStr>"))]
+    descriptor: miniscript::Descriptor<DerivationAccount>,
+    windows: Vec<Range<u32>>,
+    scripts: BTreeMap<Script, ScriptOrigin>,
+}
+
+impl ScriptIndex {
+    /// Constructs index of scripts derived from the descriptor for the indexes
+    /// within `window` on each of its derivation branches
+    pub fn new<C: Verification>(
+        secp: &Secp256k1<C>,
+        descriptor: miniscript::Descriptor<DerivationAccount>,
+        window: Range<u32>,
+    ) -> Result<ScriptIndex, DeriveError> {
+        let branches = match descriptor.derive_pattern_len()? {
+            1 => 1,
+            2 => 2,
+            _ => return Err(DeriveError::DerivePatternMismatch),
+        };
+        let mut index = ScriptIndex {
+            descriptor,
+            windows: vec![0..0; branches],
+            scripts: empty!(),
+        };
+        index.set_window(secp, window)?;
+        Ok(index)
+    }
+
+    /// Returns descriptor from which the scripts are derived
+    #[inline]
+    pub fn descriptor(&self) -> &miniscript::Descriptor<DerivationAccount> { &self.descriptor }
+
+    /// Returns derivation branches of the descriptor: `[None]` for
+    /// descriptors with a single derivation segment, and receive and change
+    /// branches otherwise
+    pub fn branches(&self) -> Vec<Option<UnhardenedIndex>> {
+        match self.windows.len() {
+            1 => vec![None],
+            _ => vec![Some(UnhardenedIndex::zero()), Some(UnhardenedIndex::one())],
+        }
+    }
+
+    /// Returns window of indexes covered by the index in the given branch, or
+    /// `None` if the descriptor has no such branch
+    pub fn window(&self, branch: Option<UnhardenedIndex>) -> Option<Range<u32>> {
+        let no = self.branch_no(branch)?;
+        self.windows.get(no).cloned()
+    }
+
+    /// Moves window of indexes on all derivation branches, deriving scripts
+    /// for the new indexes and removing scripts which are no longer covered
+    pub fn set_window<C: Verification>(
+        &mut self,
+        secp: &Secp256k1<C>,
+        window: Range<u32>,
+    ) -> Result<(), DeriveError> {
+        for branch in self.branches() {
+            self.set_branch_window(secp, branch, window.clone())?;
+        }
+        Ok(())
+    }
+
+    /// Moves window of indexes on a single derivation branch, deriving scripts
+    /// for the new indexes and removing scripts which are no longer covered
+    pub fn set_branch_window<C: Verification>(
+        &mut self,
+        secp: &Secp256k1<C>,
+        branch: Option<UnhardenedIndex>,
+        window: Range<u32>,
+    ) -> Result<(), DeriveError> {
+        let no = self
+            .branch_no(branch)
+            .ok_or(DeriveError::DerivePatternMismatch)?;
+        let old = self.windows[no].clone();
+        self.scripts.retain(|_, origin| {
+            origin.branch != branch || window.contains(&origin.index.first_index())
+        });
+        for index in window.clone() {
+            if old.contains(&index) {
+                continue;
+            }
+            let index = UnhardenedIndex::from_index(index)
+                .map_err(|_| DeriveError::DerivePatternMismatch)?;
+            let (script, origin) = self.derive(secp, branch, index)?;
+            self.scripts.insert(script, origin);
+        }
+        self.windows[no] = window;
+        Ok(())
+    }
+
+    /// Extends window of indexes on all derivation branches by `count`
+    /// indexes
+    pub fn extend<C: Verification>(
+        &mut self,
+        secp: &Secp256k1<C>,
+        count: u32,
+    ) -> Result<(), DeriveError> {
+        for branch in self.branches() {
+            self.extend_branch(secp, branch, count)?;
+        }
+        Ok(())
+    }
+
+    /// Extends window of indexes on a single derivation branch by `count`
+    /// indexes, returning scripts derived for the new indexes
+    pub fn extend_branch<C: Verification>(
+        &mut self,
+        secp: &Secp256k1<C>,
+        branch: Option<UnhardenedIndex>,
+        count: u32,
+    ) -> Result<Vec<Script>, DeriveError> {
+        let window = self
+            .window(branch)
+            .ok_or(DeriveError::DerivePatternMismatch)?;
+        let end = window.end.saturating_add(count);
+        self.set_branch_window(secp, branch, window.start..end)?;
+        Ok(self
+            .scripts
+            .iter()
+            .filter(|(_, origin)| {
+                origin.branch == branch && origin.index.first_index() >= window.end
+            })
+            .map(|(script, _)| script.clone())
+            .collect())
+    }
+
+    /// Returns origin of the scriptPubkey, if it is covered by the index
+    #[inline]
+    pub fn get(&self, script: &Script) -> Option<&ScriptOrigin> { self.scripts.get(script) }
+
+    /// Detects whether the scriptPubkey is covered by the index
+    #[inline]
+    pub fn contains(&self, script: &Script) -> bool { self.scripts.contains_key(script) }
+
+    /// Iterates over all indexed scripts with their origins
+    #[inline]
+    pub fn iter(&self) -> impl Iterator<Item = (&Script, &ScriptOrigin)> { self.scripts.iter() }
+
+    /// Returns number of indexed scripts
+    #[inline]
+    pub fn len(&self) -> usize { self.scripts.len() }
+
+    /// Detects whether the index contains no scripts
+    #[inline]
+    pub fn is_empty(&self) -> bool { self.scripts.is_empty() }
+
+    fn branch_no(&self, branch: Option<UnhardenedIndex>) -> Option<usize> {
+        match (self.windows.len(), branch) {
+            (1, None) => Some(0),
+            (2, Some(index)) if index.first_index() < 2 => Some(index.first_index() as usize),
+            _ => None,
+        }
+    }
+
+    fn derive<C: Verification>(
+        &self,
+        secp: &Secp256k1<C>,
+        branch: Option<UnhardenedIndex>,
+        index: UnhardenedIndex,
+    ) -> Result<(Script, ScriptOrigin), DeriveError> {
+        let terminal = branch.into_iter().chain([index]).collect::<Vec<_>>();
+        let script = match self.descriptor {
+            miniscript::Descriptor::Tr(_) => self.descriptor.script_pubkey_tr(secp, &terminal)?,
+            _ => self.descriptor.script_pubkey_pretr(secp, &terminal)?,
+        };
+        let mut keys = bmap! {};
+        let mut err = None;
+        self.descriptor
+            .for_each_key(|account| match account.bip32_derivation(secp, &terminal) {
+                Ok((pubkey, key_source)) => {
+                    keys.insert(pubkey, key_source);
+                    true
+                }
+                Err(e) => {
+                    err = Some(e);
+                    false
+                }
+            });
+        if let Some(err) = err {
+            return Err(err.into());
+        }
+        Ok((script, ScriptOrigin {
+            branch,
+            index,
+            keys,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use super::*;
+
+    #[test]
+    fn moving_window() {
+        let secp = Secp256k1::verification_only();
+        let descriptor = miniscript::Descriptor::<DerivationAccount>::from_str(
+            "wpkh([00000000/84h/0h/0h]xpub69PnGxAGwEBNtGPnxd71p2QbHRZvjDG1BEza1sZdRbd7uWkjHqfGxMburhdEocC5ud2NpkbhwnM29c2zdqWS36wJue1BuJgMnLTpxpxzJe1/<0;1>/*)",
+        )
+        .unwrap();
+        let change = [UnhardenedIndex::one(), UnhardenedIndex::from(7u8)];
+        let script = descriptor.script_pubkey_pretr(&secp, change).unwrap();
+
+        let mut index = ScriptIndex::new(&secp, descriptor, 0..5).unwrap();
+        assert_eq!(index.len(), 10);
+        assert!(!index.contains(&script));
+
+        let new = index
+            .extend_branch(&secp, Some(UnhardenedIndex::one()), 5)
+            .unwrap();
+        assert_eq!(new.len(), 5);
+        assert!(new.contains(&script));
+        assert_eq!(index.len(), 15);
+        let origin = index.get(&script).unwrap();
+        assert_eq!(origin.terminal(), change);
+        assert_eq!(origin.to_string(), "1/7");
+        assert_eq!(origin.keys.len(), 1);
+        assert_eq!(
+            origin.keys.values().next().unwrap().1.to_string(),
+            "m/84'/0'/0'/1/7"
+        );
+
+        index.set_window(&secp, 6..8).unwrap();
+        assert_eq!(index.len(), 4);
+        assert!(index.contains(&script));
+        assert_eq!(index.window(None), None);
+        assert_eq!(index.window(Some(UnhardenedIndex::zero())), Some(6..8));
+    }
+}
@@ -33,8 +33,12 @@ extern crate serde_crate as serde;
 mod deduction;
 pub mod derive;
 mod descriptor;
+#[cfg(feature = "miniscript")]
+mod index;
 mod input;
 #[cfg(feature = "miniscript")]
+pub mod policy;
+#[cfg(feature = "miniscript")]
 mod templates;
 
 pub use deduction::DeductionError;
@@ -42,6 +46,10 @@ pub use descriptor::{
     BareDescriptor, CompositeDescrType, DescrVariants, DescriptorClass, Error, InnerDescrType,
     OuterDescrType, ParseError, ScriptPubkeyDescr, SpkClass, UnsupportedScriptPubkey,
 };
+#[cfg(feature = "miniscript")]
+pub use index::{ScriptIndex, ScriptOrigin};
 pub use input::InputDescriptor;
 #[cfg(feature = "miniscript")]
+pub use policy::{KeyPlaceholder, PolicyError, WalletPolicy};
+#[cfg(feature = "miniscript")]
 pub use templates::ScriptTemplate;
@@ -0,0 +1,432 @@
+// Wallet-level libraries for bitcoin protocol by LNP/BP Association
+//
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// This software is distributed without any warranty.
+//
+// You should have received a copy of the Apache-2.0 License
+// along with this software.
+// If not, see <https://opensource.org/licenses/Apache-2.0>.
+
+//! BIP-388 wallet policies: descriptor templates with `@N/**` key
+//! placeholders accompanied by the vector of key information.
+
+use std::convert::Infallible;
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+use bitcoin_hd::account::ParseError as AccountParseError;
+use bitcoin_hd::{DerivationAccount, SegmentIndexes, TerminalStep, UnhardenedIndex};
+use miniscript::{
+    translate_hash_fail, Descriptor, ForEachKey, MiniscriptKey, TranslatePk, Translator,
+};
+
+/// Errors in BIP-388 wallet policy structure
+#[derive(Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum PolicyError {
+    /// wallet policy must start with a descriptor template followed by the
+    /// list of keys, one per line
+    NoTemplate,
+
+    /// invalid key placeholder `{0}`; placeholders must have form of `@N/**`
+    /// or `@N/<M;L>/*` with `M` distinct from `L`
+    InvalidPlaceholder(String),
+
+    /// key placeholder `@{0}` is used before placeholders with smaller indexes
+    PlaceholderOrder(usize),
+
+    /// key placeholder `@{0}` refers to a key absent in the key information
+    /// list
+    KeyAbsent(usize),
+
+    /// key `@{0}` is not used in the descriptor template
+    UnusedKey(usize),
+
+    /// key information list contains duplicated key `{0}`
+    DuplicateKey(String),
+
+    /// key information `{0}` must not contain derivation path after the
+    /// extended public key
+    KeyDerivation(String),
+
+    /// multipath derivations used with key placeholder `@{0}` are not disjoint
+    OverlappingPaths(usize),
+
+    /// key `{0}` derivation can't be represented in a wallet policy, which
+    /// requires all keys to end with `/<M;L>/*`
+    NonPolicyDerivation(String),
+
+    /// invalid descriptor template. {0}
+    #[from]
+    Miniscript(miniscript::Error),
+
+    /// invalid key information. {0}
+    #[from]
+    KeyInfo(AccountParseError),
+}
+
+/// Key placeholder in BIP-388 descriptor template, having form of `@N/**`
+/// (shorthand for `@N/<0;1>/*`) or `@N/<M;L>/*`, where the receiving branch
+/// index `M` must be distinct from the change branch index `L`.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
+pub struct KeyPlaceholder {
+    /// Index of the key in the wallet policy key information list
+    pub index: usize,
+
+    /// Derivation index for the first (receiving) branch
+    pub receive: UnhardenedIndex,
+
+    /// Derivation index for the second (change) branch
+    pub change: UnhardenedIndex,
+}
+
+impl KeyPlaceholder {
+    /// Constructs placeholder with the default `/**` derivation
+    pub fn with(index: usize) -> KeyPlaceholder {
+        KeyPlaceholder {
+            index,
+            receive: UnhardenedIndex::zero(),
+            change: UnhardenedIndex::one(),
+        }
+    }
+
+    /// Detects whether placeholder shares some derivation branches with the
+    /// other placeholder for the same key
+    pub fn does_overlap(&self, other: &KeyPlaceholder) -> bool {
+        self.index == other.index
+            && [self.receive, self.change]
+                .iter()
+                .any(|idx| *idx == other.receive || *idx == other.change)
+    }
+
+    fn terminal_path(&self) -> [TerminalStep; 2] {
+        let branches = TerminalStep::multipath([self.receive, self.change])
+            .expect("key placeholder with non-distinct branches");
+        [branches, TerminalStep::Wildcard]
+    }
+}
+
+impl Display for KeyPlaceholder {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        if self.receive == 0u8 && self.change == 1u8 {
+            write!(f, "@{}/**", self.index)
+        } else {
+            write!(f, "@{}/<{};{}>/*", self.index, self.receive, self.change)
+        }
+    }
+}
+
+impl FromStr for KeyPlaceholder {
+    type Err = PolicyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || PolicyError::InvalidPlaceholder(s.to_owned());
+        let (index, suffix) = s
+            .strip_prefix('@')
+            .and_then(|s| s.split_once('/'))
+            .ok_or_else(err)?;
+        let index = usize::from_str(index).map_err(|_| err())?;
+        if suffix == "**" {
+            return Ok(KeyPlaceholder::with(index));
+        }
+        let (receive, change) = suffix
+            .strip_prefix('<')
+            .and_then(|s| s.strip_suffix(">/*"))
+            .and_then(|s| s.split_once(';'))
+            .ok_or_else(err)?;
+        let receive = UnhardenedIndex::from_str(receive).map_err(|_| err())?;
+        let change = UnhardenedIndex::from_str(change).map_err(|_| err())?;
+        if receive == change {
+            return Err(err());
+        }
+        Ok(KeyPlaceholder {
+            index,
+            receive,
+            change,
+        })
+    }
+}
+
+impl MiniscriptKey for KeyPlaceholder {
+    type Sha256 = Self;
+    type Hash256 = Self;
+    type Ripemd160 = Self;
+    type Hash160 = Self;
+}
+
+/// BIP-388 wallet policy, consisting of a descriptor template using key
+/// placeholders and a list of key information (extended public keys with
+/// optional origin).
+///
+/// The string representation puts the descriptor template on the first line,
+/// followed by the key information, one per line.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct WalletPolicy {
+    template: Descriptor<KeyPlaceholder>,
+    keys: Vec<DerivationAccount>,
+}
+
+impl WalletPolicy {
+    /// Constructs wallet policy from the descriptor template and key
+    /// information, checking BIP-388 restrictions.
+    pub fn new(
+        template: Descriptor<KeyPlaceholder>,
+        keys: impl IntoIterator<Item = DerivationAccount>,
+    ) -> Result<WalletPolicy, PolicyError> {
+        let keys = keys.into_iter().collect::<Vec<_>>();
+
+        for (no, key) in keys.iter().enumerate() {
+            if !key.terminal_path.is_empty() {
+                return Err(PolicyError::KeyDerivation(key.to_string()));
+            }
+            if keys[..no]
+                .iter()
+                .any(|prev| prev.account_xpub == key.account_xpub)
+            {
+                return Err(PolicyError::DuplicateKey(key.to_string()));
+            }
+        }
+
+        let mut placeholders = Vec::<KeyPlaceholder>::new();
+        template.for_each_key(|placeholder| {
+            placeholders.push(*placeholder);
+            true
+        });
+        let mut next = 0usize;
+        for (no, placeholder) in placeholders.iter().enumerate() {
+            if placeholder.receive == placeholder.change {
+                return Err(PolicyError::InvalidPlaceholder(placeholder.to_string()));
+            }
+            if placeholder.index >= keys.len() {
+                return Err(PolicyError::KeyAbsent(placeholder.index));
+            }
+            match placeholder.index {
+                index if index > next => return Err(PolicyError::PlaceholderOrder(index)),
+                index if index == next => next += 1,
+                _ => {}
+            }
+            if placeholders[..no]
+                .iter()
+                .any(|prev| prev.does_overlap(placeholder))
+            {
+                return Err(PolicyError::OverlappingPaths(placeholder.index));
+            }
+        }
+        if next < keys.len() {
+            return Err(PolicyError::UnusedKey(next));
+        }
+
+        Ok(WalletPolicy { template, keys })
+    }
+
+    /// Constructs wallet policy out of a descriptor, which must use
+    /// `/<M;L>/*` derivation for all of its keys.
+    pub fn from_descriptor(
+        descriptor: &Descriptor<DerivationAccount>,
+    ) -> Result<WalletPolicy, PolicyError> {
+        struct PlaceholderTranslator(Vec<DerivationAccount>);
+
+        impl Translator<DerivationAccount, KeyPlaceholder, PolicyError> for PlaceholderTranslator {
+            fn pk(&mut self, pk: &DerivationAccount) -> Result<KeyPlaceholder, PolicyError> {
+                let err = || PolicyError::NonPolicyDerivation(pk.to_string());
+                let (receive, change) = match pk.terminal_path.as_ref() {
+                    [TerminalStep::Multipath(indexes), TerminalStep::Wildcard]
+                        if indexes.len() == 2 =>
+                    {
+                        (indexes[0], indexes[1])
+                    }
+                    _ => return Err(err()),
+                };
+                let key = DerivationAccount {
+                    terminal_path: empty!(),
+                    ..pk.clone()
+                };
+                let index = match self.0.iter().position(|k| *k == key) {
+                    Some(index) => index,
+                    None => {
+                        self.0.push(key);
+                        self.0.len() - 1
+                    }
+                };
+                Ok(KeyPlaceholder {
+                    index,
+                    receive,
+                    change,
+                })
+            }
+
+            translate_hash_fail!(DerivationAccount, KeyPlaceholder, PolicyError);
+        }
+
+        let mut translator = PlaceholderTranslator(vec![]);
+        let template = descriptor.translate_pk(&mut translator)?;
+        WalletPolicy::new(template, translator.0)
+    }
+
+    /// Returns descriptor template of the policy
+    #[inline]
+    pub fn template(&self) -> &Descriptor<KeyPlaceholder> { &self.template }
+
+    /// Returns key information list of the policy
+    #[inline]
+    pub fn keys(&self) -> &[DerivationAccount] { &self.keys }
+
+    /// Constructs descriptor from the policy by replacing key placeholders
+    /// with the keys and their multipath derivations.
+    pub fn to_descriptor(&self) -> Descriptor<DerivationAccount> {
+        struct KeyTranslator<'a>(&'a [DerivationAccount]);
+
+        impl<'a> Translator<KeyPlaceholder, DerivationAccount, Infallible> for KeyTranslator<'a> {
+            fn pk(&mut self, pk: &KeyPlaceholder) -> Result<DerivationAccount, Infallible> {
+                Ok(DerivationAccount {
+                    terminal_path: pk.terminal_path().into_iter().collect(),
+                    ..self.0[pk.index].clone()
+                })
+            }
+
+            translate_hash_fail!(KeyPlaceholder, DerivationAccount, Infallible);
+        }
+
+        self.template
+            .translate_pk(&mut KeyTranslator(&self.keys))
+            .expect("infallible")
+    }
+}
+
+impl TryFrom<&Descriptor<DerivationAccount>> for WalletPolicy {
+    type Error = PolicyError;
+
+    #[inline]
+    fn try_from(descriptor: &Descriptor<DerivationAccount>) -> Result<Self, Self::Error> {
+        WalletPolicy::from_descriptor(descriptor)
+    }
+}
+
+impl From<&WalletPolicy> for Descriptor<DerivationAccount> {
+    #[inline]
+    fn from(policy: &WalletPolicy) -> Self { policy.to_descriptor() }
+}
+
+impl Display for WalletPolicy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#}", self.template)?;
+        for key in &self.keys {
+            write!(f, "\n{}", key)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for WalletPolicy {
+    type Err = PolicyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut lines = s.lines().map(str::trim).filter(|line| !line.is_empty());
+        let template = lines.next().ok_or(PolicyError::NoTemplate)?;
+        let template = Descriptor::<KeyPlaceholder>::from_str(template)?;
+        let keys = lines
+            .map(DerivationAccount::from_str)
+            .collect::<Result<Vec<_>, _>>()?;
+        WalletPolicy::new(template, keys)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const KEY0: &str = "[6738736c/48h/0h/0h/2h]xpub6FHa3pjLCk84BayeJxFW2SP4XRrFd1JYnxeLeU8EqN3vDfZmbqBqaGJAyiLjTAwm6ZLRQUMv1ZACTj37sR62cfN7fe5JnJ7dh8zL4fiyLHV";
+    const KEY1: &str = "[b2b1f0cf/48h/0h/0h/2h]xpub6D4BDPcP2GT577Vvch3R8wDkScZWzQzMMUm3PWbmWvVJrZwQY4VUNgqFJPMM3No2dFDFGTsxxpG5uJh7n7epu4trkrX7x7DogT5Uv6fcLW5";
+
+    #[test]
+    fn placeholder_from_str() {
+        assert_eq!(
+            KeyPlaceholder::from_str("@0/**").unwrap(),
+            KeyPlaceholder::with(0)
+        );
+        let placeholder = KeyPlaceholder::from_str("@3/<2;3>/*").unwrap();
+        assert_eq!(placeholder.to_string(), "@3/<2;3>/*");
+        assert_eq!(
+            KeyPlaceholder::from_str("@1/<0;1>/*").unwrap().to_string(),
+            "@1/**"
+        );
+        let placeholder = KeyPlaceholder::from_str("@0/<1;0>/*").unwrap();
+        assert_eq!(placeholder.receive, UnhardenedIndex::one());
+        assert_eq!(placeholder.change, UnhardenedIndex::zero());
+        assert_eq!(placeholder.to_string(), "@0/<1;0>/*");
+        for invalid in [
+            "0/**",
+            "@0",
+            "@0/*",
+            "@0/<1;1>/*",
+            "@0/<1h;2>/*",
+            "@0/<1;2;3>/*",
+        ] {
+            assert!(KeyPlaceholder::from_str(invalid).is_err(), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn policy_roundtrip() {
+        let s = format!("wsh(sortedmulti(2,@0/**,@1/<2;3>/*))\n{}\n{}", KEY0, KEY1);
+        let policy = WalletPolicy::from_str(&s).unwrap();
+        assert_eq!(policy.to_string(), s);
+
+        let descriptor = policy.to_descriptor();
+        assert_eq!(
+            format!("{:#}", descriptor),
+            format!("wsh(sortedmulti(2,{}/<0;1>/*,{}/<2;3>/*))", KEY0, KEY1)
+        );
+        assert_eq!(WalletPolicy::from_descriptor(&descriptor).unwrap(), policy);
+
+        let s = format!("wpkh(@0/<1;0>/*)\n{}", KEY0);
+        let policy = WalletPolicy::from_str(&s).unwrap();
+        let descriptor = policy.to_descriptor();
+        assert_eq!(
+            format!("{:#}", descriptor),
+            format!("wpkh({}/<1;0>/*)", KEY0)
+        );
+        assert_eq!(WalletPolicy::from_descriptor(&descriptor).unwrap(), policy);
+    }
+
+    #[test]
+    fn policy_restrictions() {
+        let policy = |template: &str, keys: &[&str]| {
+            WalletPolicy::from_str(&format!("{}\n{}", template, keys.join("\n")))
+        };
+        assert!(policy("wsh(multi(1,@0/**,@1/**))", &[KEY0, KEY1]).is_ok());
+        assert!(policy("wsh(multi(1,@0/**,@0/<2;3>/*))", &[KEY0]).is_ok());
+        assert!(matches!(
+            policy("wsh(multi(1,@1/**,@0/**))", &[KEY0, KEY1]),
+            Err(PolicyError::PlaceholderOrder(1))
+        ));
+        assert!(matches!(
+            policy("wsh(multi(1,@0/**,@1/**))", &[KEY0, KEY0]),
+            Err(PolicyError::DuplicateKey(_))
+        ));
+        assert!(matches!(
+            policy("wsh(multi(1,@0/**,@2/**))", &[KEY0, KEY1]),
+            Err(PolicyError::KeyAbsent(2))
+        ));
+        assert!(matches!(
+            policy("wpkh(@0/**)", &[KEY0, KEY1]),
+            Err(PolicyError::UnusedKey(1))
+        ));
+        assert!(matches!(
+            policy("wsh(multi(1,@0/**,@0/<1;2>/*))", &[KEY0]),
+            Err(PolicyError::OverlappingPaths(0))
+        ));
+        assert!(matches!(
+            policy("wpkh(@0/**)", &[&format!("{}/0", KEY0)]),
+            Err(PolicyError::KeyDerivation(_))
+        ));
+        assert!(matches!(
+            WalletPolicy::from_descriptor(
+                &Descriptor::from_str(&format!("wpkh({}/0/*)", KEY0)).unwrap()
+            ),
+            Err(PolicyError::NonPolicyDerivation(_))
+        ));
+    }
+}
@@ -51,6 +51,9 @@ pub enum ParseError {
     /// incorrect xpub revocation seal `{0}`; the seal must be a valid bitcoin
     /// transaction outpoint in format of `txid:vout`.
     RevocationSeal(String),
+
+    /// derivation path `{0}` contains more than one BIP-389 multipath segment.
+    MultipleMultipath(String),
 }
 
 // TODO: Merge it with the other derivation trait supporting multiple terminal
@@ -132,6 +135,34 @@ impl DerivationAccount {
     /// Detects if the tracking account is seed-based
     pub fn seed_based(&self) -> bool { self.master != XpubRef::Unknown }
 
+    /// Counts number of BIP-389 multipath branches in the terminal derivation
+    /// path (for instance, two for `<0;1>/*`). Single-path accounts always
+    /// have a single branch.
+    #[inline]
+    pub fn multipath_len(&self) -> usize { self.terminal_path.multipath_len() }
+
+    /// Constructs single-path account for the multipath branch `no`. Returns
+    /// `None` if the account has no such branch.
+    pub fn branch(&self, no: usize) -> Option<DerivationAccount> {
+        Some(DerivationAccount {
+            terminal_path: self.terminal_path.branch(no)?,
+            ..self.clone()
+        })
+    }
+
+    /// Expands BIP-389 multipath account into the list of single-path accounts,
+    /// one per branch.
+    pub fn branches(&self) -> Vec<DerivationAccount> {
+        self.terminal_path
+            .branches()
+            .into_iter()
+            .map(|terminal_path| DerivationAccount {
+                terminal_path,
+                ..self.clone()
+            })
+            .collect()
+    }
+
     /// Counts number of keys which may be derived using this account
     pub fn keyspace_size(&self) -> usize {
         self.terminal_path
@@ -348,6 +379,7 @@ impl DerivationAccount {
         for next in split {
             account.terminal_path.push(TerminalStep::from_str(next)?);
         }
+        account.check_multipath(s)?;
 
         Ok(account)
     }
@@ -426,13 +458,28 @@ impl DerivationAccount {
             source_path.push(AccountStep::from(branch_index));
         }
 
-        Ok(DerivationAccount {
+        let account = DerivationAccount {
             master,
             account_path: source_path,
             account_xpub: branch_xpub,
             revocation_seal,
             terminal_path,
-        })
+        };
+        account.check_multipath(s)?;
+        Ok(account)
+    }
+
+    fn check_multipath(&self, s: &str) -> Result<(), ParseError> {
+        if self
+            .terminal_path
+            .iter()
+            .filter(|step| step.is_multipath())
+            .count()
+            > 1
+        {
+            return Err(ParseError::MultipleMultipath(s.to_owned()));
+        }
+        Ok(())
     }
 }
 
@@ -534,7 +581,7 @@ mod test {
     #[test]
     fn trivial_paths_bitcoincore() {
         let xpubs = xpubs();
-        for path in vec![
+        for path in [
             s!("[00000000/48h/0h/0h/2h]xpub69PnGxAGwEBNtGPnxd71p2QbHRZvjDG1BEza1sZdRbd7uWkjHqfGxMburhdEocC5ud2NpkbhwnM29c2zdqWS36wJue1BuJgMnLTpxpxzJe1/<0;1>/*"),
             s!("tpubD8P81yEGkUEs1Hk3kdpSuwLBFZYwMCaVBLckeWVneqkJPivLe6uHAmtXt9RGUSRh5EqMecxinhAybyvgBzwKX3sLGGsuuJgnfzQ47arxTCp/0/*"),
             format!("[00000000/0h/5h/8h]{}/1/0/*", xpubs[0]),
@@ -552,4 +599,83 @@ mod test {
             assert_eq!(format!("{}", account), path);
         }
     }
+
+    #[test]
+    fn multipath_branches() {
+        let xpubs = xpubs();
+        let account =
+            DerivationAccount::from_str(&format!("[00000000/84h/0h/0h]{}/<0;1>/*", xpubs[1]))
+                .unwrap();
+        assert_eq!(
+            account.to_string(),
+            format!("[00000000/84h/0h/0h]{}/<0;1>/*", xpubs[1])
+        );
+        assert_eq!(account.multipath_len(), 2);
+        let branches = account.branches();
+        assert_eq!(branches.len(), 2);
+        assert_eq!(
+            branches[0].to_string(),
+            format!("[00000000/84h/0h/0h]{}/0/*", xpubs[1])
+        );
+        assert_eq!(
+            branches[1].to_string(),
+            format!("[00000000/84h/0h/0h]{}/1/*", xpubs[1])
+        );
+        assert_eq!(account.branch(1), Some(branches[1].clone()));
+        assert_eq!(account.branch(2), None);
+        assert_eq!(
+            account.to_full_derivation_path([1u8, 7u8]).unwrap(),
+            branches[1].to_full_derivation_path([7u8]).unwrap()
+        );
+
+        let single = &branches[0];
+        assert_eq!(single.multipath_len(), 1);
+        assert_eq!(single.branches(), vec![single.clone()]);
+        assert_eq!(single.branch(1), None);
+
+        let triple = DerivationAccount::from_str(&format!("{}/<0;2;3>/*", xpubs[0])).unwrap();
+        assert_eq!(triple.to_string(), format!("{}/<0;2;3>/*", xpubs[0]));
+        assert_eq!(triple.multipath_len(), 3);
+        assert_eq!(
+            triple.branch(2).unwrap().to_string(),
+            format!("{}/3/*", xpubs[0])
+        );
+        assert!(DerivationAccount::from_str(&format!("{}/<0;0>/*", xpubs[0])).is_err());
+        assert!(DerivationAccount::from_str(&format!("{}/<0;1>/<2;3>/*", xpubs[0])).is_err());
+
+        // Branches keep positional order of BIP-389
+        let reversed = DerivationAccount::from_str(&format!("{}/<1;0>/*", xpubs[0])).unwrap();
+        assert_eq!(reversed.to_string(), format!("{}/<1;0>/*", xpubs[0]));
+        assert_eq!(
+            reversed.branch(0).unwrap().to_string(),
+            format!("{}/1/*", xpubs[0])
+        );
+
+        // BIP-88 ranges are not multipath segments
+        for range in ["{0-10}", "{0,1}", "0-10"] {
+            let ranged = DerivationAccount::from_str(&format!("{}/{}/*", xpubs[0], range)).unwrap();
+            assert_eq!(ranged.multipath_len(), 1);
+            assert_eq!(ranged.branches(), vec![ranged.clone()]);
+        }
+    }
+
+    #[test]
+    fn range_rendering() {
+        // Ranges keep rendering as before multipath segments were introduced
+        for (range, plain, alt) in [
+            ("{0,1}", "<0;1>", "{0,1}"),
+            ("{0-2}", "<0;1;2>", "{0;1;2}"),
+            ("0;5-7", "<0;5;6;7>", "{0,5;6;7}"),
+        ] {
+            let step = TerminalStep::from_str(range).unwrap();
+            assert!(matches!(step, TerminalStep::Range(_)));
+            assert_eq!(format!("{}", step), plain);
+            assert_eq!(format!("{:#}", step), alt);
+        }
+
+        let multipath = TerminalStep::from_str("<3;8>").unwrap();
+        assert!(multipath.is_multipath());
+        assert_eq!(format!("{}", multipath), "<3;8>");
+        assert_eq!(format!("{:#}", multipath), "<3;8>");
+    }
 }
@@ -0,0 +1,406 @@
+// Wallet-level libraries for bitcoin protocol by LNP/BP Association
+//
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// This software is distributed without any warranty.
+//
+// You should have received a copy of the Apache-2.0 License
+// along with this software.
+// If not, see <https://opensource.org/licenses/Apache-2.0>.
+
+//! BIP-85 deterministic entropy derivation from an extended private key.
+
+#[cfg(feature = "bip39")]
+use bip39::{Language, Mnemonic};
+use bitcoin::hashes::hmac::{Hmac, HmacEngine};
+use bitcoin::hashes::{sha512, Hash, HashEngine};
+use bitcoin::secp256k1::{self, Secp256k1, SecretKey, Signing};
+use bitcoin::util::bip32::{self, ChainCode, ChildNumber, ExtendedPrivKey};
+use bitcoin::PrivateKey;
+
+/// BIP-85 purpose field used as the first derivation step
+pub const BIP85_PURPOSE: u32 = 83696968;
+
+/// HMAC key used to produce entropy from the derived private key
+const ENTROPY_HMAC_KEY: &[u8] = b"bip-entropy-from-k";
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const BASE85_ALPHABET: &[u8; 85] =
+    b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz!#$%&()*+-;<=>?@^_`{|}~";
+
+/// BIP-85 applications, defining how the derived entropy is used.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display)]
+#[non_exhaustive]
+pub enum Bip85Application {
+    /// BIP-39 mnemonic with the given language code and number of words
+    #[display("bip39/{language}/{words}")]
+    Bip39 {
+        /// BIP-85 language code
+        language: u32,
+        /// Number of words in the mnemonic
+        words: u8,
+    },
+
+    /// Private key in WIF format for an HD-seed wallet
+    #[display("wif")]
+    Wif,
+
+    /// Extended private key
+    #[display("xprv")]
+    Xprv,
+
+    /// Raw entropy of the given number of bytes
+    #[display("hex/{0}")]
+    Hex(u8),
+
+    /// Base64-encoded password of the given length
+    #[display("pwd-base64/{0}")]
+    PwdBase64(u8),
+
+    /// Base85-encoded password of the given length
+    #[display("pwd-base85/{0}")]
+    PwdBase85(u8),
+}
+
+impl Bip85Application {
+    /// Returns BIP-85 derivation path for the application and child index,
+    /// not including the purpose step.
+    pub fn derivation(self, index: u32) -> Result<Vec<ChildNumber>, Bip85Error> {
+        let path = match self {
+            Bip85Application::Bip39 { language, words } => {
+                if !matches!(words, 12 | 15 | 18 | 21 | 24) {
+                    return Err(Bip85Error::WordCount(words));
+                }
+                vec![39, language, words as u32, index]
+            }
+            Bip85Application::Wif => vec![2, index],
+            Bip85Application::Xprv => vec![32, index],
+            Bip85Application::Hex(len) => {
+                if !(16..=64).contains(&len) {
+                    return Err(Bip85Error::HexLength(len));
+                }
+                vec![128169, len as u32, index]
+            }
+            Bip85Application::PwdBase64(len) => {
+                if !(20..=86).contains(&len) {
+                    return Err(Bip85Error::PasswordLength(len));
+                }
+                vec![707764, len as u32, index]
+            }
+            Bip85Application::PwdBase85(len) => {
+                if !(10..=80).contains(&len) {
+                    return Err(Bip85Error::PasswordLength(len));
+                }
+                vec![707785, len as u32, index]
+            }
+        };
+        Ok(path
+            .into_iter()
+            .map(ChildNumber::from_hardened_idx)
+            .collect::<Result<_, _>>()?)
+    }
+}
+
+/// Errors deriving BIP-85 entropy
+#[derive(Clone, Eq, PartialEq, Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum Bip85Error {
+    /// key derivation error: {0}
+    #[from]
+    Bip32(bip32::Error),
+
+    /// invalid derived private key: {0}
+    #[from]
+    Secp256k1(secp256k1::Error),
+
+    /// BIP-85 mnemonic must contain 12, 15, 18, 21 or 24 words, while {0} were
+    /// requested
+    WordCount(u8),
+
+    /// unsupported BIP-85 mnemonic language code {0}
+    Language(u32),
+
+    /// BIP-85 HEX entropy length must be between 16 and 64 bytes, while {0}
+    /// were requested
+    HexLength(u8),
+
+    /// password length {0} is outside of the range supported by BIP-85
+    PasswordLength(u8),
+}
+
+/// Deterministic entropy derivation according to BIP-85.
+pub trait Bip85 {
+    /// Derives 64 bytes of entropy using derivation path following BIP-85
+    /// purpose step.
+    fn bip85_entropy<C: Signing>(
+        &self,
+        secp: &Secp256k1<C>,
+        path: &[ChildNumber],
+    ) -> Result<[u8; 64], Bip85Error>;
+
+    /// Derives BIP-39 mnemonic using BIP-85 language code, which differs from
+    /// the codes used by BIP-39 wordlists.
+    #[cfg(feature = "bip39")]
+    fn bip85_mnemonic<C: Signing>(
+        &self,
+        secp: &Secp256k1<C>,
+        language: u32,
+        words: u8,
+        index: u32,
+    ) -> Result<Mnemonic, Bip85Error>;
+
+    /// Derives private key for an HD-seed wallet.
+    fn bip85_wif<C: Signing>(
+        &self,
+        secp: &Secp256k1<C>,
+        index: u32,
+    ) -> Result<PrivateKey, Bip85Error>;
+
+    /// Derives master extended private key.
+    fn bip85_xprv<C: Signing>(
+        &self,
+        secp: &Secp256k1<C>,
+        index: u32,
+    ) -> Result<ExtendedPrivKey, Bip85Error>;
+
+    /// Derives `len` bytes of raw entropy.
+    fn bip85_hex<C: Signing>(
+        &self,
+        secp: &Secp256k1<C>,
+        len: u8,
+        index: u32,
+    ) -> Result<Vec<u8>, Bip85Error>;
+
+    /// Derives password of `len` characters, encoded with either base64 or
+    /// base85.
+    fn bip85_password<C: Signing>(
+        &self,
+        secp: &Secp256k1<C>,
+        len: u8,
+        base85: bool,
+        index: u32,
+    ) -> Result<String, Bip85Error>;
+}
+
+impl Bip85 for ExtendedPrivKey {
+    fn bip85_entropy<C: Signing>(
+        &self,
+        secp: &Secp256k1<C>,
+        path: &[ChildNumber],
+    ) -> Result<[u8; 64], Bip85Error> {
+        let mut derivation = vec![ChildNumber::from_hardened_idx(BIP85_PURPOSE)?];
+        derivation.extend(path);
+        let xpriv = self.derive_priv(secp, &derivation)?;
+
+        let mut engine = HmacEngine::<sha512::Hash>::new(ENTROPY_HMAC_KEY);
+        engine.input(&xpriv.private_key.secret_bytes());
+        Ok(Hmac::<sha512::Hash>::from_engine(engine).into_inner())
+    }
+
+    #[cfg(feature = "bip39")]
+    fn bip85_mnemonic<C: Signing>(
+        &self,
+        secp: &Secp256k1<C>,
+        language: u32,
+        words: u8,
+        index: u32,
+    ) -> Result<Mnemonic, Bip85Error> {
+        let lang = match language {
+            0 => Language::English,
+            1 => Language::Japanese,
+            2 => Language::Korean,
+            3 => Language::Spanish,
+            4 => Language::SimplifiedChinese,
+            5 => Language::TraditionalChinese,
+            6 => Language::French,
+            7 => Language::Italian,
+            8 => Language::Czech,
+            _ => return Err(Bip85Error::Language(language)),
+        };
+        let path = Bip85Application::Bip39 { language, words }.derivation(index)?;
+        let entropy = self.bip85_entropy(secp, &path)?;
+        let len = words as usize * 4 / 3;
+        Ok(Mnemonic::from_entropy_in(lang, &entropy[..len])
+            .expect("BIP-85 word count is validated"))
+    }
+
+    fn bip85_wif<C: Signing>(
+        &self,
+        secp: &Secp256k1<C>,
+        index: u32,
+    ) -> Result<PrivateKey, Bip85Error> {
+        let path = Bip85Application::Wif.derivation(index)?;
+        let entropy = self.bip85_entropy(secp, &path)?;
+        Ok(PrivateKey::new(
+            SecretKey::from_slice(&entropy[..32])?,
+            self.network,
+        ))
+    }
+
+    fn bip85_xprv<C: Signing>(
+        &self,
+        secp: &Secp256k1<C>,
+        index: u32,
+    ) -> Result<ExtendedPrivKey, Bip85Error> {
+        let path = Bip85Application::Xprv.derivation(index)?;
+        let entropy = self.bip85_entropy(secp, &path)?;
+        Ok(ExtendedPrivKey {
+            network: self.network,
+            depth: 0,
+            parent_fingerprint: default!(),
+            child_number: ChildNumber::Normal { index: 0 },
+            private_key: SecretKey::from_slice(&entropy[32..])?,
+            chain_code: ChainCode::from(&entropy[..32]),
+        })
+    }
+
+    fn bip85_hex<C: Signing>(
+        &self,
+        secp: &Secp256k1<C>,
+        len: u8,
+        index: u32,
+    ) -> Result<Vec<u8>, Bip85Error> {
+        let path = Bip85Application::Hex(len).derivation(index)?;
+        let entropy = self.bip85_entropy(secp, &path)?;
+        Ok(entropy[..len as usize].to_vec())
+    }
+
+    fn bip85_password<C: Signing>(
+        &self,
+        secp: &Secp256k1<C>,
+        len: u8,
+        base85: bool,
+        index: u32,
+    ) -> Result<String, Bip85Error> {
+        let app = if base85 {
+            Bip85Application::PwdBase85(len)
+        } else {
+            Bip85Application::PwdBase64(len)
+        };
+        let entropy = self.bip85_entropy(secp, &app.derivation(index)?)?;
+        let mut password = if base85 {
+            base85_encode(&entropy)
+        } else {
+            base64_encode(&entropy)
+        };
+        password.truncate(len as usize);
+        Ok(password)
+    }
+}
+
+fn base64_encode(data: &[u8]) -> String {
+    let mut s = String::with_capacity((data.len() + 2) / 3 * 4);
+    for chunk in data.chunks(3) {
+        let n = chunk
+            .iter()
+            .chain([0u8; 2].iter())
+            .take(3)
+            .fold(0u32, |acc, byte| (acc << 8) | *byte as u32);
+        for pos in 0..=chunk.len() {
+            s.push(BASE64_ALPHABET[(n >> (18 - 6 * pos)) as usize & 0x3F] as char);
+        }
+    }
+    while s.len() % 4 != 0 {
+        s.push('=');
+    }
+    s
+}
+
+fn base85_encode(data: &[u8]) -> String {
+    let mut s = String::with_capacity((data.len() + 3) / 4 * 5);
+    for chunk in data.chunks(4) {
+        let mut n = chunk
+            .iter()
+            .chain([0u8; 3].iter())
+            .take(4)
+            .fold(0u32, |acc, byte| (acc << 8) | *byte as u32);
+        let mut digits = [0u8; 5];
+        for digit in digits.iter_mut().rev() {
+            *digit = BASE85_ALPHABET[(n % 85) as usize];
+            n /= 85;
+        }
+        s.extend(digits[..=chunk.len()].iter().map(|d| *d as char));
+    }
+    s
+}
+
+#[cfg(test)]
+mod test {
+    use core::str::FromStr;
+
+    use amplify::hex::ToHex;
+
+    use super::*;
+
+    fn master() -> ExtendedPrivKey {
+        ExtendedPrivKey::from_str(
+            "xprv9s21ZrQH143K2LBWUUQRFXhucrQqBpKdRRxNVq2zBqsx8HVqFk2uYo8kmbaLLHRdqtQpUm98uKfu3vca1LqdGhUtyoFnCNkfmXRyPXLjbKb",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn bip85_vectors() {
+        let secp = Secp256k1::new();
+        let xpriv = master();
+        let hardened = |index| ChildNumber::from_hardened_idx(index).unwrap();
+
+        assert_eq!(
+            xpriv.bip85_entropy(&secp, &[hardened(0), hardened(0)]).unwrap().to_hex(),
+            "efecfbccffea313214232d29e71563d941229afb4338c21f9517c41aaa0d16f00b83d2a09ef747e7a64e8e2bd5a14869e693da66ce94ac2da570ab7ee48618f7"
+        );
+        assert_eq!(
+            xpriv.bip85_entropy(&secp, &[hardened(0), hardened(1)]).unwrap().to_hex(),
+            "70c6e3e8ebee8dc4c0dbba66076819bb8c09672527c4277ca8729532ad711872218f826919f6b67218adde99018a6df9095ab2b58d803b5b93ec9802085a690e"
+        );
+
+        assert_eq!(
+            xpriv.bip85_wif(&secp, 0).unwrap().to_wif(),
+            "Kzyv4uF39d4Jrw2W7UryTHwZr1zQVNk4dAFyqE6BuMrMh1Za7uhp"
+        );
+        assert_eq!(
+            xpriv.bip85_xprv(&secp, 0).unwrap().to_string(),
+            "xprv9s21ZrQH143K2srSbCSg4m4kLvPMzcWydgmKEnMmoZUurYuBuYG46c6P71UGXMzmriLzCCBvKQWBUv3vPB3m1SATMhp3uEjXHJ42jFg7myX"
+        );
+        assert_eq!(
+            xpriv.bip85_hex(&secp, 64, 0).unwrap().to_hex(),
+            "492db4698cf3b73a5a24998aa3e9d7fa96275d85724a91e71aa2d645442f878555d078fd1f1f67e368976f04137b1f7a0d19232136ca50c44614af72b5582a5c"
+        );
+        assert_eq!(
+            xpriv.bip85_password(&secp, 21, false, 0).unwrap(),
+            "dKLoepugzdVJvdL56ogNV"
+        );
+        assert_eq!(
+            xpriv.bip85_password(&secp, 12, true, 0).unwrap(),
+            "_s`{TW89)i4`"
+        );
+    }
+
+    #[cfg(feature = "bip39")]
+    #[test]
+    fn bip85_mnemonic_vectors() {
+        let secp = Secp256k1::new();
+        let xpriv = master();
+
+        assert_eq!(
+            xpriv.bip85_mnemonic(&secp, 0, 12, 0).unwrap().to_string(),
+            "girl mad pet galaxy egg matter matrix prison refuse sense ordinary nose"
+        );
+        assert_eq!(
+            xpriv.bip85_mnemonic(&secp, 0, 18, 0).unwrap().to_string(),
+            "near account window bike charge season chef number sketch tomorrow excuse sniff \
+             circle vital hockey outdoor supply token"
+        );
+        assert_eq!(
+            xpriv.bip85_mnemonic(&secp, 0, 24, 0).unwrap().to_string(),
+            "puppy ocean match cereal symbol another shed magic wrap hammer bulb intact gadget \
+             divorce twin tonight reason outdoor destroy simple truth cigar social volcano"
+        );
+        assert_eq!(
+            xpriv.bip85_mnemonic(&secp, 9, 12, 0).unwrap_err(),
+            Bip85Error::Language(9)
+        );
+    }
+}
@@ -28,6 +28,13 @@ pub enum DeriveError {
     /// key derivation in the descriptor uses inconsistent wildcard pattern
     InconsistentKeyDerivePattern,
 
+    /// key derivation in the descriptor uses inconsistent number of BIP-389
+    /// multipath branches
+    InconsistentKeyMultipath,
+
+    /// descriptor has no multipath branch #{0}
+    NoMultipathBranch(usize),
+
     /// the provided derive pattern does not match descriptor derivation
     /// wildcard
     #[from(DerivePatternError)]
@@ -50,6 +57,8 @@ impl std::error::Error for DeriveError {
         match self {
             DeriveError::InconsistentKeyNetwork => None,
             DeriveError::InconsistentKeyDerivePattern => None,
+            DeriveError::InconsistentKeyMultipath => None,
+            DeriveError::NoMultipathBranch(_) => None,
             DeriveError::DerivePatternMismatch => None,
             DeriveError::NoKeys => None,
             DeriveError::NoAddressForDescriptor => None,
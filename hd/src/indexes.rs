@@ -737,24 +737,26 @@ impl TryFrom<AccountStep> for HardenedIndex {
     derive(Serialize, Deserialize),
     serde(crate = "serde_crate", rename_all = "camelCase")
 )]
-#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Display, From)]
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, From)]
 #[derive(StrictEncode, StrictDecode)]
 pub enum TerminalStep {
     /// Specific unhardened index
     #[from]
     #[from(u8)]
     #[from(u16)]
-    #[display(inner)]
     Index(UnhardenedIndex),
 
-    /// Range of unhardened indexes
+    /// Range of unhardened indexes (BIP-88)
     #[from]
-    #[display(inner)]
     Range(IndexRangeList<UnhardenedIndex>),
 
     /// Wildcard implying full range of unhardened indexes
-    #[display("*")]
     Wildcard,
+
+    /// BIP-389 multipath segment (`<a;b;...>`) with at least two distinct
+    /// indexes, each of which defines a separate derivation branch. Unlike
+    /// [`TerminalStep::Range`], the order of the indexes is significant.
+    Multipath(Vec<UnhardenedIndex>),
 }
 
 impl TerminalStep {
@@ -766,6 +768,47 @@ impl TerminalStep {
             end.into(),
         )))
     }
+
+    /// Constructs BIP-389 multipath segment, checking that it has at least
+    /// two indexes and that all of them are distinct
+    pub fn multipath(
+        indexes: impl IntoIterator<Item = impl Into<UnhardenedIndex>>,
+    ) -> Result<Self, bip32::Error> {
+        let indexes = indexes.into_iter().map(Into::into).collect::<Vec<_>>();
+        if indexes.len() < 2
+            || indexes
+                .iter()
+                .enumerate()
+                .any(|(no, index)| indexes[..no].contains(index))
+        {
+            return Err(bip32::Error::InvalidDerivationPathFormat);
+        }
+        Ok(TerminalStep::Multipath(indexes))
+    }
+
+    /// Detects whether the segment is a BIP-389 multipath segment
+    #[inline]
+    pub fn is_multipath(&self) -> bool { matches!(self, TerminalStep::Multipath(_)) }
+}
+
+impl Display for TerminalStep {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            TerminalStep::Index(index) => Display::fmt(index, f),
+            TerminalStep::Range(range) => Display::fmt(range, f),
+            TerminalStep::Wildcard => f.write_str("*"),
+            TerminalStep::Multipath(indexes) => {
+                f.write_str("<")?;
+                for (no, index) in indexes.iter().enumerate() {
+                    if no > 0 {
+                        f.write_str(";")?;
+                    }
+                    Display::fmt(index, f)?;
+                }
+                f.write_str(">")
+            }
+        }
+    }
 }
 
 impl SegmentIndexes for TerminalStep {
@@ -784,6 +827,7 @@ impl SegmentIndexes for TerminalStep {
             TerminalStep::Index(_) => 1,
             TerminalStep::Range(rng) => rng.count(),
             TerminalStep::Wildcard => HARDENED_INDEX_BOUNDARY as usize,
+            TerminalStep::Multipath(indexes) => indexes.len(),
         }
     }
 
@@ -793,6 +837,7 @@ impl SegmentIndexes for TerminalStep {
             TerminalStep::Index(i) => i.first_index() == index,
             TerminalStep::Range(range) => range.contains(index),
             TerminalStep::Wildcard => true,
+            TerminalStep::Multipath(indexes) => indexes.iter().any(|i| i.first_index() == index),
         }
     }
 
@@ -806,7 +851,8 @@ impl SegmentIndexes for TerminalStep {
         match self {
             TerminalStep::Index(index) => index.first_index(),
             TerminalStep::Range(range) => range.first_index(),
-            _ => 0,
+            TerminalStep::Multipath(indexes) => indexes.iter().min().map_or(0, |i| i.first_index()),
+            TerminalStep::Wildcard => 0,
         }
     }
 
@@ -815,7 +861,8 @@ impl SegmentIndexes for TerminalStep {
         match self {
             TerminalStep::Index(index) => index.last_index(),
             TerminalStep::Range(range) => range.last_index(),
-            _ => HARDENED_INDEX_BOUNDARY - 1,
+            TerminalStep::Multipath(indexes) => indexes.iter().max().map_or(0, |i| i.last_index()),
+            TerminalStep::Wildcard => HARDENED_INDEX_BOUNDARY - 1,
         }
     }
 
@@ -829,6 +876,7 @@ impl SegmentIndexes for TerminalStep {
         match self {
             TerminalStep::Index(index) => index.first_derivation_value(),
             TerminalStep::Range(range) => range.first_derivation_value(),
+            TerminalStep::Multipath(_) => self.first_index(),
             TerminalStep::Wildcard => 0,
         }
     }
@@ -838,6 +886,7 @@ impl SegmentIndexes for TerminalStep {
         match self {
             TerminalStep::Index(index) => index.last_derivation_value(),
             TerminalStep::Range(range) => range.last_derivation_value(),
+            TerminalStep::Multipath(_) => self.last_index(),
             TerminalStep::Wildcard => HARDENED_INDEX_BOUNDARY - 1,
         }
     }
@@ -846,7 +895,7 @@ impl SegmentIndexes for TerminalStep {
     fn checked_add_assign(&mut self, add: impl Into<u32>) -> Option<u32> {
         match self {
             TerminalStep::Index(index) => index.checked_add_assign(add),
-            TerminalStep::Range(_) => None,
+            TerminalStep::Range(_) | TerminalStep::Multipath(_) => None,
             TerminalStep::Wildcard => None,
         }
     }
@@ -855,7 +904,7 @@ impl SegmentIndexes for TerminalStep {
     fn checked_sub_assign(&mut self, sub: impl Into<u32>) -> Option<u32> {
         match self {
             TerminalStep::Index(index) => index.checked_sub_assign(sub),
-            TerminalStep::Range(_) => None,
+            TerminalStep::Range(_) | TerminalStep::Multipath(_) => None,
             TerminalStep::Wildcard => None,
         }
     }
@@ -870,7 +919,17 @@ impl FromStr for TerminalStep {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         Ok(match s {
             "*" => TerminalStep::Wildcard,
-            s if s.contains(&['-', ',', ';'][..]) => IndexRangeList::from_str(s)?.into(),
+            s if s.starts_with('<') && !s.contains(&['-', ','][..]) => TerminalStep::multipath(
+                s.strip_prefix('<')
+                    .and_then(|s| s.strip_suffix('>'))
+                    .ok_or(bip32::Error::InvalidDerivationPathFormat)?
+                    .split(';')
+                    .map(UnhardenedIndex::from_str)
+                    .collect::<Result<Vec<_>, _>>()?,
+            )?,
+            s if s.starts_with('{') || s.contains(&['-', ',', ';'][..]) => {
+                IndexRangeList::from_str(s)?.into()
+            }
             s => UnhardenedIndex::from_str(s)?.into(),
         })
     }
@@ -30,10 +30,12 @@ extern crate serde_crate as serde;
 extern crate miniscript_crate as miniscript;
 
 pub mod account;
+pub mod bip85;
 mod derive;
 mod indexes;
 mod path;
 mod ranges;
+pub mod slip39;
 pub mod standards;
 mod traits;
 mod unsatisfiable;
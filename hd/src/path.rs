@@ -18,7 +18,7 @@ use std::ops::{Deref, DerefMut};
 use bitcoin::util::bip32;
 use strict_encoding::{StrictDecode, StrictEncode};
 
-use crate::SegmentIndexes;
+use crate::{SegmentIndexes, TerminalStep};
 
 /// Derivation path that consisting only of single type of segments.
 ///
@@ -190,3 +190,50 @@ where
     /// Constructs empty derivation path.
     pub fn new() -> Self { Self::default() }
 }
+
+impl DerivationSubpath<TerminalStep> {
+    /// Returns position of the BIP-389 multipath segment (`<a;b;...>`) in the
+    /// terminal path, if any.
+    pub fn multipath_position(&self) -> Option<usize> {
+        self.0.iter().position(TerminalStep::is_multipath)
+    }
+
+    /// Counts number of BIP-389 multipath branches. Single-path terminal
+    /// paths always have a single branch.
+    pub fn multipath_len(&self) -> usize {
+        self.multipath_position()
+            .map(|pos| self.0[pos].count())
+            .unwrap_or(1)
+    }
+
+    /// Constructs single-path terminal path for the multipath branch `no`,
+    /// replacing multipath segment with the index from that branch. Branches
+    /// are enumerated in the order they are listed in the multipath segment.
+    ///
+    /// Returns `None` if the branch with the provided number does not exist.
+    pub fn branch(&self, no: usize) -> Option<Self> {
+        let pos = match self.multipath_position() {
+            None if no == 0 => return Some(self.clone()),
+            None => return None,
+            Some(pos) => pos,
+        };
+        let index = match &self.0[pos] {
+            TerminalStep::Multipath(indexes) => *indexes.get(no)?,
+            _ => unreachable!("multipath position always points to multipath segment"),
+        };
+        let mut path = self.clone();
+        path.0[pos] = TerminalStep::Index(index);
+        Some(path)
+    }
+
+    /// Expands BIP-389 multipath terminal path into the list of single-path
+    /// branches.
+    pub fn branches(&self) -> Vec<Self> {
+        (0..self.multipath_len())
+            .map(|no| {
+                self.branch(no)
+                    .expect("branch number within multipath length")
+            })
+            .collect()
+    }
+}
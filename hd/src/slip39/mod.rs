@@ -0,0 +1,1109 @@
+// Wallet-level libraries for bitcoin protocol by LNP/BP Association
+//
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// This software is distributed without any warranty.
+//
+// You should have received a copy of the Apache-2.0 License
+// along with this software.
+// If not, see <https://opensource.org/licenses/Apache-2.0>.
+
+//! SLIP-39 Shamir's secret-sharing for mnemonic codes: splitting of a master
+//! secret into groups of mnemonic shares and recovering it back.
+
+mod wordlist;
+
+use core::fmt::{self, Display, Formatter};
+use core::str::FromStr;
+use std::collections::BTreeMap;
+
+use bitcoin::hashes::hmac::{Hmac, HmacEngine};
+use bitcoin::hashes::{sha256, Hash, HashEngine};
+#[cfg(feature = "rand")]
+use bitcoin::secp256k1::rand::RngCore;
+
+pub use self::wordlist::WORDLIST;
+
+/// Number of bits encoded by a single word
+const RADIX_BITS: usize = 10;
+/// Number of words used for metadata: identifier, extendable backup flag,
+/// iteration exponent, group and member parameters and checksum
+const METADATA_WORDS: usize = 7;
+const CHECKSUM_WORDS: usize = 3;
+/// Minimal length of the master secret, in bytes
+const MIN_SECRET_LEN: usize = 16;
+/// Maximal number of groups and members in a group
+#[cfg(feature = "rand")]
+const MAX_SHARE_COUNT: u8 = 16;
+const DIGEST_LEN: usize = 4;
+const BASE_ITERATION_COUNT: u32 = 10000;
+const ROUND_COUNT: u8 = 4;
+const SECRET_INDEX: u8 = 255;
+const DIGEST_INDEX: u8 = 254;
+const CUSTOMIZATION: &[u8] = b"shamir";
+const CUSTOMIZATION_EXTENDABLE: &[u8] = b"shamir_extendable";
+
+/// Errors splitting secret into SLIP-39 shares and recovering it back
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum Slip39Error {
+    /// unknown SLIP-39 mnemonic word `{0}`
+    UnknownWord(String),
+
+    /// SLIP-39 share mnemonic has invalid length of {0} words
+    MnemonicLength(usize),
+
+    /// SLIP-39 share mnemonic has invalid padding
+    Padding,
+
+    /// SLIP-39 share mnemonic has invalid checksum
+    Checksum,
+
+    /// master secret must be at least 16 bytes long and have an even length,
+    /// while {0} bytes were provided
+    SecretLength(usize),
+
+    /// SLIP-39 passphrase must consist of printable ASCII characters only
+    Passphrase,
+
+    /// iteration exponent {0} exceeds maximum value of 15
+    IterationExponent(u8),
+
+    /// invalid group specification `{0}`; it must have form of `M-of-N`
+    GroupSpec(String),
+
+    /// group threshold {threshold} must be between 1 and the number of groups
+    /// {count}
+    GroupThreshold {
+        /// Required number of groups
+        threshold: u8,
+        /// Total number of groups
+        count: u8,
+    },
+
+    /// member threshold {threshold} must be between 1 and the number of group
+    /// members {count}
+    MemberThreshold {
+        /// Required number of group members
+        threshold: u8,
+        /// Total number of group members
+        count: u8,
+    },
+
+    /// group of {0} members must not have threshold 1; use 1-of-1 group
+    /// instead
+    SingleMemberThreshold(u8),
+
+    /// number of groups or group members {0} exceeds maximum of 16
+    TooManyShares(usize),
+
+    /// no SLIP-39 shares were provided
+    NoShares,
+
+    /// provided shares belong to different backups or have inconsistent
+    /// parameters
+    InconsistentShares,
+
+    /// multiple shares with the same index {0} are provided for a group
+    DuplicateShare(u8),
+
+    /// only {present} groups are provided while {required} are required
+    InsufficientGroups {
+        /// Number of complete groups provided
+        present: usize,
+        /// Group threshold
+        required: u8,
+    },
+
+    /// group {group} has only {present} shares while {required} are required
+    InsufficientShares {
+        /// Group index
+        group: u8,
+        /// Number of shares provided for the group
+        present: usize,
+        /// Group member threshold
+        required: u8,
+    },
+
+    /// shares digest does not match; shares are corrupted or belong to
+    /// different backups
+    Digest,
+}
+
+/// Specification of a group of shares in form of `M-of-N`, where `M` is the
+/// number of shares required to reconstruct the group secret and `N` is the
+/// total number of shares in the group.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display)]
+#[display("{threshold}-of-{count}")]
+pub struct GroupSpec {
+    /// Number of member shares required to reconstruct the group secret
+    pub threshold: u8,
+
+    /// Total number of member shares in the group
+    pub count: u8,
+}
+
+impl FromStr for GroupSpec {
+    type Err = Slip39Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || Slip39Error::GroupSpec(s.to_owned());
+        let (threshold, count) = s.split_once("-of-").ok_or_else(err)?;
+        Ok(GroupSpec {
+            threshold: threshold.parse().map_err(|_| err())?,
+            count: count.parse().map_err(|_| err())?,
+        })
+    }
+}
+
+/// Single SLIP-39 share, representable as a mnemonic.
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct Share {
+    /// Random 15-bit identifier of the backup, common for all its shares
+    pub identifier: u16,
+
+    /// Whether the backup is extendable, i.e. the identifier is not used in
+    /// the master secret encryption
+    pub extendable: bool,
+
+    /// Exponent defining the number of PBKDF2 iterations used in the master
+    /// secret encryption
+    pub iteration_exponent: u8,
+
+    /// Index of the group this share belongs to
+    pub group_index: u8,
+
+    /// Number of groups required to reconstruct the master secret
+    pub group_threshold: u8,
+
+    /// Total number of groups
+    pub group_count: u8,
+
+    /// Index of the share within its group
+    pub member_index: u8,
+
+    /// Number of shares required to reconstruct the group secret
+    pub member_threshold: u8,
+
+    /// Share value
+    pub value: Vec<u8>,
+}
+
+impl Share {
+    fn customization(extendable: bool) -> &'static [u8] {
+        if extendable {
+            CUSTOMIZATION_EXTENDABLE
+        } else {
+            CUSTOMIZATION
+        }
+    }
+
+    /// Returns indexes of the share mnemonic words in [`WORDLIST`].
+    pub fn to_word_indexes(&self) -> Vec<u16> {
+        let id_exp = ((self.identifier as u32) << 5)
+            | ((self.extendable as u32) << 4)
+            | self.iteration_exponent as u32;
+        let params = ((self.group_index as u32) << 16)
+            | ((self.group_threshold as u32 - 1) << 12)
+            | ((self.group_count as u32 - 1) << 8)
+            | ((self.member_index as u32) << 4)
+            | (self.member_threshold as u32 - 1);
+
+        let value_words = (self.value.len() * 8 + RADIX_BITS - 1) / RADIX_BITS;
+        let mut words = vec![
+            (id_exp >> 10) as u16,
+            (id_exp & 0x3FF) as u16,
+            (params >> 10) as u16,
+            (params & 0x3FF) as u16,
+        ];
+        words.extend(bytes_to_words(&self.value, value_words));
+        let checksum = rs1024_checksum(Self::customization(self.extendable), &words);
+        words.extend(checksum);
+        words
+    }
+
+    /// Constructs share from indexes of its mnemonic words in [`WORDLIST`],
+    /// verifying the checksum.
+    pub fn from_word_indexes(words: &[u16]) -> Result<Share, Slip39Error> {
+        let min_words = METADATA_WORDS + (MIN_SECRET_LEN * 8 + RADIX_BITS - 1) / RADIX_BITS;
+        if words.len() < min_words {
+            return Err(Slip39Error::MnemonicLength(words.len()));
+        }
+        let padding = (RADIX_BITS * (words.len() - METADATA_WORDS)) % 16;
+        if padding > 8 {
+            return Err(Slip39Error::MnemonicLength(words.len()));
+        }
+
+        let id_exp = ((words[0] as u32) << 10) | words[1] as u32;
+        let extendable = (id_exp >> 4) & 1 == 1;
+        if rs1024_polymod(Share::customization(extendable), words) != 1 {
+            return Err(Slip39Error::Checksum);
+        }
+
+        let params = ((words[2] as u32) << 10) | words[3] as u32;
+        let share = Share {
+            identifier: (id_exp >> 5) as u16,
+            extendable,
+            iteration_exponent: (id_exp & 0xF) as u8,
+            group_index: (params >> 16) as u8,
+            group_threshold: ((params >> 12) & 0xF) as u8 + 1,
+            group_count: ((params >> 8) & 0xF) as u8 + 1,
+            member_index: ((params >> 4) & 0xF) as u8,
+            member_threshold: (params & 0xF) as u8 + 1,
+            value: words_to_bytes(&words[4..words.len() - CHECKSUM_WORDS], padding)?,
+        };
+        if share.group_count < share.group_threshold {
+            return Err(Slip39Error::GroupThreshold {
+                threshold: share.group_threshold,
+                count: share.group_count,
+            });
+        }
+        Ok(share)
+    }
+
+    fn has_same_backup(&self, other: &Share) -> bool {
+        self.identifier == other.identifier
+            && self.extendable == other.extendable
+            && self.iteration_exponent == other.iteration_exponent
+            && self.group_threshold == other.group_threshold
+            && self.group_count == other.group_count
+            && self.value.len() == other.value.len()
+    }
+}
+
+impl Display for Share {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let words = self
+            .to_word_indexes()
+            .into_iter()
+            .map(|index| WORDLIST[index as usize])
+            .collect::<Vec<_>>();
+        f.write_str(&words.join(" "))
+    }
+}
+
+impl FromStr for Share {
+    type Err = Slip39Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let words = s
+            .split_whitespace()
+            .map(|word| {
+                let word = word.to_lowercase();
+                WORDLIST
+                    .binary_search(&word.as_str())
+                    .map(|index| index as u16)
+                    .map_err(|_| Slip39Error::UnknownWord(word))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Share::from_word_indexes(&words)
+    }
+}
+
+/// Splits master secret into groups of SLIP-39 shares. The secret is
+/// encrypted with the passphrase before splitting; the same passphrase must
+/// be provided to [`combine`] to get the original secret back.
+///
+/// Generated backup is extendable, i.e. new shares for the same master secret
+/// can be added later.
+#[cfg(feature = "rand")]
+pub fn split(
+    secret: &[u8],
+    passphrase: &str,
+    group_threshold: u8,
+    groups: &[GroupSpec],
+    iteration_exponent: u8,
+    rng: &mut impl RngCore,
+) -> Result<Vec<Vec<Share>>, Slip39Error> {
+    if secret.len() < MIN_SECRET_LEN || secret.len() % 2 != 0 {
+        return Err(Slip39Error::SecretLength(secret.len()));
+    }
+    if iteration_exponent > 0xF {
+        return Err(Slip39Error::IterationExponent(iteration_exponent));
+    }
+    if groups.len() > MAX_SHARE_COUNT as usize {
+        return Err(Slip39Error::TooManyShares(groups.len()));
+    }
+    let group_count = groups.len() as u8;
+    if group_threshold == 0 || group_threshold > group_count {
+        return Err(Slip39Error::GroupThreshold {
+            threshold: group_threshold,
+            count: group_count,
+        });
+    }
+    for group in groups {
+        if group.count > MAX_SHARE_COUNT {
+            return Err(Slip39Error::TooManyShares(group.count as usize));
+        }
+        if group.threshold == 0 || group.threshold > group.count {
+            return Err(Slip39Error::MemberThreshold {
+                threshold: group.threshold,
+                count: group.count,
+            });
+        }
+        if group.threshold == 1 && group.count > 1 {
+            return Err(Slip39Error::SingleMemberThreshold(group.count));
+        }
+    }
+
+    let identifier = (rng.next_u32() & 0x7FFF) as u16;
+    let extendable = true;
+    let encrypted = feistel(
+        secret,
+        passphrase,
+        iteration_exponent,
+        identifier,
+        extendable,
+        false,
+    )?;
+
+    let group_secrets = split_secret(group_threshold, group_count, &encrypted, rng);
+    Ok(groups
+        .iter()
+        .zip(group_secrets)
+        .map(|(group, (group_index, group_secret))| {
+            split_secret(group.threshold, group.count, &group_secret, rng)
+                .into_iter()
+                .map(|(member_index, value)| Share {
+                    identifier,
+                    extendable,
+                    iteration_exponent,
+                    group_index,
+                    group_threshold,
+                    group_count,
+                    member_index,
+                    member_threshold: group.threshold,
+                    value,
+                })
+                .collect()
+        })
+        .collect())
+}
+
+/// Recovers master secret from a set of SLIP-39 shares, which must satisfy
+/// group and member thresholds.
+pub fn combine(shares: &[Share], passphrase: &str) -> Result<Vec<u8>, Slip39Error> {
+    let first = shares.first().ok_or(Slip39Error::NoShares)?;
+
+    let mut groups = BTreeMap::<u8, BTreeMap<u8, &Share>>::new();
+    for share in shares {
+        if !share.has_same_backup(first) {
+            return Err(Slip39Error::InconsistentShares);
+        }
+        let group = groups.entry(share.group_index).or_default();
+        if let Some(other) = group.values().next() {
+            if other.member_threshold != share.member_threshold {
+                return Err(Slip39Error::InconsistentShares);
+            }
+        }
+        if let Some(other) = group.insert(share.member_index, share) {
+            if other != share {
+                return Err(Slip39Error::DuplicateShare(share.member_index));
+            }
+        }
+    }
+
+    let mut group_secrets = vec![];
+    let mut incomplete = None;
+    for (group_index, members) in groups {
+        let threshold = members
+            .values()
+            .next()
+            .expect("groups are never empty")
+            .member_threshold;
+        if members.len() < threshold as usize {
+            incomplete.get_or_insert(Slip39Error::InsufficientShares {
+                group: group_index,
+                present: members.len(),
+                required: threshold,
+            });
+            continue;
+        }
+        let members = members
+            .into_iter()
+            .take(threshold as usize)
+            .map(|(index, share)| (index, share.value.clone()))
+            .collect::<Vec<_>>();
+        group_secrets.push((group_index, recover_secret(threshold, &members)?));
+    }
+
+    if group_secrets.len() < first.group_threshold as usize {
+        return Err(incomplete.unwrap_or(Slip39Error::InsufficientGroups {
+            present: group_secrets.len(),
+            required: first.group_threshold,
+        }));
+    }
+    group_secrets.truncate(first.group_threshold as usize);
+
+    let encrypted = recover_secret(first.group_threshold, &group_secrets)?;
+    feistel(
+        &encrypted,
+        passphrase,
+        first.iteration_exponent,
+        first.identifier,
+        first.extendable,
+        true,
+    )
+}
+
+fn rs1024_polymod(customization: &[u8], words: &[u16]) -> u32 {
+    const GEN: [u32; 10] = [
+        0xE0E040, 0x1C1C080, 0x3838100, 0x7070200, 0xE0E0009, 0x1C0C2412, 0x38086C24, 0x3090FC48,
+        0x21B1F890, 0x3F3F120,
+    ];
+    let mut chk = 1u32;
+    for value in customization
+        .iter()
+        .map(|byte| *byte as u32)
+        .chain(words.iter().map(|word| *word as u32))
+    {
+        let b = chk >> 20;
+        chk = ((chk & 0xFFFFF) << 10) ^ value;
+        for (i, gen) in GEN.iter().enumerate() {
+            if (b >> i) & 1 == 1 {
+                chk ^= gen;
+            }
+        }
+    }
+    chk
+}
+
+fn rs1024_checksum(customization: &[u8], words: &[u16]) -> [u16; CHECKSUM_WORDS] {
+    let mut data = words.to_vec();
+    data.extend([0u16; CHECKSUM_WORDS]);
+    let polymod = rs1024_polymod(customization, &data) ^ 1;
+    [
+        ((polymod >> 20) & 0x3FF) as u16,
+        ((polymod >> 10) & 0x3FF) as u16,
+        (polymod & 0x3FF) as u16,
+    ]
+}
+
+/// Encodes data as 10-bit words, padding them with zero bits from the left.
+fn bytes_to_words(data: &[u8], word_count: usize) -> Vec<u16> {
+    let padding = word_count * RADIX_BITS - data.len() * 8;
+    let mut words = vec![0u16; word_count];
+    for bit_no in 0..data.len() * 8 {
+        let bit = (data[bit_no / 8] >> (7 - bit_no % 8)) & 1;
+        let pos = bit_no + padding;
+        words[pos / RADIX_BITS] |= (bit as u16) << (RADIX_BITS - 1 - pos % RADIX_BITS);
+    }
+    words
+}
+
+fn words_to_bytes(words: &[u16], padding: usize) -> Result<Vec<u8>, Slip39Error> {
+    let bit = |pos: usize| (words[pos / RADIX_BITS] >> (RADIX_BITS - 1 - pos % RADIX_BITS)) & 1;
+    if (0..padding).any(|pos| bit(pos) != 0) {
+        return Err(Slip39Error::Padding);
+    }
+    let mut data = vec![0u8; (words.len() * RADIX_BITS - padding) / 8];
+    for bit_no in 0..data.len() * 8 {
+        data[bit_no / 8] |= (bit(bit_no + padding) as u8) << (7 - bit_no % 8);
+    }
+    Ok(data)
+}
+
+/// Exponent and logarithm tables for GF(256) with Rijndael polynomial
+const GF_TABLES: ([u8; 255], [u8; 256]) = {
+    let mut exp = [0u8; 255];
+    let mut log = [0u8; 256];
+    let mut poly = 1u16;
+    let mut i = 0;
+    while i < 255 {
+        exp[i] = poly as u8;
+        log[poly as usize] = i as u8;
+        // Multiply by generator 3
+        poly = (poly << 1) ^ poly;
+        if poly & 0x100 != 0 {
+            poly ^= 0x11B;
+        }
+        i += 1;
+    }
+    (exp, log)
+};
+
+/// Evaluates at `x` the polynomial passing through all provided points with
+/// Lagrange interpolation over GF(256).
+fn interpolate(shares: &[(u8, Vec<u8>)], x: u8) -> Vec<u8> {
+    let (exp, log) = &GF_TABLES;
+    if let Some((_, value)) = shares.iter().find(|(index, _)| *index == x) {
+        return value.clone();
+    }
+
+    let log_prod: usize = shares
+        .iter()
+        .map(|(index, _)| log[(index ^ x) as usize] as usize)
+        .sum();
+    let mut result = vec![0u8; shares[0].1.len()];
+    for (index, value) in shares {
+        let log_denominator: usize = shares
+            .iter()
+            .filter(|(other, _)| other != index)
+            .map(|(other, _)| log[(index ^ other) as usize] as usize)
+            .sum();
+        let log_basis =
+            (log_prod + 255 * shares.len() - log[(index ^ x) as usize] as usize - log_denominator)
+                % 255;
+        for (res, val) in result.iter_mut().zip(value) {
+            if *val != 0 {
+                *res ^= exp[(log[*val as usize] as usize + log_basis) % 255];
+            }
+        }
+    }
+    result
+}
+
+fn create_digest(random: &[u8], secret: &[u8]) -> [u8; DIGEST_LEN] {
+    let mut engine = HmacEngine::<sha256::Hash>::new(random);
+    engine.input(secret);
+    let hmac = Hmac::<sha256::Hash>::from_engine(engine);
+    let mut digest = [0u8; DIGEST_LEN];
+    digest.copy_from_slice(&hmac[..DIGEST_LEN]);
+    digest
+}
+
+#[cfg(feature = "rand")]
+fn split_secret(
+    threshold: u8,
+    count: u8,
+    secret: &[u8],
+    rng: &mut impl RngCore,
+) -> Vec<(u8, Vec<u8>)> {
+    if threshold == 1 {
+        return (0..count).map(|index| (index, secret.to_vec())).collect();
+    }
+
+    let random_count = threshold - 2;
+    let mut shares = (0..random_count)
+        .map(|index| {
+            let mut value = vec![0u8; secret.len()];
+            rng.fill_bytes(&mut value);
+            (index, value)
+        })
+        .collect::<Vec<_>>();
+
+    let mut random = vec![0u8; secret.len() - DIGEST_LEN];
+    rng.fill_bytes(&mut random);
+    let mut digest = create_digest(&random, secret).to_vec();
+    digest.extend(random);
+
+    let mut base = shares.clone();
+    base.push((DIGEST_INDEX, digest));
+    base.push((SECRET_INDEX, secret.to_vec()));
+    for index in random_count..count {
+        shares.push((index, interpolate(&base, index)));
+    }
+    shares
+}
+
+fn recover_secret(threshold: u8, shares: &[(u8, Vec<u8>)]) -> Result<Vec<u8>, Slip39Error> {
+    if threshold == 1 {
+        return Ok(shares[0].1.clone());
+    }
+    let secret = interpolate(shares, SECRET_INDEX);
+    let digest = interpolate(shares, DIGEST_INDEX);
+    if digest[..DIGEST_LEN] != create_digest(&digest[DIGEST_LEN..], &secret) {
+        return Err(Slip39Error::Digest);
+    }
+    Ok(secret)
+}
+
+fn pbkdf2_sha256(password: &[u8], salt: &[u8], iterations: u32, output: &mut [u8]) {
+    let hmac = |data: &[&[u8]]| {
+        let mut engine = HmacEngine::<sha256::Hash>::new(password);
+        for chunk in data {
+            engine.input(chunk);
+        }
+        Hmac::<sha256::Hash>::from_engine(engine).into_inner()
+    };
+    for (block_no, chunk) in output.chunks_mut(32).enumerate() {
+        let mut u = hmac(&[salt, &(block_no as u32 + 1).to_be_bytes()]);
+        let mut t = u;
+        for _ in 1..iterations {
+            u = hmac(&[&u]);
+            t.iter_mut().zip(u).for_each(|(t, u)| *t ^= u);
+        }
+        chunk.copy_from_slice(&t[..chunk.len()]);
+    }
+}
+
+/// Encrypts or decrypts master secret with the four-round Feistel network.
+fn feistel(
+    data: &[u8],
+    passphrase: &str,
+    iteration_exponent: u8,
+    identifier: u16,
+    extendable: bool,
+    decrypt: bool,
+) -> Result<Vec<u8>, Slip39Error> {
+    if !passphrase.bytes().all(|c| (32..=126).contains(&c)) {
+        return Err(Slip39Error::Passphrase);
+    }
+    if data.len() < MIN_SECRET_LEN || data.len() % 2 != 0 {
+        return Err(Slip39Error::SecretLength(data.len()));
+    }
+
+    let mut salt = vec![];
+    if !extendable {
+        salt.extend(CUSTOMIZATION);
+        salt.extend(identifier.to_be_bytes());
+    }
+    let iterations = (BASE_ITERATION_COUNT << iteration_exponent) / ROUND_COUNT as u32;
+
+    let (l, r) = data.split_at(data.len() / 2);
+    let (mut l, mut r) = (l.to_vec(), r.to_vec());
+    let rounds: Vec<u8> = if decrypt {
+        (0..ROUND_COUNT).rev().collect()
+    } else {
+        (0..ROUND_COUNT).collect()
+    };
+    for round in rounds {
+        let mut password = vec![round];
+        password.extend(passphrase.as_bytes());
+        let mut round_salt = salt.clone();
+        round_salt.extend(&r);
+        let mut f = vec![0u8; r.len()];
+        pbkdf2_sha256(&password, &round_salt, iterations, &mut f);
+        l.iter_mut().zip(f).for_each(|(l, f)| *l ^= f);
+        core::mem::swap(&mut l, &mut r);
+    }
+    r.extend(l);
+    Ok(r)
+}
+
+#[cfg(test)]
+mod test {
+    use amplify::hex::ToHex;
+
+    use super::*;
+
+    /// SLIP-39 test vectors: description, share mnemonics and the master
+    /// secret recovered with `TREZOR` passphrase (empty if the shares are
+    /// invalid)
+    const VECTORS: &[(&str, &[&str], &str)] = &[
+        (
+            "Valid mnemonic without sharing (128 bits)",
+            &[
+                "duckling enlarge academic academic agency result length solution fridge kidney \
+                 coal piece deal husband erode duke ajar critical decision keyboard",
+            ],
+            "bb54aac4b89dc868ba37d9cc21b2cece",
+        ),
+        (
+            "Mnemonic with invalid checksum (128 bits)",
+            &[
+                "duckling enlarge academic academic agency result length solution fridge kidney \
+                 coal piece deal husband erode duke ajar critical decision kidney",
+            ],
+            "",
+        ),
+        (
+            "Mnemonic with invalid padding (128 bits)",
+            &[
+                "duckling enlarge academic academic email result length solution fridge kidney \
+                 coal piece deal husband erode duke ajar music cargo fitness",
+            ],
+            "",
+        ),
+        (
+            "Basic sharing 2-of-3 (128 bits)",
+            &[
+                "shadow pistol academic always adequate wildlife fancy gross oasis cylinder \
+                 mustang wrist rescue view short owner flip making coding armed",
+                "shadow pistol academic acid actress prayer class unknown daughter sweater depict \
+                 flip twice unkind craft early superior advocate guest smoking",
+            ],
+            "b43ceb7e57a0ea8766221624d01b0864",
+        ),
+        (
+            "Basic sharing 2-of-3 (128 bits)",
+            &[
+                "shadow pistol academic always adequate wildlife fancy gross oasis cylinder \
+                 mustang wrist rescue view short owner flip making coding armed",
+            ],
+            "",
+        ),
+        (
+            "Mnemonics with different identifiers (128 bits)",
+            &[
+                "adequate smoking academic acid debut wine petition glen cluster slow rhyme slow \
+                 simple epidemic rumor junk tracks treat olympic tolerate",
+                "adequate stay academic agency agency formal party ting frequent learn upstairs \
+                 remember smear leaf damage anatomy ladle market hush corner",
+            ],
+            "",
+        ),
+        (
+            "Mnemonics with different iteration exponents (128 bits)",
+            &[
+                "peasant leaves academic acid desert exact olympic math alive axle trial tackle \
+                 drug deny decent smear dominant desert bucket remind",
+                "peasant leader academic agency cultural blessing percent network envelope medal \
+                 junk primary human pumps jacket fragment payroll ticket evoke voice",
+            ],
+            "",
+        ),
+        (
+            "Mnemonics with mismatching group thresholds (128 bits)",
+            &[
+                "liberty category beard echo animal fawn temple briefing math username various \
+                 wolf aviation fancy visual holy thunder yelp helpful payment",
+                "liberty category beard email beyond should fancy romp founder easel pink holy \
+                 hairy romp loyalty material victim owner toxic custody",
+                "liberty category academic easy being hazard crush diminish oral lizard reaction \
+                 cluster force dilemma deploy force club veteran expect photo",
+            ],
+            "",
+        ),
+        (
+            "Mnemonics with greater group threshold than group counts (128 bits)",
+            &[
+                "music husband acrobat acid artist finance center either graduate swimming object \
+                 bike medical clothes station aspect spider maiden bulb welcome",
+                "music husband acrobat agency advance hunting bike corner density careful \
+                 material civil evil tactics remind hawk discuss hobo voice rainbow",
+                "music husband beard academic black tricycle clock mayor estimate level photo \
+                 episode exclude ecology papa source amazing salt verify divorce",
+            ],
+            "",
+        ),
+        (
+            "Mnemonics with duplicate member indices (128 bits)",
+            &[
+                "device stay academic always dive coal antenna adult black exceed stadium herald \
+                 advance soldier busy dryer daughter evaluate minister laser",
+                "device stay academic always dwarf afraid robin gravity crunch adjust soul branch \
+                 walnut coastal dream costume scholar mortgage mountain pumps",
+            ],
+            "",
+        ),
+        (
+            "Mnemonics with mismatching member thresholds (128 bits)",
+            &[
+                "hour painting academic academic device formal evoke guitar random modern justice \
+                 filter withdraw trouble identify mailman insect general cover oven",
+                "hour painting academic agency artist again daisy capital beaver fiber much enjoy \
+                 suitable symbolic identify photo editor romp float echo",
+            ],
+            "",
+        ),
+        (
+            "Mnemonics giving an invalid digest (128 bits)",
+            &[
+                "guilt walnut academic acid deliver remove equip listen vampire tactics nylon \
+                 rhythm failure husband fatigue alive blind enemy teaspoon rebound",
+                "guilt walnut academic agency brave hamster hobo declare herd taste alpha slim \
+                 criminal mild arcade formal romp branch pink ambition",
+            ],
+            "",
+        ),
+        (
+            "Insufficient number of groups (128 bits, case 1)",
+            &[
+                "eraser senior beard romp adorn nuclear spill corner cradle style ancient family \
+                 general leader ambition exchange unusual garlic promise voice",
+            ],
+            "",
+        ),
+        (
+            "Insufficient number of groups (128 bits, case 2)",
+            &[
+                "eraser senior decision scared cargo theory device idea deliver modify curly \
+                 include pancake both news skin realize vitamins away join",
+                "eraser senior decision roster beard treat identify grumpy salt index fake \
+                 aviation theater cubic bike cause research dragon emphasis counter",
+            ],
+            "",
+        ),
+        (
+            "Threshold number of groups, but insufficient number of members in one group (128 \
+             bits)",
+            &[
+                "eraser senior decision shadow artist work morning estate greatest pipeline plan \
+                 ting petition forget hormone flexible general goat admit surface",
+                "eraser senior beard romp adorn nuclear spill corner cradle style ancient family \
+                 general leader ambition exchange unusual garlic promise voice",
+            ],
+            "",
+        ),
+        (
+            "Threshold number of groups and members in each group (128 bits, case 1)",
+            &[
+                "eraser senior decision roster beard treat identify grumpy salt index fake \
+                 aviation theater cubic bike cause research dragon emphasis counter",
+                "eraser senior ceramic snake clay various huge numb argue hesitate auction \
+                 category timber browser greatest hanger petition script leaf pickup",
+                "eraser senior ceramic shaft dynamic become junior wrist silver peasant force \
+                 math alto coal amazing segment yelp velvet image paces",
+                "eraser senior ceramic round column hawk trust auction smug shame alive greatest \
+                 sheriff living perfect corner chest sled fumes adequate",
+                "eraser senior decision smug corner ruin rescue cubic angel tackle skin skunk \
+                 program roster trash rumor slush angel flea amazing",
+            ],
+            "7c3397a292a5941682d7a4ae2d898d11",
+        ),
+        (
+            "Threshold number of groups and members in each group (128 bits, case 2)",
+            &[
+                "eraser senior decision roster beard treat identify grumpy salt index fake \
+                 aviation theater cubic bike cause research dragon emphasis counter",
+                "eraser senior decision smug corner ruin rescue cubic angel tackle skin skunk \
+                 program roster trash rumor slush angel flea amazing",
+                "eraser senior beard romp adorn nuclear spill corner cradle style ancient family \
+                 general leader ambition exchange unusual garlic promise voice",
+            ],
+            "7c3397a292a5941682d7a4ae2d898d11",
+        ),
+        (
+            "Threshold number of groups and members in each group (128 bits, case 3)",
+            &[
+                "eraser senior beard romp adorn nuclear spill corner cradle style ancient family \
+                 general leader ambition exchange unusual garlic promise voice",
+                "eraser senior acrobat romp bishop medical gesture pumps secret alive ultimate \
+                 quarter priest subject class dictate spew material endless market",
+            ],
+            "7c3397a292a5941682d7a4ae2d898d11",
+        ),
+        (
+            "Valid mnemonic without sharing (256 bits)",
+            &[
+                "theory painting academic academic armed sweater year military elder discuss acne \
+                 wildlife boring employer fused large satoshi bundle carbon diagnose anatomy \
+                 hamster leaves tracks paces beyond phantom capital marvel lips brave detect luck",
+            ],
+            "989baf9dcaad5b10ca33dfd8cc75e42477025dce88ae83e75a230086a0e00e92",
+        ),
+        (
+            "Mnemonic with invalid checksum (256 bits)",
+            &[
+                "theory painting academic academic armed sweater year military elder discuss acne \
+                 wildlife boring employer fused large satoshi bundle carbon diagnose anatomy \
+                 hamster leaves tracks paces beyond phantom capital marvel lips brave detect lunar",
+            ],
+            "",
+        ),
+        (
+            "Basic sharing 2-of-3 (256 bits)",
+            &[
+                "humidity disease academic always aluminum jewelry energy woman receiver strategy \
+                 amuse duckling lying evidence network walnut tactics forget hairy rebound \
+                 impulse brother survive clothes stadium mailman rival ocean reward venture \
+                 always armed unwrap",
+                "humidity disease academic agency actress jacket gross physics cylinder solution \
+                 fake mortgage benefit public busy prepare sharp friar change work slow purchase \
+                 ruler again tricycle involve viral wireless mixture anatomy desert cargo upgrade",
+            ],
+            "c938b319067687e990e05e0da0ecce1278f75ff58d9853f19dcaeed5de104aae",
+        ),
+        (
+            "Basic sharing 2-of-3 (256 bits)",
+            &[
+                "humidity disease academic always aluminum jewelry energy woman receiver strategy \
+                 amuse duckling lying evidence network walnut tactics forget hairy rebound \
+                 impulse brother survive clothes stadium mailman rival ocean reward venture \
+                 always armed unwrap",
+            ],
+            "",
+        ),
+        (
+            "Threshold number of groups and members in each group (256 bits, case 3)",
+            &[
+                "wildlife deal beard romp alcohol space mild usual clothes union nuclear testify \
+                 course research heat listen task location thank hospital slice smell failure \
+                 fawn helpful priest ambition average recover lecture process dough stadium",
+                "wildlife deal acrobat romp anxiety axis starting require metric flexible geology \
+                 game drove editor edge screw helpful have huge holy making pitch unknown carve \
+                 holiday numb glasses survive already tenant adapt goat fangs",
+            ],
+            "5385577c8cfc6c1a8aa0f7f10ecde0a3318493262591e78b8c14c6686167123b",
+        ),
+        (
+            "Mnemonic with insufficient length",
+            &[
+                "junk necklace academic academic acne isolate join hesitate lunar roster dough \
+                 calcium chemical ladybug amount mobile glasses verify cylinder",
+            ],
+            "",
+        ),
+        (
+            "Mnemonic with invalid master secret length",
+            &[
+                "fraction necklace academic academic award teammate mouse regular testify coding \
+                 building member verdict purchase blind camera duration email prepare spirit \
+                 quarter",
+            ],
+            "",
+        ),
+        (
+            "Valid extendable mnemonic without sharing (128 bits)",
+            &[
+                "testify swimming academic academic column loyalty smear include exotic bedroom \
+                 exotic wrist lobe cover grief golden smart junior estimate learn",
+            ],
+            "1679b4516e0ee5954351d288a838f45e",
+        ),
+        (
+            "Extendable basic sharing 2-of-3 (128 bits)",
+            &[
+                "enemy favorite academic acid cowboy phrase havoc level response walnut budget \
+                 painting inside trash adjust froth kitchen learn tidy punish",
+                "enemy favorite academic always academic sniff script carpet romp kind promise \
+                 scatter center unfair training emphasis evening belong fake enforce",
+            ],
+            "48b1a4b80b8c209ad42c33672bdaa428",
+        ),
+        (
+            "Valid extendable mnemonic without sharing (256 bits)",
+            &[
+                "impulse calcium academic academic alcohol sugar lyrics pajamas column facility \
+                 finance tension extend space birthday rainbow swimming purple syndrome facility \
+                 trial warn duration snapshot shadow hormone rhyme public spine counter easy hawk \
+                 album",
+            ],
+            "8340611602fe91af634a5f4608377b5235fa2d757c51d720c0c7656249a3035f",
+        ),
+        (
+            "Extendable basic sharing 2-of-3 (256 bits)",
+            &[
+                "western apart academic always artist resident briefing sugar woman oven coding \
+                 club ajar merit pecan answer prisoner artist fraction amount desktop mild false \
+                 necklace muscle photo wealthy alpha category unwrap spew losing making",
+                "western apart academic acid answer ancient auction flip image penalty oasis \
+                 beaver multiple thunder problem switch alive heat inherit superior teaspoon \
+                 explain blanket pencil numb lend punish endless aunt garlic humidity kidney \
+                 observe",
+            ],
+            "8dc652d6d6cd370d8c963141f6d79ba440300f25c467302c1d966bff8f62300d",
+        ),
+    ];
+
+    #[test]
+    fn slip39_vectors() {
+        for (description, mnemonics, secret) in VECTORS {
+            let result = mnemonics
+                .iter()
+                .map(|mnemonic| Share::from_str(mnemonic))
+                .collect::<Result<Vec<_>, _>>()
+                .and_then(|shares| combine(&shares, "TREZOR"));
+            if secret.is_empty() {
+                assert!(result.is_err(), "{}", description);
+            } else {
+                assert_eq!(
+                    result.map(|secret| secret.to_hex()).as_deref(),
+                    Ok(*secret),
+                    "{}",
+                    description
+                );
+            }
+        }
+
+        // Extendable backup flag changes the checksum customization string and
+        // excludes the identifier from the master secret encryption
+        let share = |no: usize| Share::from_str(VECTORS[no].1[0]).unwrap();
+        assert!(!share(0).extendable);
+        assert!(!share(18).extendable);
+        assert!(share(25).extendable);
+        assert!(share(27).extendable);
+    }
+
+    #[test]
+    fn slip39_errors() {
+        let error = |no: usize| {
+            let (_, mnemonics, _) = VECTORS[no];
+            mnemonics
+                .iter()
+                .map(|mnemonic| Share::from_str(mnemonic))
+                .collect::<Result<Vec<_>, _>>()
+                .and_then(|shares| combine(&shares, "TREZOR"))
+                .unwrap_err()
+        };
+        assert_eq!(error(1), Slip39Error::Checksum);
+        assert_eq!(error(2), Slip39Error::Padding);
+        assert_eq!(error(4), Slip39Error::InsufficientShares {
+            group: 0,
+            present: 1,
+            required: 2
+        });
+        assert_eq!(error(5), Slip39Error::InconsistentShares);
+        assert_eq!(error(6), Slip39Error::InconsistentShares);
+        assert_eq!(error(7), Slip39Error::InconsistentShares);
+        assert_eq!(error(8), Slip39Error::GroupThreshold {
+            threshold: 2,
+            count: 1
+        });
+        assert_eq!(error(9), Slip39Error::DuplicateShare(2));
+        assert_eq!(error(10), Slip39Error::InconsistentShares);
+        assert_eq!(error(11), Slip39Error::Digest);
+        assert_eq!(error(12), Slip39Error::InsufficientGroups {
+            present: 1,
+            required: 2
+        });
+        assert_eq!(error(13), Slip39Error::InsufficientGroups {
+            present: 1,
+            required: 2
+        });
+        assert_eq!(error(14), Slip39Error::InsufficientShares {
+            group: 3,
+            present: 1,
+            required: 2
+        });
+        assert_eq!(error(19), Slip39Error::Checksum);
+        assert_eq!(error(21), Slip39Error::InsufficientShares {
+            group: 0,
+            present: 1,
+            required: 2
+        });
+        assert_eq!(error(23), Slip39Error::MnemonicLength(19));
+        assert_eq!(error(24), Slip39Error::MnemonicLength(21));
+    }
+
+    #[cfg(feature = "rand")]
+    #[test]
+    fn split_combine() {
+        let secret = b"0123456789abcdef0123456789abcdef";
+        let groups = [
+            GroupSpec::from_str("1-of-1").unwrap(),
+            GroupSpec::from_str("2-of-3").unwrap(),
+            GroupSpec::from_str("3-of-5").unwrap(),
+        ];
+        let shares = split(
+            secret,
+            "pass",
+            2,
+            &groups,
+            0,
+            &mut bitcoin::secp256k1::rand::thread_rng(),
+        )
+        .unwrap();
+        assert_eq!(shares.iter().map(Vec::len).collect::<Vec<_>>(), vec![
+            1, 3, 5
+        ]);
+
+        let parsed = shares
+            .iter()
+            .flatten()
+            .map(|share| Share::from_str(&share.to_string()).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(parsed, shares.concat());
+
+        let subset = [
+            &shares[1][0],
+            &shares[1][2],
+            &shares[2][4],
+            &shares[2][1],
+            &shares[2][3],
+        ]
+        .map(Clone::clone);
+        assert_eq!(combine(&subset, "pass").unwrap(), secret);
+        assert_ne!(combine(&subset, "other").unwrap(), secret);
+        assert_eq!(
+            combine(&subset[..4], "pass"),
+            Err(Slip39Error::InsufficientShares {
+                group: 2,
+                present: 2,
+                required: 3
+            })
+        );
+    }
+}
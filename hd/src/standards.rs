@@ -69,10 +69,22 @@ pub enum DerivationBlockchain {
     #[display("bitcoin")]
     Bitcoin,
 
-    /// Any testnet blockchain
+    /// Bitcoin testnet3 or any testnet blockchain not listed below
     #[display("testnet")]
     Testnet,
 
+    /// Bitcoin testnet4
+    #[display("testnet4")]
+    Testnet4,
+
+    /// Bitcoin signet, including custom signets
+    #[display("signet")]
+    Signet,
+
+    /// Bitcoin regtest
+    #[display("regtest")]
+    Regtest,
+
     /// Custom blockchain (non-testnet)
     #[display(inner)]
     #[from]
@@ -85,21 +97,31 @@ impl DerivationBlockchain {
     #[inline]
     pub fn child_number(self) -> ChildNumber {
         match self {
-            Self::Bitcoin => ChildNumber::Hardened { index: 0 },
-            Self::Testnet => ChildNumber::Hardened { index: 1 },
             Self::Custom(index) => index.into(),
+            other => other.coin_type().into(),
         }
     }
 
     /// Tests whether given derivation blockchain is a testnet.
-    pub fn is_testnet(self) -> bool { self == DerivationBlockchain::Testnet }
+    pub fn is_testnet(self) -> bool {
+        matches!(
+            self,
+            DerivationBlockchain::Testnet
+                | DerivationBlockchain::Testnet4
+                | DerivationBlockchain::Signet
+                | DerivationBlockchain::Regtest
+        )
+    }
 
     /// Returns derivation path segment child number corresponding to the given
     /// blockchain from LNPBP-43 standard
     pub fn coin_type(self) -> HardenedIndex {
         match self {
             DerivationBlockchain::Bitcoin => HardenedIndex::zero(),
-            DerivationBlockchain::Testnet => HardenedIndex::one(),
+            DerivationBlockchain::Testnet
+            | DerivationBlockchain::Testnet4
+            | DerivationBlockchain::Signet
+            | DerivationBlockchain::Regtest => HardenedIndex::one(),
             DerivationBlockchain::Custom(index) => index,
         }
     }
@@ -113,6 +135,9 @@ impl FromStr for DerivationBlockchain {
         match (s.to_lowercase().as_str(), parsed) {
             ("bitcoin", _) => Ok(Self::Bitcoin),
             ("testnet", _) => Ok(Self::Testnet),
+            ("testnet4", _) => Ok(Self::Testnet4),
+            ("signet", _) => Ok(Self::Signet),
+            ("regtest", _) => Ok(Self::Regtest),
             (_, Ok(index @ ChildNumber::Hardened { .. })) => {
                 Ok(Self::Custom(index.try_into().expect(
                     "ChildNumber::Hardened failed to convert into HardenedIndex type",
@@ -129,7 +154,6 @@ impl FromStr for DerivationBlockchain {
 /// Specific derivation scheme after BIP-43 standards
 #[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display)]
 #[derive(StrictEncode, StrictDecode)]
-#[cfg_attr(feature = "clap", derive(ArgEnum))]
 #[cfg_attr(
     feature = "serde",
     derive(Serialize, Deserialize),
@@ -373,9 +397,7 @@ pub trait DerivationStandard: Eq + Clone {
     /// Check whether provided descriptor type can be used with this derivation
     /// scheme.
     fn check_descriptor_type(&self, descriptor_type: DescriptorType) -> bool {
-        self.descriptor_types()
-            .iter()
-            .any(|d| *d == descriptor_type)
+        self.descriptor_types().contains(&descriptor_type)
     }
 
     /// Returns [`slip132::KeyApplication`] corresponding to the provided
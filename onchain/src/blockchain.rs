@@ -11,12 +11,15 @@
 
 //! Blockchain-specific data types useful for wallets
 
-use std::fmt::Debug;
-use std::hash::Hash;
+use std::cmp::Ordering;
+use std::fmt::{self, Debug, Display, Formatter};
+use std::hash::{Hash, Hasher};
+use std::io::{self, Read};
 use std::str::FromStr;
 
 use bitcoin::blockdata::constants;
-use bitcoin::{BlockHash, Network, OutPoint};
+use bitcoin::{BlockHash, Network, OutPoint, Script, Txid};
+use bitcoin_hd::{DerivationSubpath, UnhardenedIndex};
 use chrono::{DateTime, NaiveDateTime};
 #[cfg(feature = "electrum")]
 use electrum_client::ListUnspentRes;
@@ -36,6 +39,11 @@ use strict_encoding::{StrictDecode, StrictEncode};
 pub struct ParseError;
 
 /// Block mining information
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_crate")
+)]
 #[derive(Getters, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display)]
 #[derive(StrictEncode, StrictDecode)]
 #[display("{block_height}#{block_hash}@{timestamp}")]
@@ -45,6 +53,18 @@ pub struct TimeHeight {
     block_hash: BlockHash,
 }
 
+impl TimeHeight {
+    /// Constructs block mining information
+    #[inline]
+    pub fn new(timestamp: NaiveDateTime, block_height: u32, block_hash: BlockHash) -> TimeHeight {
+        TimeHeight {
+            timestamp,
+            block_height,
+            block_hash,
+        }
+    }
+}
+
 impl Default for TimeHeight {
     fn default() -> Self {
         TimeHeight {
@@ -104,14 +124,25 @@ pub enum MiningStatus {
     Blockchain(u64),
 }
 
-/// Full UTXO information
+/// Number of confirmations after which coinbase transaction outputs can be
+/// spent
+pub const COINBASE_MATURITY: u64 = 100;
+
+/// Tag prefixing strict encoding of UTXO with the extended information; it
+/// never starts the legacy encoding, which begins with [`MiningStatus`] tag
+const UTXO_EXTENDED_TAG: u8 = 0xFF;
+
+/// Full UTXO information.
+///
+/// UTXOs are identified by their outpoints: equality, ordering and hashing
+/// ignore the rest of the information, which may differ depending on the
+/// resolver.
 #[cfg_attr(
     feature = "serde",
     derive(Serialize, Deserialize),
     serde(crate = "serde_crate")
 )]
-#[derive(Getters, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display)]
-#[derive(StrictEncode, StrictDecode)]
+#[derive(Getters, Clone, Debug, Display)]
 #[display("{amount}@{outpoint}")]
 pub struct Utxo {
     /// Status of the transaction containing this UTXO
@@ -124,6 +155,163 @@ pub struct Utxo {
         serde(with = "bitcoin::util::amount::serde::as_btc")
     )]
     amount: bitcoin::Amount,
+    /// Output scriptPubkey, if known
+    #[cfg_attr(feature = "serde", serde(default))]
+    script_pubkey: Option<Script>,
+    /// Terminal derivation of the output scriptPubkey within a wallet
+    /// descriptor, if known
+    #[cfg_attr(feature = "serde", serde(default))]
+    terminal: Option<DerivationSubpath<UnhardenedIndex>>,
+    /// Information about the block containing the transaction, if it is
+    /// mined and the information is provided by the resolver
+    #[cfg_attr(feature = "serde", serde(default))]
+    time_height: Option<TimeHeight>,
+    /// Whether the UTXO is an output of a coinbase transaction
+    #[cfg_attr(feature = "serde", serde(default))]
+    coinbase: bool,
+}
+
+impl PartialEq for Utxo {
+    fn eq(&self, other: &Self) -> bool { self.outpoint == other.outpoint }
+}
+
+impl Eq for Utxo {}
+
+impl PartialOrd for Utxo {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> { Some(self.cmp(other)) }
+}
+
+impl Ord for Utxo {
+    fn cmp(&self, other: &Self) -> Ordering { self.outpoint.cmp(&other.outpoint) }
+}
+
+impl Hash for Utxo {
+    fn hash<H: Hasher>(&self, state: &mut H) { self.outpoint.hash(state) }
+}
+
+// UTXOs without the extended information keep the legacy encoding consisting
+// of the mining status, outpoint and amount, while others are prefixed with
+// `UTXO_EXTENDED_TAG` and followed by the extended fields
+impl StrictEncode for Utxo {
+    fn strict_encode<E: io::Write>(&self, mut e: E) -> Result<usize, strict_encoding::Error> {
+        let extended = self.script_pubkey.is_some()
+            || self.terminal.is_some()
+            || self.time_height.is_some()
+            || self.coinbase;
+        let mut len = 0;
+        if extended {
+            len += UTXO_EXTENDED_TAG.strict_encode(&mut e)?;
+        }
+        len += self.mined.strict_encode(&mut e)?;
+        len += self.outpoint.strict_encode(&mut e)?;
+        len += self.amount.strict_encode(&mut e)?;
+        if extended {
+            len += self.script_pubkey.strict_encode(&mut e)?;
+            len += self.terminal.strict_encode(&mut e)?;
+            len += self.time_height.strict_encode(&mut e)?;
+            len += self.coinbase.strict_encode(&mut e)?;
+        }
+        Ok(len)
+    }
+}
+
+impl StrictDecode for Utxo {
+    fn strict_decode<D: io::Read>(mut d: D) -> Result<Self, strict_encoding::Error> {
+        let tag = u8::strict_decode(&mut d)?;
+        let extended = tag == UTXO_EXTENDED_TAG;
+        let mined = if extended {
+            MiningStatus::strict_decode(&mut d)?
+        } else {
+            // The tag belongs to the mining status of the legacy encoding
+            MiningStatus::strict_decode([tag].as_slice().chain(&mut d))?
+        };
+        let mut utxo = Utxo::new(
+            mined,
+            OutPoint::strict_decode(&mut d)?,
+            bitcoin::Amount::strict_decode(&mut d)?,
+        );
+        if extended {
+            utxo.script_pubkey = StrictDecode::strict_decode(&mut d)?;
+            utxo.terminal = StrictDecode::strict_decode(&mut d)?;
+            utxo.time_height = StrictDecode::strict_decode(&mut d)?;
+            utxo.coinbase = StrictDecode::strict_decode(&mut d)?;
+        }
+        Ok(utxo)
+    }
+}
+
+impl Utxo {
+    /// Constructs UTXO information
+    #[inline]
+    pub fn new(mined: MiningStatus, outpoint: OutPoint, amount: bitcoin::Amount) -> Utxo {
+        Utxo {
+            mined,
+            outpoint,
+            amount,
+            script_pubkey: None,
+            terminal: None,
+            time_height: None,
+            coinbase: false,
+        }
+    }
+
+    /// Adds information about the output scriptPubkey
+    #[inline]
+    pub fn with_script_pubkey(mut self, script_pubkey: Script) -> Utxo {
+        self.script_pubkey = Some(script_pubkey);
+        self
+    }
+
+    /// Adds terminal derivation of the output scriptPubkey
+    #[inline]
+    pub fn with_terminal(mut self, terminal: impl AsRef<[UnhardenedIndex]>) -> Utxo {
+        self.terminal = Some(DerivationSubpath::from(terminal.as_ref()));
+        self
+    }
+
+    /// Adds information about the block containing the transaction
+    #[inline]
+    pub fn with_time_height(mut self, time_height: TimeHeight) -> Utxo {
+        self.time_height = Some(time_height);
+        self
+    }
+
+    /// Marks UTXO as an output of a coinbase transaction
+    #[inline]
+    pub fn with_coinbase(mut self, coinbase: bool) -> Utxo {
+        self.coinbase = coinbase;
+        self
+    }
+
+    /// Returns number of confirmations for the given height of the most-work
+    /// chain tip; zero for unconfirmed UTXOs
+    pub fn confirmations(&self, tip_height: u64) -> u64 {
+        match self.mined {
+            MiningStatus::Blockchain(height) if tip_height >= height => tip_height - height + 1,
+            _ => 0,
+        }
+    }
+
+    /// Detects whether UTXO can be spent in the block following the given
+    /// chain tip, i.e. that it is either not a coinbase output or has at least
+    /// [`COINBASE_MATURITY`] confirmations
+    pub fn is_mature(&self, tip_height: u64) -> bool {
+        !self.coinbase || self.confirmations(tip_height) >= COINBASE_MATURITY
+    }
+
+    /// Constructs input descriptor spending the UTXO with the default sequence
+    /// number and sighash type. Returns `None` if the terminal derivation of
+    /// the UTXO is not known.
+    #[cfg(feature = "miniscript_descriptors")]
+    pub fn to_input_descriptor(&self) -> Option<descriptors::InputDescriptor> {
+        Some(descriptors::InputDescriptor {
+            outpoint: self.outpoint,
+            terminal: self.terminal.clone()?,
+            seq_no: none!(),
+            tweak: None,
+            sighash_type: bitcoin::EcdsaSighashType::All,
+        })
+    }
 }
 
 impl FromStr for Utxo {
@@ -132,11 +320,11 @@ impl FromStr for Utxo {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let mut split = s.split('@');
         match (split.next(), split.next(), split.next()) {
-            (Some(amount), Some(outpoint), None) => Ok(Utxo {
-                mined: MiningStatus::Undefined,
-                amount: amount.parse()?,
-                outpoint: outpoint.parse()?,
-            }),
+            (Some(amount), Some(outpoint), None) => Ok(Utxo::new(
+                MiningStatus::Undefined,
+                outpoint.parse()?,
+                amount.parse()?,
+            )),
             _ => Err(ParseError),
         }
     }
@@ -145,14 +333,248 @@ impl FromStr for Utxo {
 #[cfg(feature = "electrum")]
 impl From<ListUnspentRes> for Utxo {
     fn from(res: ListUnspentRes) -> Self {
-        Utxo {
-            mined: if res.height == 0 {
+        Utxo::new(
+            if res.height == 0 {
                 MiningStatus::Mempool
             } else {
                 MiningStatus::Blockchain(res.height as u64)
             },
-            outpoint: OutPoint::new(res.tx_hash, res.tx_pos as u32),
-            amount: bitcoin::Amount::from_sat(res.value),
+            OutPoint::new(res.tx_hash, res.tx_pos as u32),
+            bitcoin::Amount::from_sat(res.value),
+        )
+    }
+}
+
+/// Transaction affecting some script, together with its mining information
+#[derive(Getters, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display)]
+#[derive(StrictEncode, StrictDecode)]
+#[display("{txid}@{mined}")]
+pub struct HistoryEntry {
+    /// Transaction id
+    txid: Txid,
+    /// Status of the transaction
+    mined: MiningStatus,
+    /// Information about the block containing the transaction, if it is
+    /// mined and the information is provided by the resolver
+    time_height: Option<TimeHeight>,
+}
+
+impl HistoryEntry {
+    /// Constructs transaction history entry
+    #[inline]
+    pub fn new(txid: Txid, mined: MiningStatus, time_height: Option<TimeHeight>) -> HistoryEntry {
+        HistoryEntry {
+            txid,
+            mined,
+            time_height,
         }
     }
 }
+
+/// Transaction fee rate. Internally kept as an integer number of satoshis per
+/// 1000 virtual bytes (sat/kvB), such that fee computations are exact; it is
+/// parsed and displayed in satoshis per virtual byte (sat/vB).
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_crate", transparent)
+)]
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Default)]
+pub struct FeeRate(u64);
+
+impl FeeRate {
+    /// Constructs fee rate from the number of satoshis per 1000 virtual bytes
+    #[inline]
+    pub const fn from_sat_per_kvb(sat_per_kvb: u64) -> FeeRate { FeeRate(sat_per_kvb) }
+
+    /// Constructs fee rate from the whole number of satoshis per virtual byte
+    #[inline]
+    pub const fn from_sat_per_vb(sat_per_vb: u64) -> FeeRate { FeeRate(sat_per_vb * 1000) }
+
+    /// Constructs fee rate from the fee in bitcoins per 1000 virtual bytes
+    /// reported by bitcoin nodes and electrum servers, rounding to the whole
+    /// number of satoshis per kvB. Returns `None` for negative or non-finite
+    /// values.
+    pub fn from_btc_per_kvb(btc_per_kvb: f64) -> Option<FeeRate> {
+        FeeRate::from_float(btc_per_kvb * 100_000_000.0)
+    }
+
+    /// Constructs fee rate from the fractional number of satoshis per virtual
+    /// byte, rounding it to the whole number of satoshis per kvB. Returns
+    /// `None` for negative or non-finite values.
+    pub fn from_sat_per_vb_f64(sat_per_vb: f64) -> Option<FeeRate> {
+        FeeRate::from_float(sat_per_vb * 1000.0)
+    }
+
+    fn from_float(sat_per_kvb: f64) -> Option<FeeRate> {
+        if !sat_per_kvb.is_finite() || sat_per_kvb < 0.0 {
+            return None;
+        }
+        Some(FeeRate(sat_per_kvb.round() as u64))
+    }
+
+    /// Computes fee rate paid by a transaction with the given fee and virtual
+    /// size, rounding down to the whole number of satoshis per kvB
+    #[inline]
+    pub fn with_fee(fee: u64, vsize: u64) -> FeeRate {
+        FeeRate((fee as u128 * 1000 / vsize.max(1) as u128) as u64)
+    }
+
+    /// Returns fee rate value in satoshis per 1000 virtual bytes
+    #[inline]
+    pub const fn sat_per_kvb(self) -> u64 { self.0 }
+
+    /// Returns fee rate value in satoshis per virtual byte
+    #[inline]
+    pub fn sat_per_vb(self) -> f64 { self.0 as f64 / 1000.0 }
+
+    /// Computes fee for a transaction data of the given weight, rounding up to
+    /// the whole number of satoshis
+    #[inline]
+    pub fn fee_for_weight(self, weight: u64) -> u64 {
+        div_ceil(self.0 as u128 * weight as u128, 4000)
+    }
+
+    /// Computes fee for a transaction data of the given virtual size, rounding
+    /// up to the whole number of satoshis
+    #[inline]
+    pub fn fee_for_vsize(self, vsize: u64) -> u64 { div_ceil(self.0 as u128 * vsize as u128, 1000) }
+}
+
+fn div_ceil(value: u128, divisor: u128) -> u64 { ((value + divisor - 1) / divisor) as u64 }
+
+impl Display for FeeRate {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let (sat, fract) = (self.0 / 1000, self.0 % 1000);
+        if fract == 0 {
+            write!(f, "{} sat/vB", sat)
+        } else {
+            let fract = format!("{:03}", fract);
+            write!(f, "{}.{} sat/vB", sat, fract.trim_end_matches('0'))
+        }
+    }
+}
+
+impl FromStr for FeeRate {
+    type Err = ParseError;
+
+    /// Parses fee rate in satoshis per virtual byte with up to three decimal
+    /// digits, optionally followed by `sat/vB` suffix.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim_end_matches("sat/vB").trim();
+        let (sat, fract) = s.split_once('.').unwrap_or((s, ""));
+        if sat.is_empty()
+            || fract.len() > 3
+            || !sat.bytes().chain(fract.bytes()).all(|c| c.is_ascii_digit())
+        {
+            return Err(ParseError);
+        }
+        let sat = u64::from_str(sat)?;
+        let fract = format!("{:0<3}", fract);
+        let fract = u64::from_str(&fract)?;
+        sat.checked_mul(1000)
+            .and_then(|rate| rate.checked_add(fract))
+            .map(FeeRate)
+            .ok_or(ParseError)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use bitcoin::hashes::Hash;
+    use strict_encoding::{strict_deserialize, strict_serialize};
+
+    use super::*;
+
+    #[derive(StrictEncode)]
+    struct LegacyUtxo {
+        mined: MiningStatus,
+        outpoint: OutPoint,
+        amount: bitcoin::Amount,
+    }
+
+    #[test]
+    fn utxo_encoding() {
+        let outpoint = OutPoint::new(Txid::from_inner([7u8; 32]), 1);
+        let amount = bitcoin::Amount::from_sat(100_000);
+        for mined in [
+            MiningStatus::Undefined,
+            MiningStatus::Mempool,
+            MiningStatus::Blockchain(700_000),
+        ] {
+            let legacy = strict_serialize(&LegacyUtxo {
+                mined,
+                outpoint,
+                amount,
+            })
+            .unwrap();
+            let utxo = Utxo::new(mined, outpoint, amount);
+            assert_eq!(strict_serialize(&utxo).unwrap(), legacy);
+            let decoded: Utxo = strict_deserialize(&legacy).unwrap();
+            assert_eq!(*decoded.mined(), mined);
+            assert_eq!(*decoded.amount(), amount);
+            assert_eq!(decoded.script_pubkey(), &None);
+        }
+
+        let utxo = Utxo::new(MiningStatus::Blockchain(1), outpoint, amount)
+            .with_script_pubkey(Script::new_op_return(&[1, 2, 3]))
+            .with_terminal([UnhardenedIndex::from(1u8), UnhardenedIndex::from(5u8)])
+            .with_time_height(TimeHeight::new(
+                DateTime::from_timestamp(1231006505, 0).unwrap().naive_utc(),
+                1,
+                BlockHash::all_zeros(),
+            ))
+            .with_coinbase(true);
+        let data = strict_serialize(&utxo).unwrap();
+        assert_eq!(data[0], UTXO_EXTENDED_TAG);
+        let decoded: Utxo = strict_deserialize(&data).unwrap();
+        assert_eq!(decoded.script_pubkey(), utxo.script_pubkey());
+        assert_eq!(decoded.terminal(), utxo.terminal());
+        assert_eq!(decoded.time_height(), utxo.time_height());
+        assert!(*decoded.coinbase());
+
+        // Identity is defined by the outpoint only
+        let other = Utxo::new(MiningStatus::Mempool, outpoint, amount);
+        assert_eq!(utxo, other);
+        assert_eq!([utxo, other].into_iter().collect::<HashSet<_>>().len(), 1);
+    }
+
+    #[test]
+    fn fee_rate_str() {
+        for (s, rate) in [
+            ("1", 1000),
+            ("20.5", 20_500),
+            ("0.001 sat/vB", 1),
+            ("12.25sat/vB", 12_250),
+        ] {
+            assert_eq!(FeeRate::from_str(s), Ok(FeeRate::from_sat_per_kvb(rate)));
+        }
+        for s in ["", "-1", ".5", "1.0001", "1e3", "NaN"] {
+            assert_eq!(FeeRate::from_str(s), Err(ParseError), "{}", s);
+        }
+        assert_eq!(FeeRate::from_sat_per_vb(3).to_string(), "3 sat/vB");
+        assert_eq!(FeeRate::from_sat_per_kvb(20_500).to_string(), "20.5 sat/vB");
+        assert_eq!(FeeRate::from_sat_per_kvb(1_005).to_string(), "1.005 sat/vB");
+    }
+
+    #[test]
+    fn fee_rate_rounding() {
+        let rate = FeeRate::from_sat_per_kvb(1_001);
+        assert_eq!(rate.fee_for_vsize(1000), 1001);
+        assert_eq!(rate.fee_for_vsize(1001), 1003);
+        assert_eq!(rate.fee_for_weight(4), 2);
+        assert_eq!(FeeRate::default().fee_for_vsize(1000), 0);
+        assert_eq!(FeeRate::with_fee(1001, 1000), rate);
+        assert_eq!(
+            FeeRate::with_fee(1000, 3),
+            FeeRate::from_sat_per_kvb(333_333)
+        );
+        assert_eq!(
+            FeeRate::from_btc_per_kvb(0.00001234),
+            Some(FeeRate::from_sat_per_kvb(1234))
+        );
+        assert_eq!(FeeRate::from_sat_per_vb_f64(-1.0), None);
+    }
+}
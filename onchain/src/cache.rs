@@ -0,0 +1,543 @@
+// Wallet-level libraries for bitcoin protocol by LNP/BP Association
+//
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// This software is distributed without any warranty.
+//
+// You should have received a copy of the Apache-2.0 License
+// along with this software.
+// If not, see <https://opensource.org/licenses/Apache-2.0>.
+
+//! Caching layer over resolver backends.
+//!
+//! Transactions are immutable for a given txid, so once retrieved they are
+//! kept in memory for the lifetime of the cache. Transactions reported by the
+//! backend as confirmed — either by UTXO and history queries made through the
+//! cache or by the mining status check of the retrieved transactions — are
+//! also saved to the on-disk store, if one is used, and are available to
+//! caches created later. Answers to UTXO and history queries are valid until
+//! the block height changes, which is either set by the caller or, with
+//! [`CachingResolver::with_height_check`], checked with the backend before
+//! each query.
+
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::{fs, io};
+
+use bitcoin::consensus::{deserialize, serialize};
+use bitcoin::{Script, Transaction, Txid};
+
+use crate::blockchain::{HistoryEntry, MiningStatus, Utxo};
+use crate::{
+    ResolveChainState, ResolveHistory, ResolveTx, ResolveTxFee, ResolveUtxo, TxResolverError,
+    UtxoResolverError,
+};
+
+/// Statistics of the cache use
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug, Default, Display)]
+#[display(
+    "transactions: {tx_hits} hits, {tx_misses} misses; UTXO: {utxo_hits} hits, {utxo_misses} \
+     misses; history: {history_hits} hits, {history_misses} misses"
+)]
+pub struct CacheStats {
+    /// Number of transactions served from the cache
+    pub tx_hits: u64,
+    /// Number of transactions requested from the backend
+    pub tx_misses: u64,
+    /// Number of script UTXO sets served from the cache
+    pub utxo_hits: u64,
+    /// Number of script UTXO sets requested from the backend
+    pub utxo_misses: u64,
+    /// Number of script histories served from the cache
+    pub history_hits: u64,
+    /// Number of script histories requested from the backend
+    pub history_misses: u64,
+    /// Number of transactions saved to the on-disk store
+    pub stored: u64,
+    /// Number of failed attempts to save transaction to the on-disk store
+    pub store_errors: u64,
+}
+
+#[derive(Default)]
+struct Cache {
+    txs: HashMap<Txid, Transaction>,
+    fees: HashMap<Txid, u64>,
+    confirmed: HashSet<Txid>,
+    utxo: HashMap<Script, HashSet<Utxo>>,
+    history: HashMap<Script, HashSet<HistoryEntry>>,
+    height: Option<u32>,
+    stats: CacheStats,
+}
+
+/// Function detecting mining status of a transaction with the inner resolver
+type TxStatusFn<R> = fn(&R, Txid) -> Result<MiningStatus, TxResolverError>;
+
+/// Resolver wrapper caching answers of the inner resolver.
+///
+/// Implements the same resolver traits as the wrapped backend. UTXO and
+/// history queries, as well as the on-disk store, require the backend to
+/// provide the blockchain state with [`ResolveChainState`].
+pub struct CachingResolver<R> {
+    inner: R,
+    store: Option<PathBuf>,
+    tx_status: Option<TxStatusFn<R>>,
+    check_height: bool,
+    cache: RefCell<Cache>,
+}
+
+impl<R> CachingResolver<R> {
+    /// Wraps resolver with the in-memory cache
+    pub fn new(inner: R) -> CachingResolver<R> {
+        CachingResolver {
+            inner,
+            store: None,
+            tx_status: None,
+            check_height: false,
+            cache: default!(),
+        }
+    }
+
+    /// Makes the cache check current block height with the backend before
+    /// each UTXO and history query, invalidating cached answers once a new
+    /// block is found
+    #[inline]
+    pub fn with_height_check(mut self) -> CachingResolver<R> {
+        self.check_height = true;
+        self
+    }
+
+    /// Returns reference to the wrapped resolver
+    #[inline]
+    pub fn inner(&self) -> &R { &self.inner }
+
+    /// Releases the wrapped resolver
+    #[inline]
+    pub fn into_inner(self) -> R { self.inner }
+
+    /// Returns statistics of the cache use
+    #[inline]
+    pub fn stats(&self) -> CacheStats { self.cache.borrow().stats }
+
+    /// Returns block height for which UTXO and history answers are cached
+    #[inline]
+    pub fn height(&self) -> Option<u32> { self.cache.borrow().height }
+
+    /// Sets current block height; if it differs from the previous one, cached
+    /// UTXO and history answers are invalidated
+    #[inline]
+    pub fn set_height(&mut self, height: u32) { self.update_height(height) }
+
+    /// Invalidates cached UTXO and history answers, for instance after a
+    /// transaction is broadcasted
+    pub fn invalidate(&mut self) {
+        let cache = self.cache.get_mut();
+        cache.utxo.clear();
+        cache.history.clear();
+    }
+
+    fn update_height(&self, height: u32) {
+        let mut cache = self.cache.borrow_mut();
+        if cache.height != Some(height) {
+            cache.utxo.clear();
+            cache.history.clear();
+            cache.height = Some(height);
+        }
+    }
+
+    fn tx_path(&self, txid: Txid) -> Option<PathBuf> {
+        self.store
+            .as_ref()
+            .map(|dir| dir.join(format!("{}.tx", txid)))
+    }
+
+    fn cached_tx(&self, txid: Txid) -> Option<Transaction> {
+        if let Some(tx) = self.cache.borrow().txs.get(&txid) {
+            return Some(tx.clone());
+        }
+        let tx = fs::read(self.tx_path(txid)?)
+            .ok()
+            .and_then(|data| deserialize::<Transaction>(&data).ok())
+            .filter(|tx| tx.txid() == txid)?;
+        let mut cache = self.cache.borrow_mut();
+        cache.confirmed.insert(txid);
+        cache.txs.insert(txid, tx.clone());
+        Some(tx)
+    }
+
+    fn insert_tx(&self, tx: Transaction) {
+        let txid = tx.txid();
+        if self.cache.borrow().confirmed.contains(&txid) {
+            self.store_tx(&tx);
+        }
+        self.cache.borrow_mut().txs.insert(txid, tx);
+    }
+
+    fn mark_confirmed(&self, txid: Txid) {
+        let tx = {
+            let mut cache = self.cache.borrow_mut();
+            if !cache.confirmed.insert(txid) {
+                return;
+            }
+            cache.txs.get(&txid).cloned()
+        };
+        if let Some(tx) = tx {
+            self.store_tx(&tx);
+        }
+    }
+
+    fn store_tx(&self, tx: &Transaction) {
+        if let Some(path) = self.tx_path(tx.txid()) {
+            let mut cache = self.cache.borrow_mut();
+            match fs::write(path, serialize(tx)) {
+                Ok(_) => cache.stats.stored += 1,
+                Err(_) => cache.stats.store_errors += 1,
+            }
+        }
+    }
+
+    fn check_confirmed(&self, txid: Txid) {
+        // Mining status is needed only to decide on persisting the
+        // transaction, so failures to get it are not reported
+        let Some(tx_status) = self.tx_status else {
+            return;
+        };
+        if self.cache.borrow().confirmed.contains(&txid) {
+            return;
+        }
+        if let Ok(MiningStatus::Blockchain(_)) = tx_status(&self.inner, txid) {
+            self.mark_confirmed(txid);
+        }
+    }
+}
+
+impl<R> CachingResolver<R>
+where
+    R: ResolveChainState,
+{
+    /// Wraps resolver with the in-memory cache backed by the on-disk store
+    /// of confirmed transactions in the `dir` directory, which is created if
+    /// it does not exist
+    pub fn with_store(inner: R, dir: impl AsRef<Path>) -> Result<CachingResolver<R>, io::Error> {
+        fs::create_dir_all(&dir)?;
+        let mut resolver = CachingResolver::new(inner);
+        resolver.store = Some(dir.as_ref().to_owned());
+        resolver.tx_status = Some(R::resolve_tx_status);
+        Ok(resolver)
+    }
+
+    fn check_height(&self) -> Result<(), UtxoResolverError> {
+        if self.check_height {
+            self.update_height(self.inner.resolve_height()?);
+        }
+        Ok(())
+    }
+}
+
+impl<R> ResolveTx for CachingResolver<R>
+where
+    R: ResolveTx,
+{
+    fn resolve_tx(&self, txid: Txid) -> Result<Transaction, TxResolverError> {
+        if let Some(tx) = self.cached_tx(txid) {
+            self.cache.borrow_mut().stats.tx_hits += 1;
+            return Ok(tx);
+        }
+        self.cache.borrow_mut().stats.tx_misses += 1;
+        let tx = self.inner.resolve_tx(txid)?;
+        self.insert_tx(tx.clone());
+        self.check_confirmed(txid);
+        Ok(tx)
+    }
+}
+
+impl<R> ResolveTxFee for CachingResolver<R>
+where
+    R: ResolveTx + ResolveTxFee,
+{
+    fn resolve_tx_fee(&self, txid: Txid) -> Result<Option<(Transaction, u64)>, TxResolverError> {
+        let fee = self.cache.borrow().fees.get(&txid).copied();
+        if let Some(tx) = self.cached_tx(txid) {
+            self.cache.borrow_mut().stats.tx_hits += 1;
+            let fee = match fee {
+                Some(fee) => fee,
+                None => {
+                    // Previous transactions are resolved through the cache
+                    let mut input_amount = 0u64;
+                    for txin in &tx.input {
+                        input_amount += self
+                            .resolve_tx(txin.previous_output.txid)?
+                            .output
+                            .get(txin.previous_output.vout as usize)
+                            .ok_or_else(|| TxResolverError::with(txid))?
+                            .value;
+                    }
+                    let output_amount = tx.output.iter().map(|txout| txout.value).sum();
+                    let fee = input_amount
+                        .checked_sub(output_amount)
+                        .ok_or_else(|| TxResolverError::with(txid))?;
+                    self.cache.borrow_mut().fees.insert(txid, fee);
+                    fee
+                }
+            };
+            return Ok(Some((tx, fee)));
+        }
+
+        self.cache.borrow_mut().stats.tx_misses += 1;
+        let (tx, fee) = match self.inner.resolve_tx_fee(txid)? {
+            Some(res) => res,
+            None => return Ok(None),
+        };
+        self.insert_tx(tx.clone());
+        self.check_confirmed(txid);
+        self.cache.borrow_mut().fees.insert(txid, fee);
+        Ok(Some((tx, fee)))
+    }
+}
+
+impl<R> ResolveUtxo for CachingResolver<R>
+where
+    R: ResolveUtxo + ResolveChainState,
+{
+    fn resolve_utxo<'script>(
+        &self,
+        scripts: impl IntoIterator<Item = &'script Script> + Clone,
+    ) -> Result<Vec<HashSet<Utxo>>, UtxoResolverError> {
+        self.check_height()?;
+        let scripts = scripts.into_iter().collect::<Vec<_>>();
+        let mut missing = {
+            let cache = self.cache.borrow();
+            scripts
+                .iter()
+                .copied()
+                .filter(|script| !cache.utxo.contains_key(*script))
+                .collect::<Vec<_>>()
+        };
+        // Repeated scripts which are not cached yet are not counted as hits
+        let hits = scripts.len() - missing.len();
+        missing.sort();
+        missing.dedup();
+
+        if !missing.is_empty() {
+            let utxo = self.inner.resolve_utxo(missing.iter().copied())?;
+            for (script, utxo_set) in missing.iter().zip(utxo) {
+                for utxo in &utxo_set {
+                    if let MiningStatus::Blockchain(_) = utxo.mined() {
+                        self.mark_confirmed(utxo.outpoint().txid);
+                    }
+                }
+                self.cache
+                    .borrow_mut()
+                    .utxo
+                    .insert((*script).clone(), utxo_set);
+            }
+        }
+
+        let mut cache = self.cache.borrow_mut();
+        cache.stats.utxo_misses += missing.len() as u64;
+        cache.stats.utxo_hits += hits as u64;
+        Ok(scripts
+            .into_iter()
+            .map(|script| cache.utxo[script].clone())
+            .collect())
+    }
+}
+
+impl<R> ResolveHistory for CachingResolver<R>
+where
+    R: ResolveHistory + ResolveChainState,
+{
+    fn resolve_history<'script>(
+        &self,
+        scripts: impl IntoIterator<Item = &'script Script> + Clone,
+    ) -> Result<Vec<HashSet<HistoryEntry>>, UtxoResolverError> {
+        self.check_height()?;
+        let scripts = scripts.into_iter().collect::<Vec<_>>();
+        let mut missing = {
+            let cache = self.cache.borrow();
+            scripts
+                .iter()
+                .copied()
+                .filter(|script| !cache.history.contains_key(*script))
+                .collect::<Vec<_>>()
+        };
+        // Repeated scripts which are not cached yet are not counted as hits
+        let hits = scripts.len() - missing.len();
+        missing.sort();
+        missing.dedup();
+
+        if !missing.is_empty() {
+            let history = self.inner.resolve_history(missing.iter().copied())?;
+            for (script, entries) in missing.iter().zip(history) {
+                for entry in &entries {
+                    if let MiningStatus::Blockchain(_) = entry.mined() {
+                        self.mark_confirmed(*entry.txid());
+                    }
+                }
+                self.cache
+                    .borrow_mut()
+                    .history
+                    .insert((*script).clone(), entries);
+            }
+        }
+
+        let mut cache = self.cache.borrow_mut();
+        cache.stats.history_misses += missing.len() as u64;
+        cache.stats.history_hits += hits as u64;
+        Ok(scripts
+            .into_iter()
+            .map(|script| cache.history[script].clone())
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bitcoin::hashes::Hash;
+    use bitcoin::{Network, OutPoint, PackedLockTime, TxIn, TxOut};
+
+    use super::*;
+    use crate::MemoryChain;
+
+    fn script(no: u8) -> Script {
+        Script::new_v0_p2wpkh(&bitcoin::WPubkeyHash::from_inner([no; 20]))
+    }
+
+    #[test]
+    fn caching() {
+        let mut chain = MemoryChain::new(Network::Regtest);
+        chain.generate(2, &script(1));
+        let coinbase = chain.block(1).unwrap().txdata[0].txid();
+        let tx = Transaction {
+            version: 2,
+            lock_time: PackedLockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(coinbase, 0),
+                ..default!()
+            }],
+            output: vec![TxOut {
+                value: 1_000_000,
+                script_pubkey: script(2),
+            }],
+        };
+        let txid = chain.send(tx.clone()).unwrap();
+        chain.mine(&script(1));
+
+        let dir = std::env::temp_dir().join(format!("onchain-cache-{}", std::process::id()));
+        let mut resolver = CachingResolver::with_store(chain.clone(), &dir).unwrap();
+        resolver.set_height(3);
+
+        let (_, fee) = resolver.resolve_tx_fee(txid).unwrap().unwrap();
+        assert_eq!(fee, 50 * 100_000_000 - 1_000_000);
+        assert_eq!(resolver.resolve_tx(txid).unwrap(), tx);
+        // Fee is computed by the backend, so the only miss is the transaction
+        // itself
+        assert_eq!(resolver.stats().tx_misses, 1);
+        assert_eq!(resolver.stats().tx_hits, 1);
+        // Backend reports the transaction as mined, so it is stored right away
+        assert_eq!(resolver.stats().stored, 1);
+
+        let utxo = resolver.resolve_utxo([&script(2), &script(2)]).unwrap();
+        assert_eq!(utxo[0], utxo[1]);
+        assert_eq!(resolver.stats().utxo_misses, 1);
+        assert_eq!(resolver.stats().utxo_hits, 0);
+        resolver.resolve_utxo([&script(2), &script(2)]).unwrap();
+        assert_eq!(resolver.stats().utxo_misses, 1);
+        assert_eq!(resolver.stats().utxo_hits, 2);
+        // Transaction is already stored and is not written again
+        assert_eq!(resolver.stats().stored, 1);
+        assert_eq!(resolver.resolve_history([&script(2)]).unwrap()[0].len(), 1);
+        resolver.resolve_history([&script(2)]).unwrap();
+        assert_eq!(resolver.stats().history_hits, 1);
+
+        // Same height keeps answers, new height invalidates them
+        resolver.set_height(3);
+        resolver.resolve_utxo([&script(2)]).unwrap();
+        assert_eq!(resolver.stats().utxo_misses, 1);
+        assert_eq!(resolver.stats().utxo_hits, 3);
+        resolver.set_height(4);
+        resolver.resolve_utxo([&script(2)]).unwrap();
+        assert_eq!(resolver.stats().utxo_misses, 2);
+
+        // Confirmed transactions are available from the store to the new
+        // caches even if the backend doesn't know them
+        let resolver =
+            CachingResolver::with_store(MemoryChain::new(Network::Regtest), &dir).unwrap();
+        assert_eq!(resolver.resolve_tx(txid).unwrap(), tx);
+        assert_eq!(resolver.stats().tx_hits, 1);
+        assert!(resolver.resolve_tx(coinbase).is_err());
+        assert_eq!(resolver.stats().tx_misses, 1);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn height_check() {
+        let mut chain = MemoryChain::new(Network::Regtest);
+        chain.generate(2, &script(1));
+        let coinbase = chain.block(1).unwrap().txdata[0].txid();
+        let tx = Transaction {
+            version: 2,
+            lock_time: PackedLockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(coinbase, 0),
+                ..default!()
+            }],
+            output: vec![TxOut {
+                value: 1_000_000,
+                script_pubkey: script(2),
+            }],
+        };
+        let txid = chain.send(tx).unwrap();
+
+        let dir = std::env::temp_dir().join(format!("onchain-height-{}", std::process::id()));
+        let resolver = CachingResolver::with_store(chain.clone(), &dir)
+            .unwrap()
+            .with_height_check();
+        resolver.resolve_tx(txid).unwrap();
+        // Unconfirmed transactions are not stored
+        assert_eq!(resolver.stats().stored, 0);
+        resolver.resolve_utxo([&script(2)]).unwrap();
+        resolver.resolve_utxo([&script(2)]).unwrap();
+        assert_eq!(resolver.height(), Some(2));
+        assert_eq!(resolver.stats().utxo_misses, 1);
+
+        // New block invalidates answers without the caller noticing it
+        chain.mine(&script(1));
+        let resolver = CachingResolver {
+            inner: chain,
+            ..resolver
+        };
+        let utxo = resolver.resolve_utxo([&script(2)]).unwrap();
+        assert_eq!(resolver.height(), Some(3));
+        assert_eq!(resolver.stats().utxo_misses, 2);
+        assert_eq!(
+            *utxo[0].iter().next().unwrap().mined(),
+            MiningStatus::Blockchain(3)
+        );
+        assert_eq!(resolver.stats().stored, 1);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn tx_only_backend() {
+        let tx = Transaction {
+            version: 2,
+            lock_time: PackedLockTime::ZERO,
+            input: vec![TxIn::default()],
+            output: vec![TxOut {
+                value: 1_000_000,
+                script_pubkey: script(2),
+            }],
+        };
+        let txid = tx.txid();
+        let resolver = CachingResolver::new(bmap! { txid => tx.clone() });
+        assert_eq!(resolver.resolve_tx(txid).unwrap(), tx);
+        assert_eq!(resolver.resolve_tx(txid).unwrap(), tx);
+        assert_eq!(resolver.stats().tx_misses, 1);
+        assert_eq!(resolver.stats().tx_hits, 1);
+        assert_eq!(resolver.stats().stored, 0);
+    }
+}
@@ -27,10 +27,23 @@ extern crate serde_crate as serde;
 extern crate miniscript_crate as miniscript;
 
 pub mod blockchain;
+pub mod cache;
+pub mod memory;
 mod network;
 mod resolvers;
 
-pub use network::PublicNetwork;
+pub use cache::{CacheStats, CachingResolver};
+pub use memory::MemoryChain;
+pub use network::{BitcoinNetwork, NetworkParseError, PublicNetwork, SignetParams};
+#[cfg(feature = "bitcoin_core")]
+pub use resolvers::bitcoin_core;
+#[cfg(feature = "esplora")]
+pub use resolvers::esplora;
+pub use resolvers::{
+    BackendError, BlockingJob, BlockingResolver, Broadcast, ResolveChainState, ResolveFeeRate,
+    ResolveHistory, ResolveTx, ResolveTxAsync, ResolveTxFee, ResolveTxFeeAsync, ResolveUtxo,
+    ResolveUtxoAsync, ResolverFuture, SpawnBlocking, ThreadedResolver, TxResolverError,
+    UtxoResolverError, WorkerPool, DEFAULT_WORKERS,
+};
 #[cfg(feature = "miniscript_descriptors")]
-pub use resolvers::ResolveDescriptor;
-pub use resolvers::{ResolveTx, ResolveTxFee, ResolveUtxo, TxResolverError, UtxoResolverError};
+pub use resolvers::{DescriptorScan, ResolveDescriptor, ResolveDescriptorAsync, ScanDescriptor};
@@ -0,0 +1,657 @@
+// Wallet-level libraries for bitcoin protocol by LNP/BP Association
+//
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// This software is distributed without any warranty.
+//
+// You should have received a copy of the Apache-2.0 License
+// along with this software.
+// If not, see <https://opensource.org/licenses/Apache-2.0>.
+
+//! In-memory blockchain simulator for deterministic offline testing of wallet
+//! logic.
+//!
+//! The simulator checks that transactions spend existing unspent outputs and
+//! do not create value, but does not verify scripts, signatures, timelocks or
+//! coinbase maturity. Blocks are produced without proof of work, with
+//! timestamps growing by ten minutes from the genesis block.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::mem;
+
+use bitcoin::blockdata::constants::genesis_block;
+use bitcoin::blockdata::script::Builder;
+use bitcoin::hashes::Hash;
+use bitcoin::{
+    Amount, Block, BlockHash, BlockHeader, Network, OutPoint, PackedLockTime, Script, Sequence,
+    Transaction, TxIn, TxMerkleNode, TxOut, Txid, Witness,
+};
+use chrono::DateTime;
+
+use crate::blockchain::{HistoryEntry, MiningStatus, TimeHeight, Utxo};
+use crate::{
+    BackendError, ResolveChainState, ResolveHistory, ResolveTx, ResolveTxFee, ResolveUtxo,
+    TxResolverError, UtxoResolverError,
+};
+
+/// Interval between timestamps of the simulated blocks, in seconds
+const BLOCK_INTERVAL: u32 = 600;
+
+/// Errors of transaction acceptance by [`MemoryChain`]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum MemoryChainError {
+    /// transaction {0} is already known
+    Duplicate(Txid),
+
+    /// coinbase transaction {0} can't be added to the mempool
+    Coinbase(Txid),
+
+    /// transaction {txid} spends unknown output {outpoint}
+    MissingInput {
+        /// Id of the rejected transaction
+        txid: Txid,
+
+        /// Unknown output spent by the transaction
+        outpoint: OutPoint,
+    },
+
+    /// transaction {txid} spends output {outpoint} which is already spent by
+    /// transaction {spent_by}
+    Conflict {
+        /// Id of the rejected transaction
+        txid: Txid,
+
+        /// Output spent by the transaction
+        outpoint: OutPoint,
+
+        /// Transaction which already spends the output
+        spent_by: Txid,
+    },
+
+    /// transaction {0} spends more than the amount of its inputs
+    Overspend(Txid),
+
+    /// can't disconnect {0} blocks from the chain, which has only {1} blocks
+    /// on top of the genesis
+    ReorgTooDeep(u32, u32),
+}
+
+/// In-memory blockchain with mempool, tracking the set of unspent outputs.
+///
+/// Implements all resolver traits, providing mining status and block
+/// information for the transactions it knows about. Chain reorganizations
+/// are simulated with [`MemoryChain::reorg`].
+#[derive(Clone, Debug)]
+pub struct MemoryChain {
+    network: Network,
+    blocks: Vec<Block>,
+    mempool: Vec<Txid>,
+    txs: HashMap<Txid, (Transaction, Option<u32>)>,
+    utxo: HashMap<OutPoint, TxOut>,
+    spends: HashMap<OutPoint, Txid>,
+    extra_nonce: u64,
+}
+
+impl MemoryChain {
+    /// Constructs chain containing just the genesis block of the network
+    pub fn new(network: Network) -> MemoryChain {
+        MemoryChain {
+            network,
+            blocks: vec![genesis_block(network)],
+            mempool: vec![],
+            txs: none!(),
+            utxo: none!(),
+            spends: none!(),
+            extra_nonce: 0,
+        }
+    }
+
+    /// Returns network of the chain
+    #[inline]
+    pub fn network(&self) -> Network { self.network }
+
+    /// Returns height of the chain tip
+    #[inline]
+    pub fn height(&self) -> u32 { self.blocks.len() as u32 - 1 }
+
+    /// Returns hash of the chain tip
+    #[inline]
+    pub fn tip(&self) -> BlockHash { self.blocks[self.blocks.len() - 1].block_hash() }
+
+    /// Returns block at the given height of the active chain
+    #[inline]
+    pub fn block(&self, height: u32) -> Option<&Block> { self.blocks.get(height as usize) }
+
+    /// Returns mining information for the block at the given height of the
+    /// active chain
+    pub fn time_height(&self, height: u32) -> Option<TimeHeight> {
+        let header = self.block(height)?.header;
+        let timestamp = DateTime::from_timestamp(header.time as i64, 0)?.naive_utc();
+        Some(TimeHeight::new(timestamp, height, header.block_hash()))
+    }
+
+    /// Returns transaction from the active chain or mempool
+    #[inline]
+    pub fn transaction(&self, txid: Txid) -> Option<&Transaction> {
+        self.txs.get(&txid).map(|(tx, _)| tx)
+    }
+
+    /// Iterates over mempool transactions in the order of their acceptance
+    pub fn mempool(&self) -> impl Iterator<Item = &Transaction> {
+        self.mempool.iter().map(|txid| &self.txs[txid].0)
+    }
+
+    /// Returns mining status of the transaction
+    pub fn mining_status(&self, txid: Txid) -> MiningStatus {
+        match self.txs.get(&txid) {
+            None => MiningStatus::UnknownTx,
+            Some((_, None)) => MiningStatus::Mempool,
+            Some((_, Some(height))) => MiningStatus::Blockchain(*height as u64),
+        }
+    }
+
+    /// Adds transaction to the mempool
+    pub fn send(&mut self, tx: Transaction) -> Result<Txid, MemoryChainError> {
+        let txid = tx.txid();
+        if tx.is_coin_base() {
+            return Err(MemoryChainError::Coinbase(txid));
+        }
+        if self.txs.contains_key(&txid) {
+            return Err(MemoryChainError::Duplicate(txid));
+        }
+        let mut input_amount = 0u64;
+        for txin in &tx.input {
+            let outpoint = txin.previous_output;
+            if let Some(spent_by) = self.spends.get(&outpoint) {
+                return Err(MemoryChainError::Conflict {
+                    txid,
+                    outpoint,
+                    spent_by: *spent_by,
+                });
+            }
+            input_amount += self
+                .utxo
+                .get(&outpoint)
+                .ok_or(MemoryChainError::MissingInput { txid, outpoint })?
+                .value;
+        }
+        if tx.output.iter().map(|txout| txout.value).sum::<u64>() > input_amount {
+            return Err(MemoryChainError::Overspend(txid));
+        }
+        self.apply(tx, None);
+        self.mempool.push(txid);
+        Ok(txid)
+    }
+
+    /// Adds transaction to the mempool, evicting mempool transactions which
+    /// spend the same outputs together with their descendants (full
+    /// replace-by-fee). Returns ids of the evicted transactions.
+    pub fn replace(&mut self, tx: Transaction) -> Result<Vec<Txid>, MemoryChainError> {
+        let mut evicted = HashSet::new();
+        let mut outpoints = tx
+            .input
+            .iter()
+            .map(|txin| txin.previous_output)
+            .collect::<Vec<_>>();
+        while let Some(outpoint) = outpoints.pop() {
+            let spent_by = match self.spends.get(&outpoint) {
+                Some(spent_by) => *spent_by,
+                None => continue,
+            };
+            let (spending_tx, height) = &self.txs[&spent_by];
+            if height.is_some() {
+                return Err(MemoryChainError::Conflict {
+                    txid: tx.txid(),
+                    outpoint,
+                    spent_by,
+                });
+            }
+            if evicted.insert(spent_by) {
+                outpoints.extend(
+                    (0..spending_tx.output.len()).map(|vout| OutPoint::new(spent_by, vout as u32)),
+                );
+            }
+        }
+
+        let (evicted, kept) = self
+            .take_mempool()
+            .into_iter()
+            .partition::<Vec<_>, _>(|tx| evicted.contains(&tx.txid()));
+        self.rebuild(kept);
+        if let Err(err) = self.send(tx) {
+            // Restore the mempool if the replacement is invalid by itself
+            let kept = self.take_mempool();
+            self.rebuild(kept.into_iter().chain(evicted));
+            return Err(err);
+        }
+        Ok(evicted.iter().map(Transaction::txid).collect())
+    }
+
+    /// Mines a new block with all mempool transactions, sending the block
+    /// subsidy and fees to the `coinbase_script`
+    pub fn mine(&mut self, coinbase_script: &Script) -> BlockHash {
+        let height = self.height() + 1;
+        let mempool = mem::take(&mut self.mempool)
+            .into_iter()
+            .map(|txid| self.txs[&txid].0.clone())
+            .collect::<Vec<_>>();
+        let fees = mempool
+            .iter()
+            .map(|tx| self.fee(tx).expect("mempool transactions are validated"))
+            .sum::<u64>();
+
+        // Extra nonce makes coinbase and block hashes unique across reorgs
+        self.extra_nonce += 1;
+        let coinbase = Transaction {
+            version: 2,
+            lock_time: PackedLockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: Builder::new()
+                    .push_int(height as i64)
+                    .push_int(self.extra_nonce as i64)
+                    .into_script(),
+                sequence: Sequence::MAX,
+                witness: Witness::new(),
+            }],
+            output: vec![TxOut {
+                value: self.subsidy(height) + fees,
+                script_pubkey: coinbase_script.clone(),
+            }],
+        };
+
+        let genesis = self.blocks[0].header;
+        let mut block = Block {
+            header: BlockHeader {
+                version: 0x2000_0000,
+                prev_blockhash: self.tip(),
+                merkle_root: TxMerkleNode::all_zeros(),
+                time: genesis.time + height * BLOCK_INTERVAL,
+                bits: genesis.bits,
+                nonce: 0,
+            },
+            txdata: Some(coinbase).into_iter().chain(mempool).collect(),
+        };
+        block.header.merkle_root = block
+            .compute_merkle_root()
+            .expect("block always has coinbase");
+        let block_hash = block.block_hash();
+
+        self.apply(block.txdata[0].clone(), Some(height));
+        for tx in &block.txdata[1..] {
+            if let Some((_, mined)) = self.txs.get_mut(&tx.txid()) {
+                *mined = Some(height);
+            }
+        }
+        self.blocks.push(block);
+        block_hash
+    }
+
+    /// Mines `count` blocks, sending block subsidies and fees to the
+    /// `coinbase_script`
+    pub fn generate(&mut self, count: u32, coinbase_script: &Script) -> Vec<BlockHash> {
+        (0..count).map(|_| self.mine(coinbase_script)).collect()
+    }
+
+    /// Disconnects `depth` blocks from the chain tip, returning their
+    /// transactions to the mempool. Transactions which become invalid, like
+    /// the ones spending disconnected coinbase outputs, are evicted from the
+    /// mempool; their ids are returned.
+    ///
+    /// New blocks can be mined afterwards to simulate a competing chain.
+    pub fn reorg(&mut self, depth: u32) -> Result<Vec<Txid>, MemoryChainError> {
+        if depth > self.height() {
+            return Err(MemoryChainError::ReorgTooDeep(depth, self.height()));
+        }
+        let disconnected = self.blocks.split_off(self.blocks.len() - depth as usize);
+        let mempool = self.take_mempool();
+        let txs = disconnected
+            .into_iter()
+            .flat_map(|block| block.txdata.into_iter().skip(1))
+            .chain(mempool)
+            .collect::<Vec<_>>();
+        Ok(self.rebuild(txs))
+    }
+
+    fn subsidy(&self, height: u32) -> u64 {
+        let halving_interval = match self.network {
+            Network::Regtest => 150,
+            _ => 210_000,
+        };
+        match height / halving_interval {
+            halvings if halvings >= 64 => 0,
+            halvings => (50 * Amount::ONE_BTC.to_sat()) >> halvings,
+        }
+    }
+
+    fn fee(&self, tx: &Transaction) -> Option<u64> {
+        if tx.is_coin_base() {
+            return Some(0);
+        }
+        let input_amount = tx
+            .input
+            .iter()
+            .map(|txin| {
+                let (prev_tx, _) = self.txs.get(&txin.previous_output.txid)?;
+                prev_tx
+                    .output
+                    .get(txin.previous_output.vout as usize)
+                    .map(|txout| txout.value)
+            })
+            .sum::<Option<u64>>()?;
+        input_amount.checked_sub(tx.output.iter().map(|txout| txout.value).sum())
+    }
+
+    fn apply(&mut self, tx: Transaction, height: Option<u32>) {
+        let txid = tx.txid();
+        if !tx.is_coin_base() {
+            for txin in &tx.input {
+                self.utxo.remove(&txin.previous_output);
+                self.spends.insert(txin.previous_output, txid);
+            }
+        }
+        for (vout, txout) in tx.output.iter().enumerate() {
+            self.utxo
+                .insert(OutPoint::new(txid, vout as u32), txout.clone());
+        }
+        self.txs.insert(txid, (tx, height));
+    }
+
+    fn take_mempool(&mut self) -> Vec<Transaction> {
+        mem::take(&mut self.mempool)
+            .into_iter()
+            .map(|txid| self.txs[&txid].0.clone())
+            .collect()
+    }
+
+    /// Re-creates chain state from the blocks, adding the provided
+    /// transactions to the mempool and returning ids of the ones which were
+    /// rejected
+    fn rebuild(&mut self, mempool: impl IntoIterator<Item = Transaction>) -> Vec<Txid> {
+        self.txs.clear();
+        self.utxo.clear();
+        self.spends.clear();
+        self.mempool.clear();
+        // Genesis outputs are not spendable and are not the part of UTXO set
+        let blocks = mem::take(&mut self.blocks);
+        for (height, block) in blocks.iter().enumerate().skip(1) {
+            for tx in &block.txdata {
+                self.apply(tx.clone(), Some(height as u32));
+            }
+        }
+        self.blocks = blocks;
+        mempool
+            .into_iter()
+            .filter_map(|tx| {
+                let txid = tx.txid();
+                self.send(tx).err().map(|_| txid)
+            })
+            .collect()
+    }
+
+    fn entry(&self, txid: Txid) -> HistoryEntry {
+        let mined = self.mining_status(txid);
+        let time_height = match mined {
+            MiningStatus::Blockchain(height) => self.time_height(height as u32),
+            _ => None,
+        };
+        HistoryEntry::new(txid, mined, time_height)
+    }
+}
+
+impl ResolveChainState for MemoryChain {
+    fn resolve_height(&self) -> Result<u32, BackendError> { Ok(self.height()) }
+
+    fn resolve_tx_status(&self, txid: Txid) -> Result<MiningStatus, TxResolverError> {
+        Ok(self.mining_status(txid))
+    }
+}
+
+impl ResolveTx for MemoryChain {
+    fn resolve_tx(&self, txid: Txid) -> Result<Transaction, TxResolverError> {
+        self.transaction(txid)
+            .cloned()
+            .ok_or_else(|| TxResolverError::with(txid))
+    }
+}
+
+impl ResolveTxFee for MemoryChain {
+    fn resolve_tx_fee(&self, txid: Txid) -> Result<Option<(Transaction, u64)>, TxResolverError> {
+        Ok(self.transaction(txid).map(|tx| {
+            let fee = self.fee(tx).expect("known transactions are validated");
+            (tx.clone(), fee)
+        }))
+    }
+}
+
+impl ResolveUtxo for MemoryChain {
+    fn resolve_utxo<'script>(
+        &self,
+        scripts: impl IntoIterator<Item = &'script Script> + Clone,
+    ) -> Result<Vec<HashSet<Utxo>>, UtxoResolverError> {
+        let mut utxo_map = BTreeMap::<&Script, HashSet<Utxo>>::new();
+        for (outpoint, txout) in &self.utxo {
+            let entry = self.entry(outpoint.txid);
+            let coinbase = self
+                .transaction(outpoint.txid)
+                .map(Transaction::is_coin_base)
+                .unwrap_or_default();
+            let mut utxo = Utxo::new(*entry.mined(), *outpoint, Amount::from_sat(txout.value))
+                .with_script_pubkey(txout.script_pubkey.clone())
+                .with_coinbase(coinbase);
+            if let Some(time_height) = entry.time_height() {
+                utxo = utxo.with_time_height(time_height.clone());
+            }
+            utxo_map
+                .entry(&txout.script_pubkey)
+                .or_default()
+                .insert(utxo);
+        }
+        Ok(scripts
+            .into_iter()
+            .map(|script| utxo_map.get(script).cloned().unwrap_or_default())
+            .collect())
+    }
+}
+
+impl ResolveHistory for MemoryChain {
+    fn resolve_history<'script>(
+        &self,
+        scripts: impl IntoIterator<Item = &'script Script> + Clone,
+    ) -> Result<Vec<HashSet<HistoryEntry>>, UtxoResolverError> {
+        let mut history_map = BTreeMap::<&Script, HashSet<HistoryEntry>>::new();
+        for (txid, (tx, _)) in &self.txs {
+            let spent = tx
+                .input
+                .iter()
+                .filter(|_| !tx.is_coin_base())
+                .filter_map(|txin| {
+                    let (prev_tx, _) = self.txs.get(&txin.previous_output.txid)?;
+                    prev_tx.output.get(txin.previous_output.vout as usize)
+                });
+            for txout in spent.chain(&tx.output) {
+                history_map
+                    .entry(&txout.script_pubkey)
+                    .or_default()
+                    .insert(self.entry(*txid));
+            }
+        }
+        Ok(scripts
+            .into_iter()
+            .map(|script| history_map.get(script).cloned().unwrap_or_default())
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    fn script(no: u8) -> Script {
+        Script::new_v0_p2wpkh(&bitcoin::WPubkeyHash::from_inner([no; 20]))
+    }
+
+    fn spend(inputs: &[OutPoint], outputs: &[(u8, u64)]) -> Transaction {
+        Transaction {
+            version: 2,
+            lock_time: PackedLockTime::ZERO,
+            input: inputs
+                .iter()
+                .map(|outpoint| TxIn {
+                    previous_output: *outpoint,
+                    ..default!()
+                })
+                .collect(),
+            output: outputs
+                .iter()
+                .map(|(no, value)| TxOut {
+                    value: *value,
+                    script_pubkey: script(*no),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn mining_and_resolving() {
+        let mut chain = MemoryChain::new(Network::Regtest);
+        chain.mine(&script(1));
+        chain.generate(100, &script(2));
+        assert_eq!(chain.height(), 101);
+
+        let coinbase = chain.block(1).unwrap().txdata[0].txid();
+        let subsidy = 50 * 100_000_000;
+        let tx = spend(&[OutPoint::new(coinbase, 0)], &[
+            (3, 10_000_000),
+            (1, subsidy - 10_000_000 - 1000),
+        ]);
+        let txid = chain.send(tx.clone()).unwrap();
+        assert_eq!(chain.mining_status(txid), MiningStatus::Mempool);
+        assert_eq!(
+            chain.resolve_tx_fee(txid).unwrap(),
+            Some((tx.clone(), 1000))
+        );
+
+        let utxo = chain.resolve_utxo([&script(1), &script(3)]).unwrap();
+        assert_eq!(utxo[0].len(), 1);
+        assert_eq!(
+            *utxo[0].iter().next().unwrap().mined(),
+            MiningStatus::Mempool
+        );
+        assert_eq!(utxo[1].len(), 1);
+
+        assert_eq!(
+            chain.send(tx.clone()),
+            Err(MemoryChainError::Duplicate(txid))
+        );
+        let double_spend = spend(&[OutPoint::new(coinbase, 0)], &[(4, 1000)]);
+        assert!(matches!(
+            chain.send(double_spend),
+            Err(MemoryChainError::Conflict { spent_by, .. }) if spent_by == txid
+        ));
+        assert!(matches!(
+            chain.send(spend(&[OutPoint::new(txid, 5)], &[(4, 1000)])),
+            Err(MemoryChainError::MissingInput { .. })
+        ));
+        assert_eq!(
+            chain.send(spend(&[OutPoint::new(txid, 0)], &[(4, 10_000_001)])),
+            Err(MemoryChainError::Overspend(
+                spend(&[OutPoint::new(txid, 0)], &[(4, 10_000_001)]).txid()
+            ))
+        );
+
+        let block_hash = chain.mine(&script(2));
+        assert_eq!(chain.mining_status(txid), MiningStatus::Blockchain(102));
+        assert_eq!(chain.mempool().count(), 0);
+        let block = chain.block(102).unwrap();
+        assert!(block.check_merkle_root());
+        assert_eq!(block.txdata[0].output[0].value, subsidy + 1000);
+
+        let history = chain.resolve_history([&script(1), &script(3)]).unwrap();
+        assert_eq!(history[0].len(), 2);
+        let entry = history[1].iter().next().unwrap();
+        assert_eq!(*entry.txid(), txid);
+        let time_height = entry.time_height().as_ref().unwrap();
+        assert_eq!(*time_height.block_height(), 102);
+        assert_eq!(*time_height.block_hash(), block_hash);
+    }
+
+    #[test]
+    fn utxo_details() {
+        let mut chain = MemoryChain::new(Network::Regtest);
+        let block_hash = chain.mine(&script(1));
+        let utxo = chain.resolve_utxo([&script(1)]).unwrap()[0]
+            .iter()
+            .next()
+            .cloned()
+            .unwrap();
+        assert_eq!(utxo.script_pubkey(), &Some(script(1)));
+        assert_eq!(
+            utxo.time_height().as_ref().map(TimeHeight::block_hash),
+            Some(&block_hash)
+        );
+        assert!(utxo.coinbase());
+        assert_eq!(utxo.confirmations(chain.height() as u64), 1);
+        assert!(!utxo.is_mature(chain.height() as u64));
+
+        chain.generate(99, &script(2));
+        assert_eq!(utxo.confirmations(chain.height() as u64), 100);
+        assert!(utxo.is_mature(chain.height() as u64));
+        assert_eq!(utxo.confirmations(0), 0);
+    }
+
+    #[test]
+    fn reorg_and_replacement() {
+        let mut chain = MemoryChain::new(Network::Regtest);
+        chain.generate(2, &script(1));
+        let coinbase = chain.block(2).unwrap().txdata[0].txid();
+        let tx = spend(&[OutPoint::new(coinbase, 0)], &[(2, 1_000_000)]);
+        let txid = chain.send(tx).unwrap();
+        let child = spend(&[OutPoint::new(txid, 0)], &[(3, 900_000)]);
+        let child_txid = chain.send(child).unwrap();
+        chain.mine(&script(1));
+        let old_tip = chain.tip();
+
+        // Transactions return to mempool after shallow reorg
+        assert_eq!(chain.reorg(1).unwrap(), vec![]);
+        assert_eq!(chain.mining_status(txid), MiningStatus::Mempool);
+        assert_eq!(chain.mempool().count(), 2);
+        chain.mine(&script(1));
+        assert_ne!(chain.tip(), old_tip);
+        assert_eq!(chain.mining_status(child_txid), MiningStatus::Blockchain(3));
+
+        // Spending outputs of a disconnected coinbase become invalid
+        assert_eq!(chain.reorg(2).unwrap(), vec![txid, child_txid]);
+        assert_eq!(chain.mining_status(txid), MiningStatus::UnknownTx);
+        assert_eq!(chain.resolve_utxo([&script(2)]).unwrap(), vec![
+            HashSet::new()
+        ]);
+        assert_eq!(chain.reorg(2), Err(MemoryChainError::ReorgTooDeep(2, 1)));
+
+        // Replacement evicts conflicting transactions with their descendants
+        let coinbase = chain.block(1).unwrap().txdata[0].txid();
+        let tx = spend(&[OutPoint::new(coinbase, 0)], &[(2, 1_000_000)]);
+        let txid = chain.send(tx).unwrap();
+        let child = spend(&[OutPoint::new(txid, 0)], &[(3, 900_000)]);
+        let child_txid = chain.send(child).unwrap();
+        let replacement = spend(&[OutPoint::new(coinbase, 0)], &[(4, 500_000)]);
+        assert_eq!(chain.replace(replacement.clone()).unwrap(), vec![
+            txid, child_txid
+        ]);
+        assert_eq!(chain.mempool().collect::<Vec<_>>(), vec![&replacement]);
+
+        // Confirmed transactions can't be replaced
+        chain.mine(&script(1));
+        assert!(matches!(
+            chain.replace(spend(&[OutPoint::new(coinbase, 0)], &[(5, 1000)])),
+            Err(MemoryChainError::Conflict { .. })
+        ));
+        assert_eq!(
+            chain.mining_status(replacement.txid()),
+            MiningStatus::Blockchain(2)
+        );
+    }
+}
@@ -9,9 +9,22 @@
 // along with this software.
 // If not, see <https://opensource.org/licenses/Apache-2.0>.
 
-use bitcoin::Network;
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+use bitcoin::consensus::serialize;
+use bitcoin::hashes::hex::{FromHex, ToHex};
+use bitcoin::hashes::{sha256d, Hash};
+use bitcoin::{Network, Script};
 use bitcoin_hd::standards::DerivationBlockchain;
 
+/// Challenge script of the global default signet
+const SIGNET_CHALLENGE: &str = "512103ad5e0edad18cb1f0fc0d28a3d4f1f3e445640337489abb10404f2d1e086be430210359ef5021964fe22d6f8e05b2463c9540ce96883fe3b278760f048f5189f2e6c452ae";
+
+/// Network magic of bitcoin testnet4, in the same format as returned by
+/// [`Network::magic`]
+const TESTNET4_MAGIC: u32 = 0x283F161C;
+
 /// Public variants of bitcoin networks
 #[derive(
     Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Debug, Display
@@ -35,6 +48,10 @@ pub enum PublicNetwork {
     /// Bitcoin signet
     #[display("signet")]
     Signet,
+
+    /// Bitcoin testnet4
+    #[display("testnet4")]
+    Testnet4,
 }
 
 impl From<PublicNetwork> for Network {
@@ -47,6 +64,7 @@ impl From<&PublicNetwork> for Network {
             PublicNetwork::Mainnet => Network::Bitcoin,
             PublicNetwork::Testnet => Network::Testnet,
             PublicNetwork::Signet => Network::Signet,
+            PublicNetwork::Testnet4 => Network::Testnet,
         }
     }
 }
@@ -72,16 +90,15 @@ impl From<&PublicNetwork> for DerivationBlockchain {
         match network {
             PublicNetwork::Mainnet => DerivationBlockchain::Bitcoin,
             PublicNetwork::Testnet => DerivationBlockchain::Testnet,
-            PublicNetwork::Signet => DerivationBlockchain::Testnet,
+            PublicNetwork::Signet => DerivationBlockchain::Signet,
+            PublicNetwork::Testnet4 => DerivationBlockchain::Testnet4,
         }
     }
 }
 
 impl PublicNetwork {
     /// Detects if the public network is belongs to a testnet
-    pub fn is_testnet(self) -> bool {
-        matches!(self, PublicNetwork::Testnet | PublicNetwork::Signet)
-    }
+    pub fn is_testnet(self) -> bool { self != PublicNetwork::Mainnet }
 
     /// Returns default electrum server port for the network
     pub fn electrum_port(self) -> u16 {
@@ -89,6 +106,257 @@ impl PublicNetwork {
             PublicNetwork::Mainnet => 50001,
             PublicNetwork::Testnet => 60001,
             PublicNetwork::Signet => 60601,
+            PublicNetwork::Testnet4 => 40001,
+        }
+    }
+}
+
+/// Parameters of a signet network
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+#[derive(StrictEncode, StrictDecode)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_crate")
+)]
+pub struct SignetParams {
+    challenge: Script,
+}
+
+impl SignetParams {
+    /// Constructs parameters of a signet with the given block challenge script
+    pub fn new(challenge: Script) -> SignetParams { SignetParams { challenge } }
+
+    /// Parameters of the global default signet
+    pub fn global() -> SignetParams {
+        SignetParams::new(Script::from(
+            Vec::from_hex(SIGNET_CHALLENGE).expect("hardcoded signet challenge"),
+        ))
+    }
+
+    /// Returns block challenge script
+    pub fn challenge(&self) -> &Script { &self.challenge }
+
+    /// Returns network magic, which is defined by the first four bytes of the
+    /// double SHA256 hash of the serialized challenge script. The value is
+    /// returned in the same format as by [`Network::magic`].
+    pub fn magic(&self) -> u32 {
+        let hash = sha256d::Hash::hash(&serialize(&self.challenge));
+        u32::from_le_bytes([hash[0], hash[1], hash[2], hash[3]])
+    }
+}
+
+/// Bitcoin networks, including test networks which are not public
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default)]
+#[derive(StrictEncode, StrictDecode)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_crate")
+)]
+pub enum BitcoinNetwork {
+    /// Bitcoin mainnet
+    Mainnet,
+
+    /// Bitcoin testnet3
+    #[default]
+    Testnet,
+
+    /// Bitcoin testnet4
+    Testnet4,
+
+    /// Global default bitcoin signet
+    Signet,
+
+    /// Custom signet with specific challenge script
+    CustomSignet(SignetParams),
+
+    /// Local regression testing network
+    Regtest,
+}
+
+impl BitcoinNetwork {
+    /// Detects if the network belongs to testnets, i.e. uses testnet address
+    /// and extended key encodings
+    pub fn is_testnet(&self) -> bool { *self != BitcoinNetwork::Mainnet }
+
+    /// Returns network magic in the same format as returned by
+    /// [`Network::magic`]
+    pub fn magic(&self) -> u32 {
+        match self {
+            BitcoinNetwork::Testnet4 => TESTNET4_MAGIC,
+            BitcoinNetwork::CustomSignet(params) => params.magic(),
+            network => Network::from(network).magic(),
+        }
+    }
+
+    /// Returns default electrum server port for the network
+    pub fn electrum_port(&self) -> u16 {
+        match self {
+            BitcoinNetwork::Mainnet => 50001,
+            BitcoinNetwork::Testnet => 60001,
+            BitcoinNetwork::Testnet4 => 40001,
+            BitcoinNetwork::Signet | BitcoinNetwork::CustomSignet(_) => 60601,
+            BitcoinNetwork::Regtest => 60401,
+        }
+    }
+}
+
+impl From<PublicNetwork> for BitcoinNetwork {
+    fn from(network: PublicNetwork) -> Self {
+        match network {
+            PublicNetwork::Mainnet => BitcoinNetwork::Mainnet,
+            PublicNetwork::Testnet => BitcoinNetwork::Testnet,
+            PublicNetwork::Signet => BitcoinNetwork::Signet,
+            PublicNetwork::Testnet4 => BitcoinNetwork::Testnet4,
+        }
+    }
+}
+
+impl TryFrom<BitcoinNetwork> for PublicNetwork {
+    type Error = ();
+    fn try_from(network: BitcoinNetwork) -> Result<Self, Self::Error> {
+        Ok(match network {
+            BitcoinNetwork::Mainnet => PublicNetwork::Mainnet,
+            BitcoinNetwork::Testnet => PublicNetwork::Testnet,
+            BitcoinNetwork::Testnet4 => PublicNetwork::Testnet4,
+            BitcoinNetwork::Signet => PublicNetwork::Signet,
+            BitcoinNetwork::CustomSignet(_) | BitcoinNetwork::Regtest => return Err(()),
+        })
+    }
+}
+
+impl From<Network> for BitcoinNetwork {
+    fn from(network: Network) -> Self {
+        match network {
+            Network::Bitcoin => BitcoinNetwork::Mainnet,
+            Network::Testnet => BitcoinNetwork::Testnet,
+            Network::Signet => BitcoinNetwork::Signet,
+            Network::Regtest => BitcoinNetwork::Regtest,
+        }
+    }
+}
+
+/// Converts into the network used for address and SLIP-132 extended key
+/// encoding: testnet4 and custom signets share encodings with testnet3 and
+/// signet.
+impl From<&BitcoinNetwork> for Network {
+    fn from(network: &BitcoinNetwork) -> Self {
+        match network {
+            BitcoinNetwork::Mainnet => Network::Bitcoin,
+            BitcoinNetwork::Testnet | BitcoinNetwork::Testnet4 => Network::Testnet,
+            BitcoinNetwork::Signet | BitcoinNetwork::CustomSignet(_) => Network::Signet,
+            BitcoinNetwork::Regtest => Network::Regtest,
+        }
+    }
+}
+
+impl From<BitcoinNetwork> for Network {
+    fn from(network: BitcoinNetwork) -> Self { Network::from(&network) }
+}
+
+impl From<&BitcoinNetwork> for DerivationBlockchain {
+    fn from(network: &BitcoinNetwork) -> Self {
+        match network {
+            BitcoinNetwork::Mainnet => DerivationBlockchain::Bitcoin,
+            BitcoinNetwork::Testnet => DerivationBlockchain::Testnet,
+            BitcoinNetwork::Testnet4 => DerivationBlockchain::Testnet4,
+            BitcoinNetwork::Signet | BitcoinNetwork::CustomSignet(_) => {
+                DerivationBlockchain::Signet
+            }
+            BitcoinNetwork::Regtest => DerivationBlockchain::Regtest,
+        }
+    }
+}
+
+impl From<BitcoinNetwork> for DerivationBlockchain {
+    fn from(network: BitcoinNetwork) -> Self { DerivationBlockchain::from(&network) }
+}
+
+impl Display for BitcoinNetwork {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            BitcoinNetwork::Mainnet => f.write_str("mainnet"),
+            BitcoinNetwork::Testnet => f.write_str("testnet"),
+            BitcoinNetwork::Testnet4 => f.write_str("testnet4"),
+            BitcoinNetwork::Signet => f.write_str("signet"),
+            BitcoinNetwork::CustomSignet(params) => {
+                write!(f, "signet:{}", params.challenge.as_bytes().to_hex())
+            }
+            BitcoinNetwork::Regtest => f.write_str("regtest"),
+        }
+    }
+}
+
+/// Errors parsing bitcoin network name
+#[derive(Clone, PartialEq, Eq, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum NetworkParseError {
+    /// unknown bitcoin network `{0}`
+    UnknownNetwork(String),
+
+    /// invalid hex encoding of custom signet challenge script `{0}`
+    InvalidChallenge(String),
+}
+
+impl FromStr for BitcoinNetwork {
+    type Err = NetworkParseError;
+
+    /// Parses network name; custom signets are specified as `signet:` prefix
+    /// followed by the hex-encoded challenge script
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(challenge) = s.strip_prefix("signet:") {
+            let challenge = Vec::from_hex(challenge)
+                .map_err(|_| NetworkParseError::InvalidChallenge(challenge.to_owned()))?;
+            return Ok(BitcoinNetwork::CustomSignet(SignetParams::new(
+                Script::from(challenge),
+            )));
+        }
+        Ok(match s.to_lowercase().as_str() {
+            "mainnet" | "bitcoin" => BitcoinNetwork::Mainnet,
+            "testnet" | "testnet3" => BitcoinNetwork::Testnet,
+            "testnet4" => BitcoinNetwork::Testnet4,
+            "signet" => BitcoinNetwork::Signet,
+            "regtest" => BitcoinNetwork::Regtest,
+            _ => return Err(NetworkParseError::UnknownNetwork(s.to_owned())),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn signet_magic() {
+        assert_eq!(SignetParams::global().magic(), Network::Signet.magic());
+        assert_eq!(BitcoinNetwork::Signet.magic(), Network::Signet.magic());
+        let custom = BitcoinNetwork::CustomSignet(SignetParams::new(Script::from(vec![0x51])));
+        assert_ne!(custom.magic(), Network::Signet.magic());
+        assert_eq!(Network::from(&custom), Network::Signet);
+    }
+
+    #[test]
+    fn network_names() {
+        for network in [
+            BitcoinNetwork::Mainnet,
+            BitcoinNetwork::Testnet,
+            BitcoinNetwork::Testnet4,
+            BitcoinNetwork::Signet,
+            BitcoinNetwork::CustomSignet(SignetParams::new(Script::from(vec![0x51]))),
+            BitcoinNetwork::Regtest,
+        ] {
+            assert_eq!(BitcoinNetwork::from_str(&network.to_string()), Ok(network));
         }
+        assert_eq!(
+            BitcoinNetwork::from_str("bitcoin"),
+            Ok(BitcoinNetwork::Mainnet)
+        );
+        assert_eq!(
+            BitcoinNetwork::from(Network::Regtest),
+            BitcoinNetwork::Regtest
+        );
+        assert!(BitcoinNetwork::from_str("signet:xyz").is_err());
+        assert!(BitcoinNetwork::from_str("liquid").is_err());
     }
 }
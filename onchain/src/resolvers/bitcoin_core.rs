@@ -0,0 +1,766 @@
+// Wallet-level libraries for bitcoin protocol by LNP/BP Association
+//
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// This software is distributed without any warranty.
+//
+// You should have received a copy of the Apache-2.0 License
+// along with this software.
+// If not, see <https://opensource.org/licenses/Apache-2.0>.
+
+//! Bitcoin Core JSON-RPC client implementing resolver traits.
+//!
+//! Transactions are retrieved with `getrawtransaction`, which requires
+//! `-txindex` for confirmed transactions not belonging to the node wallet.
+//! UTXOs are looked up with `scantxoutset`, or with `listunspent` when the
+//! client is connected to a node wallet (`/wallet/<name>` URL path) watching
+//! the scripts. Transaction history is available only from the node wallet.
+
+use std::collections::{BTreeMap, HashSet};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use std::{fs, io};
+
+use amplify::IoError;
+use base64::Engine;
+use bitcoin::consensus::{deserialize, serialize};
+use bitcoin::hashes::hex::{FromHex, ToHex};
+use bitcoin::{Amount, Network, OutPoint, Script, Transaction, Txid};
+use chrono::DateTime;
+use serde_json::{json, Value};
+
+use super::http::Endpoint;
+use super::{
+    BackendError, Broadcast, ResolveChainState, ResolveFeeRate, ResolveHistory, ResolveTx,
+    ResolveTxFee, ResolveUtxo, TxResolverError, UtxoResolverError,
+};
+use crate::blockchain::{FeeRate, HistoryEntry, MiningStatus, TimeHeight, Utxo};
+
+/// RPC error code returned by Bitcoin Core for unknown transactions
+const RPC_INVALID_ADDRESS_OR_KEY: i64 = -5;
+
+/// Returns default Bitcoin Core RPC port for the network
+pub fn default_port(network: Network) -> u16 {
+    match network {
+        Network::Bitcoin => 8332,
+        Network::Testnet => 18332,
+        Network::Signet => 38332,
+        Network::Regtest => 18443,
+    }
+}
+
+/// Authentication method for Bitcoin Core RPC
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Auth {
+    /// No authentication
+    None,
+
+    /// Authentication with the cookie file created by Bitcoin Core; the file
+    /// is re-read for each request, so node restarts are handled
+    Cookie(PathBuf),
+
+    /// Authentication with `rpcuser` and `rpcpassword`
+    UserPass(String, String),
+}
+
+/// Errors of Bitcoin Core RPC client
+#[derive(Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum Error {
+    /// I/O error during communication with Bitcoin Core: {0}
+    #[from(io::Error)]
+    Io(IoError),
+
+    /// invalid Bitcoin Core RPC URL `{0}`
+    InvalidUrl(String),
+
+    /// Bitcoin Core has responded with HTTP status {0}
+    Http(u16),
+
+    /// malformed HTTP response from Bitcoin Core
+    MalformedResponse,
+
+    /// invalid JSON data in Bitcoin Core response: {0}
+    #[from]
+    Json(serde_json::Error),
+
+    /// Bitcoin Core RPC error {code}: {message}
+    Rpc {
+        /// RPC error code
+        code: i64,
+
+        /// RPC error message
+        message: String,
+    },
+
+    /// unexpected data in Bitcoin Core response to `{0}` request
+    UnexpectedResponse(&'static str),
+
+    /// invalid transaction data returned by Bitcoin Core: {0}
+    #[from]
+    Consensus(bitcoin::consensus::encode::Error),
+
+    /// transaction history requires Bitcoin Core wallet watching the scripts;
+    /// please specify `/wallet/<name>` path in the RPC URL
+    NoWallet,
+}
+
+/// Bitcoin Core JSON-RPC client.
+///
+/// Each request is sent over a new HTTP connection.
+#[derive(Debug)]
+pub struct Client {
+    endpoint: Endpoint,
+    auth: Auth,
+    id: AtomicU64,
+}
+
+impl Client {
+    /// Constructs client for the node at `url` in form of
+    /// `[http://]host[:port][/wallet/<name>]`. If the port is not given, the
+    /// default RPC port for the `network` is used.
+    pub fn new(url: &str, network: Network, auth: Auth) -> Result<Client, Error> {
+        // Bitcoin Core RPC does not support TLS
+        let endpoint = Endpoint::parse(url, default_port(network))
+            .filter(|endpoint| !endpoint.tls)
+            .ok_or_else(|| Error::InvalidUrl(url.to_owned()))?;
+        Ok(Client {
+            endpoint,
+            auth,
+            id: AtomicU64::new(0),
+        })
+    }
+
+    /// Sets timeout for connecting to the node and for each read and write
+    /// operation (30 seconds by default)
+    #[inline]
+    pub fn with_timeout(mut self, timeout: Duration) -> Client {
+        self.endpoint.timeout = timeout;
+        self
+    }
+
+    /// Detects whether the client is connected to a node wallet
+    #[inline]
+    pub fn is_wallet(&self) -> bool { self.endpoint.path.starts_with("/wallet/") }
+
+    fn authorization(&self) -> Result<Option<String>, Error> {
+        let credentials = match &self.auth {
+            Auth::None => return Ok(None),
+            Auth::Cookie(path) => fs::read_to_string(path)?.trim().to_owned(),
+            Auth::UserPass(user, pass) => format!("{}:{}", user, pass),
+        };
+        Ok(Some(
+            base64::engine::general_purpose::STANDARD.encode(credentials),
+        ))
+    }
+
+    /// Calls RPC `method` with the given parameters, returning the result
+    pub fn call(&self, method: &str, params: &[Value]) -> Result<Value, Error> {
+        let id = self.id.fetch_add(1, Ordering::Relaxed);
+        let body = json!({
+            "jsonrpc": "1.0",
+            "id": id,
+            "method": method,
+            "params": params,
+        })
+        .to_string();
+
+        let authorization = self
+            .authorization()?
+            .map(|credentials| format!("Basic {}", credentials));
+        let mut headers = vec![("Content-Type", "application/json")];
+        if let Some(authorization) = &authorization {
+            headers.push(("Authorization", authorization));
+        }
+        let (status, body) = self
+            .endpoint
+            .request("POST", "", &headers, body.as_bytes())?;
+        let response: Value = match serde_json::from_slice(&body) {
+            Ok(response) => response,
+            Err(_) if status != 200 => return Err(Error::Http(status)),
+            Err(err) => return Err(err.into()),
+        };
+        match response.get("error") {
+            Some(Value::Null) | None => {}
+            Some(error) => {
+                return Err(Error::Rpc {
+                    code: error
+                        .get("code")
+                        .and_then(Value::as_i64)
+                        .unwrap_or_default(),
+                    message: error
+                        .get("message")
+                        .and_then(Value::as_str)
+                        .unwrap_or_default()
+                        .to_owned(),
+                })
+            }
+        }
+        if status != 200 {
+            return Err(Error::Http(status));
+        }
+        response
+            .get("result")
+            .cloned()
+            .ok_or(Error::MalformedResponse)
+    }
+
+    /// Retrieves transaction with `getrawtransaction`
+    pub fn get_raw_transaction(&self, txid: Txid) -> Result<Transaction, Error> {
+        let hex = self.call("getrawtransaction", &[json!(txid.to_hex()), json!(false)])?;
+        let data = hex
+            .as_str()
+            .and_then(|hex| Vec::<u8>::from_hex(hex).ok())
+            .ok_or(Error::UnexpectedResponse("getrawtransaction"))?;
+        Ok(deserialize(&data)?)
+    }
+
+    /// Retrieves transaction of the node wallet with `gettransaction`, which,
+    /// unlike `getrawtransaction`, does not require `-txindex`
+    pub fn get_wallet_transaction(&self, txid: Txid) -> Result<Transaction, Error> {
+        let result = self.call("gettransaction", &[json!(txid.to_hex()), json!(true)])?;
+        let data = result
+            .get("hex")
+            .and_then(Value::as_str)
+            .and_then(|hex| Vec::<u8>::from_hex(hex).ok())
+            .ok_or(Error::UnexpectedResponse("gettransaction"))?;
+        Ok(deserialize(&data)?)
+    }
+
+    /// Lists transactions of the node wallet, including watch-only ones, with
+    /// `listsinceblock`. Conflicted transactions are skipped.
+    pub fn list_wallet_transactions(&self) -> Result<Vec<HistoryEntry>, Error> {
+        let err = || Error::UnexpectedResponse("listsinceblock");
+        let result = self.call("listsinceblock", &[json!(""), json!(1), json!(true)])?;
+        let mut entries = BTreeMap::new();
+        for entry in result["transactions"].as_array().ok_or_else(err)? {
+            let txid = entry
+                .get("txid")
+                .and_then(Value::as_str)
+                .and_then(|txid| txid.parse::<Txid>().ok())
+                .ok_or_else(err)?;
+            let confirmations = entry
+                .get("confirmations")
+                .and_then(Value::as_i64)
+                .ok_or_else(err)?;
+            let (mined, time_height) = match confirmations {
+                confirmations if confirmations < 0 => continue,
+                0 => (MiningStatus::Mempool, None),
+                _ => {
+                    let height = entry
+                        .get("blockheight")
+                        .and_then(Value::as_u64)
+                        .ok_or_else(err)?;
+                    let time_height = entry
+                        .get("blocktime")
+                        .and_then(Value::as_i64)
+                        .and_then(|time| DateTime::from_timestamp(time, 0))
+                        .zip(
+                            entry
+                                .get("blockhash")
+                                .and_then(Value::as_str)
+                                .and_then(|hash| hash.parse().ok()),
+                        )
+                        .map(|(time, hash)| TimeHeight::new(time.naive_utc(), height as u32, hash));
+                    (MiningStatus::Blockchain(height), time_height)
+                }
+            };
+            // Transactions are listed once per each affected wallet address
+            entries.insert(txid, HistoryEntry::new(txid, mined, time_height));
+        }
+        Ok(entries.into_values().collect())
+    }
+
+    /// Publishes transaction with `sendrawtransaction`
+    pub fn send_raw_transaction(&self, tx: &Transaction) -> Result<Txid, Error> {
+        let txid = self.call("sendrawtransaction", &[json!(serialize(tx).to_hex())])?;
+        txid.as_str()
+            .and_then(|txid| txid.parse().ok())
+            .ok_or(Error::UnexpectedResponse("sendrawtransaction"))
+    }
+
+    /// Estimates fee rate for the confirmation within `target` blocks with
+    /// `estimatesmartfee`, returning `None` if there is not enough data for
+    /// the estimation
+    pub fn estimate_smart_fee(&self, target: u16) -> Result<Option<FeeRate>, Error> {
+        let estimate = self.call("estimatesmartfee", &[json!(target)])?;
+        // Fee rate is returned in BTC per kilo-vbyte
+        Ok(estimate
+            .get("feerate")
+            .and_then(Value::as_f64)
+            .and_then(FeeRate::from_btc_per_kvb))
+    }
+
+    /// Detects transaction mining status with `gettransaction` for the node
+    /// wallet transactions and with `getrawtransaction` for others
+    pub fn tx_status(&self, txid: Txid) -> Result<MiningStatus, Error> {
+        let wallet_tx = match self.is_wallet() {
+            true => self.call("gettransaction", &[json!(txid.to_hex()), json!(false)]),
+            false => Err(Error::NoWallet),
+        };
+        let info = match wallet_tx
+            .or_else(|_| self.call("getrawtransaction", &[json!(txid.to_hex()), json!(true)]))
+        {
+            Err(Error::Rpc { code, .. }) if code == RPC_INVALID_ADDRESS_OR_KEY => {
+                return Ok(MiningStatus::UnknownTx)
+            }
+            res => res?,
+        };
+        // Mempool transactions have no confirmations field in
+        // `getrawtransaction` response, while conflicted wallet transactions
+        // have negative number of confirmations
+        match info.get("confirmations").and_then(Value::as_i64) {
+            None | Some(0) => Ok(MiningStatus::Mempool),
+            Some(confirmations) if confirmations < 0 => Ok(MiningStatus::UnknownTx),
+            Some(confirmations) => Ok(MiningStatus::Blockchain(
+                self.get_block_count()? + 1 - confirmations as u64,
+            )),
+        }
+    }
+
+    /// Returns height of the most-work fully-validated chain
+    pub fn get_block_count(&self) -> Result<u64, Error> {
+        self.call("getblockcount", &[])?
+            .as_u64()
+            .ok_or(Error::UnexpectedResponse("getblockcount"))
+    }
+
+    /// Finds confirmed UTXOs for the given scripts with `scantxoutset`
+    pub fn scan_tx_out_set<'script>(
+        &self,
+        scripts: impl IntoIterator<Item = &'script Script>,
+    ) -> Result<Vec<(Script, Utxo)>, Error> {
+        let descriptors = scripts
+            .into_iter()
+            .map(|script| json!({ "desc": format!("raw({})", script.to_hex()) }))
+            .collect::<Vec<_>>();
+        let result = self.call("scantxoutset", &[json!("start"), Value::Array(descriptors)])?;
+        unspents(&result["unspents"], "scantxoutset", |entry| {
+            entry
+                .get("height")
+                .and_then(Value::as_u64)
+                .map(MiningStatus::Blockchain)
+        })
+    }
+
+    /// Lists UTXOs of the node wallet, including unconfirmed ones, with
+    /// `listunspent`
+    pub fn list_unspent(&self) -> Result<Vec<(Script, Utxo)>, Error> {
+        let tip = self.get_block_count()?;
+        let result = self.call("listunspent", &[
+            json!(0),
+            json!(9_999_999),
+            json!([]),
+            json!(true),
+        ])?;
+        unspents(&result, "listunspent", |entry| {
+            entry
+                .get("confirmations")
+                .and_then(Value::as_u64)
+                .map(|confirmations| match confirmations {
+                    0 => MiningStatus::Mempool,
+                    confirmations => MiningStatus::Blockchain(tip + 1 - confirmations),
+                })
+        })
+    }
+}
+
+fn unspents(
+    list: &Value,
+    method: &'static str,
+    mined: impl Fn(&Value) -> Option<MiningStatus>,
+) -> Result<Vec<(Script, Utxo)>, Error> {
+    list.as_array()
+        .ok_or(Error::UnexpectedResponse(method))?
+        .iter()
+        .map(|entry| {
+            let txid = entry
+                .get("txid")
+                .and_then(Value::as_str)
+                .and_then(|txid| txid.parse().ok());
+            let vout = entry.get("vout").and_then(Value::as_u64);
+            let script = entry
+                .get("scriptPubKey")
+                .and_then(Value::as_str)
+                .and_then(|hex| Vec::<u8>::from_hex(hex).ok());
+            let amount = entry
+                .get("amount")
+                .and_then(Value::as_f64)
+                .and_then(|btc| Amount::from_btc(btc).ok());
+            match (txid, vout, script, amount, mined(entry)) {
+                (Some(txid), Some(vout), Some(script), Some(amount), Some(mined)) => {
+                    let outpoint = OutPoint::new(txid, vout as u32);
+                    let script = Script::from(script);
+                    // Coinbase flag is provided only by `scantxoutset`
+                    let coinbase = entry
+                        .get("coinbase")
+                        .and_then(Value::as_bool)
+                        .unwrap_or_default();
+                    let utxo = Utxo::new(mined, outpoint, amount)
+                        .with_script_pubkey(script.clone())
+                        .with_coinbase(coinbase);
+                    Ok((script, utxo))
+                }
+                _ => Err(Error::UnexpectedResponse(method)),
+            }
+        })
+        .collect()
+}
+
+impl ResolveTx for Client {
+    fn resolve_tx(&self, txid: Txid) -> Result<Transaction, TxResolverError> {
+        self.get_raw_transaction(txid)
+            .map_err(|err| TxResolverError {
+                txid,
+                err: Some(Box::new(err)),
+            })
+    }
+}
+
+impl ResolveTxFee for Client {
+    fn resolve_tx_fee(&self, txid: Txid) -> Result<Option<(Transaction, u64)>, TxResolverError> {
+        let tx = match self.get_raw_transaction(txid) {
+            Ok(tx) => tx,
+            Err(Error::Rpc { code, .. }) if code == RPC_INVALID_ADDRESS_OR_KEY => return Ok(None),
+            Err(err) => {
+                return Err(TxResolverError {
+                    txid,
+                    err: Some(Box::new(err)),
+                })
+            }
+        };
+
+        let mut input_amount = 0u64;
+        for txin in &tx.input {
+            let prev_tx = self.resolve_tx(txin.previous_output.txid)?;
+            input_amount += prev_tx
+                .output
+                .get(txin.previous_output.vout as usize)
+                .ok_or_else(|| TxResolverError::with(txid))?
+                .value;
+        }
+        let output_amount = tx.output.iter().fold(0, |sum, o| sum + o.value);
+        let fee = input_amount
+            .checked_sub(output_amount)
+            .ok_or_else(|| TxResolverError::with(txid))?;
+
+        Ok(Some((tx, fee)))
+    }
+}
+
+impl ResolveFeeRate for Client {
+    fn resolve_fee_rate(&self, target: u16) -> Result<Option<FeeRate>, BackendError> {
+        Ok(self.estimate_smart_fee(target)?)
+    }
+}
+
+impl Broadcast for Client {
+    fn broadcast(&self, tx: &Transaction) -> Result<Txid, BackendError> {
+        Ok(self.send_raw_transaction(tx)?)
+    }
+}
+
+impl ResolveChainState for Client {
+    fn resolve_height(&self) -> Result<u32, BackendError> { Ok(self.get_block_count()? as u32) }
+
+    fn resolve_tx_status(&self, txid: Txid) -> Result<MiningStatus, TxResolverError> {
+        self.tx_status(txid).map_err(|err| TxResolverError {
+            txid,
+            err: Some(Box::new(err)),
+        })
+    }
+}
+
+impl ResolveHistory for Client {
+    fn resolve_history<'script>(
+        &self,
+        scripts: impl IntoIterator<Item = &'script Script> + Clone,
+    ) -> Result<Vec<HashSet<HistoryEntry>>, UtxoResolverError> {
+        if !self.is_wallet() {
+            return Err(Error::NoWallet.into());
+        }
+        let entries = self.list_wallet_transactions()?;
+        let txs = entries
+            .iter()
+            .map(|entry| Ok((*entry.txid(), self.get_wallet_transaction(*entry.txid())?)))
+            .collect::<Result<BTreeMap<_, _>, Error>>()?;
+
+        // Wallet outputs are created by wallet transactions, so the scripts of
+        // all spent wallet outputs are known
+        let mut history = BTreeMap::<&Script, HashSet<HistoryEntry>>::new();
+        for entry in &entries {
+            let tx = &txs[entry.txid()];
+            let spent = tx.input.iter().filter_map(|txin| {
+                txs.get(&txin.previous_output.txid)
+                    .and_then(|prev_tx| prev_tx.output.get(txin.previous_output.vout as usize))
+            });
+            for txout in tx.output.iter().chain(spent) {
+                history
+                    .entry(&txout.script_pubkey)
+                    .or_default()
+                    .insert(entry.clone());
+            }
+        }
+        Ok(scripts
+            .into_iter()
+            .map(|script| history.get(script).cloned().unwrap_or_default())
+            .collect())
+    }
+}
+
+impl ResolveUtxo for Client {
+    fn resolve_utxo<'script>(
+        &self,
+        scripts: impl IntoIterator<Item = &'script Script> + Clone,
+    ) -> Result<Vec<HashSet<Utxo>>, UtxoResolverError> {
+        let unspents = if self.is_wallet() {
+            self.list_unspent()?
+        } else {
+            self.scan_tx_out_set(scripts.clone())?
+        };
+        let mut utxo_map = BTreeMap::<Script, HashSet<Utxo>>::new();
+        for (script, utxo) in unspents {
+            utxo_map.entry(script).or_default().insert(utxo);
+        }
+        Ok(scripts
+            .into_iter()
+            .map(|script| utxo_map.get(script).cloned().unwrap_or_default())
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    use bitcoin::hashes::Hash;
+
+    use super::*;
+
+    /// Mock JSON-RPC server answering requests with the responses from the
+    /// `handler`, returning the authorization headers it has received.
+    fn mock_server(
+        requests: usize,
+        handler: impl Fn(&str, &Value) -> Value + Send + 'static,
+    ) -> (String, thread::JoinHandle<Vec<Option<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let handle = thread::spawn(move || {
+            let mut auth = vec![];
+            for stream in listener.incoming().take(requests) {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                let mut content_length = 0;
+                let mut authorization = None;
+                reader.read_line(&mut line).unwrap();
+                loop {
+                    line.clear();
+                    reader.read_line(&mut line).unwrap();
+                    let header = line.trim_end();
+                    if header.is_empty() {
+                        break;
+                    }
+                    let (name, value) = header.split_once(": ").unwrap();
+                    match name {
+                        "Content-Length" => content_length = value.parse().unwrap(),
+                        "Authorization" => authorization = Some(value.to_owned()),
+                        _ => {}
+                    }
+                }
+                auth.push(authorization);
+                let mut body = vec![0u8; content_length];
+                reader.read_exact(&mut body).unwrap();
+                let request: Value = serde_json::from_slice(&body).unwrap();
+                let method = request["method"].as_str().unwrap();
+                let response = match handler(method, &request["params"]) {
+                    Value::Object(error) if error.contains_key("code") => {
+                        json!({ "result": null, "error": error, "id": request["id"] })
+                    }
+                    result => json!({ "result": result, "error": null, "id": request["id"] }),
+                }
+                .to_string();
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: \
+                     {}\r\n\r\n{}",
+                    response.len(),
+                    response
+                )
+                .unwrap();
+            }
+            auth
+        });
+        (addr, handle)
+    }
+
+    fn tx() -> Transaction {
+        Transaction {
+            version: 2,
+            lock_time: bitcoin::PackedLockTime::ZERO,
+            input: vec![default!()],
+            output: vec![bitcoin::TxOut {
+                value: 10_000,
+                script_pubkey: Script::from(vec![0x51]),
+            }],
+        }
+    }
+
+    #[test]
+    fn rpc_requests() {
+        let tx = tx();
+        let txid = tx.txid();
+        let tx_hex = serialize(&tx).to_hex();
+        let (addr, server) = mock_server(4, move |method, params| match method {
+            "getrawtransaction" if params[0] == json!(txid.to_hex()) => json!(tx_hex),
+            "getrawtransaction" => json!({ "code": -5, "message": "No such transaction" }),
+            "scantxoutset" => {
+                assert_eq!(params[1][0]["desc"], "raw(51)");
+                json!({
+                    "success": true,
+                    "unspents": [{
+                        "txid": txid.to_hex(),
+                        "vout": 0,
+                        "scriptPubKey": "51",
+                        "desc": "raw(51)#xxxxxxxx",
+                        "amount": 0.0001,
+                        "height": 120
+                    }],
+                    "total_amount": 0.0001
+                })
+            }
+            "sendrawtransaction" => json!(txid.to_hex()),
+            _ => unreachable!(),
+        });
+
+        let client = Client::new(
+            &format!("http://{}", addr),
+            Network::Regtest,
+            Auth::UserPass(s!("user"), s!("pass")),
+        )
+        .unwrap();
+        assert!(!client.is_wallet());
+        assert_eq!(client.resolve_tx(txid).unwrap(), tx);
+        assert_eq!(
+            client.resolve_tx_fee(Txid::from_inner([1u8; 32])).unwrap(),
+            None
+        );
+
+        let script = Script::from(vec![0x51]);
+        let utxo = client.resolve_utxo([&script]).unwrap();
+        assert_eq!(utxo.len(), 1);
+        let utxo = utxo[0].iter().next().unwrap();
+        assert_eq!(utxo.amount().to_sat(), 10_000);
+        assert_eq!(*utxo.mined(), MiningStatus::Blockchain(120));
+        assert_eq!(*utxo.outpoint(), OutPoint::new(txid, 0));
+
+        assert_eq!(client.send_raw_transaction(&tx).unwrap(), txid);
+
+        let auth = server.join().unwrap();
+        assert_eq!(auth, vec![Some(s!("Basic dXNlcjpwYXNz")); 4]);
+    }
+
+    #[test]
+    fn wallet_history() {
+        let tx1 = tx();
+        let tx2 = Transaction {
+            input: vec![bitcoin::TxIn {
+                previous_output: OutPoint::new(tx1.txid(), 0),
+                ..default!()
+            }],
+            output: vec![bitcoin::TxOut {
+                value: 9_000,
+                script_pubkey: Script::from(vec![0x52]),
+            }],
+            ..tx1.clone()
+        };
+        let (txid1, txid2) = (tx1.txid(), tx2.txid());
+        let block_hash = bitcoin::BlockHash::from_inner([7u8; 32]);
+        let txs = bmap! { txid1 => tx1, txid2 => tx2 };
+        let (addr, server) = mock_server(3, move |method, params| match method {
+            "listsinceblock" => json!({
+                "transactions": [
+                    {
+                        "txid": txid1.to_hex(),
+                        "category": "receive",
+                        "confirmations": 3,
+                        "blockheight": 100,
+                        "blocktime": 1_600_000_000,
+                        "blockhash": block_hash.to_hex()
+                    },
+                    { "txid": txid2.to_hex(), "category": "send", "confirmations": 0 },
+                    { "txid": txid2.to_hex(), "category": "receive", "confirmations": 0 },
+                    {
+                        "txid": Txid::from_inner([1u8; 32]).to_hex(),
+                        "category": "receive",
+                        "confirmations": -1
+                    }
+                ],
+                "lastblock": block_hash.to_hex()
+            }),
+            "gettransaction" => {
+                let txid = params[0].as_str().unwrap().parse::<Txid>().unwrap();
+                json!({ "txid": txid.to_hex(), "hex": serialize(&txs[&txid]).to_hex() })
+            }
+            _ => unreachable!(),
+        });
+
+        let client = Client::new("localhost", Network::Regtest, Auth::None).unwrap();
+        assert!(matches!(
+            client.resolve_history([&Script::new()]),
+            Err(UtxoResolverError::BitcoinCore(Error::NoWallet))
+        ));
+
+        let client = Client::new(
+            &format!("http://{}/wallet/cold", addr),
+            Network::Regtest,
+            Auth::None,
+        )
+        .unwrap();
+        let (script1, script2) = (Script::from(vec![0x51]), Script::from(vec![0x52]));
+        let history = client
+            .resolve_history([&script1, &script2, &Script::new()])
+            .unwrap();
+        server.join().unwrap();
+
+        let confirmed = HistoryEntry::new(
+            txid1,
+            MiningStatus::Blockchain(100),
+            Some(TimeHeight::new(
+                DateTime::from_timestamp(1_600_000_000, 0)
+                    .unwrap()
+                    .naive_utc(),
+                100,
+                block_hash,
+            )),
+        );
+        let unconfirmed = HistoryEntry::new(txid2, MiningStatus::Mempool, None);
+        assert_eq!(history, vec![
+            set![confirmed, unconfirmed.clone()],
+            set![unconfirmed],
+            set![]
+        ]);
+    }
+
+    #[test]
+    fn url_parsing() {
+        let client = Client::new("localhost", Network::Bitcoin, Auth::None).unwrap();
+        assert_eq!(client.endpoint.host, "localhost");
+        assert_eq!(client.endpoint.port, 8332);
+        assert!(!client.is_wallet());
+        let client = Client::new(
+            "http://127.0.0.1:18443/wallet/cold",
+            Network::Regtest,
+            Auth::None,
+        )
+        .unwrap();
+        assert_eq!(client.endpoint.port, 18443);
+        assert!(client.is_wallet());
+        assert!(Client::new("https://localhost", Network::Bitcoin, Auth::None).is_err());
+        assert!(Client::new("localhost:port", Network::Bitcoin, Auth::None).is_err());
+    }
+}
@@ -9,13 +9,17 @@
 // along with this software.
 // If not, see <https://opensource.org/licenses/Apache-2.0>.
 
-use std::collections::HashSet;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
 
 use bitcoin::{Script, Transaction, Txid};
+use chrono::DateTime;
 use electrum_client::{Client, ElectrumApi};
 
-use super::{ResolveTx, ResolveTxFee, ResolveUtxo, TxResolverError, UtxoResolverError};
-use crate::blockchain::Utxo;
+use super::{
+    BackendError, Broadcast, ResolveChainState, ResolveFeeRate, ResolveHistory, ResolveTx,
+    ResolveTxFee, ResolveUtxo, TxResolverError, UtxoResolverError,
+};
+use crate::blockchain::{FeeRate, HistoryEntry, MiningStatus, TimeHeight, Utxo};
 
 impl ResolveTx for Client {
     fn resolve_tx(&self, txid: Txid) -> Result<Transaction, TxResolverError> {
@@ -26,6 +30,40 @@ impl ResolveTx for Client {
     }
 }
 
+impl ResolveChainState for Client {
+    fn resolve_height(&self) -> Result<u32, BackendError> {
+        Ok(self.block_headers_subscribe()?.height as u32)
+    }
+
+    fn resolve_tx_status(&self, txid: Txid) -> Result<MiningStatus, TxResolverError> {
+        // Electrum protocol provides mining status only as a part of script
+        // history, so we query history of one of the transaction outputs
+        let err = |err: electrum_client::Error| TxResolverError {
+            txid,
+            err: Some(Box::new(err)),
+        };
+        let tx = self.transaction_get(&txid).map_err(err)?;
+        let script = match tx
+            .output
+            .iter()
+            .find(|txout| !txout.script_pubkey.is_provably_unspendable())
+        {
+            Some(txout) => &txout.script_pubkey,
+            None => return Ok(MiningStatus::Undefined),
+        };
+        Ok(self
+            .script_get_history(script)
+            .map_err(err)?
+            .into_iter()
+            .find(|res| res.tx_hash == txid)
+            .map(|res| match res.height {
+                height if height > 0 => MiningStatus::Blockchain(height as u64),
+                _ => MiningStatus::Mempool,
+            })
+            .unwrap_or(MiningStatus::UnknownTx))
+    }
+}
+
 impl ResolveTxFee for Client {
     fn resolve_tx_fee(&self, txid: Txid) -> Result<Option<(Transaction, u64)>, TxResolverError> {
         let tx = self.resolve_tx(txid)?;
@@ -57,10 +95,98 @@ impl ResolveUtxo for Client {
         &self,
         scripts: impl IntoIterator<Item = &'script Script> + Clone,
     ) -> Result<Vec<HashSet<Utxo>>, UtxoResolverError> {
-        Ok(self
-            .batch_script_list_unspent(scripts)?
+        let unspents = self.batch_script_list_unspent(scripts.clone())?;
+        let blocks = self.blocks(unspents.iter().flatten().map(|res| res.height as i64))?;
+        Ok(unspents
+            .into_iter()
+            .zip(scripts)
+            .map(|(list, script)| {
+                list.into_iter()
+                    .map(|res| {
+                        let time_height = blocks.get(&(res.height as u32)).cloned();
+                        let utxo = Utxo::from(res).with_script_pubkey(script.clone());
+                        match time_height {
+                            Some(time_height) => utxo.with_time_height(time_height),
+                            None => utxo,
+                        }
+                    })
+                    .collect()
+            })
+            .collect())
+    }
+}
+
+trait BlockInfo {
+    /// Retrieves information about blocks at the given heights, ignoring zero
+    /// and negative heights used for mempool transactions
+    fn blocks(
+        &self,
+        heights: impl IntoIterator<Item = i64>,
+    ) -> Result<BTreeMap<u32, TimeHeight>, electrum_client::Error>;
+}
+
+impl BlockInfo for Client {
+    fn blocks(
+        &self,
+        heights: impl IntoIterator<Item = i64>,
+    ) -> Result<BTreeMap<u32, TimeHeight>, electrum_client::Error> {
+        let heights = heights
+            .into_iter()
+            .filter(|height| *height > 0)
+            .map(|height| height as u32)
+            .collect::<BTreeSet<_>>();
+        let headers = self.batch_block_header(heights.iter())?;
+        Ok(heights
+            .into_iter()
+            .zip(headers)
+            .filter_map(|(height, header)| {
+                let timestamp = DateTime::from_timestamp(header.time as i64, 0)?.naive_utc();
+                Some((
+                    height,
+                    TimeHeight::new(timestamp, height, header.block_hash()),
+                ))
+            })
+            .collect())
+    }
+}
+
+impl ResolveHistory for Client {
+    fn resolve_history<'script>(
+        &self,
+        scripts: impl IntoIterator<Item = &'script Script> + Clone,
+    ) -> Result<Vec<HashSet<HistoryEntry>>, UtxoResolverError> {
+        let history = self.batch_script_get_history(scripts)?;
+        let blocks = self.blocks(history.iter().flatten().map(|res| res.height as i64))?;
+
+        Ok(history
             .into_iter()
-            .map(|res| res.into_iter().map(Utxo::from).collect())
+            .map(|list| {
+                list.into_iter()
+                    .map(|res| match res.height {
+                        height if height > 0 => HistoryEntry::new(
+                            res.tx_hash,
+                            MiningStatus::Blockchain(height as u64),
+                            blocks.get(&(height as u32)).cloned(),
+                        ),
+                        _ => HistoryEntry::new(res.tx_hash, MiningStatus::Mempool, None),
+                    })
+                    .collect()
+            })
             .collect())
     }
 }
+
+impl ResolveFeeRate for Client {
+    fn resolve_fee_rate(&self, target: u16) -> Result<Option<FeeRate>, BackendError> {
+        // Electrum servers return fee rate in BTC per kilobyte, or -1 if the
+        // estimate is not available
+        let btc_per_kvb = self.estimate_fee(target as usize)?;
+        Ok(FeeRate::from_btc_per_kvb(btc_per_kvb))
+    }
+}
+
+impl Broadcast for Client {
+    fn broadcast(&self, tx: &Transaction) -> Result<Txid, BackendError> {
+        Ok(self.transaction_broadcast(tx)?)
+    }
+}
@@ -0,0 +1,647 @@
+// Wallet-level libraries for bitcoin protocol by LNP/BP Association
+//
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// This software is distributed without any warranty.
+//
+// You should have received a copy of the Apache-2.0 License
+// along with this software.
+// If not, see <https://opensource.org/licenses/Apache-2.0>.
+
+//! Esplora REST API client implementing resolver traits.
+//!
+//! UTXOs are looked up per script hash; queries for multiple scripts are sent
+//! in parallel batches of a configurable size.
+
+use std::collections::{BTreeMap, HashSet};
+use std::time::Duration;
+use std::{io, thread};
+
+use amplify::IoError;
+use bitcoin::consensus::{deserialize, serialize};
+use bitcoin::hashes::hex::ToHex;
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::{Amount, BlockHash, OutPoint, Script, Transaction, Txid};
+use chrono::DateTime;
+use serde_json::Value;
+
+use super::http::Endpoint;
+use super::{
+    BackendError, Broadcast, ResolveChainState, ResolveFeeRate, ResolveHistory, ResolveTx,
+    ResolveTxFee, ResolveUtxo, TxResolverError, UtxoResolverError,
+};
+use crate::blockchain::{FeeRate, HistoryEntry, MiningStatus, TimeHeight, Utxo};
+
+/// Default number of script hash queries sent in parallel
+pub const DEFAULT_BATCH_SIZE: usize = 10;
+
+/// Number of confirmed transactions returned by esplora per history page
+const CHAIN_PAGE_SIZE: usize = 25;
+
+/// Errors of Esplora client
+#[derive(Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum Error {
+    /// I/O error during communication with esplora server: {0}
+    #[from(io::Error)]
+    Io(IoError),
+
+    /// invalid esplora server URL `{0}`
+    InvalidUrl(String),
+
+    /// esplora server has responded with HTTP status {status}: {message}
+    Http {
+        /// HTTP status code
+        status: u16,
+
+        /// Response body
+        message: String,
+    },
+
+    /// invalid JSON data in esplora server response: {0}
+    #[from]
+    Json(serde_json::Error),
+
+    /// unexpected data in esplora server response to `{0}` request
+    UnexpectedResponse(&'static str),
+
+    /// invalid transaction data returned by esplora server: {0}
+    #[from]
+    Consensus(bitcoin::consensus::encode::Error),
+}
+
+/// Esplora REST API client.
+///
+/// Each request is sent over a new HTTP connection.
+#[derive(Clone, Debug)]
+pub struct Client {
+    endpoint: Endpoint,
+    batch_size: usize,
+}
+
+impl Client {
+    /// Constructs client for the esplora server at `url` in form of
+    /// `http[s]://host[:port][/path]`, for instance
+    /// `https://blockstream.info/api`.
+    pub fn new(url: &str) -> Result<Client, Error> {
+        let endpoint = Endpoint::parse(url, 80).ok_or_else(|| Error::InvalidUrl(url.to_owned()))?;
+        Ok(Client {
+            endpoint,
+            batch_size: DEFAULT_BATCH_SIZE,
+        })
+    }
+
+    /// Sets number of script hash queries sent in parallel
+    #[inline]
+    pub fn with_batch_size(mut self, batch_size: usize) -> Client {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Sets timeout for connecting to the server and for each read and write
+    /// operation (30 seconds by default)
+    #[inline]
+    pub fn with_timeout(mut self, timeout: Duration) -> Client {
+        self.endpoint.timeout = timeout;
+        self
+    }
+
+    fn request(&self, method: &str, path: &str, body: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        let (status, body) = self.endpoint.request(method, path, &[], body)?;
+        match status {
+            200 => Ok(Some(body)),
+            404 => Ok(None),
+            status => Err(Error::Http {
+                status,
+                message: String::from_utf8_lossy(&body).trim().to_owned(),
+            }),
+        }
+    }
+
+    fn get_json(&self, path: &str) -> Result<Option<Value>, Error> {
+        self.request("GET", path, &[])?
+            .map(|body| serde_json::from_slice(&body))
+            .transpose()
+            .map_err(Error::from)
+    }
+
+    /// Retrieves transaction, returning `None` if it is not known to the
+    /// server
+    pub fn tx(&self, txid: Txid) -> Result<Option<Transaction>, Error> {
+        self.request("GET", &format!("/tx/{}/raw", txid), &[])?
+            .map(|data| deserialize(&data))
+            .transpose()
+            .map_err(Error::from)
+    }
+
+    /// Retrieves fee paid by the transaction, returning `None` if the
+    /// transaction is not known to the server
+    pub fn tx_fee(&self, txid: Txid) -> Result<Option<u64>, Error> {
+        self.get_json(&format!("/tx/{}", txid))?
+            .map(|info| {
+                info.get("fee")
+                    .and_then(Value::as_u64)
+                    .ok_or(Error::UnexpectedResponse("tx"))
+            })
+            .transpose()
+    }
+
+    /// Retrieves transaction mining status together with the information
+    /// about the block containing the transaction, if it is mined
+    pub fn tx_status(&self, txid: Txid) -> Result<(MiningStatus, Option<TimeHeight>), Error> {
+        match self.get_json(&format!("/tx/{}/status", txid))? {
+            None => Ok((MiningStatus::UnknownTx, None)),
+            Some(status) => mining_status(&status).ok_or(Error::UnexpectedResponse("tx/status")),
+        }
+    }
+
+    /// Retrieves height of the current chain tip
+    pub fn tip_height(&self) -> Result<u32, Error> {
+        let body = self
+            .request("GET", "/blocks/tip/height", &[])?
+            .ok_or(Error::UnexpectedResponse("blocks/tip/height"))?;
+        String::from_utf8_lossy(&body)
+            .trim()
+            .parse()
+            .map_err(|_| Error::UnexpectedResponse("blocks/tip/height"))
+    }
+
+    /// Lists UTXOs for the given script, including unconfirmed ones
+    pub fn script_utxo(&self, script: &Script) -> Result<HashSet<Utxo>, Error> {
+        let script_hash = sha256::Hash::hash(script.as_bytes());
+        let list = self
+            .get_json(&format!("/scripthash/{}/utxo", script_hash.to_hex()))?
+            .unwrap_or_default();
+        list.as_array()
+            .ok_or(Error::UnexpectedResponse("scripthash/utxo"))?
+            .iter()
+            .map(|entry| {
+                let txid = entry
+                    .get("txid")
+                    .and_then(Value::as_str)
+                    .and_then(|txid| txid.parse().ok());
+                let vout = entry.get("vout").and_then(Value::as_u64);
+                let value = entry.get("value").and_then(Value::as_u64);
+                let status = entry.get("status").and_then(mining_status);
+                match (txid, vout, value, status) {
+                    (Some(txid), Some(vout), Some(value), Some((mined, time_height))) => {
+                        let utxo = Utxo::new(
+                            mined,
+                            OutPoint::new(txid, vout as u32),
+                            Amount::from_sat(value),
+                        )
+                        .with_script_pubkey(script.clone());
+                        Ok(match time_height {
+                            Some(time_height) => utxo.with_time_height(time_height),
+                            None => utxo,
+                        })
+                    }
+                    _ => Err(Error::UnexpectedResponse("scripthash/utxo")),
+                }
+            })
+            .collect()
+    }
+
+    /// Lists transactions spending from or paying to the given script,
+    /// including unconfirmed ones
+    pub fn script_history(&self, script: &Script) -> Result<HashSet<HistoryEntry>, Error> {
+        let script_hash = sha256::Hash::hash(script.as_bytes()).to_hex();
+        let mut history = HashSet::new();
+        let mut path = format!("/scripthash/{}/txs", script_hash);
+        loop {
+            let page = self.get_json(&path)?.unwrap_or_default();
+            let mut confirmed = vec![];
+            for entry in page
+                .as_array()
+                .ok_or(Error::UnexpectedResponse("scripthash/txs"))?
+            {
+                let txid = entry
+                    .get("txid")
+                    .and_then(Value::as_str)
+                    .and_then(|txid| txid.parse::<Txid>().ok());
+                let status = entry.get("status").and_then(mining_status);
+                let (txid, (mined, time_height)) = txid
+                    .zip(status)
+                    .ok_or(Error::UnexpectedResponse("scripthash/txs"))?;
+                if time_height.is_some() {
+                    confirmed.push(txid);
+                }
+                history.insert(HistoryEntry::new(txid, mined, time_height));
+            }
+            // Confirmed transactions are paginated, newest first
+            match confirmed.last() {
+                Some(last) if confirmed.len() >= CHAIN_PAGE_SIZE => {
+                    path = format!("/scripthash/{}/txs/chain/{}", script_hash, last)
+                }
+                _ => break,
+            }
+        }
+        Ok(history)
+    }
+
+    /// Retrieves fee rate estimates for the confirmation targets supported by
+    /// the server
+    pub fn fee_estimates(&self) -> Result<BTreeMap<u16, FeeRate>, Error> {
+        let estimates = self
+            .get_json("/fee-estimates")?
+            .ok_or(Error::UnexpectedResponse("fee-estimates"))?;
+        estimates
+            .as_object()
+            .ok_or(Error::UnexpectedResponse("fee-estimates"))?
+            .iter()
+            .map(|(target, rate)| {
+                match (
+                    target.parse::<u16>(),
+                    rate.as_f64().and_then(FeeRate::from_sat_per_vb_f64),
+                ) {
+                    (Ok(target), Some(rate)) => Ok((target, rate)),
+                    _ => Err(Error::UnexpectedResponse("fee-estimates")),
+                }
+            })
+            .collect()
+    }
+
+    /// Publishes transaction
+    pub fn broadcast(&self, tx: &Transaction) -> Result<Txid, Error> {
+        let body = self
+            .request("POST", "/tx", serialize(tx).to_hex().as_bytes())?
+            .ok_or(Error::UnexpectedResponse("tx"))?;
+        String::from_utf8_lossy(&body)
+            .trim()
+            .parse()
+            .map_err(|_| Error::UnexpectedResponse("tx"))
+    }
+}
+
+fn mining_status(status: &Value) -> Option<(MiningStatus, Option<TimeHeight>)> {
+    if !status.get("confirmed")?.as_bool()? {
+        return Some((MiningStatus::Mempool, None));
+    }
+    let height = status.get("block_height")?.as_u64()?;
+    let block_hash = status
+        .get("block_hash")?
+        .as_str()?
+        .parse::<BlockHash>()
+        .ok()?;
+    let timestamp = DateTime::from_timestamp(status.get("block_time")?.as_i64()?, 0)?;
+    Some((
+        MiningStatus::Blockchain(height),
+        Some(TimeHeight::new(
+            timestamp.naive_utc(),
+            height as u32,
+            block_hash,
+        )),
+    ))
+}
+
+impl Client {
+    /// Runs `query` for each of the scripts, sending requests in parallel
+    /// batches
+    fn batch_query<'script, T: Send>(
+        &self,
+        scripts: impl IntoIterator<Item = &'script Script>,
+        query: impl Fn(&Client, &Script) -> Result<T, Error> + Sync,
+    ) -> Result<Vec<T>, Error> {
+        let scripts = scripts.into_iter().collect::<Vec<_>>();
+        let mut results = Vec::with_capacity(scripts.len());
+        for batch in scripts.chunks(self.batch_size) {
+            let batch_results = thread::scope(|scope| {
+                batch
+                    .iter()
+                    .map(|script| scope.spawn(|| query(self, script)))
+                    .collect::<Vec<_>>()
+                    .into_iter()
+                    .map(|handle| handle.join().expect("esplora request thread panicked"))
+                    .collect::<Vec<_>>()
+            });
+            for result in batch_results {
+                results.push(result?);
+            }
+        }
+        Ok(results)
+    }
+}
+
+impl ResolveTx for Client {
+    fn resolve_tx(&self, txid: Txid) -> Result<Transaction, TxResolverError> {
+        match self.tx(txid) {
+            Ok(Some(tx)) => Ok(tx),
+            Ok(None) => Err(TxResolverError::with(txid)),
+            Err(err) => Err(TxResolverError {
+                txid,
+                err: Some(Box::new(err)),
+            }),
+        }
+    }
+}
+
+impl ResolveTxFee for Client {
+    fn resolve_tx_fee(&self, txid: Txid) -> Result<Option<(Transaction, u64)>, TxResolverError> {
+        let map_err = |err: Error| TxResolverError {
+            txid,
+            err: Some(Box::new(err)),
+        };
+        let tx = match self.tx(txid).map_err(map_err)? {
+            Some(tx) => tx,
+            None => return Ok(None),
+        };
+        let fee = self
+            .tx_fee(txid)
+            .map_err(map_err)?
+            .ok_or_else(|| TxResolverError::with(txid))?;
+        Ok(Some((tx, fee)))
+    }
+}
+
+impl ResolveFeeRate for Client {
+    fn resolve_fee_rate(&self, target: u16) -> Result<Option<FeeRate>, BackendError> {
+        // Uses estimate for the largest supported target not exceeding the
+        // requested one
+        Ok(self
+            .fee_estimates()?
+            .range(..=target)
+            .next_back()
+            .map(|(_, rate)| *rate))
+    }
+}
+
+impl Broadcast for Client {
+    fn broadcast(&self, tx: &Transaction) -> Result<Txid, BackendError> {
+        Ok(Client::broadcast(self, tx)?)
+    }
+}
+
+impl ResolveUtxo for Client {
+    fn resolve_utxo<'script>(
+        &self,
+        scripts: impl IntoIterator<Item = &'script Script> + Clone,
+    ) -> Result<Vec<HashSet<Utxo>>, UtxoResolverError> {
+        Ok(self.batch_query(scripts, Client::script_utxo)?)
+    }
+}
+
+impl ResolveChainState for Client {
+    fn resolve_height(&self) -> Result<u32, BackendError> { Ok(self.tip_height()?) }
+
+    fn resolve_tx_status(&self, txid: Txid) -> Result<MiningStatus, TxResolverError> {
+        self.tx_status(txid)
+            .map(|(mined, _)| mined)
+            .map_err(|err| TxResolverError {
+                txid,
+                err: Some(Box::new(err)),
+            })
+    }
+}
+
+impl ResolveHistory for Client {
+    fn resolve_history<'script>(
+        &self,
+        scripts: impl IntoIterator<Item = &'script Script> + Clone,
+    ) -> Result<Vec<HashSet<HistoryEntry>>, UtxoResolverError> {
+        Ok(self.batch_query(scripts, Client::script_history)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+
+    use serde_json::json;
+
+    use super::*;
+
+    /// Local HTTP stub answering requests with the status and body returned
+    /// by the `handler` for the request method, path and body.
+    fn stub_server(
+        requests: usize,
+        handler: impl Fn(&str, &str, &[u8]) -> (u16, Vec<u8>) + Send + 'static,
+    ) -> (String, thread::JoinHandle<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/api/", listener.local_addr().unwrap());
+        let handle = thread::spawn(move || {
+            for stream in listener.incoming().take(requests) {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let mut request = line.split_whitespace();
+                let method = request.next().unwrap().to_owned();
+                let path = request.next().unwrap().to_owned();
+                let mut content_length = 0;
+                loop {
+                    line.clear();
+                    reader.read_line(&mut line).unwrap();
+                    let header = line.trim_end();
+                    if header.is_empty() {
+                        break;
+                    }
+                    if let Some(len) = header.strip_prefix("Content-Length: ") {
+                        content_length = len.parse().unwrap();
+                    }
+                }
+                let mut body = vec![0u8; content_length];
+                reader.read_exact(&mut body).unwrap();
+                let path = path.strip_prefix("/api").unwrap();
+                let (status, response) = handler(&method, path, &body);
+                write!(
+                    stream,
+                    "HTTP/1.1 {} Stub\r\nContent-Length: {}\r\n\r\n",
+                    status,
+                    response.len()
+                )
+                .unwrap();
+                stream.write_all(&response).unwrap();
+            }
+        });
+        (url, handle)
+    }
+
+    fn tx() -> Transaction {
+        Transaction {
+            version: 2,
+            lock_time: bitcoin::PackedLockTime::ZERO,
+            input: vec![default!()],
+            output: vec![bitcoin::TxOut {
+                value: 10_000,
+                script_pubkey: Script::from(vec![0x51]),
+            }],
+        }
+    }
+
+    #[test]
+    fn rest_requests() {
+        let tx = tx();
+        let txid = tx.txid();
+        let tx_data = serialize(&tx);
+        let tx_hex = tx_data.to_hex();
+        let block_hash = BlockHash::from_inner([7u8; 32]);
+        let status = json!({
+            "confirmed": true,
+            "block_height": 120,
+            "block_hash": block_hash.to_hex(),
+            "block_time": 1_600_000_000
+        });
+        let scripts = (0u8..3)
+            .map(|n| Script::from(vec![0x51 + n]))
+            .collect::<Vec<_>>();
+        let funded = sha256::Hash::hash(scripts[2].as_bytes()).to_hex();
+
+        let (url, server) = stub_server(9, move |method, path, body| {
+            let txid = txid.to_hex();
+            let ok = |value: Value| (200, value.to_string().into_bytes());
+            match (
+                method,
+                path.split('/').skip(1).collect::<Vec<_>>().as_slice(),
+            ) {
+                ("GET", ["tx", id, "raw"]) if *id == txid => (200, tx_data.clone()),
+                ("GET", ["tx", id]) if *id == txid => ok(json!({ "txid": txid, "fee": 250 })),
+                ("GET", ["tx", id, "status"]) if *id == txid => ok(status.clone()),
+                ("GET", ["tx", ..]) => (404, b"Transaction not found".to_vec()),
+                ("GET", ["scripthash", hash, "utxo"]) if *hash == funded => ok(json!([
+                    { "txid": txid, "vout": 0, "value": 10_000, "status": status },
+                    { "txid": txid, "vout": 1, "value": 5_000, "status": { "confirmed": false } }
+                ])),
+                ("GET", ["scripthash", _, "utxo"]) => ok(json!([])),
+                ("POST", ["tx"]) => {
+                    assert_eq!(body, tx_hex.as_bytes());
+                    (200, txid.into_bytes())
+                }
+                _ => unreachable!(),
+            }
+        });
+
+        let client = Client::new(&url).unwrap().with_batch_size(2);
+        assert_eq!(client.resolve_tx(txid).unwrap(), tx);
+        assert_eq!(
+            client.resolve_tx_fee(txid).unwrap(),
+            Some((tx.clone(), 250))
+        );
+        assert_eq!(
+            client.resolve_tx_fee(Txid::from_inner([1u8; 32])).unwrap(),
+            None
+        );
+
+        let (mined, time_height) = client.tx_status(txid).unwrap();
+        assert_eq!(mined, MiningStatus::Blockchain(120));
+        let time_height = time_height.unwrap();
+        assert_eq!(*time_height.block_height(), 120);
+        assert_eq!(*time_height.block_hash(), block_hash);
+        assert_eq!(time_height.timestamp().and_utc().timestamp(), 1_600_000_000);
+
+        let utxo = client.resolve_utxo(&scripts).unwrap();
+        assert_eq!(utxo.len(), 3);
+        assert!(utxo[0].is_empty() && utxo[1].is_empty());
+        assert_eq!(utxo[2].len(), 2);
+        assert!(utxo[2].contains(
+            &Utxo::new(
+                MiningStatus::Blockchain(120),
+                OutPoint::new(txid, 0),
+                Amount::from_sat(10_000)
+            )
+            .with_script_pubkey(scripts[2].clone())
+            .with_time_height(time_height)
+        ));
+        assert!(utxo[2].contains(
+            &Utxo::new(
+                MiningStatus::Mempool,
+                OutPoint::new(txid, 1),
+                Amount::from_sat(5_000)
+            )
+            .with_script_pubkey(scripts[2].clone())
+        ));
+
+        assert_eq!(client.broadcast(&tx).unwrap(), txid);
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn http_errors() {
+        let (url, server) = stub_server(1, |_, _, _| {
+            (400, b"bad-txns-inputs-missingorspent".to_vec())
+        });
+        let client = Client::new(&url).unwrap();
+        match client.broadcast(&tx()) {
+            Err(Error::Http { status, message }) => {
+                assert_eq!(status, 400);
+                assert_eq!(message, "bad-txns-inputs-missingorspent");
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+        server.join().unwrap();
+        assert!(Client::new("ftp://localhost").is_err());
+    }
+
+    #[test]
+    fn history_pagination() {
+        let txid = |n: u8| Txid::from_inner([n; 32]);
+        let confirmed = move |n: u8| {
+            json!({
+                "txid": txid(n).to_hex(),
+                "status": {
+                    "confirmed": true,
+                    "block_height": 200 - n as u64,
+                    "block_hash": BlockHash::from_inner([n; 32]).to_hex(),
+                    "block_time": 1_600_000_000 - n as i64 * 600
+                }
+            })
+        };
+        let last = txid(CHAIN_PAGE_SIZE as u8).to_hex();
+        let (url, server) = stub_server(2, move |_, path, _| {
+            let page = match path.split('/').skip(3).collect::<Vec<_>>().as_slice() {
+                ["txs"] => {
+                    let mut page = vec![json!({
+                        "txid": txid(0).to_hex(),
+                        "status": { "confirmed": false }
+                    })];
+                    page.extend((1..=CHAIN_PAGE_SIZE as u8).map(confirmed));
+                    page
+                }
+                ["txs", "chain", id] if *id == last => {
+                    let from = CHAIN_PAGE_SIZE as u8 + 1;
+                    (from..from + 2).map(confirmed).collect()
+                }
+                _ => unreachable!(),
+            };
+            (200, Value::Array(page).to_string().into_bytes())
+        });
+
+        let client = Client::new(&url).unwrap();
+        let history = client
+            .resolve_history([&Script::from(vec![0x51])])
+            .unwrap()
+            .remove(0);
+        assert_eq!(history.len(), CHAIN_PAGE_SIZE + 3);
+        assert!(history.contains(&HistoryEntry::new(txid(0), MiningStatus::Mempool, None)));
+        let entry = history
+            .iter()
+            .find(|entry| *entry.txid() == txid(CHAIN_PAGE_SIZE as u8 + 2))
+            .unwrap();
+        assert_eq!(
+            *entry.mined(),
+            MiningStatus::Blockchain(200 - CHAIN_PAGE_SIZE as u64 - 2)
+        );
+        assert_eq!(
+            *entry.time_height().as_ref().unwrap().block_hash(),
+            BlockHash::from_inner([CHAIN_PAGE_SIZE as u8 + 2; 32])
+        );
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn fee_estimates() {
+        let (url, server) = stub_server(3, |method, path, _| {
+            assert_eq!((method, path), ("GET", "/fee-estimates"));
+            let estimates = json!({ "1": 20.5, "2": 12.0, "6": 4.25, "144": 1.0 });
+            (200, estimates.to_string().into_bytes())
+        });
+
+        let client = Client::new(&url).unwrap();
+        let rate = |target| client.resolve_fee_rate(target).unwrap();
+        assert_eq!(rate(1), Some(FeeRate::from_sat_per_kvb(20_500)));
+        assert_eq!(rate(5), Some(FeeRate::from_sat_per_vb(12)));
+        assert_eq!(rate(0), None);
+        server.join().unwrap();
+    }
+}
@@ -0,0 +1,275 @@
+// Wallet-level libraries for bitcoin protocol by LNP/BP Association
+//
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// This software is distributed without any warranty.
+//
+// You should have received a copy of the Apache-2.0 License
+// along with this software.
+// If not, see <https://opensource.org/licenses/Apache-2.0>.
+
+//! Minimal blocking HTTP/1.1 client used by RPC and REST resolvers. Each
+//! request is sent over a new connection.
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+/// Default timeout for establishing connection and for each read and write
+/// operation
+pub(crate) const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// HTTP server endpoint
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub(crate) struct Endpoint {
+    pub tls: bool,
+    pub host: String,
+    pub port: u16,
+    pub path: String,
+    pub timeout: Duration,
+}
+
+impl Endpoint {
+    /// Parses URL in form of `[http[s]://]host[:port][/path]`, using
+    /// `default_port` for plain HTTP URLs without port.
+    pub fn parse(url: &str, default_port: u16) -> Option<Endpoint> {
+        let (tls, rest) = match url.strip_prefix("https://") {
+            Some(rest) => (true, rest),
+            None => (false, url.strip_prefix("http://").unwrap_or(url)),
+        };
+        if rest.contains("://") {
+            return None;
+        }
+        let (authority, path) = match rest.find('/') {
+            Some(pos) => rest.split_at(pos),
+            None => (rest, ""),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse().ok()?),
+            None if tls => (authority, 443),
+            None => (authority, default_port),
+        };
+        if host.is_empty() {
+            return None;
+        }
+        Some(Endpoint {
+            tls,
+            host: host.to_owned(),
+            port,
+            path: path.trim_end_matches('/').to_owned(),
+            timeout: DEFAULT_TIMEOUT,
+        })
+    }
+
+    /// Sends request for the `path` relative to the endpoint path, returning
+    /// HTTP status and response body.
+    pub fn request(
+        &self,
+        method: &str,
+        path: &str,
+        headers: &[(&str, &str)],
+        body: &[u8],
+    ) -> io::Result<(u16, Vec<u8>)> {
+        let mut path = format!("{}{}", self.path, path);
+        if path.is_empty() {
+            path.push('/');
+        }
+        let mut request = format!(
+            "{} {} HTTP/1.1\r\nHost: {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+            method,
+            path,
+            self.host,
+            body.len()
+        );
+        for (name, value) in headers {
+            request.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        request.push_str("\r\n");
+        let mut request = request.into_bytes();
+        request.extend(body);
+
+        let stream = self.connect()?;
+        if self.tls {
+            self.tls_request(stream, &request)
+        } else {
+            exchange(stream, &request)
+        }
+    }
+
+    /// Connects to the first reachable address of the endpoint host, applying
+    /// the endpoint timeout to the connection and all further reads and writes.
+    fn connect(&self) -> io::Result<TcpStream> {
+        let mut last_err = None;
+        for addr in (self.host.as_str(), self.port).to_socket_addrs()? {
+            match TcpStream::connect_timeout(&addr, self.timeout) {
+                Ok(stream) => {
+                    stream.set_read_timeout(Some(self.timeout))?;
+                    stream.set_write_timeout(Some(self.timeout))?;
+                    return Ok(stream);
+                }
+                Err(err) => last_err = Some(err),
+            }
+        }
+        Err(last_err.unwrap_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                "host name resolves to no addresses",
+            )
+        }))
+    }
+
+    #[cfg(feature = "esplora")]
+    fn tls_request(&self, stream: TcpStream, request: &[u8]) -> io::Result<(u16, Vec<u8>)> {
+        use std::convert::TryFrom;
+        use std::sync::Arc;
+
+        let mut roots = rustls::RootCertStore::empty();
+        roots.add_server_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.0.iter().map(|ta| {
+            rustls::OwnedTrustAnchor::from_subject_spki_name_constraints(
+                ta.subject,
+                ta.spki,
+                ta.name_constraints,
+            )
+        }));
+        let config = rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let server_name = rustls::ServerName::try_from(self.host.as_str())
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+        let connection = rustls::ClientConnection::new(Arc::new(config), server_name)
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+        exchange(rustls::StreamOwned::new(connection, stream), request)
+    }
+
+    #[cfg(not(feature = "esplora"))]
+    fn tls_request(&self, _: TcpStream, _: &[u8]) -> io::Result<(u16, Vec<u8>)> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "HTTPS requires `esplora` feature",
+        ))
+    }
+}
+
+fn exchange(mut stream: impl Read + Write, request: &[u8]) -> io::Result<(u16, Vec<u8>)> {
+    stream.write_all(request)?;
+    stream.flush()?;
+    read_response(BufReader::new(stream))
+}
+
+fn malformed() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "malformed HTTP response")
+}
+
+fn read_response(mut reader: impl BufRead) -> io::Result<(u16, Vec<u8>)> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let status = line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|status| status.parse().ok())
+        .ok_or_else(malformed)?;
+
+    let mut content_length = None;
+    let mut chunked = false;
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Err(malformed());
+        }
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        let (name, value) = header.split_once(':').ok_or_else(malformed)?;
+        let value = value.trim();
+        if name.eq_ignore_ascii_case("content-length") {
+            content_length = Some(value.parse().map_err(|_| malformed())?);
+        } else if name.eq_ignore_ascii_case("transfer-encoding") {
+            chunked = value.eq_ignore_ascii_case("chunked");
+        }
+    }
+
+    let mut body = vec![];
+    if chunked {
+        loop {
+            line.clear();
+            reader.read_line(&mut line)?;
+            let size = usize::from_str_radix(line.trim(), 16).map_err(|_| malformed())?;
+            if size == 0 {
+                break;
+            }
+            let start = body.len();
+            body.resize(start + size, 0);
+            reader.read_exact(&mut body[start..])?;
+            line.clear();
+            reader.read_line(&mut line)?;
+        }
+    } else if let Some(len) = content_length {
+        body.resize(len, 0);
+        reader.read_exact(&mut body)?;
+    } else {
+        reader.read_to_end(&mut body)?;
+    }
+    Ok((status, body))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn endpoint_parsing() {
+        let endpoint = Endpoint::parse("localhost", 8332).unwrap();
+        assert_eq!((endpoint.host.as_str(), endpoint.port), ("localhost", 8332));
+        assert_eq!(endpoint.timeout, DEFAULT_TIMEOUT);
+        assert_eq!(endpoint.path, "");
+        assert!(!endpoint.tls);
+
+        let endpoint = Endpoint::parse("https://blockstream.info/api/", 80).unwrap();
+        assert_eq!(
+            (endpoint.host.as_str(), endpoint.port),
+            ("blockstream.info", 443)
+        );
+        assert_eq!(endpoint.path, "/api");
+        assert!(endpoint.tls);
+
+        let endpoint = Endpoint::parse("http://127.0.0.1:18443/wallet/cold", 8332).unwrap();
+        assert_eq!(
+            (endpoint.host.as_str(), endpoint.port),
+            ("127.0.0.1", 18443)
+        );
+        assert_eq!(endpoint.path, "/wallet/cold");
+
+        assert_eq!(Endpoint::parse("ftp://localhost", 80), None);
+        assert_eq!(Endpoint::parse("localhost:port", 80), None);
+        assert_eq!(Endpoint::parse(":80", 80), None);
+    }
+
+    #[test]
+    fn request_timeout() {
+        // Server accepting connection but never responding
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut endpoint =
+            Endpoint::parse(&format!("http://{}", listener.local_addr().unwrap()), 80).unwrap();
+        endpoint.timeout = Duration::from_millis(100);
+        let err = endpoint.request("GET", "/", &[], &[]).unwrap_err();
+        assert!(
+            matches!(
+                err.kind(),
+                io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+            ),
+            "{:?}",
+            err
+        );
+    }
+
+    #[test]
+    fn chunked_response() {
+        let response = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nhello\r\n6\r\n world\r\n0\r\n\r\n";
+        let (status, body) = read_response(&response[..]).unwrap();
+        assert_eq!(status, 200);
+        assert_eq!(body, b"hello world");
+    }
+}
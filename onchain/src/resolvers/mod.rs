@@ -12,15 +12,28 @@
 //! Resolvers are traits allow accessing or computing information from a
 //! bitcoin transaction graph (from blockchain, state channel, index, PSBT etc).
 
+#[cfg(feature = "bitcoin_core")]
+pub mod bitcoin_core;
 #[cfg(feature = "electrum")]
 mod electrum;
+#[cfg(feature = "esplora")]
+pub mod esplora;
+#[cfg(any(feature = "bitcoin_core", feature = "esplora"))]
+mod http;
+mod nonblocking;
 
 use std::collections::{BTreeMap, HashSet};
 
 use bitcoin::{Script, Transaction, Txid};
 use bitcoin_hd::DeriveError;
 
-use crate::blockchain::Utxo;
+use crate::blockchain::{FeeRate, HistoryEntry, MiningStatus, Utxo};
+#[cfg(feature = "miniscript_descriptors")]
+pub use crate::resolvers::nonblocking::ResolveDescriptorAsync;
+pub use crate::resolvers::nonblocking::{
+    BlockingJob, BlockingResolver, ResolveTxAsync, ResolveTxFeeAsync, ResolveUtxoAsync,
+    ResolverFuture, SpawnBlocking, ThreadedResolver, WorkerPool, DEFAULT_WORKERS,
+};
 
 #[derive(Debug, Display, Error)]
 #[display(doc_comments)]
@@ -29,7 +42,7 @@ pub struct TxResolverError {
     /// transaction id causing the error
     pub txid: Txid,
     /// error message
-    pub err: Option<Box<dyn std::error::Error>>,
+    pub err: Option<Box<dyn std::error::Error + Send + Sync>>,
 }
 
 impl TxResolverError {
@@ -54,6 +67,16 @@ pub enum UtxoResolverError {
     #[from]
     Electrum(electrum_client::Error),
 
+    /// bitcoin core RPC error {0}
+    #[cfg(feature = "bitcoin_core")]
+    #[from]
+    BitcoinCore(bitcoin_core::Error),
+
+    /// esplora server error {0}
+    #[cfg(feature = "esplora")]
+    #[from]
+    Esplora(esplora::Error),
+
     /// Derivation error
     #[from]
     #[display(inner)]
@@ -62,6 +85,45 @@ pub enum UtxoResolverError {
     /// unable to derive descriptor for index {0} which is out of range for
     /// unhardened index derivation
     IndexOutOfRange(usize),
+
+    /// descriptor scanning requires derivation pattern with one or two
+    /// wildcard segments, while the descriptor uses {0}
+    DerivePatternLen(usize),
+}
+
+impl From<BackendError> for UtxoResolverError {
+    fn from(err: BackendError) -> Self {
+        match err {
+            #[cfg(feature = "electrum")]
+            BackendError::Electrum(err) => UtxoResolverError::Electrum(err),
+            #[cfg(feature = "bitcoin_core")]
+            BackendError::BitcoinCore(err) => UtxoResolverError::BitcoinCore(err),
+            #[cfg(feature = "esplora")]
+            BackendError::Esplora(err) => UtxoResolverError::Esplora(err),
+        }
+    }
+}
+
+/// Errors of the blockchain backend requests not related to specific
+/// transactions or scripts: fee rate estimation, transaction broadcasting and
+/// chain state queries
+#[derive(Debug, Display, Error, From)]
+#[display(doc_comments)]
+pub enum BackendError {
+    /// electrum server error {0}
+    #[cfg(feature = "electrum")]
+    #[from]
+    Electrum(electrum_client::Error),
+
+    /// bitcoin core RPC error {0}
+    #[cfg(feature = "bitcoin_core")]
+    #[from]
+    BitcoinCore(bitcoin_core::Error),
+
+    /// esplora server error {0}
+    #[cfg(feature = "esplora")]
+    #[from]
+    Esplora(esplora::Error),
 }
 
 /// UTXO resolver
@@ -73,19 +135,51 @@ pub trait ResolveUtxo {
     ) -> Result<Vec<HashSet<Utxo>>, UtxoResolverError>;
 }
 
+/// Fee rate estimator
+pub trait ResolveFeeRate {
+    /// Estimates fee rate required for a transaction to get confirmed within
+    /// `target` blocks. Returns `None` if the backend has no estimate for the
+    /// target.
+    fn resolve_fee_rate(&self, target: u16) -> Result<Option<FeeRate>, BackendError>;
+}
+
+/// Transaction broadcaster
+pub trait Broadcast {
+    /// Publishes transaction to the bitcoin network, returning its id
+    fn broadcast(&self, tx: &Transaction) -> Result<Txid, BackendError>;
+}
+
+/// Transaction history resolver
+pub trait ResolveHistory {
+    /// Finds transactions spending from or paying to each of the provided
+    /// scripts
+    fn resolve_history<'script>(
+        &self,
+        scripts: impl IntoIterator<Item = &'script Script> + Clone,
+    ) -> Result<Vec<HashSet<HistoryEntry>>, UtxoResolverError>;
+}
+
+/// Resolver of the current blockchain state
+pub trait ResolveChainState {
+    /// Returns height of the current chain tip
+    fn resolve_height(&self) -> Result<u32, BackendError>;
+
+    /// Detects mining status of the transaction. Transactions not known to
+    /// the backend have [`MiningStatus::UnknownTx`] status.
+    fn resolve_tx_status(&self, txid: Txid) -> Result<MiningStatus, TxResolverError>;
+}
+
 #[cfg(feature = "miniscript_descriptors")]
 mod _miniscript_descriptors {
-    use std::cell::RefCell;
     use std::collections::{BTreeMap, HashSet};
-    use std::rc::Rc;
 
     use bitcoin::secp256k1::{Secp256k1, Verification};
     use bitcoin::Script;
-    use bitcoin_hd::{DerivationAccount, DeriveError, SegmentIndexes, UnhardenedIndex};
+    use bitcoin_hd::{DerivationAccount, SegmentIndexes, UnhardenedIndex};
     use descriptors::derive::Descriptor;
 
     use crate::blockchain::Utxo;
-    use crate::{ResolveUtxo, UtxoResolverError};
+    use crate::{ResolveHistory, ResolveUtxo, UtxoResolverError};
 
     /// Does complex resolution for miniscript descriptors
     pub trait ResolveDescriptor: ResolveUtxo {
@@ -98,50 +192,169 @@ mod _miniscript_descriptors {
             from_index: UnhardenedIndex,
             count: u32,
         ) -> Result<BTreeMap<UnhardenedIndex, (Script, HashSet<Utxo>)>, UtxoResolverError> {
-            let terminal_derivation = terminal_derivation.as_ref();
-            let mut derivation =
-                Vec::<UnhardenedIndex>::with_capacity(terminal_derivation.len() + 1);
-            derivation.extend(terminal_derivation);
-            derivation.push(UnhardenedIndex::zero());
-            let derivation = Rc::new(RefCell::new(derivation));
-
-            let indexes = (0..count)
-                .map(|offset| {
-                    from_index.checked_add(offset).ok_or_else(|| {
-                        UtxoResolverError::IndexOutOfRange(
-                            from_index.first_index() as usize + offset as usize,
-                        )
-                    })
-                })
-                .collect::<Result<Vec<_>, UtxoResolverError>>()?;
-
-            let scripts = indexes
-                .into_iter()
-                .map(|index| {
-                    if let Some(i) = derivation.borrow_mut().last_mut() {
-                        *i = index
-                    }
-                    Ok((
-                        index,
-                        descriptor.script_pubkey_pretr(secp, &*derivation.borrow())?,
-                    ))
-                })
-                .collect::<Result<BTreeMap<_, _>, DeriveError>>()?;
+            let scripts = derive_scripts(secp, descriptor, terminal_derivation, from_index, count)?;
 
             Ok(self
-                .resolve_utxo(scripts.values())?
+                .resolve_utxo(scripts.values().map(|(script, _)| script))?
                 .into_iter()
-                .zip(scripts.keys())
-                .zip(scripts.values())
-                .map(|((utxo_set, index), script)| (*index, (script.clone(), utxo_set)))
+                .zip(scripts)
+                .map(|(utxo_set, (index, (script, terminal)))| {
+                    let utxo_set = utxo_set
+                        .into_iter()
+                        .map(|utxo| utxo.with_terminal(&terminal))
+                        .collect();
+                    (index, (script, utxo_set))
+                })
                 .collect())
         }
     }
 
+    /// Derives `count` scripts starting from `from_index` at the end of the
+    /// terminal derivation, returning them together with their full terminal
+    /// derivation
+    pub(super) fn derive_scripts<C: Verification>(
+        secp: &Secp256k1<C>,
+        descriptor: &miniscript::Descriptor<DerivationAccount>,
+        terminal_derivation: impl AsRef<[UnhardenedIndex]>,
+        from_index: UnhardenedIndex,
+        count: u32,
+    ) -> Result<BTreeMap<UnhardenedIndex, (Script, Vec<UnhardenedIndex>)>, UtxoResolverError> {
+        let terminal_derivation = terminal_derivation.as_ref();
+        (0..count)
+            .map(|offset| {
+                let index = from_index.checked_add(offset).ok_or_else(|| {
+                    UtxoResolverError::IndexOutOfRange(
+                        from_index.first_index() as usize + offset as usize,
+                    )
+                })?;
+                let mut terminal = Vec::with_capacity(terminal_derivation.len() + 1);
+                terminal.extend(terminal_derivation);
+                terminal.push(index);
+                let script = descriptor.script_pubkey_pretr(secp, &terminal)?;
+                Ok((index, (script, terminal)))
+            })
+            .collect()
+    }
+
     impl<T> ResolveDescriptor for T where T: ResolveUtxo {}
+
+    /// Results of gap-limit scanning of a descriptor
+    #[derive(Clone, PartialEq, Eq, Debug, Default)]
+    pub struct DescriptorScan {
+        /// Last used index for each of the scanned derivation branches, which
+        /// are identified by their terminal derivation prefix: `[0]` for the
+        /// receive and `[1]` for the change branch, or an empty prefix for
+        /// descriptors with a single derivation branch. Branches without used
+        /// scripts have `None` value.
+        pub last_used: BTreeMap<Vec<UnhardenedIndex>, Option<UnhardenedIndex>>,
+
+        /// Used scripts with their full terminal derivation and set of UTXOs
+        pub scripts: BTreeMap<Script, (Vec<UnhardenedIndex>, HashSet<Utxo>)>,
+    }
+
+    impl DescriptorScan {
+        /// Returns first index after the last used one in the given branch,
+        /// i.e. the index which should be used for the next new script.
+        /// Returns `None` for unknown branches and if the index overflows.
+        pub fn next_index(&self, branch: &[UnhardenedIndex]) -> Option<UnhardenedIndex> {
+            match self.last_used.get(branch)? {
+                None => Some(UnhardenedIndex::zero()),
+                Some(last_used) => last_used.checked_inc(),
+            }
+        }
+
+        /// Iterates over all found UTXOs together with the terminal
+        /// derivation of their scripts
+        pub fn utxo(&self) -> impl Iterator<Item = (&[UnhardenedIndex], &Utxo)> {
+            self.scripts.values().flat_map(|(terminal, utxo_set)| {
+                utxo_set.iter().map(move |utxo| (terminal.as_slice(), utxo))
+            })
+        }
+    }
+
+    /// Gap-limit scanning of miniscript descriptors, allowing wallet
+    /// restoration from a descriptor alone
+    pub trait ScanDescriptor: ResolveUtxo + ResolveHistory {
+        /// Walks each derivation branch of the descriptor (receive and change
+        /// branches for descriptors with two-segment derivation pattern),
+        /// stopping after `gap_limit` consecutive scripts which have no
+        /// transaction history. UTXOs are resolved for all used scripts.
+        fn scan_descriptor<C: Verification>(
+            &self,
+            secp: &Secp256k1<C>,
+            descriptor: &miniscript::Descriptor<DerivationAccount>,
+            gap_limit: u32,
+        ) -> Result<DescriptorScan, UtxoResolverError> {
+            let branches = match descriptor.derive_pattern_len()? {
+                1 => vec![vec![]],
+                2 => vec![vec![UnhardenedIndex::zero()], vec![UnhardenedIndex::one()]],
+                len => return Err(UtxoResolverError::DerivePatternLen(len)),
+            };
+            let taproot = matches!(descriptor, miniscript::Descriptor::Tr(_));
+            let gap_limit = gap_limit.max(1);
+
+            let mut scan = DescriptorScan::default();
+            let mut used = BTreeMap::<Script, Vec<UnhardenedIndex>>::new();
+            for branch in branches {
+                let mut last_used = None::<UnhardenedIndex>;
+                let mut next = 0u32;
+                loop {
+                    let end = last_used
+                        .map(|index| index.first_index() + 1)
+                        .unwrap_or_default()
+                        .saturating_add(gap_limit);
+                    if next >= end {
+                        break;
+                    }
+                    let scripts = (next..end)
+                        .map(|index| {
+                            let index = UnhardenedIndex::from_index(index)
+                                .map_err(|_| UtxoResolverError::IndexOutOfRange(index as usize))?;
+                            let mut terminal = branch.clone();
+                            terminal.push(index);
+                            let script = if taproot {
+                                descriptor.script_pubkey_tr(secp, &terminal)?
+                            } else {
+                                descriptor.script_pubkey_pretr(secp, &terminal)?
+                            };
+                            Ok((script, terminal))
+                        })
+                        .collect::<Result<Vec<_>, UtxoResolverError>>()?;
+
+                    let history = self.resolve_history(scripts.iter().map(|(script, _)| script))?;
+                    for ((script, terminal), history) in scripts.into_iter().zip(history) {
+                        if !history.is_empty() {
+                            last_used = terminal.last().copied();
+                            used.insert(script, terminal);
+                        }
+                    }
+                    next = end;
+                }
+                scan.last_used.insert(branch, last_used);
+            }
+
+            if !used.is_empty() {
+                let utxo = self.resolve_utxo(used.keys())?;
+                scan.scripts = used
+                    .into_iter()
+                    .zip(utxo)
+                    .map(|((script, terminal), utxo_set)| {
+                        let utxo_set = utxo_set
+                            .into_iter()
+                            .map(|utxo| utxo.with_terminal(&terminal))
+                            .collect();
+                        (script, (terminal, utxo_set))
+                    })
+                    .collect();
+            }
+            Ok(scan)
+        }
+    }
+
+    impl<T> ScanDescriptor for T where T: ResolveUtxo + ResolveHistory {}
 }
 #[cfg(feature = "miniscript_descriptors")]
-pub use _miniscript_descriptors::ResolveDescriptor;
+pub use _miniscript_descriptors::{DescriptorScan, ResolveDescriptor, ScanDescriptor};
 
 impl ResolveTx for BTreeMap<Txid, Transaction> {
     fn resolve_tx(&self, txid: Txid) -> Result<Transaction, TxResolverError> {
@@ -157,3 +370,117 @@ pub trait ResolveTxFee {
     /// ([`Txid`])
     fn resolve_tx_fee(&self, txid: Txid) -> Result<Option<(Transaction, u64)>, TxResolverError>;
 }
+
+#[cfg(all(test, feature = "miniscript_descriptors"))]
+mod test {
+    use std::str::FromStr;
+
+    use bitcoin::hashes::Hash;
+    use bitcoin::secp256k1::SECP256K1;
+    use bitcoin::{Amount, OutPoint};
+    use bitcoin_hd::{DerivationAccount, SegmentIndexes, UnhardenedIndex};
+    use descriptors::derive::Descriptor;
+
+    use super::*;
+    use crate::blockchain::MiningStatus;
+
+    #[derive(Default)]
+    struct Resolver(BTreeMap<Script, (HashSet<HistoryEntry>, HashSet<Utxo>)>);
+
+    impl Resolver {
+        fn add(&mut self, script: Script, spent: bool) {
+            let txid = Txid::from_inner([self.0.len() as u8; 32]);
+            let entry = HistoryEntry::new(txid, MiningStatus::Mempool, None);
+            let utxo = if spent {
+                none!()
+            } else {
+                HashSet::from([Utxo::new(
+                    MiningStatus::Mempool,
+                    OutPoint::new(txid, 0),
+                    Amount::from_sat(1000),
+                )])
+            };
+            self.0.insert(script, (HashSet::from([entry]), utxo));
+        }
+    }
+
+    impl ResolveUtxo for Resolver {
+        fn resolve_utxo<'script>(
+            &self,
+            scripts: impl IntoIterator<Item = &'script Script> + Clone,
+        ) -> Result<Vec<HashSet<Utxo>>, UtxoResolverError> {
+            Ok(scripts
+                .into_iter()
+                .map(|script| self.0.get(script).map(|(_, utxo)| utxo.clone()))
+                .map(Option::unwrap_or_default)
+                .collect())
+        }
+    }
+
+    impl ResolveHistory for Resolver {
+        fn resolve_history<'script>(
+            &self,
+            scripts: impl IntoIterator<Item = &'script Script> + Clone,
+        ) -> Result<Vec<HashSet<HistoryEntry>>, UtxoResolverError> {
+            Ok(scripts
+                .into_iter()
+                .map(|script| self.0.get(script).map(|(history, _)| history.clone()))
+                .map(Option::unwrap_or_default)
+                .collect())
+        }
+    }
+
+    #[test]
+    fn gap_limit_scan() {
+        let descriptor = miniscript::Descriptor::<DerivationAccount>::from_str(
+            "wpkh([00000000/84h/0h/0h]xpub69PnGxAGwEBNtGPnxd71p2QbHRZvjDG1BEza1sZdRbd7uWkjHqfGxMburhdEocC5ud2NpkbhwnM29c2zdqWS36wJue1BuJgMnLTpxpxzJe1/<0;1>/*)",
+        )
+        .unwrap();
+        let script = |case: u8, index: u8| {
+            descriptor
+                .script_pubkey_pretr(SECP256K1, [
+                    UnhardenedIndex::from(case),
+                    UnhardenedIndex::from(index),
+                ])
+                .unwrap()
+        };
+
+        let mut resolver = Resolver::default();
+        // Spent outputs still count as used scripts
+        resolver.add(script(0, 0), true);
+        resolver.add(script(0, 3), false);
+        resolver.add(script(0, 7), true);
+        // Beyond the gap limit
+        resolver.add(script(0, 13), false);
+        resolver.add(script(1, 0), false);
+
+        let scan = resolver.scan_descriptor(SECP256K1, &descriptor, 5).unwrap();
+        let receive = vec![UnhardenedIndex::zero()];
+        assert_eq!(scan.last_used, bmap! {
+            receive.clone() => Some(UnhardenedIndex::from(7u8)),
+            vec![UnhardenedIndex::one()] => Some(UnhardenedIndex::zero())
+        });
+        assert_eq!(scan.scripts.len(), 4);
+        assert_eq!(scan.scripts[&script(0, 3)].0, vec![
+            UnhardenedIndex::zero(),
+            UnhardenedIndex::from(3u8)
+        ]);
+        assert!(!scan.scripts.contains_key(&script(0, 13)));
+        assert_eq!(scan.next_index(&receive), Some(UnhardenedIndex::from(8u8)));
+        let mut utxo = scan
+            .utxo()
+            .map(|(terminal, _)| terminal.to_vec())
+            .collect::<Vec<_>>();
+        utxo.sort();
+        assert_eq!(utxo, vec![
+            vec![UnhardenedIndex::zero(), UnhardenedIndex::from(3u8)],
+            vec![UnhardenedIndex::one(), UnhardenedIndex::zero()]
+        ]);
+
+        let scan = Resolver::default()
+            .scan_descriptor(SECP256K1, &descriptor, 5)
+            .unwrap();
+        assert_eq!(scan.last_used.values().flatten().count(), 0);
+        assert_eq!(scan.next_index(&receive), Some(UnhardenedIndex::zero()));
+    }
+}
@@ -0,0 +1,508 @@
+// Wallet-level libraries for bitcoin protocol by LNP/BP Association
+//
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// This software is distributed without any warranty.
+//
+// You should have received a copy of the Apache-2.0 License
+// along with this software.
+// If not, see <https://opensource.org/licenses/Apache-2.0>.
+
+//! Asynchronous counterparts of the resolver traits and adapters between
+//! blocking and asynchronous resolvers. The adapters do not depend on a
+//! specific async runtime: blocking calls are run by a bounded [`WorkerPool`]
+//! or by any other executor implementing [`SpawnBlocking`], for instance the
+//! `spawn_blocking` function of the runtime used by the application.
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::future::{poll_fn, Future};
+use std::pin::{pin, Pin};
+use std::sync::{mpsc, Arc, Mutex};
+use std::task::{Context, Poll, Wake, Waker};
+use std::{panic, thread};
+
+use bitcoin::{Script, Transaction, Txid};
+
+use crate::blockchain::Utxo;
+use crate::{ResolveTx, ResolveTxFee, ResolveUtxo, TxResolverError, UtxoResolverError};
+
+/// Boxed future returned by asynchronous resolvers
+pub type ResolverFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// Asynchronous transaction resolver
+pub trait ResolveTxAsync {
+    /// Tries to find a transaction by transaction id ([`Txid`])
+    fn resolve_tx_async(
+        &self,
+        txid: Txid,
+    ) -> ResolverFuture<'_, Result<Transaction, TxResolverError>>;
+
+    /// Resolves multiple transactions concurrently, failing if any of them
+    /// can't be found
+    fn resolve_txs_async(
+        &self,
+        txids: impl IntoIterator<Item = Txid>,
+    ) -> ResolverFuture<'_, Result<BTreeMap<Txid, Transaction>, TxResolverError>>
+    where
+        Self: Sized + Sync,
+    {
+        let txids = txids.into_iter().collect::<BTreeSet<_>>();
+        Box::pin(async move {
+            let txs = join_all(txids.iter().map(|txid| self.resolve_tx_async(*txid))).await;
+            txids
+                .iter()
+                .zip(txs)
+                .map(|(txid, tx)| tx.map(|tx| (*txid, tx)))
+                .collect()
+        })
+    }
+}
+
+/// Asynchronous transaction fee resolver
+pub trait ResolveTxFeeAsync {
+    /// Tries to find a transaction and compute its fee by transaction id
+    /// ([`Txid`])
+    fn resolve_tx_fee_async(
+        &self,
+        txid: Txid,
+    ) -> ResolverFuture<'_, Result<Option<(Transaction, u64)>, TxResolverError>>;
+}
+
+/// Asynchronous UTXO resolver
+pub trait ResolveUtxoAsync {
+    /// Finds UTXO set for the provided address lists
+    fn resolve_utxo_async<'a>(
+        &'a self,
+        scripts: &'a [Script],
+    ) -> ResolverFuture<'a, Result<Vec<HashSet<Utxo>>, UtxoResolverError>>;
+}
+
+/// Blocking job run by [`SpawnBlocking`] executors
+pub type BlockingJob = Box<dyn FnOnce() + Send>;
+
+/// Executor of blocking jobs used by [`ThreadedResolver`].
+///
+/// Implemented for [`WorkerPool`] and for closures taking [`BlockingJob`],
+/// which allows to plug in `spawn_blocking` function of an async runtime.
+pub trait SpawnBlocking: Send + Sync {
+    /// Schedules job for the execution outside of the async executor threads
+    fn spawn_blocking(&self, job: BlockingJob);
+}
+
+impl<F> SpawnBlocking for F
+where
+    F: Fn(BlockingJob) + Send + Sync,
+{
+    fn spawn_blocking(&self, job: BlockingJob) { self(job) }
+}
+
+/// Default number of threads in the [`WorkerPool`]
+pub const DEFAULT_WORKERS: usize = 4;
+
+/// Fixed-size pool of threads running blocking jobs. Jobs scheduled when all
+/// threads are busy wait in the queue.
+///
+/// Threads terminate once the pool is dropped and all scheduled jobs are
+/// completed.
+#[derive(Debug)]
+pub struct WorkerPool {
+    queue: Mutex<mpsc::Sender<BlockingJob>>,
+    workers: usize,
+}
+
+impl Default for WorkerPool {
+    fn default() -> Self { WorkerPool::new(DEFAULT_WORKERS) }
+}
+
+impl WorkerPool {
+    /// Starts pool with the given number of threads; pool always has at least
+    /// one thread
+    pub fn new(workers: usize) -> WorkerPool {
+        let workers = workers.max(1);
+        let (sender, receiver) = mpsc::channel::<BlockingJob>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        for _ in 0..workers {
+            let receiver = receiver.clone();
+            thread::spawn(move || loop {
+                let job = match receiver.lock().expect("worker pool poisoned").recv() {
+                    Ok(job) => job,
+                    Err(_) => break,
+                };
+                // Panics are reported by the job itself, so the thread is kept
+                let _ = panic::catch_unwind(panic::AssertUnwindSafe(job));
+            });
+        }
+        WorkerPool {
+            queue: Mutex::new(sender),
+            workers,
+        }
+    }
+
+    /// Returns number of threads in the pool
+    #[inline]
+    pub fn workers(&self) -> usize { self.workers }
+}
+
+impl SpawnBlocking for WorkerPool {
+    fn spawn_blocking(&self, job: BlockingJob) {
+        self.queue
+            .lock()
+            .expect("worker pool poisoned")
+            .send(job)
+            .expect("worker pool threads are alive while the pool exists")
+    }
+}
+
+/// Adapter running blocking resolver calls with a [`SpawnBlocking`] executor,
+/// which allows use of any blocking resolver from the asynchronous code
+/// without blocking the async executor. Defaults to the [`WorkerPool`].
+#[derive(Debug)]
+pub struct ThreadedResolver<R, E = WorkerPool> {
+    inner: Arc<R>,
+    executor: Arc<E>,
+}
+
+impl<R, E> Clone for ThreadedResolver<R, E> {
+    fn clone(&self) -> Self {
+        ThreadedResolver {
+            inner: self.inner.clone(),
+            executor: self.executor.clone(),
+        }
+    }
+}
+
+impl<R> ThreadedResolver<R>
+where
+    R: Send + Sync + 'static,
+{
+    /// Wraps blocking resolver, running its calls with the [`WorkerPool`] of
+    /// [`DEFAULT_WORKERS`] threads
+    pub fn new(inner: R) -> ThreadedResolver<R> {
+        ThreadedResolver::with_executor(inner, WorkerPool::default())
+    }
+}
+
+impl<R, E> ThreadedResolver<R, E>
+where
+    R: Send + Sync + 'static,
+    E: SpawnBlocking,
+{
+    /// Wraps blocking resolver, running its calls with the provided executor
+    pub fn with_executor(inner: R, executor: E) -> ThreadedResolver<R, E> {
+        ThreadedResolver {
+            inner: Arc::new(inner),
+            executor: Arc::new(executor),
+        }
+    }
+
+    /// Returns reference to the wrapped resolver
+    #[inline]
+    pub fn inner(&self) -> &R { &self.inner }
+
+    /// Returns reference to the executor running resolver calls
+    #[inline]
+    pub fn executor(&self) -> &E { &self.executor }
+
+    fn run<T>(&self, f: impl FnOnce(&R) -> T + Send + 'static) -> ResolverFuture<'static, T>
+    where
+        T: Send + 'static,
+    {
+        let shared = Arc::new(Mutex::new(Shared {
+            result: None,
+            waker: None,
+        }));
+        let inner = self.inner.clone();
+        let job_shared = shared.clone();
+        self.executor.spawn_blocking(Box::new(move || {
+            let result = panic::catch_unwind(panic::AssertUnwindSafe(|| f(&inner)));
+            let mut shared = job_shared.lock().expect("resolver job poisoned");
+            shared.result = Some(result);
+            if let Some(waker) = shared.waker.take() {
+                waker.wake();
+            }
+        }));
+        Box::pin(JobFuture(shared))
+    }
+}
+
+struct Shared<T> {
+    result: Option<thread::Result<T>>,
+    waker: Option<Waker>,
+}
+
+struct JobFuture<T>(Arc<Mutex<Shared<T>>>);
+
+impl<T> Future for JobFuture<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let mut shared = self.0.lock().expect("resolver job poisoned");
+        match shared.result.take() {
+            Some(Ok(value)) => Poll::Ready(value),
+            Some(Err(panic)) => panic::resume_unwind(panic),
+            None => {
+                shared.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+impl<R, E> ResolveTxAsync for ThreadedResolver<R, E>
+where
+    R: ResolveTx + Send + Sync + 'static,
+    E: SpawnBlocking,
+{
+    fn resolve_tx_async(
+        &self,
+        txid: Txid,
+    ) -> ResolverFuture<'_, Result<Transaction, TxResolverError>> {
+        self.run(move |resolver| resolver.resolve_tx(txid))
+    }
+}
+
+impl<R, E> ResolveTxFeeAsync for ThreadedResolver<R, E>
+where
+    R: ResolveTxFee + Send + Sync + 'static,
+    E: SpawnBlocking,
+{
+    fn resolve_tx_fee_async(
+        &self,
+        txid: Txid,
+    ) -> ResolverFuture<'_, Result<Option<(Transaction, u64)>, TxResolverError>> {
+        self.run(move |resolver| resolver.resolve_tx_fee(txid))
+    }
+}
+
+impl<R, E> ResolveUtxoAsync for ThreadedResolver<R, E>
+where
+    R: ResolveUtxo + Send + Sync + 'static,
+    E: SpawnBlocking,
+{
+    fn resolve_utxo_async<'a>(
+        &'a self,
+        scripts: &'a [Script],
+    ) -> ResolverFuture<'a, Result<Vec<HashSet<Utxo>>, UtxoResolverError>> {
+        let scripts = scripts.to_vec();
+        self.run(move |resolver| resolver.resolve_utxo(&scripts))
+    }
+}
+
+/// Adapter providing blocking resolver API for an asynchronous resolver.
+///
+/// Each call blocks the current thread until the resolver future completes,
+/// so the adapter must not be used from within the async runtime threads.
+#[derive(Clone, Debug, Default)]
+pub struct BlockingResolver<R>(R);
+
+impl<R> BlockingResolver<R> {
+    /// Wraps asynchronous resolver
+    #[inline]
+    pub fn new(inner: R) -> BlockingResolver<R> { BlockingResolver(inner) }
+
+    /// Returns reference to the wrapped resolver
+    #[inline]
+    pub fn inner(&self) -> &R { &self.0 }
+
+    /// Releases the wrapped resolver
+    #[inline]
+    pub fn into_inner(self) -> R { self.0 }
+}
+
+impl<R> ResolveTx for BlockingResolver<R>
+where
+    R: ResolveTxAsync,
+{
+    fn resolve_tx(&self, txid: Txid) -> Result<Transaction, TxResolverError> {
+        block_on(self.0.resolve_tx_async(txid))
+    }
+}
+
+impl<R> ResolveTxFee for BlockingResolver<R>
+where
+    R: ResolveTxFeeAsync,
+{
+    fn resolve_tx_fee(&self, txid: Txid) -> Result<Option<(Transaction, u64)>, TxResolverError> {
+        block_on(self.0.resolve_tx_fee_async(txid))
+    }
+}
+
+impl<R> ResolveUtxo for BlockingResolver<R>
+where
+    R: ResolveUtxoAsync,
+{
+    fn resolve_utxo<'script>(
+        &self,
+        scripts: impl IntoIterator<Item = &'script Script> + Clone,
+    ) -> Result<Vec<HashSet<Utxo>>, UtxoResolverError> {
+        let scripts = scripts.into_iter().cloned().collect::<Vec<_>>();
+        block_on(self.0.resolve_utxo_async(&scripts))
+    }
+}
+
+struct ThreadWaker(thread::Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) { self.0.unpark() }
+}
+
+/// Runs future to its completion, blocking the current thread
+fn block_on<F: Future>(future: F) -> F::Output {
+    let mut future = pin!(future);
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+/// Polls all futures concurrently, returning their outputs in the original
+/// order
+async fn join_all<'a, T>(futures: impl IntoIterator<Item = ResolverFuture<'a, T>>) -> Vec<T> {
+    let mut futures = futures.into_iter().map(Some).collect::<Vec<_>>();
+    let mut outputs = futures.iter().map(|_| None).collect::<Vec<_>>();
+    poll_fn(|cx| {
+        let mut pending = false;
+        for (slot, output) in futures.iter_mut().zip(&mut outputs) {
+            if let Some(future) = slot {
+                match future.as_mut().poll(cx) {
+                    Poll::Ready(value) => {
+                        *output = Some(value);
+                        *slot = None;
+                    }
+                    Poll::Pending => pending = true,
+                }
+            }
+        }
+        if pending {
+            Poll::Pending
+        } else {
+            Poll::Ready(
+                outputs
+                    .iter_mut()
+                    .map(|output| output.take().expect("all futures are completed"))
+                    .collect(),
+            )
+        }
+    })
+    .await
+}
+
+#[cfg(feature = "miniscript_descriptors")]
+mod _miniscript_descriptors {
+    use std::collections::{BTreeMap, HashSet};
+    use std::slice;
+
+    use bitcoin::secp256k1::{Secp256k1, Verification};
+    use bitcoin::Script;
+    use bitcoin_hd::{DerivationAccount, UnhardenedIndex};
+
+    use super::{join_all, ResolveUtxoAsync, ResolverFuture};
+    use crate::blockchain::Utxo;
+    use crate::resolvers::_miniscript_descriptors::derive_scripts;
+    use crate::UtxoResolverError;
+
+    type DescriptorUtxo = BTreeMap<UnhardenedIndex, (Script, HashSet<Utxo>)>;
+
+    /// Asynchronous resolution for miniscript descriptors
+    pub trait ResolveDescriptorAsync: ResolveUtxoAsync {
+        /// Finds UTXO set for the addresses derivable from the given
+        /// descriptor, querying each of the derived scripts concurrently
+        fn resolve_descriptor_utxo_async<C: Verification>(
+            &self,
+            secp: &Secp256k1<C>,
+            descriptor: &miniscript::Descriptor<DerivationAccount>,
+            terminal_derivation: impl AsRef<[UnhardenedIndex]>,
+            from_index: UnhardenedIndex,
+            count: u32,
+        ) -> ResolverFuture<'_, Result<DescriptorUtxo, UtxoResolverError>>
+        where
+            Self: Sized + Sync,
+        {
+            let scripts = derive_scripts(secp, descriptor, terminal_derivation, from_index, count);
+            Box::pin(async move {
+                let scripts = scripts?;
+                let utxo = join_all(
+                    scripts
+                        .values()
+                        .map(|(script, _)| self.resolve_utxo_async(slice::from_ref(script))),
+                )
+                .await;
+                scripts
+                    .into_iter()
+                    .zip(utxo)
+                    .map(|((index, (script, terminal)), utxo)| {
+                        let utxo_set = utxo?
+                            .into_iter()
+                            .next()
+                            .unwrap_or_default()
+                            .into_iter()
+                            .map(|utxo| utxo.with_terminal(&terminal))
+                            .collect();
+                        Ok((index, (script, utxo_set)))
+                    })
+                    .collect()
+            })
+        }
+    }
+
+    impl<T> ResolveDescriptorAsync for T where T: ResolveUtxoAsync {}
+}
+#[cfg(feature = "miniscript_descriptors")]
+pub use _miniscript_descriptors::ResolveDescriptorAsync;
+
+#[cfg(test)]
+mod test {
+    use bitcoin::hashes::Hash;
+    use bitcoin::{Network, WPubkeyHash};
+
+    use super::*;
+    use crate::MemoryChain;
+
+    #[test]
+    fn adapters() {
+        let script = Script::new_v0_p2wpkh(&WPubkeyHash::from_inner([1u8; 20]));
+        let mut chain = MemoryChain::new(Network::Regtest);
+        chain.generate(3, &script);
+        let txids = (1..=3)
+            .map(|height| chain.block(height).unwrap().txdata[0].txid())
+            .collect::<Vec<_>>();
+
+        let threaded = ThreadedResolver::new(chain);
+        let txs = block_on(threaded.resolve_txs_async(txids.iter().copied())).unwrap();
+        assert_eq!(txs.len(), 3);
+        assert!(block_on(threaded.resolve_txs_async([Txid::all_zeros()])).is_err());
+
+        // Round-trip through both adapters
+        let resolver = BlockingResolver::new(threaded);
+        assert_eq!(&resolver.resolve_tx(txids[0]).unwrap(), &txs[&txids[0]]);
+        assert_eq!(resolver.resolve_utxo([&script]).unwrap()[0].len(), 3);
+        assert_eq!(resolver.resolve_tx_fee(txids[1]).unwrap().unwrap().1, 0);
+        assert_eq!(resolver.resolve_tx_fee(Txid::all_zeros()).unwrap(), None);
+    }
+
+    #[test]
+    fn executors() {
+        let script = Script::new_v0_p2wpkh(&WPubkeyHash::from_inner([1u8; 20]));
+        let mut chain = MemoryChain::new(Network::Regtest);
+        chain.generate(8, &script);
+        let txids = (1..=8)
+            .map(|height| chain.block(height).unwrap().txdata[0].txid())
+            .collect::<Vec<_>>();
+
+        // More concurrent calls than threads in the pool
+        let pooled = ThreadedResolver::with_executor(chain.clone(), WorkerPool::new(2));
+        assert_eq!(pooled.executor().workers(), 2);
+        let txs = block_on(pooled.resolve_txs_async(txids.iter().copied())).unwrap();
+        assert_eq!(txs.len(), 8);
+
+        // Injected executor runs the jobs in place
+        let inline = ThreadedResolver::with_executor(chain, |job: BlockingJob| job());
+        let txs = block_on(inline.resolve_txs_async(txids.iter().copied())).unwrap();
+        assert_eq!(txs.len(), 8);
+    }
+}
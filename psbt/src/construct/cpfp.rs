@@ -0,0 +1,327 @@
+// Descriptor wallet library extending bitcoin & miniscript functionality
+// by LNP/BP Association (https://lnp-bp.org)
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the Apache-2.0 License
+// along with this software.
+// If not, see <https://opensource.org/licenses/Apache-2.0>.
+
+//! Child-pays-for-parent transactions accelerating unconfirmed transactions
+//! which pay to the wallet.
+
+use bitcoin::secp256k1::SECP256K1;
+use bitcoin::{OutPoint, Txid};
+use bitcoin_blockchain::locks::SeqNo;
+use bitcoin_hd::{DerivationAccount, DeriveError, UnhardenedIndex};
+use bitcoin_onchain::blockchain::FeeRate;
+use bitcoin_onchain::{ResolveTx, ResolveTxFee, TxResolverError};
+use descriptors::{InputDescriptor, ScriptIndex};
+use miniscript::Descriptor;
+
+use super::weight::{self, TapSpendPath};
+use super::Error;
+use crate::Psbt;
+
+/// Errors happening during construction of the child-pays-for-parent
+/// transaction
+#[derive(Debug, Display, From)]
+#[display(doc_comments)]
+pub enum CpfpError {
+    /// unable to construct the child transaction. {0}
+    #[from]
+    Construct(Error),
+
+    /// unable to resolve the parent transaction. {0}
+    #[from]
+    ResolvingTx(TxResolverError),
+
+    /// parent transaction {0} is not known
+    TxUnknown(Txid),
+
+    /// parent transaction {0} has no outputs of the wallet descriptor derived
+    /// within the first {1} indexes
+    NoWalletOutputs(Txid, u32),
+
+    /// parent transaction already pays fee rate {parent} which is not below
+    /// the target fee rate {target}
+    FeeRateReached {
+        /// Fee rate paid by the parent transaction
+        parent: FeeRate,
+
+        /// Target fee rate for the package
+        target: FeeRate,
+    },
+
+    /// insufficient funds for the child transaction: wallet outputs hold
+    /// {available} sats while {required} sats are required for the fee and
+    /// non-dust output
+    InsufficientFunds {
+        /// Amount of the spent wallet outputs
+        available: u64,
+
+        /// Fee required by the child plus dust limit for its output
+        required: u64,
+    },
+}
+
+impl std::error::Error for CpfpError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CpfpError::Construct(err) => Some(err),
+            CpfpError::ResolvingTx(err) => Some(err),
+            CpfpError::TxUnknown(_) => None,
+            CpfpError::NoWalletOutputs(_, _) => None,
+            CpfpError::FeeRateReached { .. } => None,
+            CpfpError::InsufficientFunds { .. } => None,
+        }
+    }
+}
+
+impl From<miniscript::Error> for CpfpError {
+    fn from(err: miniscript::Error) -> Self { CpfpError::Construct(err.into()) }
+}
+
+impl From<DeriveError> for CpfpError {
+    fn from(err: DeriveError) -> Self { CpfpError::Construct(err.into()) }
+}
+
+/// Child transaction paying for its unconfirmed parent
+#[derive(Clone, PartialEq, Debug)]
+pub struct Cpfp {
+    /// PSBT of the child transaction, which has to be signed
+    pub psbt: Psbt,
+
+    /// Fee paid by the child transaction, in satoshis
+    pub fee: u64,
+
+    /// Estimated virtual size of the final child transaction
+    pub vsize: u64,
+
+    /// Fee paid by the parent transaction, in satoshis
+    pub parent_fee: u64,
+
+    /// Virtual size of the parent transaction
+    pub parent_vsize: u64,
+}
+
+impl Psbt {
+    /// Constructs child transaction spending all outputs of the unconfirmed
+    /// transaction `parent_txid` which belong to the `descriptor`, such that
+    /// the parent and the child together pay the target `fee_rate`.
+    ///
+    /// Wallet outputs are detected among scripts derived from the descriptor
+    /// for the first `lookup_depth` indexes of each derivation branch. The
+    /// funds are sent to the change address derived at `change_index`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn construct_cpfp(
+        parent_txid: Txid,
+        descriptor: &Descriptor<DerivationAccount>,
+        lookup_depth: u32,
+        fee_rate: FeeRate,
+        change_index: impl Into<UnhardenedIndex>,
+        tap_path: TapSpendPath,
+        resolver: &(impl ResolveTx + ResolveTxFee),
+    ) -> Result<Cpfp, CpfpError> {
+        let (parent, parent_fee) = resolver
+            .resolve_tx_fee(parent_txid)?
+            .ok_or(CpfpError::TxUnknown(parent_txid))?;
+        let parent_vsize = weight::weight_to_vsize(parent.weight() as u64);
+        // Compare fees instead of the rounded fee rates
+        if fee_rate.fee_for_vsize(parent_vsize) <= parent_fee {
+            return Err(CpfpError::FeeRateReached {
+                parent: FeeRate::with_fee(parent_fee, parent_vsize),
+                target: fee_rate,
+            });
+        }
+
+        let scripts = ScriptIndex::new(SECP256K1, descriptor.clone(), 0..lookup_depth)?;
+        let inputs = parent
+            .output
+            .iter()
+            .enumerate()
+            .filter_map(|(vout, txout)| {
+                scripts
+                    .get(&txout.script_pubkey)
+                    .map(|origin| InputDescriptor {
+                        outpoint: OutPoint::new(parent_txid, vout as u32),
+                        terminal: origin.terminal().into_iter().collect(),
+                        seq_no: SeqNo::rbf(),
+                        tweak: None,
+                        sighash_type: bitcoin::EcdsaSighashType::All,
+                    })
+            })
+            .collect::<Vec<_>>();
+        if inputs.is_empty() {
+            return Err(CpfpError::NoWalletOutputs(parent_txid, lookup_depth));
+        }
+
+        // All funds go to the change output, from which the fee is taken
+        let mut psbt = Psbt::construct(descriptor, &inputs, &[], change_index, 0, resolver)?;
+        let vsize = psbt.estimate_vsize(descriptor, tap_path)?;
+        let fee = fee_rate.fee_for_vsize(parent_vsize + vsize) - parent_fee;
+
+        // Wallet outputs of the parent may have zero value, leaving no change
+        let Some(output) = psbt.outputs.first_mut() else {
+            return Err(CpfpError::InsufficientFunds {
+                available: 0,
+                required: fee,
+            });
+        };
+        let available = output.amount;
+        let required = fee + output.script.dust_value().to_sat();
+        if available < required {
+            return Err(CpfpError::InsufficientFunds {
+                available,
+                required,
+            });
+        }
+        output.amount -= fee;
+
+        Ok(Cpfp {
+            psbt,
+            fee,
+            vsize,
+            parent_fee,
+            parent_vsize,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+    use std::str::FromStr;
+
+    use bitcoin::hashes::Hash;
+    use bitcoin::secp256k1::SECP256K1;
+    use bitcoin::{PackedLockTime, Script, Transaction, TxIn, TxOut};
+    use bitcoin_hd::SegmentIndexes;
+    use descriptors::derive::Descriptor as _;
+
+    use super::*;
+
+    struct Resolver(BTreeMap<Txid, (Transaction, u64)>);
+
+    impl ResolveTx for Resolver {
+        fn resolve_tx(&self, txid: Txid) -> Result<Transaction, TxResolverError> {
+            self.0
+                .get(&txid)
+                .map(|(tx, _)| tx.clone())
+                .ok_or_else(|| TxResolverError::with(txid))
+        }
+    }
+
+    impl ResolveTxFee for Resolver {
+        fn resolve_tx_fee(
+            &self,
+            txid: Txid,
+        ) -> Result<Option<(Transaction, u64)>, TxResolverError> {
+            Ok(self.0.get(&txid).cloned())
+        }
+    }
+
+    #[test]
+    fn package_fee_rate() {
+        let descriptor = Descriptor::<DerivationAccount>::from_str(
+            "wpkh([00000000/84h/0h/0h]xpub69PnGxAGwEBNtGPnxd71p2QbHRZvjDG1BEza1sZdRbd7uWkjHqfGxMburhdEocC5ud2NpkbhwnM29c2zdqWS36wJue1BuJgMnLTpxpxzJe1/<0;1>/*)",
+        )
+        .unwrap();
+        let script = descriptor
+            .script_pubkey_pretr(SECP256K1, [
+                UnhardenedIndex::zero(),
+                UnhardenedIndex::from(5u8),
+            ])
+            .unwrap();
+        let foreign = Script::new_v0_p2wpkh(&bitcoin::WPubkeyHash::from_slice(&[1u8; 20]).unwrap());
+        let parent = Transaction {
+            version: 2,
+            lock_time: PackedLockTime::ZERO,
+            input: vec![TxIn::default()],
+            output: vec![
+                TxOut {
+                    value: 30_000,
+                    script_pubkey: foreign,
+                },
+                TxOut {
+                    value: 100_000,
+                    script_pubkey: script,
+                },
+            ],
+        };
+        let mut empty = parent.clone();
+        empty.output[1].value = 0;
+        let empty_txid = empty.txid();
+        let parent_txid = parent.txid();
+        let parent_vsize = weight::weight_to_vsize(parent.weight() as u64);
+        let resolver = Resolver(bmap! {
+            parent_txid => (parent, parent_vsize),
+            empty_txid => (empty, parent_vsize)
+        });
+
+        let fee_rate = FeeRate::from_sat_per_vb(10);
+        let cpfp = Psbt::construct_cpfp(
+            parent_txid,
+            &descriptor,
+            10,
+            fee_rate,
+            0u16,
+            TapSpendPath::KeyPath,
+            &resolver,
+        )
+        .unwrap();
+        assert_eq!(cpfp.psbt.inputs.len(), 1);
+        assert_eq!(
+            cpfp.psbt.inputs[0].previous_outpoint,
+            OutPoint::new(parent_txid, 1)
+        );
+        assert_eq!(cpfp.psbt.outputs.len(), 1);
+        assert_eq!(cpfp.psbt.fee().unwrap(), cpfp.fee);
+        assert_eq!(
+            cpfp.parent_fee + cpfp.fee,
+            fee_rate.fee_for_vsize(cpfp.parent_vsize + cpfp.vsize)
+        );
+
+        assert!(matches!(
+            Psbt::construct_cpfp(
+                parent_txid,
+                &descriptor,
+                10,
+                FeeRate::from_sat_per_vb(1),
+                0u16,
+                TapSpendPath::KeyPath,
+                &resolver,
+            ),
+            Err(CpfpError::FeeRateReached { .. })
+        ));
+        assert!(matches!(
+            Psbt::construct_cpfp(
+                parent_txid,
+                &descriptor,
+                5,
+                fee_rate,
+                0u16,
+                TapSpendPath::KeyPath,
+                &resolver,
+            ),
+            Err(CpfpError::NoWalletOutputs(_, 5))
+        ));
+        assert!(matches!(
+            Psbt::construct_cpfp(
+                empty_txid,
+                &descriptor,
+                10,
+                fee_rate,
+                0u16,
+                TapSpendPath::KeyPath,
+                &resolver,
+            ),
+            Err(CpfpError::InsufficientFunds { available: 0, .. })
+        ));
+    }
+}
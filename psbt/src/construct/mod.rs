@@ -14,19 +14,30 @@
 
 //! Functions, errors and traits specific for PSBT constructor role.
 
-use std::collections::BTreeSet;
+pub mod cpfp;
+pub mod rbf;
+pub mod select;
+pub mod update;
+pub mod weight;
 
-use bitcoin::secp256k1::SECP256K1;
+use std::collections::BTreeMap;
+
+use bitcoin::secp256k1::{self, SECP256K1};
+use bitcoin::util::bip32::KeySource;
 use bitcoin::util::psbt::TapTree;
 use bitcoin::util::taproot::{LeafVersion, TapLeafHash, TaprootBuilder, TaprootBuilderError};
-use bitcoin::{Script, Txid, XOnlyPublicKey};
+use bitcoin::{Script, TxOut, Txid, XOnlyPublicKey};
 use bitcoin_hd::{DerivationAccount, DeriveError, SegmentIndexes, UnhardenedIndex};
-use bitcoin_onchain::{ResolveTx, TxResolverError};
+use bitcoin_onchain::blockchain::FeeRate;
+use bitcoin_onchain::{ResolveTx, ResolveTxAsync, TxResolverError};
 use bitcoin_scripts::PubkeyScript;
-use descriptors::derive::DeriveDescriptor;
+use descriptors::derive::{DeriveDescriptor, Descriptor as _, MultipathDescriptor};
 use descriptors::InputDescriptor;
+use miniscript::descriptor::Tr;
 use miniscript::{Descriptor, ForEachKey, ToPublicKey};
 
+use self::select::SelectionError;
+use self::weight::TapSpendPath;
 use crate::{self as psbt, Psbt, PsbtVersion};
 
 #[derive(Debug, Display, From)]
@@ -56,6 +67,10 @@ pub enum Error {
     #[from]
     TaprootBuilderError(TaprootBuilderError),
 
+    /// descriptor does not define a separate derivation branch for change
+    /// outputs
+    NoChangeBranch,
+
     /// PSBT can't be constructed according to the consensus rules since
     /// it spends more ({output} sats) than the sum of its input amounts
     /// ({input} sats)
@@ -66,6 +81,10 @@ pub enum Error {
         /// Amount sent: sum of output value + transaction fee
         output: u64,
     },
+
+    /// unable to select transaction inputs. {0}
+    #[from]
+    Selection(SelectionError),
 }
 
 impl std::error::Error for Error {
@@ -76,12 +95,27 @@ impl std::error::Error for Error {
             Error::OutputUnknown(_, _) => None,
             Error::ScriptPubkeyMismatch(_, _, _, _) => None,
             Error::Miniscript(err) => Some(err),
+            Error::NoChangeBranch => None,
             Error::Inflation { .. } => None,
             Error::TaprootBuilderError(err) => Some(err),
+            Error::Selection(err) => Some(err),
         }
     }
 }
 
+/// PSBT constructed with the fee computed from a fee rate
+#[derive(Clone, PartialEq, Debug)]
+pub struct FeeRateConstruction {
+    /// Constructed PSBT
+    pub psbt: Psbt,
+
+    /// Fee paid by the transaction, in satoshis
+    pub fee: u64,
+
+    /// Estimated virtual size of the final transaction
+    pub vsize: u64,
+}
+
 impl Psbt {
     pub fn construct<'inputs, 'outputs>(
         descriptor: &Descriptor<DerivationAccount>,
@@ -91,174 +125,60 @@ impl Psbt {
         fee: u64,
         tx_resolver: &impl ResolveTx,
     ) -> Result<Psbt, Error> {
-        let mut xpub = bmap! {};
-        descriptor.for_each_key(|account| {
-            if let Some(key_source) = account.account_key_source() {
-                xpub.insert(account.account_xpub, key_source);
-            }
-            true
-        });
-
-        let mut total_spent = 0u64;
-        let mut psbt_inputs: Vec<psbt::Input> = vec![];
-
-        for (index, input) in inputs.into_iter().enumerate() {
-            let txid = input.outpoint.txid;
-            let mut tx = tx_resolver.resolve_tx(txid)?;
-
-            // Cut out witness data
-            for inp in &mut tx.input {
-                inp.witness = zero!();
-            }
+        Psbt::construct_multi(
+            inputs.into_iter().map(|input| (descriptor, input)),
+            outputs,
+            descriptor,
+            change_index,
+            fee,
+            PsbtVersion::V0,
+            tx_resolver,
+        )
+    }
 
-            let prev_output = tx
-                .output
-                .get(input.outpoint.vout as usize)
-                .ok_or(Error::OutputUnknown(txid, input.outpoint.vout))?;
-            let (script_pubkey, dtype, tr_descriptor, pretr_descriptor) = match descriptor {
-                Descriptor::Tr(_) => {
-                    let output_descriptor = DeriveDescriptor::<XOnlyPublicKey>::derive_descriptor(
-                        descriptor,
-                        SECP256K1,
-                        &input.terminal,
-                    )?;
-                    (
-                        output_descriptor.script_pubkey(),
-                        descriptors::CompositeDescrType::from(&output_descriptor),
-                        Some(output_descriptor),
-                        None,
-                    )
-                }
-                _ => {
-                    let output_descriptor =
-                        DeriveDescriptor::<bitcoin::PublicKey>::derive_descriptor(
-                            descriptor,
-                            SECP256K1,
-                            &input.terminal,
-                        )?;
-                    (
-                        output_descriptor.script_pubkey(),
-                        descriptors::CompositeDescrType::from(&output_descriptor),
-                        None,
-                        Some(output_descriptor),
-                    )
-                }
-            };
-            if prev_output.script_pubkey != script_pubkey {
-                return Err(Error::ScriptPubkeyMismatch(
-                    txid,
-                    input.outpoint.vout,
-                    prev_output.script_pubkey.clone(),
-                    script_pubkey,
-                ));
-            }
-            let mut bip32_derivation = bmap! {};
-            let result = descriptor.for_each_key(|account| {
-                match account.bip32_derivation(SECP256K1, &input.terminal) {
-                    Ok((pubkey, key_source)) => {
-                        bip32_derivation.insert(pubkey, key_source);
-                        true
-                    }
-                    Err(_) => false,
+    /// Constructs PSBT spending outputs of multiple descriptors, for instance
+    /// different accounts of the same wallet.
+    ///
+    /// Each input is tagged with the descriptor it spends from and gets
+    /// derivation and script data of that descriptor. The global `xpub` map
+    /// merges account keys of all descriptors used by the inputs and of the
+    /// `change_descriptor`, which receives the change derived at
+    /// `change_index`. The PSBT is created in the provided `psbt_version`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn construct_multi<'inputs, 'outputs>(
+        inputs: impl IntoIterator<
+            Item = (
+                &'inputs Descriptor<DerivationAccount>,
+                &'inputs InputDescriptor,
+            ),
+        >,
+        outputs: impl IntoIterator<Item = &'outputs (PubkeyScript, u64)>,
+        change_descriptor: &Descriptor<DerivationAccount>,
+        change_index: impl Into<UnhardenedIndex>,
+        fee: u64,
+        psbt_version: PsbtVersion,
+        tx_resolver: &impl ResolveTx,
+    ) -> Result<Psbt, Error> {
+        let mut xpub = bmap! {};
+        let mut add_xpubs = |descriptor: &Descriptor<DerivationAccount>| {
+            descriptor.for_each_key(|account| {
+                if let Some(key_source) = account.account_key_source() {
+                    xpub.insert(account.account_xpub, key_source);
                 }
+                true
             });
-            if !result {
-                return Err(DeriveError::DerivePatternMismatch.into());
-            }
-
-            total_spent += prev_output.value;
-
-            let mut psbt_input = psbt::Input {
-                index,
-                previous_outpoint: input.outpoint,
-                sequence_number: Some(input.seq_no),
-                bip32_derivation,
-                sighash_type: Some(input.sighash_type.into()),
-                ..default!()
-            };
+        };
 
-            if dtype.is_segwit() {
-                psbt_input.witness_utxo = Some(prev_output.clone());
-            }
-            // This is required even in case of segwit outputs, since at least Ledger Nano X
-            // do not trust just `non_witness_utxo` data.
-            psbt_input.non_witness_utxo = Some(tx.clone());
-
-            if let Some(Descriptor::<XOnlyPublicKey>::Tr(tr)) = tr_descriptor {
-                psbt_input.bip32_derivation.clear();
-                psbt_input.tap_merkle_root = tr.spend_info().merkle_root();
-                psbt_input.tap_internal_key = Some(tr.internal_key().to_x_only_pubkey());
-                let spend_info = tr.spend_info();
-                psbt_input.tap_scripts = spend_info
-                    .as_script_map()
-                    .iter()
-                    .map(|((script, leaf_ver), _)| {
-                        (
-                            spend_info
-                                .control_block(&(script.clone(), *leaf_ver))
-                                .expect("taproot scriptmap is broken"),
-                            (script.clone(), *leaf_ver),
-                        )
-                    })
-                    .collect();
-                if let Some(taptree) = tr.taptree() {
-                    descriptor.for_each_key(|key| {
-                        let (pubkey, key_source) = key
-                            .bip32_derivation(SECP256K1, &input.terminal)
-                            .expect("failing on second pass of the same function");
-                        let pubkey = XOnlyPublicKey::from(pubkey);
-                        let mut leaves = vec![];
-                        for (_, ms) in taptree.iter() {
-                            for pk in ms.iter_pk() {
-                                if pk == pubkey {
-                                    leaves.push(TapLeafHash::from_script(
-                                        &ms.encode(),
-                                        LeafVersion::TapScript,
-                                    ));
-                                }
-                            }
-                        }
-                        let entry = psbt_input
-                            .tap_key_origins
-                            .entry(pubkey.to_x_only_pubkey())
-                            .or_insert((vec![], key_source));
-                        entry.0.extend(leaves);
-                        true
-                    });
-                }
-                descriptor.for_each_key(|key| {
-                    let (pubkey, key_source) = key
-                        .bip32_derivation(SECP256K1, &input.terminal)
-                        .expect("failing on second pass of the same function");
-                    let pubkey = XOnlyPublicKey::from(pubkey);
-                    if pubkey == *tr.internal_key() {
-                        psbt_input
-                            .tap_key_origins
-                            .entry(pubkey.to_x_only_pubkey())
-                            .or_insert((vec![], key_source));
-                    }
-                    true
-                });
-                for (leaves, _) in psbt_input.tap_key_origins.values_mut() {
-                    *leaves = leaves
-                        .iter()
-                        .cloned()
-                        .collect::<BTreeSet<_>>()
-                        .into_iter()
-                        .collect();
-                }
-            } else if let Some(output_descriptor) = pretr_descriptor {
-                let lock_script = output_descriptor.explicit_script()?;
-                if dtype.has_redeem_script() {
-                    psbt_input.redeem_script = Some(lock_script.clone().into());
-                }
-                if dtype.has_witness_script() {
-                    psbt_input.witness_script = Some(lock_script.into());
-                }
-            }
+        let mut total_spent = 0u64;
+        let mut psbt_inputs: Vec<psbt::Input> = vec![];
 
+        for (index, (descriptor, input)) in inputs.into_iter().enumerate() {
+            let (psbt_input, amount) = construct_input(descriptor, index, input, tx_resolver)?;
+            add_xpubs(descriptor);
+            total_spent += amount;
             psbt_inputs.push(psbt_input);
         }
+        add_xpubs(change_descriptor);
 
         let mut total_sent = 0u64;
         let mut psbt_outputs: Vec<_> = outputs
@@ -286,81 +206,549 @@ impl Psbt {
         };
 
         if change > 0 {
-            let change_derivation = [UnhardenedIndex::one(), change_index.into()];
-            let mut bip32_derivation = bmap! {};
-            let bip32_derivation_fn = |account: &DerivationAccount| {
-                let (pubkey, key_source) = account
-                    .bip32_derivation(SECP256K1, change_derivation)
-                    .expect("already tested descriptor derivation mismatch");
-                bip32_derivation.insert(pubkey, key_source);
-                true
-            };
-
-            let mut psbt_change_output = psbt::Output {
-                index: psbt_outputs.len(),
-                amount: change,
-                ..default!()
-            };
-            if let Descriptor::Tr(_) = descriptor {
-                let change_descriptor = DeriveDescriptor::<XOnlyPublicKey>::derive_descriptor(
-                    descriptor,
-                    SECP256K1,
-                    change_derivation,
-                )?;
-                let change_descriptor = match change_descriptor {
-                    Descriptor::Tr(tr) => tr,
-                    _ => unreachable!(),
-                };
-
-                psbt_change_output.script = change_descriptor.script_pubkey().into();
-                descriptor.for_each_key(bip32_derivation_fn);
-
-                let internal_key: XOnlyPublicKey =
-                    change_descriptor.internal_key().to_x_only_pubkey();
-                psbt_change_output.tap_internal_key = Some(internal_key);
-                if let Some(tree) = change_descriptor.taptree() {
-                    let mut builder = TaprootBuilder::new();
-                    for (depth, ms) in tree.iter() {
-                        builder = builder
-                            .add_leaf(depth, ms.encode())
-                            .expect("insane miniscript taptree");
-                    }
-                    psbt_change_output.tap_tree =
-                        Some(TapTree::try_from(builder).expect("non-finalized TaprootBuilder"));
-                }
-            } else {
-                let change_descriptor = DeriveDescriptor::<bitcoin::PublicKey>::derive_descriptor(
-                    descriptor,
-                    SECP256K1,
-                    change_derivation,
-                )?;
-                psbt_change_output.script = change_descriptor.script_pubkey().into();
-
-                let dtype = descriptors::CompositeDescrType::from(&change_descriptor);
-                descriptor.for_each_key(bip32_derivation_fn);
-
-                let lock_script = change_descriptor.explicit_script()?;
-                if dtype.has_redeem_script() {
-                    psbt_change_output.redeem_script = Some(lock_script.clone().into());
-                }
-                if dtype.has_witness_script() {
-                    psbt_change_output.witness_script = Some(lock_script.into());
-                }
-            }
-
-            psbt_change_output.bip32_derivation = bip32_derivation;
-            psbt_outputs.push(psbt_change_output);
+            psbt_outputs.push(construct_change_output(
+                change_descriptor,
+                psbt_outputs.len(),
+                change_index,
+                change,
+            )?);
         }
 
         Ok(Psbt {
-            psbt_version: PsbtVersion::V0,
+            psbt_version,
             tx_version: 2,
             xpub,
             inputs: psbt_inputs,
             outputs: psbt_outputs,
             fallback_locktime: None,
+            tx_modifiable: None,
             proprietary: none!(),
             unknown: none!(),
         })
     }
+
+    /// Constructs PSBT paying fee at the given rate. The fee is computed from
+    /// the weight of the final transaction, estimated with
+    /// [`Psbt::estimate_weight`] for the provided taproot spending path.
+    ///
+    /// If the change left after paying the fee is below the dust limit, the
+    /// change output is omitted and its amount is added to the fee.
+    pub fn construct_with_fee_rate<'inputs>(
+        descriptor: &Descriptor<DerivationAccount>,
+        inputs: impl IntoIterator<Item = &'inputs InputDescriptor>,
+        outputs: &[(PubkeyScript, u64)],
+        change_index: impl Into<UnhardenedIndex>,
+        fee_rate: FeeRate,
+        tap_path: TapSpendPath,
+        tx_resolver: &impl ResolveTx,
+    ) -> Result<FeeRateConstruction, Error> {
+        Psbt::construct_multi_with_fee_rate(
+            inputs.into_iter().map(|input| (descriptor, input)),
+            outputs,
+            descriptor,
+            change_index,
+            fee_rate,
+            PsbtVersion::V0,
+            tap_path,
+            tx_resolver,
+        )
+    }
+
+    /// Constructs PSBT spending outputs of multiple descriptors, like
+    /// [`Psbt::construct_multi`], paying fee at the given rate. The weight of
+    /// each input is estimated from the descriptor it spends, as done by
+    /// [`Psbt::estimate_weight_multi`].
+    ///
+    /// If the change left after paying the fee is below the dust limit, the
+    /// change output is omitted and its amount is added to the fee.
+    #[allow(clippy::too_many_arguments)]
+    pub fn construct_multi_with_fee_rate<'inputs>(
+        inputs: impl IntoIterator<
+            Item = (
+                &'inputs Descriptor<DerivationAccount>,
+                &'inputs InputDescriptor,
+            ),
+        >,
+        outputs: &[(PubkeyScript, u64)],
+        change_descriptor: &Descriptor<DerivationAccount>,
+        change_index: impl Into<UnhardenedIndex>,
+        fee_rate: FeeRate,
+        psbt_version: PsbtVersion,
+        tap_path: TapSpendPath,
+        tx_resolver: &impl ResolveTx,
+    ) -> Result<FeeRateConstruction, Error> {
+        let inputs = inputs.into_iter().collect::<Vec<_>>();
+        let descriptors = inputs
+            .iter()
+            .map(|(descriptor, _)| *descriptor)
+            .collect::<Vec<_>>();
+        let mut psbt = Psbt::construct_multi(
+            inputs,
+            outputs,
+            change_descriptor,
+            change_index,
+            0,
+            psbt_version,
+            tx_resolver,
+        )?;
+        let total_sent = outputs.iter().map(|(_, amount)| amount).sum::<u64>();
+
+        let weight = psbt.estimate_weight_multi(descriptors.iter().copied(), tap_path)?;
+        let fee = fee_rate.fee_for_weight(weight);
+        let mut change = 0u64;
+        if psbt.outputs.len() > outputs.len() {
+            let change_output = psbt.outputs.last_mut().expect("change output is present");
+            change = change_output.amount;
+            let dust_limit = change_output.script.dust_value().to_sat();
+            if change >= fee + dust_limit {
+                change_output.amount -= fee;
+                return Ok(FeeRateConstruction {
+                    psbt,
+                    fee,
+                    vsize: weight::weight_to_vsize(weight),
+                });
+            }
+            psbt.outputs.pop();
+        }
+
+        let weight = psbt.estimate_weight_multi(descriptors.iter().copied(), tap_path)?;
+        let fee = fee_rate.fee_for_weight(weight);
+        if change < fee {
+            return Err(Error::Inflation {
+                input: total_sent + change,
+                output: total_sent + fee,
+            });
+        }
+        Ok(FeeRateConstruction {
+            psbt,
+            fee: change,
+            vsize: weight::weight_to_vsize(weight),
+        })
+    }
+
+    /// Asynchronous version of [`Psbt::construct`]: transactions spent by the
+    /// inputs are resolved concurrently before the PSBT is constructed.
+    pub async fn construct_async<'inputs, 'outputs>(
+        descriptor: &Descriptor<DerivationAccount>,
+        inputs: impl IntoIterator<Item = &'inputs InputDescriptor>,
+        outputs: impl IntoIterator<Item = &'outputs (PubkeyScript, u64)>,
+        change_index: impl Into<UnhardenedIndex>,
+        fee: u64,
+        tx_resolver: &(impl ResolveTxAsync + Sync),
+    ) -> Result<Psbt, Error> {
+        let change_index = change_index.into();
+        let inputs = inputs.into_iter().collect::<Vec<_>>();
+        let txs = tx_resolver
+            .resolve_txs_async(inputs.iter().map(|input| input.outpoint.txid))
+            .await?;
+        Psbt::construct(descriptor, inputs, outputs, change_index, fee, &txs)
+    }
+
+    /// Asynchronous version of [`Psbt::construct_with_fee_rate`]: transactions
+    /// spent by the inputs are resolved concurrently before the PSBT is
+    /// constructed.
+    pub async fn construct_with_fee_rate_async<'inputs>(
+        descriptor: &Descriptor<DerivationAccount>,
+        inputs: impl IntoIterator<Item = &'inputs InputDescriptor>,
+        outputs: &[(PubkeyScript, u64)],
+        change_index: impl Into<UnhardenedIndex>,
+        fee_rate: FeeRate,
+        tap_path: TapSpendPath,
+        tx_resolver: &(impl ResolveTxAsync + Sync),
+    ) -> Result<FeeRateConstruction, Error> {
+        let change_index = change_index.into();
+        let inputs = inputs.into_iter().collect::<Vec<_>>();
+        let txs = tx_resolver
+            .resolve_txs_async(inputs.iter().map(|input| input.outpoint.txid))
+            .await?;
+        Psbt::construct_with_fee_rate(
+            descriptor,
+            inputs,
+            outputs,
+            change_index,
+            fee_rate,
+            tap_path,
+            &txs,
+        )
+    }
+}
+
+/// Constructs PSBT input spending descriptor output, returning it together with
+/// the amount of the spent output.
+fn construct_input(
+    descriptor: &Descriptor<DerivationAccount>,
+    index: usize,
+    input: &InputDescriptor,
+    tx_resolver: &impl ResolveTx,
+) -> Result<(psbt::Input, u64), Error> {
+    let txid = input.outpoint.txid;
+    let mut tx = tx_resolver.resolve_tx(txid)?;
+
+    // Cut out witness data
+    for inp in &mut tx.input {
+        inp.witness = zero!();
+    }
+
+    let prev_output = tx
+        .output
+        .get(input.outpoint.vout as usize)
+        .ok_or(Error::OutputUnknown(txid, input.outpoint.vout))?
+        .clone();
+
+    let mut psbt_input = psbt::Input {
+        index,
+        previous_outpoint: input.outpoint,
+        sequence_number: Some(input.seq_no),
+        sighash_type: Some(input.sighash_type.into()),
+        ..default!()
+    };
+    update_input(descriptor, &input.terminal, &prev_output, &mut psbt_input)?;
+
+    // This is required even in case of segwit outputs, since at least Ledger Nano X
+    // do not trust just `non_witness_utxo` data.
+    psbt_input.non_witness_utxo = Some(tx);
+
+    Ok((psbt_input, prev_output.value))
+}
+
+/// Fills BIP-174 and BIP-371 fields of the PSBT input spending `prev_output`,
+/// which must be an output of the descriptor derived at `terminal`.
+///
+/// Key derivation information is added to the one already present in the
+/// input, such that the data provided by other wallets is preserved.
+fn update_input(
+    descriptor: &Descriptor<DerivationAccount>,
+    terminal: impl AsRef<[UnhardenedIndex]>,
+    prev_output: &TxOut,
+    psbt_input: &mut psbt::Input,
+) -> Result<(), Error> {
+    let terminal = terminal.as_ref();
+    let outpoint = psbt_input.previous_outpoint;
+    let (script_pubkey, dtype, tr_descriptor, pretr_descriptor) = match descriptor {
+        Descriptor::Tr(_) => {
+            let output_descriptor = DeriveDescriptor::<XOnlyPublicKey>::derive_descriptor(
+                descriptor, SECP256K1, terminal,
+            )?;
+            (
+                output_descriptor.script_pubkey(),
+                descriptors::CompositeDescrType::from(&output_descriptor),
+                Some(output_descriptor),
+                None,
+            )
+        }
+        _ => {
+            let output_descriptor = DeriveDescriptor::<bitcoin::PublicKey>::derive_descriptor(
+                descriptor, SECP256K1, terminal,
+            )?;
+            (
+                output_descriptor.script_pubkey(),
+                descriptors::CompositeDescrType::from(&output_descriptor),
+                None,
+                Some(output_descriptor),
+            )
+        }
+    };
+    if prev_output.script_pubkey != script_pubkey {
+        return Err(Error::ScriptPubkeyMismatch(
+            outpoint.txid,
+            outpoint.vout,
+            prev_output.script_pubkey.clone(),
+            script_pubkey,
+        ));
+    }
+    let bip32_derivation = bip32_derivation(descriptor, terminal)?;
+
+    if dtype.is_segwit() {
+        psbt_input.witness_utxo = Some(prev_output.clone());
+    }
+
+    if let Some(Descriptor::<XOnlyPublicKey>::Tr(tr)) = tr_descriptor {
+        psbt_input.tap_merkle_root = tr.spend_info().merkle_root();
+        psbt_input.tap_internal_key = Some(tr.internal_key().to_x_only_pubkey());
+        let spend_info = tr.spend_info();
+        psbt_input.tap_scripts = spend_info
+            .as_script_map()
+            .iter()
+            .map(|((script, leaf_ver), _)| {
+                (
+                    spend_info
+                        .control_block(&(script.clone(), *leaf_ver))
+                        .expect("taproot scriptmap is broken"),
+                    (script.clone(), *leaf_ver),
+                )
+            })
+            .collect();
+        psbt_input
+            .tap_key_origins
+            .extend(tap_key_origins(descriptor, &tr, terminal)?);
+    } else if let Some(output_descriptor) = pretr_descriptor {
+        psbt_input.bip32_derivation.extend(bip32_derivation);
+        let lock_script = output_descriptor.explicit_script()?;
+        if dtype.has_redeem_script() {
+            psbt_input.redeem_script = Some(lock_script.clone().into());
+        }
+        if dtype.has_witness_script() {
+            psbt_input.witness_script = Some(lock_script.into());
+        }
+    }
+
+    Ok(())
+}
+
+/// Constructs PSBT change output for the descriptor derived at `change_index`.
+fn construct_change_output(
+    descriptor: &Descriptor<DerivationAccount>,
+    index: usize,
+    change_index: impl Into<UnhardenedIndex>,
+    amount: u64,
+) -> Result<psbt::Output, Error> {
+    // BIP-389 assigns the second branch of multipath descriptors to
+    // change outputs; single-path descriptors use `1/*` terminal pattern
+    // for the change, as defined by BIP-44.
+    let change_index = change_index.into();
+    let (change_branch, change_derivation) = match descriptor.multipath_len()? {
+        1 if descriptor.derive_pattern_len()? == 2 => (descriptor.clone(), vec![
+            UnhardenedIndex::one(),
+            change_index,
+        ]),
+        1 => return Err(Error::NoChangeBranch),
+        _ => (descriptor.branch(1)?, vec![change_index]),
+    };
+
+    let mut psbt_change_output = psbt::Output {
+        index,
+        amount,
+        ..default!()
+    };
+    update_output(&change_branch, change_derivation, &mut psbt_change_output)?;
+    Ok(psbt_change_output)
+}
+
+/// Fills scriptPubkey together with BIP-174 and BIP-371 fields of the PSBT
+/// output paying to the descriptor derived at `terminal`.
+///
+/// Key derivation information is added to the one already present in the
+/// output, such that the data provided by other wallets is preserved.
+fn update_output(
+    descriptor: &Descriptor<DerivationAccount>,
+    terminal: impl AsRef<[UnhardenedIndex]>,
+    psbt_output: &mut psbt::Output,
+) -> Result<(), Error> {
+    let terminal = terminal.as_ref();
+    psbt_output
+        .bip32_derivation
+        .extend(bip32_derivation(descriptor, terminal)?);
+
+    if let Descriptor::Tr(_) = descriptor {
+        let output_descriptor =
+            DeriveDescriptor::<XOnlyPublicKey>::derive_descriptor(descriptor, SECP256K1, terminal)?;
+        let output_descriptor = match output_descriptor {
+            Descriptor::Tr(tr) => tr,
+            _ => unreachable!(),
+        };
+
+        psbt_output.script = output_descriptor.script_pubkey().into();
+
+        let internal_key: XOnlyPublicKey = output_descriptor.internal_key().to_x_only_pubkey();
+        psbt_output.tap_internal_key = Some(internal_key);
+        if let Some(tree) = output_descriptor.taptree() {
+            let mut builder = TaprootBuilder::new();
+            for (depth, ms) in tree.iter() {
+                builder = builder
+                    .add_leaf(depth, ms.encode())
+                    .expect("insane miniscript taptree");
+            }
+            psbt_output.tap_tree =
+                Some(TapTree::try_from(builder).expect("non-finalized TaprootBuilder"));
+        }
+        psbt_output.tap_key_origins.extend(tap_key_origins(
+            descriptor,
+            &output_descriptor,
+            terminal,
+        )?);
+    } else {
+        let output_descriptor = DeriveDescriptor::<bitcoin::PublicKey>::derive_descriptor(
+            descriptor, SECP256K1, terminal,
+        )?;
+        psbt_output.script = output_descriptor.script_pubkey().into();
+
+        let dtype = descriptors::CompositeDescrType::from(&output_descriptor);
+        let lock_script = output_descriptor.explicit_script()?;
+        if dtype.has_redeem_script() {
+            psbt_output.redeem_script = Some(lock_script.clone().into());
+        }
+        if dtype.has_witness_script() {
+            psbt_output.witness_script = Some(lock_script.into());
+        }
+    }
+
+    Ok(())
+}
+
+/// Derives public keys of the descriptor at `terminal` together with their
+/// origins.
+fn bip32_derivation(
+    descriptor: &Descriptor<DerivationAccount>,
+    terminal: &[UnhardenedIndex],
+) -> Result<BTreeMap<secp256k1::PublicKey, KeySource>, DeriveError> {
+    let mut bip32_derivation = bmap! {};
+    let result =
+        descriptor.for_each_key(
+            |account| match account.bip32_derivation(SECP256K1, terminal) {
+                Ok((pubkey, key_source)) => {
+                    bip32_derivation.insert(pubkey, key_source);
+                    true
+                }
+                Err(_) => false,
+            },
+        );
+    if !result {
+        return Err(DeriveError::DerivePatternMismatch);
+    }
+    Ok(bip32_derivation)
+}
+
+/// Computes BIP-371 origins of the taproot keys of the descriptor derived at
+/// `terminal`, listing for each key the hashes of the script leaves it is
+/// used in.
+fn tap_key_origins(
+    descriptor: &Descriptor<DerivationAccount>,
+    tr: &Tr<XOnlyPublicKey>,
+    terminal: &[UnhardenedIndex],
+) -> Result<BTreeMap<XOnlyPublicKey, (Vec<TapLeafHash>, KeySource)>, DeriveError> {
+    let mut tap_key_origins = BTreeMap::<XOnlyPublicKey, (Vec<TapLeafHash>, KeySource)>::new();
+    for (pubkey, key_source) in bip32_derivation(descriptor, terminal)? {
+        let pubkey = XOnlyPublicKey::from(pubkey);
+        let mut leaves = bset! {};
+        for (_, ms) in tr.iter_scripts() {
+            if ms.iter_pk().any(|pk| pk == pubkey) {
+                leaves.insert(TapLeafHash::from_script(
+                    &ms.encode(),
+                    LeafVersion::TapScript,
+                ));
+            }
+        }
+        if !leaves.is_empty() || pubkey == *tr.internal_key() {
+            tap_key_origins.insert(pubkey, (leaves.into_iter().collect(), key_source));
+        }
+    }
+    Ok(tap_key_origins)
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use bitcoin::{OutPoint, PackedLockTime, Transaction, TxIn, TxOut};
+    use bitcoin_hd::SegmentIndexes;
+    use descriptors::derive::Descriptor as _;
+
+    use super::*;
+
+    #[test]
+    fn multi_descriptor() {
+        let segwit = Descriptor::<DerivationAccount>::from_str(
+            "wpkh([d34db33f/84h/0h/0h]xpub69PnGxAGwEBNtGPnxd71p2QbHRZvjDG1BEza1sZdRbd7uWkjHqfGxMburhdEocC5ud2NpkbhwnM29c2zdqWS36wJue1BuJgMnLTpxpxzJe1/<0;1>/*)",
+        )
+        .unwrap();
+        let taproot = Descriptor::<DerivationAccount>::from_str(
+            "tr([d34db33f/86h/0h/0h]xpub661MyMwAqRbcFtXgS5sYJABqqG9YLmC4Q1Rdap9gSE8NqtwybGhePY2gZ29ESFjqJoCu1Rupje8YtGqsefD265TMg7usUDFdp6W1EGMcet8/<0;1>/*)",
+        )
+        .unwrap();
+        let terminal = [UnhardenedIndex::zero(), UnhardenedIndex::from(2u8)];
+        let prev_tx = Transaction {
+            version: 2,
+            lock_time: PackedLockTime::ZERO,
+            input: vec![TxIn::default()],
+            output: vec![
+                TxOut {
+                    value: 50_000,
+                    script_pubkey: segwit.script_pubkey_pretr(SECP256K1, terminal).unwrap(),
+                },
+                TxOut {
+                    value: 70_000,
+                    script_pubkey: taproot.script_pubkey_tr(SECP256K1, terminal).unwrap(),
+                },
+            ],
+        };
+        let inputs = (0..2)
+            .map(|vout| InputDescriptor {
+                outpoint: OutPoint::new(prev_tx.txid(), vout),
+                terminal: terminal.iter().copied().collect(),
+                seq_no: none!(),
+                tweak: None,
+                sighash_type: bitcoin::EcdsaSighashType::All,
+            })
+            .collect::<Vec<_>>();
+        let resolver = bmap! { prev_tx.txid() => prev_tx.clone() };
+
+        let psbt = Psbt::construct_multi(
+            [(&segwit, &inputs[0]), (&taproot, &inputs[1])],
+            &[],
+            &taproot,
+            UnhardenedIndex::from(3u8),
+            1000,
+            PsbtVersion::V2,
+            &resolver,
+        )
+        .unwrap();
+
+        assert_eq!(psbt.psbt_version, PsbtVersion::V2);
+        assert_eq!(psbt.inputs[0].bip32_derivation.len(), 1);
+        assert_eq!(psbt.inputs[0].tap_internal_key, None);
+        assert!(psbt.inputs[1].bip32_derivation.is_empty());
+        assert_eq!(psbt.inputs[1].tap_key_origins.len(), 1);
+        assert!(psbt.inputs[1].tap_internal_key.is_some());
+        assert_eq!(psbt.xpub.len(), 2);
+        assert_eq!(psbt.outputs.len(), 1);
+        assert_eq!(psbt.outputs[0].amount, 119_000);
+        assert_eq!(
+            &*psbt.outputs[0].script,
+            &taproot
+                .script_pubkey_tr(SECP256K1, [
+                    UnhardenedIndex::one(),
+                    UnhardenedIndex::from(3u8)
+                ])
+                .unwrap()
+        );
+
+        // Spending with a wrong descriptor is detected
+        assert!(matches!(
+            Psbt::construct_multi(
+                [(&taproot, &inputs[0])],
+                &[],
+                &segwit,
+                UnhardenedIndex::zero(),
+                1000,
+                PsbtVersion::V0,
+                &resolver,
+            ),
+            Err(Error::ScriptPubkeyMismatch(..))
+        ));
+    }
+
+    #[test]
+    fn change_derivation() {
+        let xpub = "[d34db33f/84h/0h/0h]xpub69PnGxAGwEBNtGPnxd71p2QbHRZvjDG1BEza1sZdRbd7uWkjHqfGxMburhdEocC5ud2NpkbhwnM29c2zdqWS36wJue1BuJgMnLTpxpxzJe1";
+        let change_index = UnhardenedIndex::from(5u8);
+        let expected = Descriptor::<DerivationAccount>::from_str(&format!("wpkh({}/*/*)", xpub))
+            .unwrap()
+            .script_pubkey_pretr(SECP256K1, [UnhardenedIndex::one(), change_index])
+            .unwrap();
+
+        for terminal in ["<0;1>/*", "*/*"] {
+            let descriptor =
+                Descriptor::<DerivationAccount>::from_str(&format!("wpkh({}/{})", xpub, terminal))
+                    .unwrap();
+            let output = construct_change_output(&descriptor, 0, change_index, 1000).unwrap();
+            assert_eq!(&*output.script, &expected, "{}", terminal);
+        }
+
+        let descriptor =
+            Descriptor::<DerivationAccount>::from_str(&format!("wpkh({}/0/*)", xpub)).unwrap();
+        assert!(matches!(
+            construct_change_output(&descriptor, 0, change_index, 1000),
+            Err(Error::NoChangeBranch)
+        ));
+    }
 }
@@ -0,0 +1,592 @@
+// Descriptor wallet library extending bitcoin & miniscript functionality
+// by LNP/BP Association (https://lnp-bp.org)
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the Apache-2.0 License
+// along with this software.
+// If not, see <https://opensource.org/licenses/Apache-2.0>.
+
+//! Fee bumping of unconfirmed transactions with BIP-125 replace-by-fee.
+
+use std::collections::BTreeSet;
+
+use bitcoin::secp256k1::SECP256K1;
+use bitcoin::{OutPoint, Txid};
+use bitcoin_blockchain::locks::SeqNo;
+use bitcoin_hd::{DerivationAccount, DeriveError, SegmentIndexes, UnhardenedIndex};
+use bitcoin_onchain::blockchain::FeeRate;
+use bitcoin_onchain::{ResolveTx, ResolveTxFee, TxResolverError};
+use bitcoin_scripts::PubkeyScript;
+use descriptors::{InputDescriptor, ScriptIndex};
+use miniscript::{Descriptor, ForEachKey};
+
+use super::select::Candidate;
+use super::weight::TapSpendPath;
+use super::{construct_change_output, construct_input, update_output, Error};
+use crate::{FeeError, Output, Psbt};
+
+/// Default minimal fee rate by which a replacement must increase the fee of
+/// the replaced transaction (BIP-125 rule 4)
+pub const INCREMENTAL_RELAY_FEE: FeeRate = FeeRate::from_sat_per_vb(1);
+
+/// Errors happening during construction of the fee-bumping replacement
+#[derive(Debug, Display, From)]
+#[display(doc_comments)]
+pub enum BumpError {
+    /// unable to construct the replacement transaction. {0}
+    #[from]
+    Construct(Error),
+
+    /// unable to resolve the replaced transaction. {0}
+    #[from]
+    ResolvingTx(TxResolverError),
+
+    /// transaction {0} which has to be replaced is not known
+    TxUnknown(Txid),
+
+    /// input {0} of the replaced transaction does not spend an output of the
+    /// wallet descriptor derived within the first {1} indexes
+    ForeignInput(OutPoint, u32),
+
+    /// unable to compute fee of the replaced transaction. {0}
+    #[from]
+    Fee(FeeError),
+
+    /// fee rate {proposed} does not exceed the fee rate {original} of the
+    /// replaced transaction
+    FeeRateTooLow {
+        /// Fee rate of the replaced transaction
+        original: FeeRate,
+
+        /// Fee rate requested for the replacement
+        proposed: FeeRate,
+    },
+
+    /// insufficient funds for the replacement: {available} sats are available
+    /// while {required} sats are required
+    InsufficientFunds {
+        /// Amount available for paying the fee
+        available: u64,
+
+        /// Fee required by the replacement
+        required: u64,
+    },
+}
+
+impl std::error::Error for BumpError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            BumpError::Construct(err) => Some(err),
+            BumpError::ResolvingTx(err) => Some(err),
+            BumpError::TxUnknown(_) => None,
+            BumpError::ForeignInput(_, _) => None,
+            BumpError::Fee(err) => Some(err),
+            BumpError::FeeRateTooLow { .. } => None,
+            BumpError::InsufficientFunds { .. } => None,
+        }
+    }
+}
+
+impl From<miniscript::Error> for BumpError {
+    fn from(err: miniscript::Error) -> Self { BumpError::Construct(err.into()) }
+}
+
+impl From<DeriveError> for BumpError {
+    fn from(err: DeriveError) -> Self { BumpError::Construct(err.into()) }
+}
+
+/// Replacement PSBT bumping the fee of the original transaction
+#[derive(Clone, PartialEq, Debug)]
+pub struct FeeBump {
+    /// Replacement PSBT, which has to be signed
+    pub psbt: Psbt,
+
+    /// Fee paid by the replaced transaction, in satoshis
+    pub original_fee: u64,
+
+    /// Fee paid by the replacement, in satoshis
+    pub fee: u64,
+
+    /// Estimated virtual size of the final replacement transaction
+    pub vsize: u64,
+}
+
+impl Psbt {
+    /// Constructs BIP-125 replacement of the transaction represented by this
+    /// PSBT, paying fee at the given rate.
+    ///
+    /// Recipient outputs are kept intact and the additional fee is taken from
+    /// the change output, which is detected by the presence of key derivation
+    /// information from the `descriptor` accounts. If the change is not
+    /// sufficient, the `extra_inputs` are added, starting from the largest
+    /// ones; in this case a new change output is derived at `change_index` if
+    /// the original PSBT had none. Extra inputs must be confirmed, since the
+    /// replacement may not spend new unconfirmed outputs.
+    ///
+    /// The fee of the replacement is never less than the original fee plus
+    /// [`INCREMENTAL_RELAY_FEE`] for the replacement size. All inputs are
+    /// signalling replaceability and have their signatures removed.
+    pub fn bump_fee(
+        &self,
+        descriptor: &Descriptor<DerivationAccount>,
+        fee_rate: FeeRate,
+        extra_inputs: impl IntoIterator<Item = Candidate>,
+        change_index: impl Into<UnhardenedIndex>,
+        tap_path: TapSpendPath,
+        tx_resolver: &impl ResolveTx,
+    ) -> Result<FeeBump, BumpError> {
+        let original_fee = self.fee()?;
+        let original_vsize = self.estimate_vsize(descriptor, tap_path)?;
+        // Compare fees instead of the rounded fee rates
+        if fee_rate.fee_for_vsize(original_vsize) <= original_fee {
+            return Err(BumpError::FeeRateTooLow {
+                original: FeeRate::with_fee(original_fee, original_vsize),
+                proposed: fee_rate,
+            });
+        }
+
+        let mut psbt = self.clone();
+        let mut input_sum = 0u64;
+        for input in &mut psbt.inputs {
+            input_sum += input.input_prevout().map_err(FeeError::from)?.value;
+            input.sequence_number = Some(rbf_seq_no(input.sequence_number.unwrap_or_default()));
+            input.partial_sigs.clear();
+            input.tap_key_sig = None;
+            input.tap_script_sigs.clear();
+            input.final_script_sig = None;
+            input.final_script_witness = None;
+        }
+
+        let spent = psbt
+            .inputs
+            .iter()
+            .map(|input| input.previous_outpoint)
+            .collect::<BTreeSet<_>>();
+        let mut extra_inputs = extra_inputs
+            .into_iter()
+            .filter(|candidate| !spent.contains(&candidate.input.outpoint))
+            .collect::<Vec<_>>();
+        // Candidates are taken from the end of the list
+        extra_inputs.sort_by_key(|candidate| candidate.amount);
+
+        let change_index = change_index.into();
+        let mut change = psbt
+            .outputs
+            .iter()
+            .position(|output| is_change(descriptor, output));
+
+        loop {
+            // Relay policy checks fee rates against the virtual size
+            let vsize = psbt.estimate_vsize(descriptor, tap_path)?;
+            let required = fee_rate
+                .fee_for_vsize(vsize)
+                .max(original_fee + INCREMENTAL_RELAY_FEE.fee_for_vsize(vsize));
+            let sent = psbt
+                .outputs
+                .iter()
+                .enumerate()
+                .filter(|(index, _)| Some(*index) != change)
+                .map(|(_, output)| output.amount)
+                .sum::<u64>();
+            let available = input_sum.saturating_sub(sent);
+
+            if available >= required {
+                let excess = available - required;
+                match change {
+                    Some(pos) if excess >= psbt.outputs[pos].script.dust_value().to_sat() => {
+                        psbt.outputs[pos].amount = excess;
+                    }
+                    Some(pos) => {
+                        // Change is below dust limit and goes to the miners;
+                        // the transaction gets smaller, so the fee is still
+                        // sufficient
+                        psbt.outputs.remove(pos);
+                        for (index, output) in psbt.outputs.iter_mut().enumerate() {
+                            output.index = index;
+                        }
+                        change = None;
+                        continue;
+                    }
+                    None => {}
+                }
+                return Ok(FeeBump {
+                    fee: psbt.fee()?,
+                    vsize: psbt.estimate_vsize(descriptor, tap_path)?,
+                    psbt,
+                    original_fee,
+                });
+            }
+
+            let candidate = extra_inputs.pop().ok_or(BumpError::InsufficientFunds {
+                available,
+                required,
+            })?;
+            let mut input = candidate.input;
+            input.seq_no = rbf_seq_no(input.seq_no);
+            let (psbt_input, amount) =
+                construct_input(descriptor, psbt.inputs.len(), &input, tx_resolver)?;
+            input_sum += amount;
+            psbt.inputs.push(psbt_input);
+
+            if change.is_none() {
+                change = Some(psbt.outputs.len());
+                psbt.outputs.push(construct_change_output(
+                    descriptor,
+                    psbt.outputs.len(),
+                    change_index,
+                    0,
+                )?);
+            }
+        }
+    }
+
+    /// Reconstructs PSBT for the unconfirmed transaction `txid` spending the
+    /// outputs of the `descriptor`.
+    ///
+    /// Inputs and change outputs are matched against scripts derived from the
+    /// descriptor for the first `lookup_depth` indexes of each derivation
+    /// branch; for two-segment derivation patterns only the second branch is
+    /// considered to be change. Outputs keep their original order, and the
+    /// first change output gets derivation information, such that
+    /// [`Psbt::bump_fee`] adjusts its amount in place.
+    pub fn with_replaced_tx(
+        txid: Txid,
+        descriptor: &Descriptor<DerivationAccount>,
+        lookup_depth: u32,
+        resolver: &(impl ResolveTx + ResolveTxFee),
+    ) -> Result<Psbt, BumpError> {
+        let (tx, fee) = resolver
+            .resolve_tx_fee(txid)?
+            .ok_or(BumpError::TxUnknown(txid))?;
+
+        let scripts = ScriptIndex::new(SECP256K1, descriptor.clone(), 0..lookup_depth)?;
+
+        let mut inputs = Vec::with_capacity(tx.input.len());
+        for txin in &tx.input {
+            let outpoint = txin.previous_output;
+            let prev_tx = resolver.resolve_tx(outpoint.txid)?;
+            let prev_output = prev_tx
+                .output
+                .get(outpoint.vout as usize)
+                .ok_or(Error::OutputUnknown(outpoint.txid, outpoint.vout))?;
+            let origin = scripts
+                .get(&prev_output.script_pubkey)
+                .ok_or(BumpError::ForeignInput(outpoint, lookup_depth))?;
+            inputs.push(InputDescriptor {
+                outpoint,
+                terminal: origin.terminal().into_iter().collect(),
+                seq_no: SeqNo::from_consensus(txin.sequence.0),
+                tweak: None,
+                sighash_type: bitcoin::EcdsaSighashType::All,
+            });
+        }
+
+        let change = tx.output.iter().enumerate().find_map(|(pos, txout)| {
+            scripts
+                .get(&txout.script_pubkey)
+                .filter(|origin| {
+                    origin
+                        .branch
+                        .map_or(true, |branch| branch == UnhardenedIndex::one())
+                })
+                .map(|origin| (pos, origin.terminal()))
+        });
+        let outputs = tx
+            .output
+            .iter()
+            .map(|txout| (PubkeyScript::from(txout.script_pubkey.clone()), txout.value))
+            .collect::<Vec<_>>();
+
+        // All input value is spent by the outputs and the fee, so no new
+        // change output is added
+        let mut psbt = Psbt::construct(
+            descriptor,
+            &inputs,
+            &outputs,
+            UnhardenedIndex::zero(),
+            fee,
+            resolver,
+        )?;
+        if let Some((pos, terminal)) = change {
+            update_output(descriptor, terminal, &mut psbt.outputs[pos])?;
+        }
+        psbt.tx_version = tx.version as u32;
+        psbt.fallback_locktime = match tx.lock_time.0 {
+            0 => None,
+            other => Some(other.into()),
+        };
+        Ok(psbt)
+    }
+
+    /// Constructs BIP-125 replacement for the unconfirmed transaction `txid`
+    /// spending the outputs of the `descriptor`. See
+    /// [`Psbt::with_replaced_tx`] and [`Psbt::bump_fee`] for the details.
+    #[allow(clippy::too_many_arguments)]
+    pub fn bump_tx_fee(
+        txid: Txid,
+        descriptor: &Descriptor<DerivationAccount>,
+        lookup_depth: u32,
+        fee_rate: FeeRate,
+        extra_inputs: impl IntoIterator<Item = Candidate>,
+        change_index: impl Into<UnhardenedIndex>,
+        tap_path: TapSpendPath,
+        resolver: &(impl ResolveTx + ResolveTxFee),
+    ) -> Result<FeeBump, BumpError> {
+        Psbt::with_replaced_tx(txid, descriptor, lookup_depth, resolver)?.bump_fee(
+            descriptor,
+            fee_rate,
+            extra_inputs,
+            change_index,
+            tap_path,
+            resolver,
+        )
+    }
+}
+
+/// Makes sequence number signal replaceability, keeping relative time locks
+/// and explicit RBF sequence numbers.
+fn rbf_seq_no(seq_no: SeqNo) -> SeqNo {
+    if seq_no.is_rbf() {
+        seq_no
+    } else {
+        SeqNo::rbf()
+    }
+}
+
+/// Detects whether the output carries key derivation information from one of
+/// the descriptor accounts.
+fn is_change(descriptor: &Descriptor<DerivationAccount>, output: &Output) -> bool {
+    let mut origins = output.bip32_derivation.values().chain(
+        output
+            .tap_key_origins
+            .values()
+            .map(|(_, key_source)| key_source),
+    );
+    origins.any(|(fingerprint, path)| {
+        !descriptor.for_each_key(|account| {
+            let account_path = account.to_account_derivation_path();
+            *fingerprint != account.master_fingerprint().unwrap_or_default()
+                || !path.as_ref().starts_with(account_path.as_ref())
+        })
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeMap;
+    use std::str::FromStr;
+
+    use bitcoin::hashes::Hash;
+    use bitcoin::{PackedLockTime, Script, Transaction, TxIn, TxOut};
+    use descriptors::derive::Descriptor as _;
+
+    use super::*;
+    use crate::construct::weight::input_weight;
+
+    struct Resolver(BTreeMap<Txid, Transaction>);
+
+    impl ResolveTx for Resolver {
+        fn resolve_tx(&self, txid: Txid) -> Result<Transaction, TxResolverError> {
+            self.0.resolve_tx(txid)
+        }
+    }
+
+    impl ResolveTxFee for Resolver {
+        fn resolve_tx_fee(
+            &self,
+            txid: Txid,
+        ) -> Result<Option<(Transaction, u64)>, TxResolverError> {
+            let tx = match self.0.get(&txid) {
+                Some(tx) => tx.clone(),
+                None => return Ok(None),
+            };
+            let input = tx
+                .input
+                .iter()
+                .map(|txin| {
+                    let prev_tx = self.resolve_tx(txin.previous_output.txid)?;
+                    Ok(prev_tx.output[txin.previous_output.vout as usize].value)
+                })
+                .sum::<Result<u64, TxResolverError>>()?;
+            let output = tx.output.iter().map(|txout| txout.value).sum::<u64>();
+            Ok(Some((tx, input - output)))
+        }
+    }
+
+    fn funding_tx(script_pubkey: Script, value: u64) -> Transaction {
+        Transaction {
+            version: 2,
+            lock_time: PackedLockTime::ZERO,
+            input: vec![TxIn::default()],
+            output: vec![TxOut {
+                value,
+                script_pubkey,
+            }],
+        }
+    }
+
+    #[test]
+    fn bump_fee() {
+        let descriptor = Descriptor::<DerivationAccount>::from_str(
+            "wpkh([00000000/84h/0h/0h]xpub69PnGxAGwEBNtGPnxd71p2QbHRZvjDG1BEza1sZdRbd7uWkjHqfGxMburhdEocC5ud2NpkbhwnM29c2zdqWS36wJue1BuJgMnLTpxpxzJe1/<0;1>/*)",
+        )
+        .unwrap();
+        let terminal = |index: u16| [UnhardenedIndex::zero(), UnhardenedIndex::from(index)];
+        let script = |index: u16| {
+            descriptor
+                .script_pubkey_pretr(SECP256K1, terminal(index))
+                .unwrap()
+        };
+        let input = |txid: Txid, index: u16| InputDescriptor {
+            outpoint: OutPoint::new(txid, 0),
+            terminal: terminal(index).into_iter().collect(),
+            seq_no: none!(),
+            tweak: None,
+            sighash_type: bitcoin::EcdsaSighashType::All,
+        };
+
+        let tx1 = funding_tx(script(0), 100_000);
+        let tx2 = funding_tx(script(1), 50_000);
+        let (txid1, txid2) = (tx1.txid(), tx2.txid());
+        let mut resolver = Resolver(bmap! { txid1 => tx1, txid2 => tx2 });
+
+        let recipient = PubkeyScript::from(Script::new_v0_p2wpkh(
+            &bitcoin::WPubkeyHash::from_slice(&[1u8; 20]).unwrap(),
+        ));
+        let outputs = [(recipient.clone(), 60_000)];
+        let psbt = Psbt::construct(
+            &descriptor,
+            &[input(txid1, 0)],
+            &outputs,
+            0u16,
+            200,
+            &resolver,
+        )
+        .unwrap();
+        let extra = Candidate {
+            input: input(txid2, 1),
+            amount: 50_000,
+            weight: input_weight(&descriptor, TapSpendPath::KeyPath).unwrap(),
+        };
+
+        // Extra fee is taken from the change
+        let bump = psbt
+            .bump_fee(
+                &descriptor,
+                FeeRate::from_sat_per_vb(5),
+                [extra.clone()],
+                1u16,
+                TapSpendPath::KeyPath,
+                &resolver,
+            )
+            .unwrap();
+        assert_eq!(bump.original_fee, 200);
+        assert_eq!(bump.psbt.inputs.len(), 1);
+        assert_eq!(bump.psbt.outputs.len(), 2);
+        assert_eq!(bump.psbt.outputs[0].amount, 60_000);
+        assert!(bump.fee >= bump.vsize * 5);
+        assert!(bump.fee >= 200 + bump.vsize);
+        assert_eq!(bump.psbt.outputs[1].amount, 100_000 - 60_000 - bump.fee);
+        assert!(bump.psbt.inputs[0].sequence_number.unwrap().is_rbf());
+
+        // Replacement must pay higher fee rate
+        assert!(matches!(
+            psbt.bump_fee(
+                &descriptor,
+                FeeRate::from_sat_per_vb(1),
+                [],
+                1u16,
+                TapSpendPath::KeyPath,
+                &resolver,
+            ),
+            Err(BumpError::FeeRateTooLow { .. })
+        ));
+
+        // Change is not sufficient and an extra input is added
+        let bump = psbt
+            .bump_fee(
+                &descriptor,
+                FeeRate::from_sat_per_vb(300),
+                [extra],
+                1u16,
+                TapSpendPath::KeyPath,
+                &resolver,
+            )
+            .unwrap();
+        assert_eq!(bump.psbt.inputs.len(), 2);
+        assert_eq!(bump.psbt.outputs[0].amount, 60_000);
+        assert!(bump.fee >= bump.vsize * 300);
+        assert_eq!(bump.psbt.outputs[1].amount, 150_000 - 60_000 - bump.fee);
+
+        // Replacement of the transaction known to the resolver
+        let tx = psbt.to_unsigned_tx().unwrap();
+        let txid = tx.txid();
+        resolver.0.insert(txid, tx);
+        let original = Psbt::with_replaced_tx(txid, &descriptor, 10, &resolver).unwrap();
+        assert_eq!(original.to_txid(), Ok(txid));
+        let bump = Psbt::bump_tx_fee(
+            txid,
+            &descriptor,
+            10,
+            FeeRate::from_sat_per_vb(5),
+            [],
+            0u16,
+            TapSpendPath::KeyPath,
+            &resolver,
+        )
+        .unwrap();
+        assert_eq!(bump.original_fee, 200);
+        assert_eq!(bump.psbt.outputs[0].amount, 60_000);
+        assert_eq!(bump.psbt.outputs[1].amount, 100_000 - 60_000 - bump.fee);
+
+        // Change output preceding the recipient keeps its position
+        let change = descriptor
+            .script_pubkey_pretr(SECP256K1, [
+                UnhardenedIndex::one(),
+                UnhardenedIndex::from(3u8),
+            ])
+            .unwrap();
+        let tx = Transaction {
+            version: 2,
+            lock_time: PackedLockTime::ZERO,
+            input: vec![TxIn {
+                previous_output: OutPoint::new(txid1, 0),
+                ..default!()
+            }],
+            output: vec![
+                TxOut {
+                    value: 39_800,
+                    script_pubkey: change,
+                },
+                TxOut {
+                    value: 60_000,
+                    script_pubkey: recipient.clone().into(),
+                },
+            ],
+        };
+        let txid = tx.txid();
+        resolver.0.insert(txid, tx);
+        let original = Psbt::with_replaced_tx(txid, &descriptor, 10, &resolver).unwrap();
+        assert_eq!(original.to_txid(), Ok(txid));
+        assert!(is_change(&descriptor, &original.outputs[0]));
+        let bump = original
+            .bump_fee(
+                &descriptor,
+                FeeRate::from_sat_per_vb(5),
+                [],
+                0u16,
+                TapSpendPath::KeyPath,
+                &resolver,
+            )
+            .unwrap();
+        assert_eq!(bump.psbt.outputs.len(), 2);
+        assert_eq!(bump.psbt.outputs[0].amount, 100_000 - 60_000 - bump.fee);
+        assert_eq!(bump.psbt.outputs[1].amount, 60_000);
+    }
+}
@@ -0,0 +1,858 @@
+// Descriptor wallet library extending bitcoin & miniscript functionality
+// by LNP/BP Association (https://lnp-bp.org)
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the Apache-2.0 License
+// along with this software.
+// If not, see <https://opensource.org/licenses/Apache-2.0>.
+
+//! Coin selection: choosing transaction inputs out of the set of available
+//! UTXOs with Branch-and-Bound, knapsack and single-random-draw algorithms.
+
+use std::collections::{BTreeMap, HashSet};
+
+use bitcoin::secp256k1::rand::seq::SliceRandom;
+use bitcoin::secp256k1::rand::{Rng, RngCore};
+use bitcoin::Script;
+use bitcoin_hd::{DerivationAccount, UnhardenedIndex};
+use bitcoin_onchain::blockchain::{FeeRate, Utxo};
+use bitcoin_onchain::ResolveTx;
+use bitcoin_scripts::PubkeyScript;
+use descriptors::{CompositeDescrType, InputDescriptor};
+use miniscript::Descriptor;
+
+use super::weight::{self, TapSpendPath};
+use super::{construct_change_output, Error};
+use crate::Psbt;
+
+/// Maximal number of Branch-and-Bound search iterations
+const BNB_MAX_TRIES: usize = 100_000;
+
+/// Number of knapsack stochastic approximation iterations
+const KNAPSACK_ITERATIONS: usize = 1000;
+
+/// Coin selection errors
+#[derive(Clone, Eq, PartialEq, Hash, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum SelectionError {
+    /// insufficient funds: available UTXOs provide {available} sats after
+    /// paying for their spending, while {required} sats are required
+    InsufficientFunds {
+        /// Sum of effective values of all available UTXOs
+        available: u64,
+        /// Amount required to pay for the outputs and transaction fee
+        required: u64,
+    },
+
+    /// no solution was found by the {0} coin selection algorithm
+    NoSolution(Algorithm),
+
+    /// none of the coin selection algorithms found a set of available UTXOs
+    /// paying for the outputs and transaction fee either exactly or with a
+    /// change output
+    NoSelection,
+}
+
+/// Coin selection algorithm
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display)]
+pub enum Algorithm {
+    /// Branch-and-Bound search for a changeless solution
+    #[display("branch-and-bound")]
+    BranchAndBound,
+
+    /// Knapsack solver with stochastic approximation of the best subset
+    #[display("knapsack")]
+    Knapsack,
+
+    /// Single random draw
+    #[display("single-random-draw")]
+    SingleRandomDraw,
+}
+
+/// UTXO which can be selected as a transaction input
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Candidate {
+    /// Input descriptor spending the UTXO
+    pub input: InputDescriptor,
+
+    /// Amount held by the UTXO, in satoshis
+    pub amount: u64,
+
+    /// Weight of the transaction input spending the UTXO, including its
+    /// satisfaction data
+    pub weight: u64,
+}
+
+impl Candidate {
+    /// Constructs candidates out of the UTXOs resolved with
+    /// `ResolveDescriptor::resolve_descriptor_utxo`, which must be called with
+    /// the same descriptor and terminal derivation.
+    ///
+    /// The weight of the inputs is computed from the maximal satisfaction
+    /// weight of the descriptor, assuming the provided taproot spending path.
+    /// Coinbase outputs which can't be spent in the block following
+    /// `tip_height` are skipped.
+    pub fn with_resolved_utxo(
+        descriptor: &Descriptor<DerivationAccount>,
+        terminal_derivation: impl AsRef<[UnhardenedIndex]>,
+        resolved: &BTreeMap<UnhardenedIndex, (Script, HashSet<Utxo>)>,
+        tip_height: u64,
+        tap_path: TapSpendPath,
+    ) -> Result<Vec<Candidate>, miniscript::Error> {
+        let weight = weight::input_weight(descriptor, tap_path)?;
+        Ok(resolved
+            .iter()
+            .flat_map(|(index, (_, utxo_set))| utxo_set.iter().map(move |utxo| (index, utxo)))
+            .filter(|(_, utxo)| utxo.is_mature(tip_height))
+            .map(|(index, utxo)| {
+                let mut terminal = terminal_derivation.as_ref().to_vec();
+                terminal.push(*index);
+                Candidate {
+                    input: InputDescriptor {
+                        outpoint: *utxo.outpoint(),
+                        terminal: terminal.into_iter().collect(),
+                        seq_no: none!(),
+                        tweak: None,
+                        sighash_type: bitcoin::EcdsaSighashType::All,
+                    },
+                    amount: utxo.amount().to_sat(),
+                    weight,
+                }
+            })
+            .collect())
+    }
+
+    /// Constructs candidates out of the UTXOs spending outputs of the
+    /// descriptor, like the ones found by `ScanDescriptor::scan_descriptor`.
+    /// UTXOs with unknown terminal derivation and coinbase outputs which
+    /// can't be spent in the block following `tip_height` are skipped.
+    ///
+    /// The weight of the inputs is computed from the maximal satisfaction
+    /// weight of the descriptor, assuming the provided taproot spending path.
+    pub fn with_utxo<'utxo>(
+        descriptor: &Descriptor<DerivationAccount>,
+        utxo: impl IntoIterator<Item = &'utxo Utxo>,
+        tip_height: u64,
+        tap_path: TapSpendPath,
+    ) -> Result<Vec<Candidate>, miniscript::Error> {
+        let weight = weight::input_weight(descriptor, tap_path)?;
+        Ok(utxo
+            .into_iter()
+            .filter(|utxo| utxo.is_mature(tip_height))
+            .filter_map(|utxo| {
+                Some(Candidate {
+                    input: InputDescriptor {
+                        outpoint: *utxo.outpoint(),
+                        terminal: utxo.terminal().clone()?,
+                        seq_no: none!(),
+                        tweak: None,
+                        sighash_type: bitcoin::EcdsaSighashType::All,
+                    },
+                    amount: utxo.amount().to_sat(),
+                    weight,
+                })
+            })
+            .collect())
+    }
+
+    /// Fee required to spend the UTXO at a given fee rate
+    #[inline]
+    pub fn fee(&self, fee_rate: FeeRate) -> u64 { fee_rate.fee_for_weight(self.weight) }
+
+    /// Amount held by the UTXO minus the fee required to spend it
+    #[inline]
+    pub fn effective_value(&self, fee_rate: FeeRate) -> i64 {
+        self.amount as i64 - self.fee(fee_rate) as i64
+    }
+}
+
+/// Parameters of coin selection
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct SelectionParams {
+    /// Sum of transaction output amounts, not including change
+    pub target: u64,
+
+    /// Fee rate for the transaction
+    pub fee_rate: FeeRate,
+
+    /// Fee rate expected to be paid for spending the change and the UTXOs
+    /// which are not selected now. Used to estimate the waste metric.
+    pub long_term_fee_rate: FeeRate,
+
+    /// Weight of the transaction without its inputs and change output
+    pub base_weight: u64,
+
+    /// Weight of the change output
+    pub change_weight: u64,
+
+    /// Weight of the input spending the change output
+    pub change_spend_weight: u64,
+
+    /// Minimal value of change output; smaller change is added to the fee
+    pub min_change: u64,
+}
+
+impl SelectionParams {
+    /// Computes parameters for selecting inputs which spend outputs of the
+    /// `descriptor` and pay to the `outputs`, with change sent back to the
+    /// `descriptor` at `change_index`.
+    ///
+    /// The fee rate is used also as the long-term fee rate; change below the
+    /// dust limit of the change output is added to the fee.
+    pub fn with_descriptor(
+        descriptor: &Descriptor<DerivationAccount>,
+        outputs: &[(PubkeyScript, u64)],
+        change_index: impl Into<UnhardenedIndex>,
+        fee_rate: FeeRate,
+        tap_path: TapSpendPath,
+    ) -> Result<SelectionParams, Error> {
+        let change = construct_change_output(descriptor, outputs.len(), change_index, 0)?;
+        let segwit = CompositeDescrType::from(descriptor).is_segwit();
+        Ok(SelectionParams {
+            target: outputs.iter().map(|(_, amount)| amount).sum(),
+            fee_rate,
+            long_term_fee_rate: fee_rate,
+            base_weight: weight::tx_weight(
+                None,
+                outputs
+                    .iter()
+                    .map(|(script, _)| weight::output_weight(script)),
+                segwit,
+            ),
+            change_weight: weight::output_weight(&change.script),
+            change_spend_weight: weight::input_weight(descriptor, tap_path)?,
+            min_change: change.script.dust_value().to_sat(),
+        })
+    }
+
+    /// Fee for the transaction data not related to its inputs
+    #[inline]
+    pub fn base_fee(&self) -> u64 { self.fee_rate.fee_for_weight(self.base_weight) }
+
+    /// Cost of creating change output now and spending it later
+    #[inline]
+    pub fn cost_of_change(&self) -> u64 {
+        self.fee_rate.fee_for_weight(self.change_weight)
+            + self
+                .long_term_fee_rate
+                .fee_for_weight(self.change_spend_weight)
+    }
+
+    /// Amount which has to be covered by the effective values of the
+    /// selected inputs
+    #[inline]
+    pub fn selection_target(&self) -> u64 { self.target + self.base_fee() }
+
+    fn input_waste(&self, candidate: &Candidate) -> i64 {
+        candidate.fee(self.fee_rate) as i64 - candidate.fee(self.long_term_fee_rate) as i64
+    }
+}
+
+/// Result of coin selection
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Selection {
+    /// Algorithm which produced the selection
+    pub algorithm: Algorithm,
+
+    /// Selected inputs
+    pub inputs: Vec<InputDescriptor>,
+
+    /// Sum of amounts of the selected inputs
+    pub amount: u64,
+
+    /// Transaction fee, including the excess of changeless selections
+    pub fee: u64,
+
+    /// Amount of change output, or zero if the transaction has no change
+    pub change: u64,
+
+    /// Waste metric of the selection: the fee paid now for the inputs above
+    /// the long-term fee rate plus either the cost of change or the excess
+    /// given to the miners. Selections with smaller waste are better.
+    pub waste: i64,
+}
+
+impl Selection {
+    fn with(
+        algorithm: Algorithm,
+        selected: &[&Candidate],
+        params: &SelectionParams,
+    ) -> Option<Selection> {
+        let amount = selected.iter().map(|c| c.amount).sum::<u64>();
+        let input_weight = selected.iter().map(|c| c.weight).sum::<u64>();
+        let input_waste = selected.iter().map(|c| params.input_waste(c)).sum::<i64>();
+
+        let changeless_fee = params
+            .fee_rate
+            .fee_for_weight(params.base_weight + input_weight);
+        let fee = params
+            .fee_rate
+            .fee_for_weight(params.base_weight + input_weight + params.change_weight);
+        let (fee, change, waste) = match amount.checked_sub(params.target + fee) {
+            Some(change) if change >= params.min_change => {
+                (fee, change, input_waste + params.cost_of_change() as i64)
+            }
+            _ => {
+                let excess = amount.checked_sub(params.target + changeless_fee)?;
+                (changeless_fee + excess, 0, input_waste + excess as i64)
+            }
+        };
+
+        Some(Selection {
+            algorithm,
+            inputs: selected.iter().map(|c| c.input.clone()).collect(),
+            amount,
+            fee,
+            change,
+            waste,
+        })
+    }
+}
+
+/// PSBT constructed out of the inputs chosen with coin selection
+#[derive(Clone, PartialEq, Debug)]
+pub struct SelectionConstruction {
+    /// Constructed PSBT
+    pub psbt: Psbt,
+
+    /// Coin selection which produced the PSBT inputs, fee and change
+    pub selection: Selection,
+}
+
+impl Psbt {
+    /// Constructs PSBT paying to the `outputs` with inputs chosen out of the
+    /// wallet UTXOs with [`select`], such that no input descriptors have to be
+    /// provided. All `candidates` must spend outputs of the `descriptor`, which
+    /// also receives the change at `change_index`.
+    ///
+    /// Selection parameters may be computed with
+    /// [`SelectionParams::with_descriptor`]; their target is replaced with the
+    /// sum of the output amounts.
+    pub fn construct_with_selection(
+        descriptor: &Descriptor<DerivationAccount>,
+        candidates: &[Candidate],
+        outputs: &[(PubkeyScript, u64)],
+        change_index: impl Into<UnhardenedIndex>,
+        params: &SelectionParams,
+        rng: &mut impl RngCore,
+        tx_resolver: &impl ResolveTx,
+    ) -> Result<SelectionConstruction, Error> {
+        let params = SelectionParams {
+            target: outputs.iter().map(|(_, amount)| amount).sum(),
+            ..*params
+        };
+        let selection = select(candidates, &params, rng)?;
+        let psbt = Psbt::construct(
+            descriptor,
+            &selection.inputs,
+            outputs,
+            change_index,
+            selection.fee,
+            tx_resolver,
+        )?;
+        Ok(SelectionConstruction { psbt, selection })
+    }
+}
+
+/// Runs all coin selection algorithms and returns the selection with the
+/// least waste; Branch-and-Bound changeless solution wins the ties.
+pub fn select(
+    candidates: &[Candidate],
+    params: &SelectionParams,
+    rng: &mut impl RngCore,
+) -> Result<Selection, SelectionError> {
+    let available = candidates
+        .iter()
+        .map(|c| c.effective_value(params.fee_rate))
+        .filter(|value| *value > 0)
+        .sum::<i64>() as u64;
+    if available < params.selection_target() {
+        return Err(SelectionError::InsufficientFunds {
+            available,
+            required: params.selection_target(),
+        });
+    }
+
+    [
+        branch_and_bound(candidates, params),
+        knapsack(candidates, params, rng),
+        single_random_draw(candidates, params, rng),
+    ]
+    .into_iter()
+    .filter_map(Result::ok)
+    .min_by_key(|selection| selection.waste)
+    .ok_or(SelectionError::NoSelection)
+}
+
+fn positive_pool<'c>(candidates: &'c [Candidate], params: &SelectionParams) -> Vec<&'c Candidate> {
+    candidates
+        .iter()
+        .filter(|c| c.effective_value(params.fee_rate) > 0)
+        .collect()
+}
+
+/// Branch-and-Bound search for an input set which pays for the outputs and
+/// the fee without producing change, within the tolerance of the cost of
+/// change. Among the matching sets the one with the least waste is returned.
+pub fn branch_and_bound(
+    candidates: &[Candidate],
+    params: &SelectionParams,
+) -> Result<Selection, SelectionError> {
+    let mut pool = positive_pool(candidates, params);
+    pool.sort_by_key(|c| -c.effective_value(params.fee_rate));
+    let values = pool
+        .iter()
+        .map(|c| c.effective_value(params.fee_rate))
+        .collect::<Vec<_>>();
+    let wastes = pool
+        .iter()
+        .map(|c| params.input_waste(c))
+        .collect::<Vec<_>>();
+
+    let target = params.selection_target() as i64;
+    let upper_bound = target + params.cost_of_change() as i64;
+    let high_fee_rate = params.fee_rate > params.long_term_fee_rate;
+
+    let mut available = values.iter().sum::<i64>();
+    let mut value = 0i64;
+    let mut waste = 0i64;
+    let mut selection = Vec::<bool>::with_capacity(pool.len());
+    let mut best: Option<(Vec<bool>, i64)> = None;
+
+    for _ in 0..BNB_MAX_TRIES {
+        let best_waste = best.as_ref().map(|(_, w)| *w).unwrap_or(i64::MAX);
+        let mut backtrack = false;
+        if value + available < target
+            || value > upper_bound
+            || (waste > best_waste && high_fee_rate)
+        {
+            backtrack = true;
+        } else if value >= target {
+            let total_waste = waste + value - target;
+            if total_waste <= best_waste {
+                best = Some((selection.clone(), total_waste));
+            }
+            backtrack = true;
+        }
+
+        if backtrack {
+            // Walk back to the last included candidate
+            while let Some(false) = selection.last() {
+                selection.pop();
+                available += values[selection.len()];
+            }
+            let Some(last) = selection.last_mut() else {
+                break;
+            };
+            // The candidate was included previously; try to exclude it now
+            *last = false;
+            let pos = selection.len() - 1;
+            value -= values[pos];
+            waste -= wastes[pos];
+        } else {
+            let pos = selection.len();
+            available -= values[pos];
+            // Skip branches which are equivalent to the previously excluded one
+            if pos > 0
+                && !selection[pos - 1]
+                && values[pos] == values[pos - 1]
+                && wastes[pos] == wastes[pos - 1]
+            {
+                selection.push(false);
+            } else {
+                selection.push(true);
+                value += values[pos];
+                waste += wastes[pos];
+            }
+        }
+    }
+
+    let (selection, _) = best.ok_or(SelectionError::NoSolution(Algorithm::BranchAndBound))?;
+    let selected = pool
+        .into_iter()
+        .zip(selection)
+        .filter_map(|(c, selected)| selected.then_some(c))
+        .collect::<Vec<_>>();
+    let mut result = Selection::with(Algorithm::BranchAndBound, &selected, params)
+        .ok_or(SelectionError::NoSolution(Algorithm::BranchAndBound))?;
+    // Branch-and-bound solutions are always changeless
+    if result.change > 0 {
+        result.fee += result.change;
+        result.waste += result.change as i64 - params.cost_of_change() as i64;
+        result.change = 0;
+    }
+    Ok(result)
+}
+
+/// Knapsack solver: looks for the subset of inputs which covers the target
+/// plus minimal change with the least excess, using stochastic approximation,
+/// and falls back to the smallest single input exceeding the target.
+pub fn knapsack(
+    candidates: &[Candidate],
+    params: &SelectionParams,
+    rng: &mut impl RngCore,
+) -> Result<Selection, SelectionError> {
+    let mut pool = positive_pool(candidates, params);
+    pool.shuffle(rng);
+    let value = |c: &Candidate| c.effective_value(params.fee_rate) as u64;
+
+    let target = params.selection_target() + params.fee_rate.fee_for_weight(params.change_weight);
+    let change_target = target + params.min_change;
+
+    if let Some(exact) = pool.iter().find(|c| value(c) == target) {
+        return Selection::with(Algorithm::Knapsack, &[exact], params)
+            .ok_or(SelectionError::NoSolution(Algorithm::Knapsack));
+    }
+
+    let mut lesser = pool
+        .iter()
+        .copied()
+        .filter(|c| value(c) < change_target)
+        .collect::<Vec<_>>();
+    let lowest_larger = pool
+        .iter()
+        .copied()
+        .filter(|c| value(c) >= change_target)
+        .min_by_key(|c| value(c));
+    let lesser_total = lesser.iter().map(|c| value(c)).sum::<u64>();
+
+    let selected = if lesser_total == target {
+        lesser
+    } else if lesser_total < target {
+        vec![lowest_larger.ok_or(SelectionError::NoSolution(Algorithm::Knapsack))?]
+    } else {
+        lesser.sort_by_key(|c| std::cmp::Reverse(value(c)));
+        let values = lesser.iter().map(|c| value(c)).collect::<Vec<_>>();
+        let (mut best, mut best_total) = approximate_best_subset(&values, target, rng);
+        if best_total != target && lesser_total >= change_target {
+            (best, best_total) = approximate_best_subset(&values, change_target, rng);
+        }
+        match lowest_larger {
+            Some(larger)
+                if (best_total != target && best_total < change_target)
+                    || value(larger) <= best_total =>
+            {
+                vec![larger]
+            }
+            _ => lesser
+                .into_iter()
+                .zip(best)
+                .filter_map(|(c, selected)| selected.then_some(c))
+                .collect(),
+        }
+    };
+
+    Selection::with(Algorithm::Knapsack, &selected, params)
+        .ok_or(SelectionError::NoSolution(Algorithm::Knapsack))
+}
+
+fn approximate_best_subset(
+    values: &[u64],
+    target: u64,
+    rng: &mut impl RngCore,
+) -> (Vec<bool>, u64) {
+    let mut best = vec![true; values.len()];
+    let mut best_total = values.iter().sum::<u64>();
+
+    for _ in 0..KNAPSACK_ITERATIONS {
+        if best_total == target {
+            break;
+        }
+        let mut included = vec![false; values.len()];
+        let mut total = 0u64;
+        let mut reached = false;
+        for pass in 0..2 {
+            if reached {
+                break;
+            }
+            for (pos, value) in values.iter().enumerate() {
+                // The first pass selects randomly, the second one adds all
+                // remaining values
+                let take = if pass == 0 {
+                    rng.gen_bool(0.5)
+                } else {
+                    !included[pos]
+                };
+                if !take {
+                    continue;
+                }
+                total += value;
+                included[pos] = true;
+                if total >= target {
+                    reached = true;
+                    if total < best_total {
+                        best_total = total;
+                        best = included.clone();
+                    }
+                    total -= value;
+                    included[pos] = false;
+                }
+            }
+        }
+    }
+
+    (best, best_total)
+}
+
+/// Single random draw: adds randomly chosen inputs until they cover the
+/// target, the fee and the change.
+pub fn single_random_draw(
+    candidates: &[Candidate],
+    params: &SelectionParams,
+    rng: &mut impl RngCore,
+) -> Result<Selection, SelectionError> {
+    let mut pool = positive_pool(candidates, params);
+    pool.shuffle(rng);
+
+    let target = params.selection_target()
+        + params.fee_rate.fee_for_weight(params.change_weight)
+        + params.min_change;
+    let mut total = 0u64;
+    let mut selected = vec![];
+    for candidate in pool {
+        total += candidate.effective_value(params.fee_rate) as u64;
+        selected.push(candidate);
+        if total >= target {
+            return Selection::with(Algorithm::SingleRandomDraw, &selected, params)
+                .ok_or(SelectionError::NoSolution(Algorithm::SingleRandomDraw));
+        }
+    }
+    Err(SelectionError::NoSolution(Algorithm::SingleRandomDraw))
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use bitcoin::hashes::Hash;
+    use bitcoin::secp256k1::rand::rngs::StdRng;
+    use bitcoin::secp256k1::rand::SeedableRng;
+    use bitcoin::secp256k1::SECP256K1;
+    use bitcoin::{OutPoint, PackedLockTime, Transaction, TxIn, TxOut, Txid};
+    use bitcoin_hd::SegmentIndexes;
+    use bitcoin_onchain::blockchain::MiningStatus;
+    use descriptors::derive::Descriptor as _;
+
+    use super::*;
+
+    fn candidates(amounts: &[u64]) -> Vec<Candidate> {
+        amounts
+            .iter()
+            .enumerate()
+            .map(|(vout, amount)| Candidate {
+                input: InputDescriptor {
+                    outpoint: OutPoint::new(Txid::all_zeros(), vout as u32),
+                    terminal: default!(),
+                    seq_no: none!(),
+                    tweak: None,
+                    sighash_type: bitcoin::EcdsaSighashType::All,
+                },
+                amount: *amount,
+                weight: 272,
+            })
+            .collect()
+    }
+
+    fn params_for(target: u64) -> SelectionParams {
+        SelectionParams {
+            target,
+            fee_rate: FeeRate::from_sat_per_vb(1),
+            long_term_fee_rate: FeeRate::from_sat_per_vb(1),
+            base_weight: 40 + 31 * 4,
+            change_weight: 31 * 4,
+            change_spend_weight: 272,
+            min_change: 1000,
+        }
+    }
+
+    #[test]
+    fn bnb_changeless() {
+        // Effective values are amounts minus 68 sats of input fee
+        let pool = candidates(&[90_068, 200_068, 300_068, 50_000, 7_068]);
+        let params = params_for(307_000 - params_for(0).base_fee());
+
+        let selection = branch_and_bound(&pool, &params).unwrap();
+        assert_eq!(selection.change, 0);
+        assert_eq!(selection.amount, 307_136);
+        assert_eq!(selection.waste, 0);
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let selection = select(&pool, &params, &mut rng).unwrap();
+        assert_eq!(selection.algorithm, Algorithm::BranchAndBound);
+        assert_eq!(selection.amount - selection.fee, params.target);
+    }
+
+    #[test]
+    fn fallback_with_change() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let pool = candidates(&[100_000, 200_000, 300_000]);
+        let params = params_for(150_000);
+
+        assert_eq!(
+            branch_and_bound(&pool, &params),
+            Err(SelectionError::NoSolution(Algorithm::BranchAndBound))
+        );
+        for selection in [
+            knapsack(&pool, &params, &mut rng).unwrap(),
+            single_random_draw(&pool, &params, &mut rng).unwrap(),
+        ] {
+            assert!(selection.change >= params.min_change);
+            assert_eq!(
+                selection.amount,
+                params.target + selection.fee + selection.change
+            );
+        }
+        assert_eq!(knapsack(&pool, &params, &mut rng).unwrap().amount, 200_000);
+
+        assert_eq!(
+            select(&pool, &params_for(600_000), &mut rng),
+            Err(SelectionError::InsufficientFunds {
+                available: 599_796,
+                required: 600_041
+            })
+        );
+    }
+
+    #[test]
+    fn immature_coinbase() {
+        let descriptor = Descriptor::<DerivationAccount>::from_str(
+            "wpkh([00000000/84h/0h/0h]xpub69PnGxAGwEBNtGPnxd71p2QbHRZvjDG1BEza1sZdRbd7uWkjHqfGxMburhdEocC5ud2NpkbhwnM29c2zdqWS36wJue1BuJgMnLTpxpxzJe1/<0;1>/*)",
+        )
+        .unwrap();
+        let terminal = [UnhardenedIndex::zero(), UnhardenedIndex::zero()];
+        let utxo = |vout: u32, height: u64, coinbase: bool| {
+            Utxo::new(
+                MiningStatus::Blockchain(height),
+                OutPoint::new(Txid::all_zeros(), vout),
+                bitcoin::Amount::from_sat(10_000),
+            )
+            .with_terminal(terminal)
+            .with_coinbase(coinbase)
+        };
+        let utxo_set = [
+            utxo(0, 1000, false),
+            utxo(1, 950, true),
+            utxo(2, 901, true),
+            Utxo::new(
+                MiningStatus::Mempool,
+                OutPoint::new(Txid::all_zeros(), 3),
+                bitcoin::Amount::from_sat(10_000),
+            ),
+        ];
+
+        let candidates =
+            Candidate::with_utxo(&descriptor, &utxo_set, 1000, TapSpendPath::KeyPath).unwrap();
+        let vouts = candidates
+            .iter()
+            .map(|c| c.input.outpoint.vout)
+            .collect::<Vec<_>>();
+        assert_eq!(vouts, vec![0, 2]);
+
+        let resolved = bmap! {
+            UnhardenedIndex::zero() => (Script::new(), utxo_set.into_iter().collect::<HashSet<_>>())
+        };
+        let candidates = Candidate::with_resolved_utxo(
+            &descriptor,
+            [UnhardenedIndex::zero()],
+            &resolved,
+            999,
+            TapSpendPath::KeyPath,
+        )
+        .unwrap();
+        let mut vouts = candidates
+            .iter()
+            .map(|c| c.input.outpoint.vout)
+            .collect::<Vec<_>>();
+        vouts.sort();
+        assert_eq!(vouts, vec![0, 3]);
+    }
+
+    #[test]
+    fn construct_selected() {
+        let descriptor = Descriptor::<DerivationAccount>::from_str(
+            "wpkh([d34db33f/84h/0h/0h]xpub69PnGxAGwEBNtGPnxd71p2QbHRZvjDG1BEza1sZdRbd7uWkjHqfGxMburhdEocC5ud2NpkbhwnM29c2zdqWS36wJue1BuJgMnLTpxpxzJe1/<0;1>/*)",
+        )
+        .unwrap();
+        let terminal = [UnhardenedIndex::zero(), UnhardenedIndex::from(2u8)];
+        let prev_tx = Transaction {
+            version: 2,
+            lock_time: PackedLockTime::ZERO,
+            input: vec![TxIn::default()],
+            output: [100_000, 200_000, 300_000]
+                .into_iter()
+                .map(|value| TxOut {
+                    value,
+                    script_pubkey: descriptor.script_pubkey_pretr(SECP256K1, terminal).unwrap(),
+                })
+                .collect(),
+        };
+        let utxo_set = prev_tx
+            .output
+            .iter()
+            .enumerate()
+            .map(|(vout, txout)| {
+                Utxo::new(
+                    MiningStatus::Blockchain(100),
+                    OutPoint::new(prev_tx.txid(), vout as u32),
+                    bitcoin::Amount::from_sat(txout.value),
+                )
+                .with_terminal(terminal)
+            })
+            .collect::<Vec<_>>();
+        let resolver = bmap! { prev_tx.txid() => prev_tx.clone() };
+
+        let candidates =
+            Candidate::with_utxo(&descriptor, &utxo_set, 200, TapSpendPath::KeyPath).unwrap();
+        let outputs = [(PubkeyScript::from(Script::from(vec![0x51; 34])), 150_000)];
+        let params = SelectionParams::with_descriptor(
+            &descriptor,
+            &outputs,
+            UnhardenedIndex::from(7u8),
+            FeeRate::from_sat_per_vb(2),
+            TapSpendPath::KeyPath,
+        )
+        .unwrap();
+        let construction = Psbt::construct_with_selection(
+            &descriptor,
+            &candidates,
+            &outputs,
+            UnhardenedIndex::from(7u8),
+            &params,
+            &mut StdRng::seed_from_u64(0),
+            &resolver,
+        )
+        .unwrap();
+
+        let SelectionConstruction { psbt, selection } = construction;
+        assert_eq!(psbt.inputs.len(), selection.inputs.len());
+        assert!(selection.change >= params.min_change);
+        assert_eq!(psbt.outputs.len(), 2);
+        assert_eq!(psbt.outputs[1].amount, selection.change);
+        assert_eq!(
+            psbt.inputs
+                .iter()
+                .map(|input| input.input_prevout().unwrap().value)
+                .sum::<u64>(),
+            150_000 + selection.fee + selection.change
+        );
+
+        assert!(matches!(
+            Psbt::construct_with_selection(
+                &descriptor,
+                &candidates,
+                &[(outputs[0].0.clone(), 600_000)],
+                UnhardenedIndex::from(7u8),
+                &params,
+                &mut StdRng::seed_from_u64(0),
+                &resolver,
+            ),
+            Err(Error::Selection(SelectionError::InsufficientFunds { .. }))
+        ));
+    }
+}
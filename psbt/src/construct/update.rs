@@ -0,0 +1,258 @@
+// Descriptor wallet library extending bitcoin & miniscript functionality
+// by LNP/BP Association (https://lnp-bp.org)
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the Apache-2.0 License
+// along with this software.
+// If not, see <https://opensource.org/licenses/Apache-2.0>.
+
+//! PSBT updater role: adding wallet descriptor information to PSBTs created
+//! by other parties.
+
+use std::collections::BTreeSet;
+use std::ops::Range;
+
+use bitcoin::secp256k1::SECP256K1;
+use bitcoin::Script;
+use bitcoin_hd::DerivationAccount;
+use bitcoin_onchain::ResolveTx;
+use descriptors::ScriptIndex;
+use miniscript::{Descriptor, ForEachKey};
+
+use super::{update_input, update_output, Error};
+use crate::Psbt;
+
+/// Indexes of PSBT inputs and outputs updated by [`Psbt::update`]
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Default)]
+pub struct UpdateReport {
+    /// Indexes of the inputs spending descriptor outputs
+    pub inputs: Vec<usize>,
+
+    /// Indexes of the outputs paying to the descriptors
+    pub outputs: Vec<usize>,
+}
+
+impl UpdateReport {
+    /// Detects whether none of PSBT inputs and outputs were updated
+    #[inline]
+    pub fn is_empty(&self) -> bool { self.inputs.is_empty() && self.outputs.is_empty() }
+}
+
+impl Psbt {
+    /// Fills BIP-174 and BIP-371 fields of PSBT inputs and outputs belonging
+    /// to any of the `descriptors`: key derivation information, redeem and
+    /// witness scripts, taproot internal keys, script trees and key origins.
+    /// Account xpubs of the matched descriptors are added to the global PSBT
+    /// data.
+    ///
+    /// Inputs and outputs are matched against scripts derived from each
+    /// descriptor for the indexes within `window` on each of its derivation
+    /// branches. Transactions spent by the matched inputs are resolved with
+    /// `tx_resolver` to fill in `non_witness_utxo`; inputs having neither
+    /// `witness_utxo` nor `non_witness_utxo` are resolved to learn the spent
+    /// scriptPubkey. Inputs whose spent output can't be resolved are assumed
+    /// to be foreign and are left intact.
+    pub fn update(
+        &mut self,
+        descriptors: &[Descriptor<DerivationAccount>],
+        window: Range<u32>,
+        tx_resolver: &impl ResolveTx,
+    ) -> Result<UpdateReport, Error> {
+        let indexes = descriptors
+            .iter()
+            .map(|descriptor| ScriptIndex::new(SECP256K1, descriptor.clone(), window.clone()))
+            .collect::<Result<Vec<_>, _>>()?;
+        let find = |script: &Script| {
+            indexes
+                .iter()
+                .enumerate()
+                .find_map(|(no, index)| Some((no, index.get(script)?.terminal())))
+        };
+
+        let mut report = UpdateReport::default();
+        let mut matched = BTreeSet::new();
+
+        for input in &mut self.inputs {
+            let outpoint = input.previous_outpoint;
+            let mut prev_tx = input
+                .non_witness_utxo
+                .clone()
+                .filter(|tx| tx.txid() == outpoint.txid);
+            let script_pubkey = match input.input_prevout() {
+                Ok(txout) => txout.script_pubkey.clone(),
+                Err(_) => {
+                    let tx = match tx_resolver.resolve_tx(outpoint.txid) {
+                        Ok(tx) => tx,
+                        Err(_) => continue,
+                    };
+                    let script_pubkey = match tx.output.get(outpoint.vout as usize) {
+                        Some(txout) => txout.script_pubkey.clone(),
+                        None => continue,
+                    };
+                    prev_tx = Some(tx);
+                    script_pubkey
+                }
+            };
+            let (no, terminal) = match find(&script_pubkey) {
+                Some(found) => found,
+                None => continue,
+            };
+
+            let mut tx = match prev_tx {
+                Some(tx) => tx,
+                None => tx_resolver.resolve_tx(outpoint.txid)?,
+            };
+            // Cut out witness data
+            for txin in &mut tx.input {
+                txin.witness = zero!();
+            }
+            let prev_output = tx
+                .output
+                .get(outpoint.vout as usize)
+                .ok_or(Error::OutputUnknown(outpoint.txid, outpoint.vout))?
+                .clone();
+
+            update_input(&descriptors[no], terminal, &prev_output, input)?;
+            input.non_witness_utxo = Some(tx);
+            report.inputs.push(input.index());
+            matched.insert(no);
+        }
+
+        for output in &mut self.outputs {
+            if let Some((no, terminal)) = find(&output.script) {
+                update_output(&descriptors[no], terminal, output)?;
+                report.outputs.push(output.index());
+                matched.insert(no);
+            }
+        }
+
+        for no in matched {
+            descriptors[no].for_each_key(|account| {
+                if let Some(key_source) = account.account_key_source() {
+                    self.xpub.insert(account.account_xpub, key_source);
+                }
+                true
+            });
+        }
+
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use bitcoin::hashes::Hash;
+    use bitcoin::secp256k1::SECP256K1;
+    use bitcoin::{OutPoint, PackedLockTime, Script, Transaction, TxIn, TxOut, Txid};
+    use bitcoin_hd::{SegmentIndexes, UnhardenedIndex};
+    use bitcoin_scripts::PubkeyScript;
+    use descriptors::derive::Descriptor as _;
+    use descriptors::InputDescriptor;
+
+    use super::*;
+    use crate::{Input, PsbtVersion};
+
+    #[test]
+    fn foreign_psbt() {
+        let account = "[00000000/86h/0h/0h]xpub69PnGxAGwEBNtGPnxd71p2QbHRZvjDG1BEza1sZdRbd7uWkjHqfGxMburhdEocC5ud2NpkbhwnM29c2zdqWS36wJue1BuJgMnLTpxpxzJe1/<0;1>/*";
+        let other = "xpub661MyMwAqRbcFtXgS5sYJABqqG9YLmC4Q1Rdap9gSE8NqtwybGhePY2gZ29ESFjqJoCu1Rupje8YtGqsefD265TMg7usUDFdp6W1EGMcet8/<0;1>/*";
+        let descriptors = [
+            Descriptor::<DerivationAccount>::from_str(&format!("wpkh({account})")).unwrap(),
+            Descriptor::<DerivationAccount>::from_str(&format!(
+                "tr({account},and_v(v:pk({other}),older(144)))"
+            ))
+            .unwrap(),
+        ];
+        let terminal = [UnhardenedIndex::zero(), UnhardenedIndex::from(3u8)];
+        let foreign = Script::new_v0_p2wpkh(&bitcoin::WPubkeyHash::from_slice(&[1u8; 20]).unwrap());
+        let prev_tx = Transaction {
+            version: 2,
+            lock_time: PackedLockTime::ZERO,
+            input: vec![TxIn::default()],
+            output: vec![
+                TxOut {
+                    value: 100_000,
+                    script_pubkey: descriptors[0]
+                        .script_pubkey_pretr(SECP256K1, terminal)
+                        .unwrap(),
+                },
+                TxOut {
+                    value: 100_000,
+                    script_pubkey: descriptors[1]
+                        .script_pubkey_tr(SECP256K1, terminal)
+                        .unwrap(),
+                },
+            ],
+        };
+        let resolver = bmap! { prev_tx.txid() => prev_tx.clone() };
+
+        for (vout, descriptor) in descriptors.iter().enumerate() {
+            let input = InputDescriptor {
+                outpoint: OutPoint::new(prev_tx.txid(), vout as u32),
+                terminal: terminal.iter().copied().collect(),
+                seq_no: none!(),
+                tweak: None,
+                sighash_type: bitcoin::EcdsaSighashType::All,
+            };
+            let mut expected = Psbt::construct(
+                descriptor,
+                [&input],
+                &[(PubkeyScript::from(foreign.clone()), 10_000)],
+                UnhardenedIndex::from(4u8),
+                1000,
+                &resolver,
+            )
+            .unwrap();
+
+            // PSBT created by other party, which includes a foreign input
+            let mut psbt = Psbt::with(expected.to_unsigned_tx().unwrap(), PsbtVersion::V0).unwrap();
+            // Sequence number and sighash type are not touched by the updater
+            expected.inputs[0].sequence_number = psbt.inputs[0].sequence_number;
+            expected.inputs[0].sighash_type = None;
+            let foreign_input = Input {
+                index: 1,
+                previous_outpoint: OutPoint::new(Txid::all_zeros(), 0),
+                witness_utxo: Some(TxOut {
+                    value: 5_000,
+                    script_pubkey: foreign.clone(),
+                }),
+                ..default!()
+            };
+            psbt.inputs.push(foreign_input.clone());
+            expected.inputs.push(foreign_input);
+            let unknown = psbt.clone();
+
+            let report = psbt.update(&descriptors, 0..5, &resolver).unwrap();
+            assert_eq!(report, UpdateReport {
+                inputs: vec![0],
+                outputs: vec![1],
+            });
+            assert_eq!(psbt, expected);
+
+            // Derivation index is outside of the search window
+            let mut psbt = unknown.clone();
+            assert!(psbt
+                .update(&descriptors, 0..3, &resolver)
+                .unwrap()
+                .is_empty());
+            assert_eq!(psbt, unknown);
+
+            // Foreign input without spent output information which can't be
+            // resolved is skipped
+            let mut psbt = unknown.clone();
+            psbt.inputs[1].witness_utxo = None;
+            expected.inputs[1].witness_utxo = None;
+            let report = psbt.update(&descriptors, 0..5, &resolver).unwrap();
+            assert_eq!(report.inputs, vec![0]);
+            assert_eq!(psbt, expected);
+        }
+    }
+}
@@ -0,0 +1,326 @@
+// Descriptor wallet library extending bitcoin & miniscript functionality
+// by LNP/BP Association (https://lnp-bp.org)
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// To the extent possible under law, the author(s) have dedicated all
+// copyright and related and neighboring rights to this software to
+// the public domain worldwide. This software is distributed without
+// any warranty.
+//
+// You should have received a copy of the Apache-2.0 License
+// along with this software.
+// If not, see <https://opensource.org/licenses/Apache-2.0>.
+
+//! Estimation of the final transaction weight from the maximal satisfaction
+//! weight of the descriptor.
+
+use std::iter;
+
+use bitcoin::consensus::encode::VarInt;
+use bitcoin::Script;
+use bitcoin_hd::DerivationAccount;
+use descriptors::CompositeDescrType;
+use miniscript::Descriptor;
+
+use crate::Psbt;
+
+/// Weight of transaction input data not including script satisfaction:
+/// outpoint and sequence number
+pub const TXIN_BASE_WEIGHT: u64 = (32 + 4 + 4) * 4;
+
+/// Weight of a taproot key-path spend satisfaction: scriptSig length, witness
+/// stack length, signature length and signature with non-default sighash type
+pub const TAPROOT_KEY_SPEND_WEIGHT: u64 = 4 + 1 + 1 + 65;
+
+/// Way taproot outputs are assumed to be spent in weight estimation
+#[derive(
+    Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display, Default
+)]
+pub enum TapSpendPath {
+    /// Spending with the signature of the internal key
+    #[default]
+    #[display("key-path")]
+    KeyPath,
+
+    /// Spending with the most expensive script from the script tree
+    #[display("script-path")]
+    ScriptPath,
+}
+
+/// Computes upper bound on the weight of a satisfaction for an input spending
+/// descriptor output, including scriptSig and witness length data.
+///
+/// For taproot descriptors without script tree the key path is always used.
+pub fn max_satisfaction_weight(
+    descriptor: &Descriptor<DerivationAccount>,
+    tap_path: TapSpendPath,
+) -> Result<u64, miniscript::Error> {
+    match (descriptor, tap_path) {
+        (Descriptor::Tr(_), TapSpendPath::KeyPath) => Ok(TAPROOT_KEY_SPEND_WEIGHT),
+        (descriptor, _) => descriptor.max_satisfaction_weight().map(|w| w as u64),
+    }
+}
+
+/// Computes upper bound on the weight of a transaction input spending
+/// descriptor output.
+#[inline]
+pub fn input_weight(
+    descriptor: &Descriptor<DerivationAccount>,
+    tap_path: TapSpendPath,
+) -> Result<u64, miniscript::Error> {
+    Ok(TXIN_BASE_WEIGHT + max_satisfaction_weight(descriptor, tap_path)?)
+}
+
+/// Computes weight of a transaction output with the given `scriptPubkey`.
+#[inline]
+pub fn output_weight(script_pubkey: &Script) -> u64 {
+    (8 + VarInt(script_pubkey.len() as u64).len() as u64 + script_pubkey.len() as u64) * 4
+}
+
+/// Computes weight of a transaction with the given input weights (including
+/// their satisfaction data) and output weights.
+pub fn tx_weight(
+    input_weights: impl IntoIterator<Item = u64>,
+    output_weights: impl IntoIterator<Item = u64>,
+    segwit: bool,
+) -> u64 {
+    let (input_count, inputs) = input_weights
+        .into_iter()
+        .fold((0u64, 0u64), |(count, sum), weight| {
+            (count + 1, sum + weight)
+        });
+    let (output_count, outputs) = output_weights
+        .into_iter()
+        .fold((0u64, 0u64), |(count, sum), weight| {
+            (count + 1, sum + weight)
+        });
+    // version, input and output counts and lock time
+    let base = (4 + VarInt(input_count).len() as u64 + VarInt(output_count).len() as u64 + 4) * 4;
+    // segwit marker and flag
+    let marker = if segwit { 2 } else { 0 };
+    base + marker + inputs + outputs
+}
+
+/// Converts transaction weight into virtual size
+#[inline]
+pub fn weight_to_vsize(weight: u64) -> u64 { (weight + 3) / 4 }
+
+impl Psbt {
+    /// Estimates weight of the final transaction which will be produced from
+    /// this PSBT once all of its inputs spending outputs of the `descriptor`
+    /// are satisfied. Inputs which are already finalized are counted with
+    /// their actual satisfaction data.
+    #[inline]
+    pub fn estimate_weight(
+        &self,
+        descriptor: &Descriptor<DerivationAccount>,
+        tap_path: TapSpendPath,
+    ) -> Result<u64, miniscript::Error> {
+        self.estimate_weight_multi(iter::repeat(descriptor).take(self.inputs.len()), tap_path)
+    }
+
+    /// Estimates weight of the final transaction which will be produced from
+    /// this PSBT, where each of the inputs spends an output of its own
+    /// descriptor, provided in the order of the inputs. Inputs which are
+    /// already finalized are counted with their actual satisfaction data.
+    ///
+    /// Errors if no descriptor is provided for some of the inputs which are
+    /// not finalized.
+    pub fn estimate_weight_multi<'descr>(
+        &self,
+        descriptors: impl IntoIterator<Item = &'descr Descriptor<DerivationAccount>>,
+        tap_path: TapSpendPath,
+    ) -> Result<u64, miniscript::Error> {
+        let mut descriptors = descriptors.into_iter();
+        // Descriptors of the inputs which are not finalized yet
+        let mut pending = Vec::with_capacity(self.inputs.len());
+        for input in &self.inputs {
+            let descriptor = descriptors.next();
+            let finalized =
+                input.final_script_sig.is_some() || input.final_script_witness.is_some();
+            match descriptor {
+                _ if finalized => pending.push(None),
+                Some(descriptor) => pending.push(Some(descriptor)),
+                None => {
+                    return Err(miniscript::Error::Unexpected(format!(
+                        "no descriptor is provided for PSBT input #{}",
+                        input.index
+                    )))
+                }
+            }
+        }
+
+        // Transaction gets segwit serialization if any of its inputs has
+        // witness, in which case inputs without witness take one byte of the
+        // empty witness stack length
+        let segwit = self
+            .inputs
+            .iter()
+            .zip(&pending)
+            .any(|(input, descriptor)| match descriptor {
+                Some(descriptor) => CompositeDescrType::from(*descriptor).is_segwit(),
+                None => input.final_script_witness.is_some(),
+            });
+        let empty_witness = u64::from(segwit);
+
+        let input_weights = self
+            .inputs
+            .iter()
+            .zip(pending)
+            .map(|(input, descriptor)| match descriptor {
+                Some(descriptor) if CompositeDescrType::from(descriptor).is_segwit() => {
+                    input_weight(descriptor, tap_path)
+                }
+                Some(descriptor) => Ok(input_weight(descriptor, tap_path)? + empty_witness),
+                None => {
+                    let sig_script_len = input
+                        .final_script_sig
+                        .as_ref()
+                        .map(|s| s.len())
+                        .unwrap_or_default();
+                    Ok(TXIN_BASE_WEIGHT
+                        + (VarInt(sig_script_len as u64).len() + sig_script_len) as u64 * 4
+                        + input
+                            .final_script_witness
+                            .as_ref()
+                            .map(|w| w.serialized_len() as u64)
+                            .unwrap_or(empty_witness))
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let output_weights = self
+            .outputs
+            .iter()
+            .map(|output| output_weight(&output.script));
+        Ok(tx_weight(input_weights, output_weights, segwit))
+    }
+
+    /// Estimates virtual size of the final transaction which will be produced
+    /// from this PSBT. See [`Psbt::estimate_weight`] for the details.
+    #[inline]
+    pub fn estimate_vsize(
+        &self,
+        descriptor: &Descriptor<DerivationAccount>,
+        tap_path: TapSpendPath,
+    ) -> Result<u64, miniscript::Error> {
+        self.estimate_weight(descriptor, tap_path)
+            .map(weight_to_vsize)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use bitcoin::secp256k1::SECP256K1;
+    use bitcoin::{OutPoint, PackedLockTime, Transaction, TxIn, TxOut};
+    use bitcoin_hd::{SegmentIndexes, UnhardenedIndex};
+    use descriptors::derive::Descriptor as _;
+    use descriptors::InputDescriptor;
+
+    use super::*;
+    use crate::PsbtVersion;
+
+    #[test]
+    fn taproot_spend_paths() {
+        let account = "[00000000/86h/0h/0h]xpub69PnGxAGwEBNtGPnxd71p2QbHRZvjDG1BEza1sZdRbd7uWkjHqfGxMburhdEocC5ud2NpkbhwnM29c2zdqWS36wJue1BuJgMnLTpxpxzJe1/<0;1>/*";
+        let other = "xpub661MyMwAqRbcFtXgS5sYJABqqG9YLmC4Q1Rdap9gSE8NqtwybGhePY2gZ29ESFjqJoCu1Rupje8YtGqsefD265TMg7usUDFdp6W1EGMcet8/<0;1>/*";
+        let keypath = Descriptor::<DerivationAccount>::from_str(&format!("tr({account})")).unwrap();
+        assert_eq!(
+            input_weight(&keypath, TapSpendPath::ScriptPath).unwrap(),
+            TXIN_BASE_WEIGHT + TAPROOT_KEY_SPEND_WEIGHT
+        );
+
+        let scripted = Descriptor::<DerivationAccount>::from_str(&format!(
+            "tr({account},and_v(v:pk({other}),older(144)))"
+        ))
+        .unwrap();
+        let keypath = input_weight(&scripted, TapSpendPath::KeyPath).unwrap();
+        let scriptpath = input_weight(&scripted, TapSpendPath::ScriptPath).unwrap();
+        assert_eq!(keypath, 231);
+        assert!(scriptpath > keypath);
+
+        // One-input two-output taproot transaction, assuming signatures with
+        // non-default sighash type
+        let weight = tx_weight(
+            [keypath],
+            [output_weight(&Script::from(vec![0x51; 34])); 2],
+            true,
+        );
+        assert_eq!(weight_to_vsize(weight), 155);
+    }
+
+    #[test]
+    fn per_input_descriptors() {
+        let xpub = "[d34db33f/84h/0h/0h]xpub69PnGxAGwEBNtGPnxd71p2QbHRZvjDG1BEza1sZdRbd7uWkjHqfGxMburhdEocC5ud2NpkbhwnM29c2zdqWS36wJue1BuJgMnLTpxpxzJe1/<0;1>/*";
+        let legacy = Descriptor::<DerivationAccount>::from_str(&format!("pkh({xpub})")).unwrap();
+        let segwit = Descriptor::<DerivationAccount>::from_str(&format!("wpkh({xpub})")).unwrap();
+        let terminal = [UnhardenedIndex::zero(), UnhardenedIndex::zero()];
+        let prev_tx = Transaction {
+            version: 2,
+            lock_time: PackedLockTime::ZERO,
+            input: vec![TxIn::default()],
+            output: vec![
+                TxOut {
+                    value: 50_000,
+                    script_pubkey: legacy.script_pubkey_pretr(SECP256K1, terminal).unwrap(),
+                },
+                TxOut {
+                    value: 70_000,
+                    script_pubkey: segwit.script_pubkey_pretr(SECP256K1, terminal).unwrap(),
+                },
+            ],
+        };
+        let inputs = (0..2)
+            .map(|vout| InputDescriptor {
+                outpoint: OutPoint::new(prev_tx.txid(), vout),
+                terminal: terminal.iter().copied().collect(),
+                seq_no: none!(),
+                tweak: None,
+                sighash_type: bitcoin::EcdsaSighashType::All,
+            })
+            .collect::<Vec<_>>();
+        let resolver = bmap! { prev_tx.txid() => prev_tx.clone() };
+        let output = |psbt: &Psbt| output_weight(&psbt.outputs[0].script);
+
+        // Legacy transactions have no empty witness data
+        let psbt = Psbt::construct(&legacy, &inputs[..1], &[], 0u8, 1000, &resolver).unwrap();
+        assert_eq!(
+            psbt.estimate_weight(&legacy, TapSpendPath::KeyPath)
+                .unwrap(),
+            tx_weight(
+                [input_weight(&legacy, TapSpendPath::KeyPath).unwrap()],
+                [output(&psbt)],
+                false
+            )
+        );
+
+        // Legacy input in segwit transaction takes a byte of empty witness
+        let psbt = Psbt::construct_multi(
+            [(&legacy, &inputs[0]), (&segwit, &inputs[1])],
+            &[],
+            &segwit,
+            0u8,
+            1000,
+            PsbtVersion::V0,
+            &resolver,
+        )
+        .unwrap();
+        assert_eq!(
+            psbt.estimate_weight_multi([&legacy, &segwit], TapSpendPath::KeyPath)
+                .unwrap(),
+            tx_weight(
+                [
+                    input_weight(&legacy, TapSpendPath::KeyPath).unwrap() + 1,
+                    input_weight(&segwit, TapSpendPath::KeyPath).unwrap()
+                ],
+                [output(&psbt)],
+                true
+            )
+        );
+        assert!(psbt
+            .estimate_weight_multi([&legacy], TapSpendPath::KeyPath)
+            .is_err());
+    }
+}
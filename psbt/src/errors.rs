@@ -9,7 +9,7 @@
 // along with this software.
 // If not, see <https://opensource.org/licenses/Apache-2.0>.
 
-use bitcoin::Txid;
+use bitcoin::{OutPoint, Txid};
 
 /// Errors during [`Input`](super::Input) construction from an unsigned
 /// transaction input (see [`Input::new`](super::Input::new)).
@@ -59,6 +59,29 @@ pub enum InputMatchError {
     UnmatchedInputNumber(u32),
 }
 
+/// Error computing transaction locktime of a PSBT (see
+/// [`Psbt::lock_time`](super::Psbt::lock_time))
+#[derive(
+    Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display, Error
+)]
+#[display(doc_comments)]
+/// PSBT inputs require locktimes of incompatible types, thus the transaction
+/// locktime can't be defined
+pub struct LocktimeConflict;
+
+/// Errors combining PSBTs (see [`Psbt::combine`](super::Psbt::combine))
+#[derive(Debug, Display, Error, From)]
+#[display(inner)]
+pub enum CombineError {
+    /// Error computing transaction locktime of one of the PSBTs
+    #[from]
+    Locktime(LocktimeConflict),
+
+    /// PSBTs can't be combined
+    #[from]
+    Psbt(bitcoin::psbt::Error),
+}
+
 /// Errors happening during fee computation
 #[derive(
     Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Display, Error, From
@@ -73,3 +96,37 @@ pub enum FeeError {
     /// Sum of inputs is less than sum of outputs
     InputsLessThanOutputs,
 }
+
+/// Errors modifying inputs and outputs of a PSBT by a constructor (see
+/// [`Psbt::add_input`](super::Psbt::add_input) and related methods)
+#[derive(
+    Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Display, Error
+)]
+#[display(doc_comments)]
+pub enum ModifyError {
+    /// PSBT does not allow adding or removing inputs
+    InputsNotModifiable,
+
+    /// PSBT does not allow adding or removing outputs
+    OutputsNotModifiable,
+
+    /// PSBT has no input #{0}
+    NoInput(usize),
+
+    /// PSBT has no output #{0}
+    NoOutput(usize),
+
+    /// PSBT already has an input spending {0}
+    DuplicateInput(OutPoint),
+
+    /// locktime required by the input is incompatible with locktime
+    /// requirements of the other PSBT inputs
+    IncompatibleLocktime,
+
+    /// modification changes transaction locktime, invalidating existing
+    /// signatures
+    LocktimeChange,
+
+    /// modification invalidates existing signature of input #{0}
+    SignatureInvalidated(usize),
+}
@@ -9,20 +9,94 @@
 // along with this software.
 // If not, see <https://opensource.org/licenses/Apache-2.0>.
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::fmt::{Display, Formatter};
+use std::io::Cursor;
 use std::str::FromStr;
 
 use base64::Engine;
+use bitcoin::consensus::encode::{self, Decodable};
 use bitcoin::util::bip32::{ExtendedPubKey, KeySource};
-use bitcoin::{consensus, Transaction, Txid};
+use bitcoin::{consensus, Transaction, Txid, VarInt};
 use bitcoin_blockchain::locks::LockTime;
 #[cfg(feature = "serde")]
 use serde_with::{hex::Hex, As, Same};
 
+use crate::input::PSBT_IN_V2_TYPES;
+use crate::output::PSBT_OUT_V2_TYPES;
 use crate::serialize::{Deserialize, Serialize};
-use crate::v0::PsbtV0;
-use crate::{raw, Error, FeeError, Input, Output, PsbtVersion, TxError};
+use crate::v0::{InputV0, OutputV0, PsbtV0};
+use crate::{
+    raw, CombineError, Error, FeeError, Input, LocktimeConflict, Output, PsbtVersion, TxError,
+};
+
+/// PSBT magic bytes, followed by the separator
+const PSBT_MAGIC: &[u8; 5] = b"psbt\xff";
+
+/// Type: Unsigned Transaction PSBT_GLOBAL_UNSIGNED_TX = 0x00
+const PSBT_GLOBAL_UNSIGNED_TX: u8 = 0x00;
+/// Type: Extended Public Key PSBT_GLOBAL_XPUB = 0x01
+const PSBT_GLOBAL_XPUB: u8 = 0x01;
+/// Type: Transaction Version PSBT_GLOBAL_TX_VERSION = 0x02
+const PSBT_GLOBAL_TX_VERSION: u8 = 0x02;
+/// Type: Fallback Locktime PSBT_GLOBAL_FALLBACK_LOCKTIME = 0x03
+const PSBT_GLOBAL_FALLBACK_LOCKTIME: u8 = 0x03;
+/// Type: Input Count PSBT_GLOBAL_INPUT_COUNT = 0x04
+const PSBT_GLOBAL_INPUT_COUNT: u8 = 0x04;
+/// Type: Output Count PSBT_GLOBAL_OUTPUT_COUNT = 0x05
+const PSBT_GLOBAL_OUTPUT_COUNT: u8 = 0x05;
+/// Type: Transaction Modifiable Flags PSBT_GLOBAL_TX_MODIFIABLE = 0x06
+const PSBT_GLOBAL_TX_MODIFIABLE: u8 = 0x06;
+/// Type: Version Number PSBT_GLOBAL_VERSION = 0xFB
+const PSBT_GLOBAL_VERSION: u8 = 0xFB;
+/// Type: Proprietary Use Type PSBT_GLOBAL_PROPRIETARY = 0xFC
+const PSBT_GLOBAL_PROPRIETARY: u8 = 0xFC;
+
+/// Global key types defined by BIP370 for PSBT version 2 only
+const PSBT_GLOBAL_V2_TYPES: [u8; 5] = [
+    PSBT_GLOBAL_TX_VERSION,
+    PSBT_GLOBAL_FALLBACK_LOCKTIME,
+    PSBT_GLOBAL_INPUT_COUNT,
+    PSBT_GLOBAL_OUTPUT_COUNT,
+    PSBT_GLOBAL_TX_MODIFIABLE,
+];
+
+/// Flags of the BIP370 `PSBT_GLOBAL_TX_MODIFIABLE` field, defining which
+/// parts of the transaction can still be modified by the PSBT constructors.
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Default)]
+#[derive(StrictEncode, StrictDecode)]
+#[cfg_attr(
+    feature = "serde",
+    derive(Serialize, Deserialize),
+    serde(crate = "serde_crate")
+)]
+pub struct TxModifiable {
+    /// Inputs can be added or removed (bit 0).
+    pub inputs: bool,
+
+    /// Outputs can be added or removed (bit 1).
+    pub outputs: bool,
+
+    /// Transaction has a `SIGHASH_SINGLE` signature, thus inputs and outputs
+    /// must be added or removed in pairs keeping their indexes matched (bit 2).
+    pub sighash_single: bool,
+}
+
+impl From<u8> for TxModifiable {
+    fn from(flags: u8) -> Self {
+        TxModifiable {
+            inputs: flags & 0x01 != 0,
+            outputs: flags & 0x02 != 0,
+            sighash_single: flags & 0x04 != 0,
+        }
+    }
+}
+
+impl From<TxModifiable> for u8 {
+    fn from(flags: TxModifiable) -> Self {
+        flags.inputs as u8 | (flags.outputs as u8) << 1 | (flags.sighash_single as u8) << 2
+    }
+}
 
 // TODO: Do manual serde and strict encoding implementation to check the
 //       deserialized values
@@ -43,6 +117,11 @@ pub struct Psbt {
     /// Fallback locktime (used if none of the inputs specifies their locktime).
     pub fallback_locktime: Option<LockTime>,
 
+    /// Flags defining which parts of the transaction can be modified (used in
+    /// PSBT version 2 only).
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub tx_modifiable: Option<TxModifiable>,
+
     /// The corresponding key-value map for each input.
     pub inputs: Vec<Input>,
 
@@ -94,6 +173,7 @@ impl Psbt {
             xpub: Default::default(),
             tx_version,
             fallback_locktime,
+            tx_modifiable: None,
             inputs,
             outputs,
             proprietary: Default::default(),
@@ -101,34 +181,78 @@ impl Psbt {
         })
     }
 
-    pub fn lock_time(&self) -> LockTime {
-        let required_time_locktime = self
+    /// Converts PSBT into the given version.
+    ///
+    /// Since BIP174 does not support per-input locktime requirements and
+    /// transaction modification flags, conversion into version 0 moves the
+    /// locktime required by the inputs into the fallback locktime and drops
+    /// the modification flags, keeping the unsigned transaction unchanged.
+    ///
+    /// # Errors
+    ///
+    /// Conversion into version 0 fails if the inputs require locktimes of
+    /// incompatible types.
+    pub fn into_version(mut self, psbt_version: PsbtVersion) -> Result<Psbt, LocktimeConflict> {
+        if psbt_version == PsbtVersion::V0 {
+            let lock_time = self.lock_time()?;
+            self.fallback_locktime = match lock_time.into_consensus() {
+                0 => None,
+                _ => Some(lock_time),
+            };
+            for input in &mut self.inputs {
+                input.required_time_locktime = None;
+                input.required_height_locktime = None;
+            }
+            self.tx_modifiable = None;
+        }
+        self.psbt_version = psbt_version;
+        Ok(self)
+    }
+
+    /// Computes transaction locktime from the locktime requirements of all
+    /// inputs as defined by BIP370. Height-based locktime is used if all
+    /// inputs having requirements support it; otherwise time-based locktime
+    /// is used if all inputs having requirements support it. Returns `None` if
+    /// the inputs require locktimes of incompatible types.
+    pub fn final_locktime(&self) -> Option<LockTime> {
+        let locked = self
             .inputs
             .iter()
-            .filter_map(|input| input.required_time_locktime)
-            .max();
-        let required_height_locktime = self
-            .inputs
+            .filter(|input| input.locktime().is_some())
+            .collect::<Vec<_>>();
+
+        if locked.is_empty() {
+            Some(self.fallback_locktime.unwrap_or_default())
+        } else if locked
             .iter()
-            .filter_map(|input| input.required_height_locktime)
-            .max();
-
-        match (
-            required_time_locktime,
-            required_height_locktime,
-            self.fallback_locktime,
-        ) {
-            (None, None, fallback) => fallback.unwrap_or_default(),
-            (Some(lock), None, _) => lock.into(),
-            (None, Some(lock), _) => lock.into(),
-            (Some(lock1), Some(_lock2), Some(fallback)) if fallback.is_time_based() => lock1.into(),
-            (Some(_lock1), Some(lock2), Some(fallback)) if fallback.is_height_based() => {
-                lock2.into()
-            }
-            (Some(lock1), Some(_lock2), _) => lock1.into(),
+            .all(|input| input.required_height_locktime.is_some())
+        {
+            locked
+                .iter()
+                .filter_map(|input| input.required_height_locktime)
+                .max()
+                .map(LockTime::from)
+        } else if locked
+            .iter()
+            .all(|input| input.required_time_locktime.is_some())
+        {
+            locked
+                .iter()
+                .filter_map(|input| input.required_time_locktime)
+                .max()
+                .map(LockTime::from)
+        } else {
+            None
         }
     }
 
+    /// Returns transaction locktime computed with [`Psbt::final_locktime`],
+    /// or an error if the inputs require locktimes of incompatible types.
+    #[inline]
+    pub fn lock_time(&self) -> Result<LockTime, LocktimeConflict> {
+        self.final_locktime().ok_or(LocktimeConflict)
+    }
+
     pub(crate) fn tx_version(&self) -> i32 { i32::from_be_bytes(self.tx_version.to_be_bytes()) }
 
     /// Returns fee for a transaction, or returns error reporting resolver
@@ -151,54 +275,56 @@ impl Psbt {
     /// Returns transaction ID for an unsigned transaction. For SegWit
     /// transactions this is equal to the signed transaction id.
     #[inline]
-    pub fn to_txid(&self) -> Txid { self.to_unsigned_tx().txid() }
+    pub fn to_txid(&self) -> Result<Txid, LocktimeConflict> {
+        self.to_unsigned_tx().map(|tx| tx.txid())
+    }
 
     /// Constructs transaction with empty `scriptSig` and `witness`
-    pub fn to_unsigned_tx(&self) -> Transaction {
+    pub fn to_unsigned_tx(&self) -> Result<Transaction, LocktimeConflict> {
         let version = self.tx_version();
 
-        let lock_time = bitcoin::PackedLockTime(self.lock_time().into_consensus());
+        let lock_time = bitcoin::PackedLockTime(self.lock_time()?.into_consensus());
 
         let tx_inputs = self.inputs.iter().map(Input::to_unsigned_txin).collect();
         let tx_outputs = self.outputs.iter().map(Output::to_txout).collect();
 
-        Transaction {
+        Ok(Transaction {
             version,
             lock_time,
             input: tx_inputs,
             output: tx_outputs,
-        }
+        })
     }
 
     /// Returns transaction with empty `scriptSig` and `witness`
-    pub fn into_unsigned_tx(self) -> Transaction {
+    pub fn into_unsigned_tx(self) -> Result<Transaction, LocktimeConflict> {
         let version = self.tx_version();
 
-        let lock_time = bitcoin::PackedLockTime(self.lock_time().into_consensus());
+        let lock_time = bitcoin::PackedLockTime(self.lock_time()?.into_consensus());
 
         let tx_inputs = self.inputs.iter().map(Input::to_unsigned_txin).collect();
         let tx_outputs = self.outputs.into_iter().map(Output::into_txout).collect();
 
-        Transaction {
+        Ok(Transaction {
             version,
             lock_time,
             input: tx_inputs,
             output: tx_outputs,
-        }
+        })
     }
 
     /// Extract the (partially) signed transaction from this PSBT by filling in
     /// the available signature information in place.
     #[inline]
-    pub fn extract_signed_tx(&self) -> Transaction {
-        let mut tx: Transaction = self.to_unsigned_tx();
+    pub fn extract_signed_tx(&self) -> Result<Transaction, LocktimeConflict> {
+        let mut tx: Transaction = self.to_unsigned_tx()?;
 
         for (vin, psbtin) in tx.input.iter_mut().zip(self.inputs.iter()) {
             vin.script_sig = psbtin.final_script_sig.clone().unwrap_or_default().into();
             vin.witness = psbtin.final_script_witness.clone().unwrap_or_default();
         }
 
-        tx
+        Ok(tx)
     }
 
     /// Combines this [`Psbt`] with `other` PSBT as described by BIP 174.
@@ -206,9 +332,9 @@ impl Psbt {
     /// In accordance with BIP 174 this function is commutative i.e.,
     /// `A.combine(B) == B.combine(A)`
     #[inline]
-    pub fn combine(self, other: Self) -> Result<Self, Error> {
-        let mut first = PsbtV0::from(self);
-        first.combine(other.into())?;
+    pub fn combine(self, other: Self) -> Result<Self, CombineError> {
+        let mut first = PsbtV0::try_from(self)?;
+        first.combine(PsbtV0::try_from(other)?)?;
         Ok(first.into())
     }
 }
@@ -246,6 +372,7 @@ impl From<PsbtV0> for Psbt {
             xpub: v0.xpub,
             tx_version,
             fallback_locktime,
+            tx_modifiable: None,
             inputs,
             outputs,
             proprietary: v0.proprietary,
@@ -254,13 +381,24 @@ impl From<PsbtV0> for Psbt {
     }
 }
 
-impl From<Psbt> for PsbtV0 {
-    fn from(psbt: Psbt) -> Self {
-        let version = psbt.tx_version();
-        let lock_time = bitcoin::PackedLockTime(psbt.lock_time().into_consensus());
+impl TryFrom<Psbt> for PsbtV0 {
+    type Error = LocktimeConflict;
 
-        let (v0_inputs, tx_inputs) = psbt.inputs.into_iter().map(Input::split).unzip();
-        let (v0_outputs, tx_outputs) = psbt.outputs.into_iter().map(Output::split).unzip();
+    fn try_from(psbt: Psbt) -> Result<Self, Self::Error> {
+        let lock_time = psbt.lock_time()?;
+        Ok(psbt.into_v0(lock_time))
+    }
+}
+
+impl Psbt {
+    /// Converts PSBT into version 0 with the unsigned transaction using the
+    /// provided locktime
+    fn into_v0(self, lock_time: LockTime) -> PsbtV0 {
+        let version = self.tx_version();
+        let lock_time = bitcoin::PackedLockTime(lock_time.into_consensus());
+
+        let (v0_inputs, tx_inputs) = self.inputs.into_iter().map(Input::split).unzip();
+        let (v0_outputs, tx_outputs) = self.outputs.into_iter().map(Output::split).unzip();
 
         let unsigned_tx = Transaction {
             version,
@@ -272,24 +410,255 @@ impl From<Psbt> for PsbtV0 {
         PsbtV0 {
             unsigned_tx,
             version: PsbtVersion::V0 as u32,
-            xpub: psbt.xpub,
-            proprietary: psbt.proprietary,
-            unknown: psbt.unknown,
+            xpub: self.xpub,
+            proprietary: self.proprietary,
+            unknown: self.unknown,
             inputs: v0_inputs,
             outputs: v0_outputs,
         }
     }
+
+    fn serialize_v2(&self) -> Vec<u8> {
+        let mut pairs = vec![];
+        let mut push = |type_value: u8, key: Vec<u8>, value: Vec<u8>| {
+            pairs.push(raw::Pair {
+                key: raw::Key { type_value, key },
+                value,
+            })
+        };
+
+        for (xpub, key_source) in &self.xpub {
+            push(
+                PSBT_GLOBAL_XPUB,
+                xpub.encode().to_vec(),
+                key_source.serialize(),
+            );
+        }
+        push(
+            PSBT_GLOBAL_TX_VERSION,
+            vec![],
+            encode::serialize(&self.tx_version),
+        );
+        if let Some(lock_time) = self.fallback_locktime {
+            push(
+                PSBT_GLOBAL_FALLBACK_LOCKTIME,
+                vec![],
+                encode::serialize(&lock_time.into_consensus()),
+            );
+        }
+        push(
+            PSBT_GLOBAL_INPUT_COUNT,
+            vec![],
+            encode::serialize(&VarInt(self.inputs.len() as u64)),
+        );
+        push(
+            PSBT_GLOBAL_OUTPUT_COUNT,
+            vec![],
+            encode::serialize(&VarInt(self.outputs.len() as u64)),
+        );
+        if let Some(flags) = self.tx_modifiable {
+            push(PSBT_GLOBAL_TX_MODIFIABLE, vec![], vec![flags.into()]);
+        }
+        push(
+            PSBT_GLOBAL_VERSION,
+            vec![],
+            encode::serialize(&(PsbtVersion::V2 as u32)),
+        );
+        for (key, value) in &self.proprietary {
+            push(PSBT_GLOBAL_PROPRIETARY, key.to_key().key, value.clone());
+        }
+        for (key, value) in &self.unknown {
+            push(key.type_value, key.key.clone(), value.clone());
+        }
+
+        let mut data = PSBT_MAGIC.to_vec();
+        for pair in pairs {
+            data.extend(encode::serialize(&pair));
+        }
+        data.push(0x00);
+        for input in &self.inputs {
+            data.extend(encode::serialize(&input.clone().into_v2()));
+        }
+        for output in &self.outputs {
+            data.extend(encode::serialize(&output.clone().into_v2()));
+        }
+        data
+    }
+
+    fn deserialize_v2(
+        pairs: Vec<raw::Pair>,
+        cursor: &mut Cursor<&[u8]>,
+    ) -> Result<Psbt, encode::Error> {
+        let mut keys = BTreeSet::new();
+        let mut xpub = bmap! {};
+        let mut tx_version = None;
+        let mut fallback_locktime = None;
+        let mut input_count = None;
+        let mut output_count = None;
+        let mut tx_modifiable = None;
+        let mut proprietary = bmap! {};
+        let mut unknown = bmap! {};
+
+        for raw::Pair { key, value } in pairs {
+            if !keys.insert(key.clone()) {
+                return Err(Error::DuplicateKey(key).into());
+            }
+            match (key.type_value, key.key.is_empty()) {
+                (PSBT_GLOBAL_XPUB, false) => {
+                    let account = ExtendedPubKey::decode(&key.key).map_err(|_| {
+                        encode::Error::ParseFailed(
+                            "Can't deserialize ExtendedPublicKey from global XPUB key data",
+                        )
+                    })?;
+                    xpub.insert(account, KeySource::deserialize(&value)?);
+                }
+                (PSBT_GLOBAL_TX_VERSION, true) => {
+                    tx_version = Some(encode::deserialize::<u32>(&value)?)
+                }
+                (PSBT_GLOBAL_FALLBACK_LOCKTIME, true) => {
+                    fallback_locktime = Some(LockTime::from(encode::deserialize::<u32>(&value)?))
+                }
+                (PSBT_GLOBAL_INPUT_COUNT, true) => {
+                    input_count = Some(encode::deserialize::<VarInt>(&value)?.0)
+                }
+                (PSBT_GLOBAL_OUTPUT_COUNT, true) => {
+                    output_count = Some(encode::deserialize::<VarInt>(&value)?.0)
+                }
+                (PSBT_GLOBAL_TX_MODIFIABLE, true) => {
+                    tx_modifiable = Some(TxModifiable::from(encode::deserialize::<u8>(&value)?))
+                }
+                (PSBT_GLOBAL_VERSION, true) => {}
+                (PSBT_GLOBAL_PROPRIETARY, _) => {
+                    proprietary.insert(raw::ProprietaryKey::try_from(key)?, value);
+                }
+                (
+                    PSBT_GLOBAL_UNSIGNED_TX
+                    | PSBT_GLOBAL_XPUB
+                    | PSBT_GLOBAL_TX_VERSION
+                    | PSBT_GLOBAL_FALLBACK_LOCKTIME
+                    | PSBT_GLOBAL_INPUT_COUNT
+                    | PSBT_GLOBAL_OUTPUT_COUNT
+                    | PSBT_GLOBAL_TX_MODIFIABLE
+                    | PSBT_GLOBAL_VERSION,
+                    _,
+                ) => return Err(Error::InvalidKey(key).into()),
+                _ => {
+                    unknown.insert(key, value);
+                }
+            }
+        }
+
+        let tx_version = tx_version.ok_or(encode::Error::ParseFailed(
+            "PSBT v2 must have transaction version",
+        ))?;
+        if tx_version < 2 {
+            return Err(encode::Error::ParseFailed(
+                "PSBT v2 must have transaction version 2 or higher",
+            ));
+        }
+        let input_count =
+            input_count.ok_or(encode::Error::ParseFailed("PSBT v2 must have input count"))?;
+        let output_count =
+            output_count.ok_or(encode::Error::ParseFailed("PSBT v2 must have output count"))?;
+
+        let inputs = (0..input_count as usize)
+            .map(|index| Input::with_v2(index, InputV0::consensus_decode(cursor)?))
+            .collect::<Result<_, _>>()?;
+        let outputs = (0..output_count as usize)
+            .map(|index| Output::with_v2(index, OutputV0::consensus_decode(cursor)?))
+            .collect::<Result<_, _>>()?;
+
+        Ok(Psbt {
+            psbt_version: PsbtVersion::V2,
+            tx_version,
+            fallback_locktime,
+            tx_modifiable,
+            inputs,
+            outputs,
+            xpub,
+            proprietary,
+            unknown,
+        })
+    }
 }
 
-// TODO: Implement own PSBT BIP174 serialization trait and its own custom error
-//       type handling different PSBT versions.
 impl Serialize for Psbt {
-    fn serialize(&self) -> Vec<u8> { consensus::encode::serialize::<PsbtV0>(&self.clone().into()) }
+    fn serialize(&self) -> Vec<u8> {
+        match self.psbt_version {
+            // Version 0 PSBTs carry a single transaction locktime; a version 2
+            // PSBT relabeled without `into_version` may have inputs requiring
+            // incompatible locktimes, in which case the fallback one is used
+            PsbtVersion::V0 => {
+                let lock_time = self
+                    .final_locktime()
+                    .unwrap_or_else(|| self.fallback_locktime.unwrap_or_default());
+                consensus::encode::serialize(&self.clone().into_v0(lock_time))
+            }
+            PsbtVersion::V2 => self.serialize_v2(),
+        }
+    }
 }
 
 impl Deserialize for Psbt {
     fn deserialize(bytes: &[u8]) -> Result<Self, consensus::encode::Error> {
-        consensus::deserialize::<PsbtV0>(bytes).map(Psbt::from)
+        if !bytes.starts_with(PSBT_MAGIC) {
+            return Err(Error::InvalidMagic.into());
+        }
+        let mut cursor = Cursor::new(&bytes[PSBT_MAGIC.len()..]);
+
+        let mut pairs = vec![];
+        loop {
+            match raw::Pair::consensus_decode(&mut cursor) {
+                Ok(pair) => pairs.push(pair),
+                Err(encode::Error::Psbt(Error::NoMorePairs)) => break,
+                Err(err) => return Err(err),
+            }
+        }
+        let version = pairs
+            .iter()
+            .find(|pair| pair.key.type_value == PSBT_GLOBAL_VERSION && pair.key.key.is_empty())
+            .map(|pair| encode::deserialize::<u32>(&pair.value))
+            .transpose()?;
+        if version != Some(PsbtVersion::V2 as u32) {
+            let psbt = consensus::deserialize::<PsbtV0>(bytes)?;
+            check_v0_keys(&psbt)?;
+            return Ok(Psbt::from(psbt));
+        }
+
+        let psbt = Psbt::deserialize_v2(pairs, &mut cursor)?;
+        if cursor.position() as usize != cursor.get_ref().len() {
+            return Err(encode::Error::ParseFailed(
+                "data not consumed entirely when explicitly deserializing",
+            ));
+        }
+        Ok(psbt)
+    }
+}
+
+/// Ensures version 0 PSBT does not contain fields defined by BIP370 for
+/// version 2 only, which are left by the version 0 decoder among the unknown
+/// key-value pairs
+fn check_v0_keys(psbt: &PsbtV0) -> Result<(), Error> {
+    let find = |unknown: &BTreeMap<raw::Key, Vec<u8>>, types: &[u8]| {
+        unknown
+            .keys()
+            .find(|key| types.contains(&key.type_value))
+            .cloned()
+    };
+    let invalid = find(&psbt.unknown, &PSBT_GLOBAL_V2_TYPES)
+        .or_else(|| {
+            psbt.inputs
+                .iter()
+                .find_map(|input| find(&input.unknown, &PSBT_IN_V2_TYPES))
+        })
+        .or_else(|| {
+            psbt.outputs
+                .iter()
+                .find_map(|output| find(&output.unknown, &PSBT_OUT_V2_TYPES))
+        });
+    match invalid {
+        Some(key) => Err(Error::InvalidKey(key)),
+        None => Ok(()),
     }
 }
 
@@ -329,9 +698,534 @@ impl FromStr for Psbt {
 #[cfg(test)]
 mod test {
     use amplify::hex::FromHex;
+    use bitcoin::hashes::Hash;
+    use bitcoin::{OutPoint, PackedLockTime, Script, TxIn, TxOut};
+    use bitcoin_blockchain::locks::{LockHeight, LockTimestamp, SeqNo};
 
     use super::*;
 
+    /// Valid PSBTs of the BIP370 test vector cases
+    const BIP370_VALID: [(&str, &str); 12] = [
+        (
+            "1 input, 2 output PSBTv2, required fields only",
+            "cHNidP8BAgQCAAAAAQQBAQEFAQIB+wQCAAAAAAEOIAsK2SFBnByHGXNdctxzn56p4GONH+TB7vD5\
+            lECEgV/IAQ8EAAAAAAABAwgIrwgAAAAAAAEEFgAUxDD2TEdW2jENvRoIVXLvKZkmJywAAQMIi73r\
+            CwAAAAABBBYAFE3Rk6yWSlasG54cyoRU/i9HT4UTAA==",
+        ),
+        (
+            "1 input, 2 output updated PSBTv2",
+            "cHNidP8BAgQCAAAAAQQBAQEFAQIB+wQCAAAAAAEAUgIAAAABwaolbiFLlqGCL5PeQr/ztfP/jQUZ\
+            MG41FddRWl6AWxIAAAAAAP////8BGMaaOwAAAAAWABSwo68UQghBJpPKfRZoUrUtsK7wbgAAAAAB\
+            AR8Yxpo7AAAAABYAFLCjrxRCCEEmk8p9FmhStS2wrvBuAQ4gCwrZIUGcHIcZc11y3HOfnqngY40f\
+            5MHu8PmUQISBX8gBDwQAAAAAACICAtYB+EhGpnVfd2vgDj2d6PsQrMk1+4PEX7AWLUytWreSGPad\
+            hz5UAACAAQAAgAAAAIAAAAAAKgAAAAEDCAivCAAAAAAAAQQWABTEMPZMR1baMQ29GghVcu8pmSYn\
+            LAAiAgLjb7/1PdU0Bwz4/TlmFGgPNXqbhdtzQL8c+nRdKtezQBj2nYc+VAAAgAEAAIAAAACAAQAA\
+            AGQAAAABAwiLvesLAAAAAAEEFgAUTdGTrJZKVqwbnhzKhFT+L0dPhRMA",
+        ),
+        (
+            "1 input, 2 output updated PSBTv2 with PSBT_IN_SEQUENCE",
+            "cHNidP8BAgQCAAAAAQQBAQEFAQIB+wQCAAAAAAEAUgIAAAABwaolbiFLlqGCL5PeQr/ztfP/jQUZ\
+            MG41FddRWl6AWxIAAAAAAP////8BGMaaOwAAAAAWABSwo68UQghBJpPKfRZoUrUtsK7wbgAAAAAB\
+            AR8Yxpo7AAAAABYAFLCjrxRCCEEmk8p9FmhStS2wrvBuAQ4gCwrZIUGcHIcZc11y3HOfnqngY40f\
+            5MHu8PmUQISBX8gBDwQAAAAAARAE/v///wAiAgLWAfhIRqZ1X3dr4A49nej7EKzJNfuDxF+wFi1M\
+            rVq3khj2nYc+VAAAgAEAAIAAAACAAAAAACoAAAABAwgIrwgAAAAAAAEEFgAUxDD2TEdW2jENvRoI\
+            VXLvKZkmJywAIgIC42+/9T3VNAcM+P05ZhRoDzV6m4Xbc0C/HPp0XSrXs0AY9p2HPlQAAIABAACA\
+            AAAAgAEAAABkAAAAAQMIi73rCwAAAAABBBYAFE3Rk6yWSlasG54cyoRU/i9HT4UTAA==",
+        ),
+        (
+            "1 input, 2 output updated PSBTv2 with PSBT_IN_REQUIRED_TIME_LOCKTIME",
+            "cHNidP8BAgQCAAAAAQQBAQEFAQIB+wQCAAAAAAEAUgIAAAABwaolbiFLlqGCL5PeQr/ztfP/jQUZ\
+            MG41FddRWl6AWxIAAAAAAP////8BGMaaOwAAAAAWABSwo68UQghBJpPKfRZoUrUtsK7wbgAAAAAB\
+            AR8Yxpo7AAAAABYAFLCjrxRCCEEmk8p9FmhStS2wrvBuAQ4gCwrZIUGcHIcZc11y3HOfnqngY40f\
+            5MHu8PmUQISBX8gBDwQAAAAAAREEjI3EYgAiAgLWAfhIRqZ1X3dr4A49nej7EKzJNfuDxF+wFi1M\
+            rVq3khj2nYc+VAAAgAEAAIAAAACAAAAAACoAAAABAwgIrwgAAAAAAAEEFgAUxDD2TEdW2jENvRoI\
+            VXLvKZkmJywAIgIC42+/9T3VNAcM+P05ZhRoDzV6m4Xbc0C/HPp0XSrXs0AY9p2HPlQAAIABAACA\
+            AAAAgAEAAABkAAAAAQMIi73rCwAAAAABBBYAFE3Rk6yWSlasG54cyoRU/i9HT4UTAA==",
+        ),
+        (
+            "1 input, 2 output updated PSBTv2 with PSBT_IN_REQUIRED_HEIGHT_LOCKTIME",
+            "cHNidP8BAgQCAAAAAQQBAQEFAQIB+wQCAAAAAAEAUgIAAAABwaolbiFLlqGCL5PeQr/ztfP/jQUZ\
+            MG41FddRWl6AWxIAAAAAAP////8BGMaaOwAAAAAWABSwo68UQghBJpPKfRZoUrUtsK7wbgAAAAAB\
+            AR8Yxpo7AAAAABYAFLCjrxRCCEEmk8p9FmhStS2wrvBuAQ4gCwrZIUGcHIcZc11y3HOfnqngY40f\
+            5MHu8PmUQISBX8gBDwQAAAAAARIEECcAAAAiAgLWAfhIRqZ1X3dr4A49nej7EKzJNfuDxF+wFi1M\
+            rVq3khj2nYc+VAAAgAEAAIAAAACAAAAAACoAAAABAwgIrwgAAAAAAAEEFgAUxDD2TEdW2jENvRoI\
+            VXLvKZkmJywAIgIC42+/9T3VNAcM+P05ZhRoDzV6m4Xbc0C/HPp0XSrXs0AY9p2HPlQAAIABAACA\
+            AAAAgAEAAABkAAAAAQMIi73rCwAAAAABBBYAFE3Rk6yWSlasG54cyoRU/i9HT4UTAA==",
+        ),
+        (
+            "1 input, 2 output updated PSBTv2 with both locktime requirements",
+            "cHNidP8BAgQCAAAAAQQBAQEFAQIB+wQCAAAAAAEAUgIAAAABwaolbiFLlqGCL5PeQr/ztfP/jQUZ\
+            MG41FddRWl6AWxIAAAAAAP////8BGMaaOwAAAAAWABSwo68UQghBJpPKfRZoUrUtsK7wbgAAAAAB\
+            AR8Yxpo7AAAAABYAFLCjrxRCCEEmk8p9FmhStS2wrvBuAQ4gCwrZIUGcHIcZc11y3HOfnqngY40f\
+            5MHu8PmUQISBX8gBDwQAAAAAAREEjI3EYgESBBAnAAAAIgIC1gH4SEamdV93a+AOPZ3o+xCsyTX7\
+            g8RfsBYtTK1at5IY9p2HPlQAAIABAACAAAAAgAAAAAAqAAAAAQMICK8IAAAAAAABBBYAFMQw9kxH\
+            VtoxDb0aCFVy7ymZJicsACICAuNvv/U91TQHDPj9OWYUaA81epuF23NAvxz6dF0q17NAGPadhz5U\
+            AACAAQAAgAAAAIABAAAAZAAAAAEDCIu96wsAAAAAAQQWABRN0ZOslkpWrBueHMqEVP4vR0+FEwA=",
+        ),
+        (
+            "1 input, 2 output updated PSBTv2 with inputs modifiable",
+            "cHNidP8BAgQCAAAAAQQBAQEFAQIBBgEBAfsEAgAAAAABAFICAAAAAcGqJW4hS5ahgi+T3kK/87Xz\
+            /40FGTBuNRXXUVpegFsSAAAAAAD/////ARjGmjsAAAAAFgAUsKOvFEIIQSaTyn0WaFK1LbCu8G4A\
+            AAAAAQEfGMaaOwAAAAAWABSwo68UQghBJpPKfRZoUrUtsK7wbgEOIAsK2SFBnByHGXNdctxzn56p\
+            4GONH+TB7vD5lECEgV/IAQ8EAAAAAAAiAgLWAfhIRqZ1X3dr4A49nej7EKzJNfuDxF+wFi1MrVq3\
+            khj2nYc+VAAAgAEAAIAAAACAAAAAACoAAAABAwgIrwgAAAAAAAEEFgAUxDD2TEdW2jENvRoIVXLv\
+            KZkmJywAIgIC42+/9T3VNAcM+P05ZhRoDzV6m4Xbc0C/HPp0XSrXs0AY9p2HPlQAAIABAACAAAAA\
+            gAEAAABkAAAAAQMIi73rCwAAAAABBBYAFE3Rk6yWSlasG54cyoRU/i9HT4UTAA==",
+        ),
+        (
+            "1 input, 2 output updated PSBTv2 with outputs modifiable",
+            "cHNidP8BAgQCAAAAAQQBAQEFAQIBBgECAfsEAgAAAAABAFICAAAAAcGqJW4hS5ahgi+T3kK/87Xz\
+            /40FGTBuNRXXUVpegFsSAAAAAAD/////ARjGmjsAAAAAFgAUsKOvFEIIQSaTyn0WaFK1LbCu8G4A\
+            AAAAAQEfGMaaOwAAAAAWABSwo68UQghBJpPKfRZoUrUtsK7wbgEOIAsK2SFBnByHGXNdctxzn56p\
+            4GONH+TB7vD5lECEgV/IAQ8EAAAAAAAiAgLWAfhIRqZ1X3dr4A49nej7EKzJNfuDxF+wFi1MrVq3\
+            khj2nYc+VAAAgAEAAIAAAACAAAAAACoAAAABAwgIrwgAAAAAAAEEFgAUxDD2TEdW2jENvRoIVXLv\
+            KZkmJywAIgIC42+/9T3VNAcM+P05ZhRoDzV6m4Xbc0C/HPp0XSrXs0AY9p2HPlQAAIABAACAAAAA\
+            gAEAAABkAAAAAQMIi73rCwAAAAABBBYAFE3Rk6yWSlasG54cyoRU/i9HT4UTAA==",
+        ),
+        (
+            "1 input, 2 output updated PSBTv2 with SIGHASH_SINGLE flag",
+            "cHNidP8BAgQCAAAAAQQBAQEFAQIBBgEEAfsEAgAAAAABAFICAAAAAcGqJW4hS5ahgi+T3kK/87Xz\
+            /40FGTBuNRXXUVpegFsSAAAAAAD/////ARjGmjsAAAAAFgAUsKOvFEIIQSaTyn0WaFK1LbCu8G4A\
+            AAAAAQEfGMaaOwAAAAAWABSwo68UQghBJpPKfRZoUrUtsK7wbgEOIAsK2SFBnByHGXNdctxzn56p\
+            4GONH+TB7vD5lECEgV/IAQ8EAAAAAAAiAgLWAfhIRqZ1X3dr4A49nej7EKzJNfuDxF+wFi1MrVq3\
+            khj2nYc+VAAAgAEAAIAAAACAAAAAACoAAAABAwgIrwgAAAAAAAEEFgAUxDD2TEdW2jENvRoIVXLv\
+            KZkmJywAIgIC42+/9T3VNAcM+P05ZhRoDzV6m4Xbc0C/HPp0XSrXs0AY9p2HPlQAAIABAACAAAAA\
+            gAEAAABkAAAAAQMIi73rCwAAAAABBBYAFE3Rk6yWSlasG54cyoRU/i9HT4UTAA==",
+        ),
+        (
+            "1 input, 2 output updated PSBTv2 with undefined modifiable flag",
+            "cHNidP8BAgQCAAAAAQQBAQEFAQIBBgEIAfsEAgAAAAABAFICAAAAAcGqJW4hS5ahgi+T3kK/87Xz\
+            /40FGTBuNRXXUVpegFsSAAAAAAD/////ARjGmjsAAAAAFgAUsKOvFEIIQSaTyn0WaFK1LbCu8G4A\
+            AAAAAQEfGMaaOwAAAAAWABSwo68UQghBJpPKfRZoUrUtsK7wbgEOIAsK2SFBnByHGXNdctxzn56p\
+            4GONH+TB7vD5lECEgV/IAQ8EAAAAAAAiAgLWAfhIRqZ1X3dr4A49nej7EKzJNfuDxF+wFi1MrVq3\
+            khj2nYc+VAAAgAEAAIAAAACAAAAAACoAAAABAwgIrwgAAAAAAAEEFgAUxDD2TEdW2jENvRoIVXLv\
+            KZkmJywAIgIC42+/9T3VNAcM+P05ZhRoDzV6m4Xbc0C/HPp0XSrXs0AY9p2HPlQAAIABAACAAAAA\
+            gAEAAABkAAAAAQMIi73rCwAAAAABBBYAFE3Rk6yWSlasG54cyoRU/i9HT4UTAA==",
+        ),
+        (
+            "1 input, 2 output updated PSBTv2 with all modifiable flags",
+            "cHNidP8BAgQCAAAAAQQBAQEFAQIBBgEHAfsEAgAAAAABAFICAAAAAcGqJW4hS5ahgi+T3kK/87Xz\
+            /40FGTBuNRXXUVpegFsSAAAAAAD/////ARjGmjsAAAAAFgAUsKOvFEIIQSaTyn0WaFK1LbCu8G4A\
+            AAAAAQEfGMaaOwAAAAAWABSwo68UQghBJpPKfRZoUrUtsK7wbgEOIAsK2SFBnByHGXNdctxzn56p\
+            4GONH+TB7vD5lECEgV/IAQ8EAAAAAAAiAgLWAfhIRqZ1X3dr4A49nej7EKzJNfuDxF+wFi1MrVq3\
+            khj2nYc+VAAAgAEAAIAAAACAAAAAACoAAAABAwgIrwgAAAAAAAEEFgAUxDD2TEdW2jENvRoIVXLv\
+            KZkmJywAIgIC42+/9T3VNAcM+P05ZhRoDzV6m4Xbc0C/HPp0XSrXs0AY9p2HPlQAAIABAACAAAAA\
+            gAEAAABkAAAAAQMIi73rCwAAAAABBBYAFE3Rk6yWSlasG54cyoRU/i9HT4UTAA==",
+        ),
+        (
+            "1 input, 2 output updated PSBTv2 with PSBT_GLOBAL_FALLBACK_LOCKTIME",
+            "cHNidP8BAgQCAAAAAQMEAAAAAAEEAQEBBQECAfsEAgAAAAABAFICAAAAAcGqJW4hS5ahgi+T3kK/\
+            87Xz/40FGTBuNRXXUVpegFsSAAAAAAD/////ARjGmjsAAAAAFgAUsKOvFEIIQSaTyn0WaFK1LbCu\
+            8G4AAAAAAQEfGMaaOwAAAAAWABSwo68UQghBJpPKfRZoUrUtsK7wbgEOIAsK2SFBnByHGXNdctxz\
+            n56p4GONH+TB7vD5lECEgV/IAQ8EAAAAAAAiAgLWAfhIRqZ1X3dr4A49nej7EKzJNfuDxF+wFi1M\
+            rVq3khj2nYc+VAAAgAEAAIAAAACAAAAAACoAAAABAwgIrwgAAAAAAAEEFgAUxDD2TEdW2jENvRoI\
+            VXLvKZkmJywAIgIC42+/9T3VNAcM+P05ZhRoDzV6m4Xbc0C/HPp0XSrXs0AY9p2HPlQAAIABAACA\
+            AAAAgAEAAABkAAAAAQMIi73rCwAAAAABBBYAFE3Rk6yWSlasG54cyoRU/i9HT4UTAA==",
+        ),
+    ];
+
+    /// Invalid PSBTs of the BIP370 test vector cases
+    const BIP370_INVALID: [(&str, &str); 23] = [
+        (
+            "PSBTv0 but with PSBT_GLOBAL_VERSION set to 2",
+            "cHNidP8BAHECAAAAAQsK2SFBnByHGXNdctxzn56p4GONH+TB7vD5lECEgV/IAAAAAAD+////Agiv\
+            CAAAAAAAFgAUxDD2TEdW2jENvRoIVXLvKZkmJyyLvesLAAAAABYAFE3Rk6yWSlasG54cyoRU/i9H\
+            T4UTAAAAAAH7BAIAAAAAAQBSAgAAAAHBqiVuIUuWoYIvk95Cv/O18/+NBRkwbjUV11FaXoBbEgAA\
+            AAAA/////wEYxpo7AAAAABYAFLCjrxRCCEEmk8p9FmhStS2wrvBuAAAAAAEBHxjGmjsAAAAAFgAU\
+            sKOvFEIIQSaTyn0WaFK1LbCu8G4AIgIC1gH4SEamdV93a+AOPZ3o+xCsyTX7g8RfsBYtTK1at5IY\
+            9p2HPlQAAIABAACAAAAAgAAAAAAqAAAAACICAuNvv/U91TQHDPj9OWYUaA81epuF23NAvxz6dF0q\
+            17NAGPadhz5UAACAAQAAgAAAAIABAAAAZAAAAAA=",
+        ),
+        (
+            "PSBTv0 with PSBT_GLOBAL_TX_VERSION",
+            "cHNidP8BAHECAAAAAQsK2SFBnByHGXNdctxzn56p4GONH+TB7vD5lECEgV/IAAAAAAD+////Agiv\
+            CAAAAAAAFgAUxDD2TEdW2jENvRoIVXLvKZkmJyyLvesLAAAAABYAFE3Rk6yWSlasG54cyoRU/i9H\
+            T4UTAAAAAAECBAIAAAAAAQBSAgAAAAHBqiVuIUuWoYIvk95Cv/O18/+NBRkwbjUV11FaXoBbEgAA\
+            AAAA/////wEYxpo7AAAAABYAFLCjrxRCCEEmk8p9FmhStS2wrvBuAAAAAAEBHxjGmjsAAAAAFgAU\
+            sKOvFEIIQSaTyn0WaFK1LbCu8G4AIgIC1gH4SEamdV93a+AOPZ3o+xCsyTX7g8RfsBYtTK1at5IY\
+            9p2HPlQAAIABAACAAAAAgAAAAAAqAAAAACICAuNvv/U91TQHDPj9OWYUaA81epuF23NAvxz6dF0q\
+            17NAGPadhz5UAACAAQAAgAAAAIABAAAAZAAAAAA=",
+        ),
+        (
+            "PSBTv0 with PSBT_GLOBAL_FALLBACK_LOCKTIME",
+            "cHNidP8BAHECAAAAAQsK2SFBnByHGXNdctxzn56p4GONH+TB7vD5lECEgV/IAAAAAAD+////Agiv\
+            CAAAAAAAFgAUxDD2TEdW2jENvRoIVXLvKZkmJyyLvesLAAAAABYAFE3Rk6yWSlasG54cyoRU/i9H\
+            T4UTAAAAAAEDBAAAAAAAAQBSAgAAAAHBqiVuIUuWoYIvk95Cv/O18/+NBRkwbjUV11FaXoBbEgAA\
+            AAAA/////wEYxpo7AAAAABYAFLCjrxRCCEEmk8p9FmhStS2wrvBuAAAAAAEBHxjGmjsAAAAAFgAU\
+            sKOvFEIIQSaTyn0WaFK1LbCu8G4AIgIC1gH4SEamdV93a+AOPZ3o+xCsyTX7g8RfsBYtTK1at5IY\
+            9p2HPlQAAIABAACAAAAAgAAAAAAqAAAAACICAuNvv/U91TQHDPj9OWYUaA81epuF23NAvxz6dF0q\
+            17NAGPadhz5UAACAAQAAgAAAAIABAAAAZAAAAAA=",
+        ),
+        (
+            "PSBTv0 with PSBT_GLOBAL_INPUT_COUNT",
+            "cHNidP8BAHECAAAAAQsK2SFBnByHGXNdctxzn56p4GONH+TB7vD5lECEgV/IAAAAAAD+////Agiv\
+            CAAAAAAAFgAUxDD2TEdW2jENvRoIVXLvKZkmJyyLvesLAAAAABYAFE3Rk6yWSlasG54cyoRU/i9H\
+            T4UTAAAAAAEEAQEAAQBSAgAAAAHBqiVuIUuWoYIvk95Cv/O18/+NBRkwbjUV11FaXoBbEgAAAAAA\
+            /////wEYxpo7AAAAABYAFLCjrxRCCEEmk8p9FmhStS2wrvBuAAAAAAEBHxjGmjsAAAAAFgAUsKOv\
+            FEIIQSaTyn0WaFK1LbCu8G4AIgIC1gH4SEamdV93a+AOPZ3o+xCsyTX7g8RfsBYtTK1at5IY9p2H\
+            PlQAAIABAACAAAAAgAAAAAAqAAAAACICAuNvv/U91TQHDPj9OWYUaA81epuF23NAvxz6dF0q17NA\
+            GPadhz5UAACAAQAAgAAAAIABAAAAZAAAAAA=",
+        ),
+        (
+            "PSBTv0 with PSBT_GLOBAL_OUTPUT_COUNT",
+            "cHNidP8BAHECAAAAAQsK2SFBnByHGXNdctxzn56p4GONH+TB7vD5lECEgV/IAAAAAAD+////Agiv\
+            CAAAAAAAFgAUxDD2TEdW2jENvRoIVXLvKZkmJyyLvesLAAAAABYAFE3Rk6yWSlasG54cyoRU/i9H\
+            T4UTAAAAAAEFAQIAAQBSAgAAAAHBqiVuIUuWoYIvk95Cv/O18/+NBRkwbjUV11FaXoBbEgAAAAAA\
+            /////wEYxpo7AAAAABYAFLCjrxRCCEEmk8p9FmhStS2wrvBuAAAAAAEBHxjGmjsAAAAAFgAUsKOv\
+            FEIIQSaTyn0WaFK1LbCu8G4AIgIC1gH4SEamdV93a+AOPZ3o+xCsyTX7g8RfsBYtTK1at5IY9p2H\
+            PlQAAIABAACAAAAAgAAAAAAqAAAAACICAuNvv/U91TQHDPj9OWYUaA81epuF23NAvxz6dF0q17NA\
+            GPadhz5UAACAAQAAgAAAAIABAAAAZAAAAAA=",
+        ),
+        (
+            "PSBTv0 with PSBT_GLOBAL_TX_MODIFIABLE",
+            "cHNidP8BAHECAAAAAQsK2SFBnByHGXNdctxzn56p4GONH+TB7vD5lECEgV/IAAAAAAD+////Agiv\
+            CAAAAAAAFgAUxDD2TEdW2jENvRoIVXLvKZkmJyyLvesLAAAAABYAFE3Rk6yWSlasG54cyoRU/i9H\
+            T4UTAAAAAAEGAQAAAQBSAgAAAAHBqiVuIUuWoYIvk95Cv/O18/+NBRkwbjUV11FaXoBbEgAAAAAA\
+            /////wEYxpo7AAAAABYAFLCjrxRCCEEmk8p9FmhStS2wrvBuAAAAAAEBHxjGmjsAAAAAFgAUsKOv\
+            FEIIQSaTyn0WaFK1LbCu8G4AIgIC1gH4SEamdV93a+AOPZ3o+xCsyTX7g8RfsBYtTK1at5IY9p2H\
+            PlQAAIABAACAAAAAgAAAAAAqAAAAACICAuNvv/U91TQHDPj9OWYUaA81epuF23NAvxz6dF0q17NA\
+            GPadhz5UAACAAQAAgAAAAIABAAAAZAAAAAA=",
+        ),
+        (
+            "PSBTv0 with PSBT_IN_PREVIOUS_TXID",
+            "cHNidP8BAHECAAAAAQsK2SFBnByHGXNdctxzn56p4GONH+TB7vD5lECEgV/IAAAAAAD+////Agiv\
+            CAAAAAAAFgAUxDD2TEdW2jENvRoIVXLvKZkmJyyLvesLAAAAABYAFE3Rk6yWSlasG54cyoRU/i9H\
+            T4UTAAAAAAABAFICAAAAAcGqJW4hS5ahgi+T3kK/87Xz/40FGTBuNRXXUVpegFsSAAAAAAD/////\
+            ARjGmjsAAAAAFgAUsKOvFEIIQSaTyn0WaFK1LbCu8G4AAAAAAQEfGMaaOwAAAAAWABSwo68UQghB\
+            JpPKfRZoUrUtsK7wbgEOIAsK2SFBnByHGXNdctxzn56p4GONH+TB7vD5lECEgV/IACICAtYB+EhG\
+            pnVfd2vgDj2d6PsQrMk1+4PEX7AWLUytWreSGPadhz5UAACAAQAAgAAAAIAAAAAAKgAAAAAiAgLj\
+            b7/1PdU0Bwz4/TlmFGgPNXqbhdtzQL8c+nRdKtezQBj2nYc+VAAAgAEAAIAAAACAAQAAAGQAAAAA",
+        ),
+        (
+            "PSBTv0 with PSBT_IN_OUTPUT_INDEX",
+            "cHNidP8BAHECAAAAAQsK2SFBnByHGXNdctxzn56p4GONH+TB7vD5lECEgV/IAAAAAAD+////Agiv\
+            CAAAAAAAFgAUxDD2TEdW2jENvRoIVXLvKZkmJyyLvesLAAAAABYAFE3Rk6yWSlasG54cyoRU/i9H\
+            T4UTAAAAAAABAFICAAAAAcGqJW4hS5ahgi+T3kK/87Xz/40FGTBuNRXXUVpegFsSAAAAAAD/////\
+            ARjGmjsAAAAAFgAUsKOvFEIIQSaTyn0WaFK1LbCu8G4AAAAAAQEfGMaaOwAAAAAWABSwo68UQghB\
+            JpPKfRZoUrUtsK7wbgEPBAAAAAAAIgIC1gH4SEamdV93a+AOPZ3o+xCsyTX7g8RfsBYtTK1at5IY\
+            9p2HPlQAAIABAACAAAAAgAAAAAAqAAAAACICAuNvv/U91TQHDPj9OWYUaA81epuF23NAvxz6dF0q\
+            17NAGPadhz5UAACAAQAAgAAAAIABAAAAZAAAAAA=",
+        ),
+        (
+            "PSBTv0 with PSBT_IN_SEQUENCE",
+            "cHNidP8BAHECAAAAAQsK2SFBnByHGXNdctxzn56p4GONH+TB7vD5lECEgV/IAAAAAAD+////Agiv\
+            CAAAAAAAFgAUxDD2TEdW2jENvRoIVXLvKZkmJyyLvesLAAAAABYAFE3Rk6yWSlasG54cyoRU/i9H\
+            T4UTAAAAAAABAFICAAAAAcGqJW4hS5ahgi+T3kK/87Xz/40FGTBuNRXXUVpegFsSAAAAAAD/////\
+            ARjGmjsAAAAAFgAUsKOvFEIIQSaTyn0WaFK1LbCu8G4AAAAAAQEfGMaaOwAAAAAWABSwo68UQghB\
+            JpPKfRZoUrUtsK7wbgEQBP////8AIgIC1gH4SEamdV93a+AOPZ3o+xCsyTX7g8RfsBYtTK1at5IY\
+            9p2HPlQAAIABAACAAAAAgAAAAAAqAAAAACICAuNvv/U91TQHDPj9OWYUaA81epuF23NAvxz6dF0q\
+            17NAGPadhz5UAACAAQAAgAAAAIABAAAAZAAAAAA=",
+        ),
+        (
+            "PSBTv0 with PSBT_IN_REQUIRED_TIME_LOCKTIME",
+            "cHNidP8BAHECAAAAAQsK2SFBnByHGXNdctxzn56p4GONH+TB7vD5lECEgV/IAAAAAAD+////Agiv\
+            CAAAAAAAFgAUxDD2TEdW2jENvRoIVXLvKZkmJyyLvesLAAAAABYAFE3Rk6yWSlasG54cyoRU/i9H\
+            T4UTAAAAAAABAFICAAAAAcGqJW4hS5ahgi+T3kK/87Xz/40FGTBuNRXXUVpegFsSAAAAAAD/////\
+            ARjGmjsAAAAAFgAUsKOvFEIIQSaTyn0WaFK1LbCu8G4AAAAAAQEfGMaaOwAAAAAWABSwo68UQghB\
+            JpPKfRZoUrUtsK7wbgERBIyNxGIAIgIC1gH4SEamdV93a+AOPZ3o+xCsyTX7g8RfsBYtTK1at5IY\
+            9p2HPlQAAIABAACAAAAAgAAAAAAqAAAAACICAuNvv/U91TQHDPj9OWYUaA81epuF23NAvxz6dF0q\
+            17NAGPadhz5UAACAAQAAgAAAAIABAAAAZAAAAAA=",
+        ),
+        (
+            "PSBTv0 with PSBT_IN_REQUIRED_HEIGHT_LOCKTIME",
+            "cHNidP8BAHECAAAAAQsK2SFBnByHGXNdctxzn56p4GONH+TB7vD5lECEgV/IAAAAAAD+////Agiv\
+            CAAAAAAAFgAUxDD2TEdW2jENvRoIVXLvKZkmJyyLvesLAAAAABYAFE3Rk6yWSlasG54cyoRU/i9H\
+            T4UTAAAAAAABAFICAAAAAcGqJW4hS5ahgi+T3kK/87Xz/40FGTBuNRXXUVpegFsSAAAAAAD/////\
+            ARjGmjsAAAAAFgAUsKOvFEIIQSaTyn0WaFK1LbCu8G4AAAAAAQEfGMaaOwAAAAAWABSwo68UQghB\
+            JpPKfRZoUrUtsK7wbgESBBAnAAAAIgIC1gH4SEamdV93a+AOPZ3o+xCsyTX7g8RfsBYtTK1at5IY\
+            9p2HPlQAAIABAACAAAAAgAAAAAAqAAAAACICAuNvv/U91TQHDPj9OWYUaA81epuF23NAvxz6dF0q\
+            17NAGPadhz5UAACAAQAAgAAAAIABAAAAZAAAAAA=",
+        ),
+        (
+            "PSBTv0 with PSBT_OUT_AMOUNT",
+            "cHNidP8BAHECAAAAAQsK2SFBnByHGXNdctxzn56p4GONH+TB7vD5lECEgV/IAAAAAAD+////Agiv\
+            CAAAAAAAFgAUxDD2TEdW2jENvRoIVXLvKZkmJyyLvesLAAAAABYAFE3Rk6yWSlasG54cyoRU/i9H\
+            T4UTAAAAAAABAFICAAAAAcGqJW4hS5ahgi+T3kK/87Xz/40FGTBuNRXXUVpegFsSAAAAAAD/////\
+            ARjGmjsAAAAAFgAUsKOvFEIIQSaTyn0WaFK1LbCu8G4AAAAAAQEfGMaaOwAAAAAWABSwo68UQghB\
+            JpPKfRZoUrUtsK7wbgAiAgLWAfhIRqZ1X3dr4A49nej7EKzJNfuDxF+wFi1MrVq3khj2nYc+VAAA\
+            gAEAAIAAAACAAAAAACoAAAABAwgIrwgAAAAAAAAiAgLjb7/1PdU0Bwz4/TlmFGgPNXqbhdtzQL8c\
+            +nRdKtezQBj2nYc+VAAAgAEAAIAAAACAAQAAAGQAAAAA",
+        ),
+        (
+            "PSBTv0 with PSBT_OUT_SCRIPT",
+            "cHNidP8BAHECAAAAAQsK2SFBnByHGXNdctxzn56p4GONH+TB7vD5lECEgV/IAAAAAAD+////Agiv\
+            CAAAAAAAFgAUxDD2TEdW2jENvRoIVXLvKZkmJyyLvesLAAAAABYAFE3Rk6yWSlasG54cyoRU/i9H\
+            T4UTAAAAAAABAFICAAAAAcGqJW4hS5ahgi+T3kK/87Xz/40FGTBuNRXXUVpegFsSAAAAAAD/////\
+            ARjGmjsAAAAAFgAUsKOvFEIIQSaTyn0WaFK1LbCu8G4AAAAAAQEfGMaaOwAAAAAWABSwo68UQghB\
+            JpPKfRZoUrUtsK7wbgAiAgLWAfhIRqZ1X3dr4A49nej7EKzJNfuDxF+wFi1MrVq3khj2nYc+VAAA\
+            gAEAAIAAAACAAAAAACoAAAABBBYAFMQw9kxHVtoxDb0aCFVy7ymZJicsACICAuNvv/U91TQHDPj9\
+            OWYUaA81epuF23NAvxz6dF0q17NAGPadhz5UAACAAQAAgAAAAIABAAAAZAAAAAA=",
+        ),
+        (
+            "PSBTv2 missing PSBT_GLOBAL_TX_VERSION",
+            "cHNidP8BBAEBAQUBAgH7BAIAAAAAAQBSAgAAAAHBqiVuIUuWoYIvk95Cv/O18/+NBRkwbjUV11Fa\
+            XoBbEgAAAAAA/////wEYxpo7AAAAABYAFLCjrxRCCEEmk8p9FmhStS2wrvBuAAAAAAEBHxjGmjsA\
+            AAAAFgAUsKOvFEIIQSaTyn0WaFK1LbCu8G4BDiALCtkhQZwchxlzXXLcc5+eqeBjjR/kwe7w+ZRA\
+            hIFfyAEPBAAAAAAAIgIC1gH4SEamdV93a+AOPZ3o+xCsyTX7g8RfsBYtTK1at5IY9p2HPlQAAIAB\
+            AACAAAAAgAAAAAAqAAAAAQMICK8IAAAAAAABBBYAFMQw9kxHVtoxDb0aCFVy7ymZJicsACICAuNv\
+            v/U91TQHDPj9OWYUaA81epuF23NAvxz6dF0q17NAGPadhz5UAACAAQAAgAAAAIABAAAAZAAAAAED\
+            CIu96wsAAAAAAQQWABRN0ZOslkpWrBueHMqEVP4vR0+FEwA=",
+        ),
+        (
+            "PSBTv2 with PSBT_GLOBAL_TX_VERSION less than 2",
+            "cHNidP8BAgQBAAAAAQQBAQEFAQIB+wQCAAAAAAEAUgIAAAABwaolbiFLlqGCL5PeQr/ztfP/jQUZ\
+            MG41FddRWl6AWxIAAAAAAP////8BGMaaOwAAAAAWABSwo68UQghBJpPKfRZoUrUtsK7wbgAAAAAB\
+            AR8Yxpo7AAAAABYAFLCjrxRCCEEmk8p9FmhStS2wrvBuAQ4gCwrZIUGcHIcZc11y3HOfnqngY40f\
+            5MHu8PmUQISBX8gBDwQAAAAAACICAtYB+EhGpnVfd2vgDj2d6PsQrMk1+4PEX7AWLUytWreSGPad\
+            hz5UAACAAQAAgAAAAIAAAAAAKgAAAAEDCAivCAAAAAAAAQQWABTEMPZMR1baMQ29GghVcu8pmSYn\
+            LAAiAgLjb7/1PdU0Bwz4/TlmFGgPNXqbhdtzQL8c+nRdKtezQBj2nYc+VAAAgAEAAIAAAACAAQAA\
+            AGQAAAABAwiLvesLAAAAAAEEFgAUTdGTrJZKVqwbnhzKhFT+L0dPhRMA",
+        ),
+        (
+            "PSBTv2 missing PSBT_GLOBAL_INPUT_COUNT",
+            "cHNidP8BAgQCAAAAAQUBAgH7BAIAAAAAAQBSAgAAAAHBqiVuIUuWoYIvk95Cv/O18/+NBRkwbjUV\
+            11FaXoBbEgAAAAAA/////wEYxpo7AAAAABYAFLCjrxRCCEEmk8p9FmhStS2wrvBuAAAAAAEBHxjG\
+            mjsAAAAAFgAUsKOvFEIIQSaTyn0WaFK1LbCu8G4BDiALCtkhQZwchxlzXXLcc5+eqeBjjR/kwe7w\
+            +ZRAhIFfyAEPBAAAAAAAIgIC1gH4SEamdV93a+AOPZ3o+xCsyTX7g8RfsBYtTK1at5IY9p2HPlQA\
+            AIABAACAAAAAgAAAAAAqAAAAAQMICK8IAAAAAAABBBYAFMQw9kxHVtoxDb0aCFVy7ymZJicsACIC\
+            AuNvv/U91TQHDPj9OWYUaA81epuF23NAvxz6dF0q17NAGPadhz5UAACAAQAAgAAAAIABAAAAZAAA\
+            AAEDCIu96wsAAAAAAQQWABRN0ZOslkpWrBueHMqEVP4vR0+FEwA=",
+        ),
+        (
+            "PSBTv2 missing PSBT_GLOBAL_OUTPUT_COUNT",
+            "cHNidP8BAgQCAAAAAQQBAQH7BAIAAAAAAQBSAgAAAAHBqiVuIUuWoYIvk95Cv/O18/+NBRkwbjUV\
+            11FaXoBbEgAAAAAA/////wEYxpo7AAAAABYAFLCjrxRCCEEmk8p9FmhStS2wrvBuAAAAAAEBHxjG\
+            mjsAAAAAFgAUsKOvFEIIQSaTyn0WaFK1LbCu8G4BDiALCtkhQZwchxlzXXLcc5+eqeBjjR/kwe7w\
+            +ZRAhIFfyAEPBAAAAAAAIgIC1gH4SEamdV93a+AOPZ3o+xCsyTX7g8RfsBYtTK1at5IY9p2HPlQA\
+            AIABAACAAAAAgAAAAAAqAAAAAQMICK8IAAAAAAABBBYAFMQw9kxHVtoxDb0aCFVy7ymZJicsACIC\
+            AuNvv/U91TQHDPj9OWYUaA81epuF23NAvxz6dF0q17NAGPadhz5UAACAAQAAgAAAAIABAAAAZAAA\
+            AAEDCIu96wsAAAAAAQQWABRN0ZOslkpWrBueHMqEVP4vR0+FEwA=",
+        ),
+        (
+            "PSBTv2 missing PSBT_IN_PREVIOUS_TXID",
+            "cHNidP8BAgQCAAAAAQQBAQEFAQIB+wQCAAAAAAEAUgIAAAABwaolbiFLlqGCL5PeQr/ztfP/jQUZ\
+            MG41FddRWl6AWxIAAAAAAP////8BGMaaOwAAAAAWABSwo68UQghBJpPKfRZoUrUtsK7wbgAAAAAB\
+            AR8Yxpo7AAAAABYAFLCjrxRCCEEmk8p9FmhStS2wrvBuAQ8EAAAAAAAiAgLWAfhIRqZ1X3dr4A49\
+            nej7EKzJNfuDxF+wFi1MrVq3khj2nYc+VAAAgAEAAIAAAACAAAAAACoAAAABAwgIrwgAAAAAAAEE\
+            FgAUxDD2TEdW2jENvRoIVXLvKZkmJywAIgIC42+/9T3VNAcM+P05ZhRoDzV6m4Xbc0C/HPp0XSrX\
+            s0AY9p2HPlQAAIABAACAAAAAgAEAAABkAAAAAQMIi73rCwAAAAABBBYAFE3Rk6yWSlasG54cyoRU\
+            /i9HT4UTAA==",
+        ),
+        (
+            "PSBTv2 missing PSBT_IN_OUTPUT_INDEX",
+            "cHNidP8BAgQCAAAAAQQBAQEFAQIB+wQCAAAAAAEAUgIAAAABwaolbiFLlqGCL5PeQr/ztfP/jQUZ\
+            MG41FddRWl6AWxIAAAAAAP////8BGMaaOwAAAAAWABSwo68UQghBJpPKfRZoUrUtsK7wbgAAAAAB\
+            AR8Yxpo7AAAAABYAFLCjrxRCCEEmk8p9FmhStS2wrvBuAQ4gCwrZIUGcHIcZc11y3HOfnqngY40f\
+            5MHu8PmUQISBX8gAIgIC1gH4SEamdV93a+AOPZ3o+xCsyTX7g8RfsBYtTK1at5IY9p2HPlQAAIAB\
+            AACAAAAAgAAAAAAqAAAAAQMICK8IAAAAAAABBBYAFMQw9kxHVtoxDb0aCFVy7ymZJicsACICAuNv\
+            v/U91TQHDPj9OWYUaA81epuF23NAvxz6dF0q17NAGPadhz5UAACAAQAAgAAAAIABAAAAZAAAAAED\
+            CIu96wsAAAAAAQQWABRN0ZOslkpWrBueHMqEVP4vR0+FEwA=",
+        ),
+        (
+            "PSBTv2 missing PSBT_OUT_AMOUNT",
+            "cHNidP8BAgQCAAAAAQQBAQEFAQIB+wQCAAAAAAEAUgIAAAABwaolbiFLlqGCL5PeQr/ztfP/jQUZ\
+            MG41FddRWl6AWxIAAAAAAP////8BGMaaOwAAAAAWABSwo68UQghBJpPKfRZoUrUtsK7wbgAAAAAB\
+            AR8Yxpo7AAAAABYAFLCjrxRCCEEmk8p9FmhStS2wrvBuAQ4gCwrZIUGcHIcZc11y3HOfnqngY40f\
+            5MHu8PmUQISBX8gBDwQAAAAAACICAtYB+EhGpnVfd2vgDj2d6PsQrMk1+4PEX7AWLUytWreSGPad\
+            hz5UAACAAQAAgAAAAIAAAAAAKgAAAAEEFgAUxDD2TEdW2jENvRoIVXLvKZkmJywAIgIC42+/9T3V\
+            NAcM+P05ZhRoDzV6m4Xbc0C/HPp0XSrXs0AY9p2HPlQAAIABAACAAAAAgAEAAABkAAAAAQMIi73r\
+            CwAAAAABBBYAFE3Rk6yWSlasG54cyoRU/i9HT4UTAA==",
+        ),
+        (
+            "PSBTv2 missing PSBT_OUT_SCRIPT",
+            "cHNidP8BAgQCAAAAAQQBAQEFAQIB+wQCAAAAAAEAUgIAAAABwaolbiFLlqGCL5PeQr/ztfP/jQUZ\
+            MG41FddRWl6AWxIAAAAAAP////8BGMaaOwAAAAAWABSwo68UQghBJpPKfRZoUrUtsK7wbgAAAAAB\
+            AR8Yxpo7AAAAABYAFLCjrxRCCEEmk8p9FmhStS2wrvBuAQ4gCwrZIUGcHIcZc11y3HOfnqngY40f\
+            5MHu8PmUQISBX8gBDwQAAAAAACICAtYB+EhGpnVfd2vgDj2d6PsQrMk1+4PEX7AWLUytWreSGPad\
+            hz5UAACAAQAAgAAAAIAAAAAAKgAAAAEDCAivCAAAAAAAACICAuNvv/U91TQHDPj9OWYUaA81epuF\
+            23NAvxz6dF0q17NAGPadhz5UAACAAQAAgAAAAIABAAAAZAAAAAEDCIu96wsAAAAAAQQWABRN0ZOs\
+            lkpWrBueHMqEVP4vR0+FEwA=",
+        ),
+        (
+            "PSBTv2 with PSBT_IN_REQUIRED_TIME_LOCKTIME less than 500000000",
+            "cHNidP8BAgQCAAAAAQQBAQEFAQIB+wQCAAAAAAEAUgIAAAABwaolbiFLlqGCL5PeQr/ztfP/jQUZ\
+            MG41FddRWl6AWxIAAAAAAP////8BGMaaOwAAAAAWABSwo68UQghBJpPKfRZoUrUtsK7wbgAAAAAB\
+            AR8Yxpo7AAAAABYAFLCjrxRCCEEmk8p9FmhStS2wrvBuAQ4gCwrZIUGcHIcZc11y3HOfnqngY40f\
+            5MHu8PmUQISBX8gBDwQAAAAAAREE/2TNHQAiAgLWAfhIRqZ1X3dr4A49nej7EKzJNfuDxF+wFi1M\
+            rVq3khj2nYc+VAAAgAEAAIAAAACAAAAAACoAAAABAwgIrwgAAAAAAAEEFgAUxDD2TEdW2jENvRoI\
+            VXLvKZkmJywAIgIC42+/9T3VNAcM+P05ZhRoDzV6m4Xbc0C/HPp0XSrXs0AY9p2HPlQAAIABAACA\
+            AAAAgAEAAABkAAAAAQMIi73rCwAAAAABBBYAFE3Rk6yWSlasG54cyoRU/i9HT4UTAA==",
+        ),
+        (
+            "PSBTv2 with PSBT_IN_REQUIRED_HEIGHT_LOCKTIME greater than or equal to 500000000",
+            "cHNidP8BAgQCAAAAAQQBAQEFAQIB+wQCAAAAAAEAUgIAAAABwaolbiFLlqGCL5PeQr/ztfP/jQUZ\
+            MG41FddRWl6AWxIAAAAAAP////8BGMaaOwAAAAAWABSwo68UQghBJpPKfRZoUrUtsK7wbgAAAAAB\
+            AR8Yxpo7AAAAABYAFLCjrxRCCEEmk8p9FmhStS2wrvBuAQ4gCwrZIUGcHIcZc11y3HOfnqngY40f\
+            5MHu8PmUQISBX8gBDwQAAAAAARIEAGXNHQAiAgLWAfhIRqZ1X3dr4A49nej7EKzJNfuDxF+wFi1M\
+            rVq3khj2nYc+VAAAgAEAAIAAAACAAAAAACoAAAABAwgIrwgAAAAAAAEEFgAUxDD2TEdW2jENvRoI\
+            VXLvKZkmJywAIgIC42+/9T3VNAcM+P05ZhRoDzV6m4Xbc0C/HPp0XSrXs0AY9p2HPlQAAIABAACA\
+            AAAAgAEAAABkAAAAAQMIi73rCwAAAAABBBYAFE3Rk6yWSlasG54cyoRU/i9HT4UTAA==",
+        ),
+    ];
+
+    fn psbt_v2() -> Psbt {
+        let tx = Transaction {
+            version: 2,
+            lock_time: PackedLockTime(800_000),
+            input: vec![
+                TxIn {
+                    previous_output: OutPoint::new(Txid::from_inner([1u8; 32]), 3),
+                    ..TxIn::default()
+                },
+                TxIn {
+                    previous_output: OutPoint::new(Txid::from_inner([2u8; 32]), 0),
+                    sequence: bitcoin::Sequence(0xFFFFFFFD),
+                    ..TxIn::default()
+                },
+            ],
+            output: vec![TxOut {
+                value: 10_000,
+                script_pubkey: Script::from(vec![0x51]),
+            }],
+        };
+        let mut psbt = Psbt::with(tx, PsbtVersion::V2).unwrap();
+        psbt.inputs[0].required_height_locktime = Some(LockHeight::try_from(810_000).unwrap());
+        psbt.inputs[1].required_height_locktime = Some(LockHeight::try_from(805_000).unwrap());
+        psbt.inputs[1].unknown.insert(
+            raw::Key {
+                type_value: 0xA0,
+                key: vec![1, 2],
+            },
+            vec![3],
+        );
+        psbt.outputs[0].unknown.insert(
+            raw::Key {
+                type_value: 0xA0,
+                key: vec![],
+            },
+            vec![4],
+        );
+        psbt.tx_modifiable = Some(TxModifiable {
+            inputs: true,
+            outputs: false,
+            sighash_single: true,
+        });
+        psbt
+    }
+
+    #[test]
+    fn psbt_v2_serialization() {
+        let psbt = psbt_v2();
+        let data = psbt.serialize();
+        assert_eq!(Psbt::deserialize(&data).unwrap(), psbt);
+        assert_eq!(Psbt::from_str(&psbt.to_string()).unwrap(), psbt);
+        assert_eq!(u8::from(psbt.tx_modifiable.unwrap()), 0x05);
+
+        // PSBT v2 does not carry unsigned transaction
+        let mut cursor = Cursor::new(&data[PSBT_MAGIC.len()..]);
+        while let Ok(pair) = raw::Pair::consensus_decode(&mut cursor) {
+            assert_ne!(pair.key.type_value, PSBT_GLOBAL_UNSIGNED_TX);
+        }
+
+        let mut broken = psbt.clone();
+        broken.unknown.insert(
+            raw::Key {
+                type_value: PSBT_GLOBAL_UNSIGNED_TX,
+                key: vec![],
+            },
+            vec![],
+        );
+        assert!(matches!(
+            Psbt::deserialize(&broken.serialize()),
+            Err(encode::Error::Psbt(Error::InvalidKey(_)))
+        ));
+        assert!(Psbt::deserialize(&data[..data.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn psbt_version_conversion() {
+        let psbt = psbt_v2();
+        let tx = psbt.to_unsigned_tx().unwrap();
+        assert_eq!(tx.lock_time, PackedLockTime(810_000));
+        assert_eq!(tx.input[1].sequence, bitcoin::Sequence(0xFFFFFFFD));
+
+        let v0 = psbt.clone().into_version(PsbtVersion::V0).unwrap();
+        assert_eq!(v0.to_unsigned_tx(), Ok(tx.clone()));
+        assert_eq!(v0.tx_modifiable, None);
+        assert_eq!(v0.fallback_locktime, Some(LockTime::from(810_000)));
+        assert!(v0.inputs.iter().all(|input| input.locktime().is_none()));
+        let v0_data = v0.serialize();
+        assert_eq!(Psbt::deserialize(&v0_data).unwrap(), v0);
+        assert_eq!(
+            v0_data,
+            psbt.clone()
+                .into_version(PsbtVersion::V0)
+                .unwrap()
+                .serialize()
+        );
+        assert_eq!(
+            PsbtV0::try_from(v0.clone()).unwrap(),
+            consensus::deserialize::<PsbtV0>(&v0_data).unwrap()
+        );
+
+        let v2 = v0.clone().into_version(PsbtVersion::V2).unwrap();
+        assert_eq!(v2.to_unsigned_tx(), Ok(tx));
+        assert_eq!(Psbt::deserialize(&v2.serialize()).unwrap(), v2);
+        assert_eq!(v2.into_version(PsbtVersion::V0), Ok(v0));
+
+        let mut timelocked = psbt;
+        timelocked.inputs[0].required_height_locktime = None;
+        timelocked.inputs[0].required_time_locktime =
+            Some(LockTimestamp::try_from(1_700_000_000).unwrap());
+        timelocked.inputs[0].sequence_number = Some(SeqNo::from_consensus(0));
+        let data = timelocked.serialize();
+        assert_eq!(Psbt::deserialize(&data).unwrap(), timelocked);
+    }
+
+    #[test]
+    fn locktime_conflict() {
+        let mut psbt = psbt_v2();
+        psbt.inputs[0].required_height_locktime = Some(LockHeight::try_from(800_000).unwrap());
+        psbt.inputs[0].required_time_locktime = None;
+        psbt.inputs[1].required_height_locktime = None;
+        psbt.inputs[1].required_time_locktime =
+            Some(LockTimestamp::try_from(1_700_000_000).unwrap());
+
+        assert_eq!(psbt.final_locktime(), None);
+        assert_eq!(psbt.lock_time(), Err(LocktimeConflict));
+        assert_eq!(psbt.to_unsigned_tx(), Err(LocktimeConflict));
+        assert_eq!(psbt.to_txid(), Err(LocktimeConflict));
+        assert_eq!(psbt.extract_signed_tx(), Err(LocktimeConflict));
+        assert_eq!(PsbtV0::try_from(psbt.clone()), Err(LocktimeConflict));
+        assert_eq!(
+            psbt.clone().into_version(PsbtVersion::V0),
+            Err(LocktimeConflict)
+        );
+        assert!(matches!(
+            psbt.clone().combine(psbt.clone()),
+            Err(CombineError::Locktime(LocktimeConflict))
+        ));
+
+        // Version 2 PSBT keeps per-input requirements and stays serializable
+        assert_eq!(Psbt::deserialize(&psbt.serialize()).unwrap(), psbt);
+
+        // Relabeled PSBT is serialized with the fallback locktime
+        let fallback = LockTime::from(LockHeight::try_from(700_000).unwrap());
+        let mut relabeled = psbt;
+        relabeled.psbt_version = PsbtVersion::V0;
+        relabeled.fallback_locktime = Some(fallback);
+        assert!(!relabeled.to_string().is_empty());
+        let v0 = Psbt::deserialize(&relabeled.serialize()).unwrap();
+        assert_eq!(v0.lock_time(), Ok(fallback));
+    }
+
+    #[test]
+    fn bip370_vectors() {
+        for (case, data) in BIP370_VALID {
+            let psbt = Psbt::from_str(data).unwrap_or_else(|err| panic!("{case}: {err}"));
+            assert_eq!(psbt.psbt_version, PsbtVersion::V2, "{case}");
+            assert_eq!(psbt.inputs.len(), 1, "{case}");
+            assert_eq!(psbt.outputs.len(), 2, "{case}");
+            // Undefined modification flags are not preserved
+            if !case.contains("undefined") {
+                assert_eq!(psbt.to_string(), data, "{case}");
+            }
+        }
+        for (case, data) in BIP370_INVALID {
+            assert!(Psbt::from_str(data).is_err(), "{case}");
+        }
+    }
+
     #[test]
     #[ignore]
     fn psbt_bip174_serialization() {
@@ -12,6 +12,7 @@
 use std::collections::BTreeMap;
 
 use bitcoin::blockdata::transaction::NonStandardSighashType;
+use bitcoin::consensus::encode;
 use bitcoin::hashes::{hash160, ripemd160, sha256, sha256d};
 use bitcoin::psbt::PsbtSighashType;
 use bitcoin::util::bip32::KeySource;
@@ -29,6 +30,26 @@ use serde_with::{hex::Hex, As, Same};
 use crate::v0::InputV0;
 use crate::{raw, InputMatchError, TxinError};
 
+/// Type: Previous TXID PSBT_IN_PREVIOUS_TXID = 0x0E
+const PSBT_IN_PREVIOUS_TXID: u8 = 0x0E;
+/// Type: Spent Output Index PSBT_IN_OUTPUT_INDEX = 0x0F
+const PSBT_IN_OUTPUT_INDEX: u8 = 0x0F;
+/// Type: Sequence Number PSBT_IN_SEQUENCE = 0x10
+const PSBT_IN_SEQUENCE: u8 = 0x10;
+/// Type: Required Time-based Locktime PSBT_IN_REQUIRED_TIME_LOCKTIME = 0x11
+const PSBT_IN_REQUIRED_TIME_LOCKTIME: u8 = 0x11;
+/// Type: Required Height-based Locktime PSBT_IN_REQUIRED_HEIGHT_LOCKTIME = 0x12
+const PSBT_IN_REQUIRED_HEIGHT_LOCKTIME: u8 = 0x12;
+
+/// Input key types defined by BIP370 for PSBT version 2 only
+pub(crate) const PSBT_IN_V2_TYPES: [u8; 5] = [
+    PSBT_IN_PREVIOUS_TXID,
+    PSBT_IN_OUTPUT_INDEX,
+    PSBT_IN_SEQUENCE,
+    PSBT_IN_REQUIRED_TIME_LOCKTIME,
+    PSBT_IN_REQUIRED_HEIGHT_LOCKTIME,
+];
+
 // TODO: Do manual serde implementation to check the deserialized values
 #[derive(Clone, Eq, PartialEq, Debug, Default)]
 #[derive(StrictEncode, StrictDecode)]
@@ -208,6 +229,55 @@ impl Input {
         }
     }
 
+    /// Constructs input from a BIP370 input map, extracting version 2 fields
+    /// from the unknown key-value pairs parsed by the version 0 decoder.
+    pub(crate) fn with_v2(index: usize, mut v0: InputV0) -> Result<Self, encode::Error> {
+        let mut take = |type_value: u8| {
+            v0.unknown.remove(&raw::Key {
+                type_value,
+                key: vec![],
+            })
+        };
+
+        let txid = take(PSBT_IN_PREVIOUS_TXID).ok_or(encode::Error::ParseFailed(
+            "PSBT v2 input must have previous txid",
+        ))?;
+        let vout = take(PSBT_IN_OUTPUT_INDEX).ok_or(encode::Error::ParseFailed(
+            "PSBT v2 input must have output index",
+        ))?;
+        let sequence_number = take(PSBT_IN_SEQUENCE)
+            .map(|data| encode::deserialize::<u32>(&data).map(SeqNo::from_consensus))
+            .transpose()?;
+        let required_time_locktime = take(PSBT_IN_REQUIRED_TIME_LOCKTIME)
+            .map(|data| {
+                LockTimestamp::try_from(encode::deserialize::<u32>(&data)?).map_err(|_| {
+                    encode::Error::ParseFailed("invalid PSBT v2 input required time locktime")
+                })
+            })
+            .transpose()?;
+        let required_height_locktime = take(PSBT_IN_REQUIRED_HEIGHT_LOCKTIME)
+            .map(|data| {
+                LockHeight::try_from(encode::deserialize::<u32>(&data)?).map_err(|_| {
+                    encode::Error::ParseFailed("invalid PSBT v2 input required height locktime")
+                })
+            })
+            .transpose()?;
+
+        let txin = TxIn {
+            previous_output: OutPoint::new(
+                encode::deserialize(&txid)?,
+                encode::deserialize(&vout)?,
+            ),
+            ..TxIn::default()
+        };
+        Ok(Input {
+            sequence_number,
+            required_time_locktime,
+            required_height_locktime,
+            ..Input::with(index, v0, txin)
+        })
+    }
+
     #[inline]
     pub fn index(&self) -> usize { self.index }
 
@@ -326,6 +396,51 @@ impl Input {
             },
         )
     }
+
+    /// Converts input into a BIP370 input map, keeping version 2 fields among
+    /// the unknown key-value pairs for the version 0 encoder.
+    pub(crate) fn into_v2(self) -> InputV0 {
+        let mut fields = vec![
+            (
+                PSBT_IN_PREVIOUS_TXID,
+                encode::serialize(&self.previous_outpoint.txid),
+            ),
+            (
+                PSBT_IN_OUTPUT_INDEX,
+                encode::serialize(&self.previous_outpoint.vout),
+            ),
+        ];
+        if let Some(seq_no) = self.sequence_number {
+            fields.push((
+                PSBT_IN_SEQUENCE,
+                encode::serialize(&seq_no.into_consensus()),
+            ));
+        }
+        if let Some(lock) = self.required_time_locktime {
+            fields.push((
+                PSBT_IN_REQUIRED_TIME_LOCKTIME,
+                encode::serialize(&lock.into_consensus()),
+            ));
+        }
+        if let Some(lock) = self.required_height_locktime {
+            fields.push((
+                PSBT_IN_REQUIRED_HEIGHT_LOCKTIME,
+                encode::serialize(&lock.into_consensus()),
+            ));
+        }
+
+        let mut v0 = InputV0::from(self);
+        for (type_value, value) in fields {
+            v0.unknown.insert(
+                raw::Key {
+                    type_value,
+                    key: vec![],
+                },
+                value,
+            );
+        }
+        v0
+    }
 }
 
 impl From<Input> for InputV0 {
@@ -38,6 +38,7 @@ extern crate miniscript_crate as miniscript;
 mod errors;
 mod global;
 mod input;
+mod modify;
 mod output;
 pub mod p2c;
 
@@ -50,8 +51,10 @@ pub mod sign;
 
 pub use bitcoin::psbt::raw::ProprietaryKey;
 pub use bitcoin::psbt::{raw, serialize, Error, PsbtSighashType};
-pub use errors::{FeeError, InputMatchError, TxError, TxinError};
-pub use global::{Psbt, PsbtParseError};
+pub use errors::{
+    CombineError, FeeError, InputMatchError, LocktimeConflict, ModifyError, TxError, TxinError,
+};
+pub use global::{Psbt, PsbtParseError, TxModifiable};
 pub use input::Input;
 pub use output::Output;
 pub(crate) mod v0 {
@@ -0,0 +1,422 @@
+// Wallet-level libraries for bitcoin protocol by LNP/BP Association
+//
+// Written in 2020-2022 by
+//     Dr. Maxim Orlovsky <orlovsky@lnp-bp.org>
+//
+// This software is distributed without any warranty.
+//
+// You should have received a copy of the Apache-2.0 License
+// along with this software.
+// If not, see <https://opensource.org/licenses/Apache-2.0>.
+
+//! BIP370 constructor role: adding and removing inputs and outputs of a PSBT
+//! built interactively by multiple parties.
+
+use bitcoin::{EcdsaSighashType, SchnorrSighashType};
+
+use crate::{Input, ModifyError, Output, Psbt, TxModifiable};
+
+/// Transaction outputs committed to by a signature
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+enum Commitment {
+    All,
+    None,
+    Single,
+}
+
+/// Modification of the transaction inputs or outputs
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+enum Change {
+    AddInput,
+    RemoveInput(usize),
+    AddOutput,
+    RemoveOutput(usize),
+}
+
+impl Input {
+    /// Returns outputs commitment and `ANYONECANPAY` flag for each of the
+    /// input signatures. Since sighash types of finalized inputs are not
+    /// known, they are deduced from the transaction modification flags,
+    /// which signers must update according to the sighash types they use.
+    fn sig_commitments(&self, flags: TxModifiable) -> Vec<(Commitment, bool)> {
+        let ecdsa = self.partial_sigs.values().map(|sig| match sig.hash_ty {
+            EcdsaSighashType::All => (Commitment::All, false),
+            EcdsaSighashType::None => (Commitment::None, false),
+            EcdsaSighashType::Single => (Commitment::Single, false),
+            EcdsaSighashType::AllPlusAnyoneCanPay => (Commitment::All, true),
+            EcdsaSighashType::NonePlusAnyoneCanPay => (Commitment::None, true),
+            EcdsaSighashType::SinglePlusAnyoneCanPay => (Commitment::Single, true),
+        });
+        let schnorr = self
+            .tap_key_sig
+            .iter()
+            .chain(self.tap_script_sigs.values())
+            .map(|sig| match sig.hash_ty {
+                SchnorrSighashType::Default | SchnorrSighashType::All => (Commitment::All, false),
+                SchnorrSighashType::None => (Commitment::None, false),
+                SchnorrSighashType::Single => (Commitment::Single, false),
+                SchnorrSighashType::AllPlusAnyoneCanPay => (Commitment::All, true),
+                SchnorrSighashType::NonePlusAnyoneCanPay => (Commitment::None, true),
+                SchnorrSighashType::SinglePlusAnyoneCanPay => (Commitment::Single, true),
+            });
+        let mut commitments = ecdsa.chain(schnorr).collect::<Vec<_>>();
+
+        if self.final_script_sig.is_some() || self.final_script_witness.is_some() {
+            let commitment = match flags {
+                TxModifiable {
+                    sighash_single: true,
+                    ..
+                } => Commitment::Single,
+                TxModifiable { outputs: true, .. } => Commitment::None,
+                _ => Commitment::All,
+            };
+            commitments.push((commitment, flags.inputs));
+        }
+        commitments
+    }
+}
+
+impl Psbt {
+    /// Adds input to the PSBT, returning its index.
+    ///
+    /// The input can be added only if the inputs are modifiable according to
+    /// the BIP370 transaction modification flags (PSBTs without the flags,
+    /// including all version 0 PSBTs, are not modifiable). Addition fails if
+    /// it invalidates existing signatures, or if it makes the transaction
+    /// locktime undeterminable or changes it while the PSBT has signatures.
+    pub fn add_input(&mut self, mut input: Input) -> Result<usize, ModifyError> {
+        if !self.modifiable().inputs {
+            return Err(ModifyError::InputsNotModifiable);
+        }
+        if self
+            .inputs
+            .iter()
+            .any(|existing| existing.previous_outpoint == input.previous_outpoint)
+        {
+            return Err(ModifyError::DuplicateInput(input.previous_outpoint));
+        }
+        self.check_signatures(Change::AddInput)?;
+
+        let lock_time = self.final_locktime();
+        let index = self.inputs.len();
+        input.index = index;
+        self.inputs.push(input);
+        match self.final_locktime() {
+            None => {
+                self.inputs.pop();
+                Err(ModifyError::IncompatibleLocktime)
+            }
+            new if new != lock_time && self.is_signed() => {
+                self.inputs.pop();
+                Err(ModifyError::LocktimeChange)
+            }
+            _ => Ok(index),
+        }
+    }
+
+    /// Removes input from the PSBT, returning it. Indexes of the following
+    /// inputs are decremented.
+    ///
+    /// The input can be removed only if the inputs are modifiable according
+    /// to the BIP370 transaction modification flags. Removal fails if it
+    /// invalidates signatures of other inputs, including `SIGHASH_SINGLE`
+    /// signatures of the following inputs which would lose their paired
+    /// outputs, or if it changes locktime while the PSBT has signatures.
+    pub fn remove_input(&mut self, index: usize) -> Result<Input, ModifyError> {
+        if !self.modifiable().inputs {
+            return Err(ModifyError::InputsNotModifiable);
+        }
+        if index >= self.inputs.len() {
+            return Err(ModifyError::NoInput(index));
+        }
+        self.check_signatures(Change::RemoveInput(index))?;
+
+        let lock_time = self.final_locktime();
+        let input = self.inputs.remove(index);
+        if self.final_locktime() != lock_time && self.is_signed() {
+            self.inputs.insert(index, input);
+            return Err(ModifyError::LocktimeChange);
+        }
+        self.reindex();
+        Ok(input)
+    }
+
+    /// Adds output to the PSBT, returning its index.
+    ///
+    /// The output can be added only if the outputs are modifiable according
+    /// to the BIP370 transaction modification flags. Addition fails if it
+    /// invalidates existing signatures, committing to all outputs or to the
+    /// output with the same index (`SIGHASH_SINGLE`).
+    pub fn add_output(&mut self, mut output: Output) -> Result<usize, ModifyError> {
+        if !self.modifiable().outputs {
+            return Err(ModifyError::OutputsNotModifiable);
+        }
+        self.check_signatures(Change::AddOutput)?;
+
+        let index = self.outputs.len();
+        output.index = index;
+        self.outputs.push(output);
+        Ok(index)
+    }
+
+    /// Removes output from the PSBT, returning it. Indexes of the following
+    /// outputs are decremented.
+    ///
+    /// The output can be removed only if the outputs are modifiable according
+    /// to the BIP370 transaction modification flags. Removal fails if it
+    /// invalidates existing signatures, committing to all outputs or to the
+    /// removed or following outputs paired with their inputs by
+    /// `SIGHASH_SINGLE`.
+    pub fn remove_output(&mut self, index: usize) -> Result<Output, ModifyError> {
+        if !self.modifiable().outputs {
+            return Err(ModifyError::OutputsNotModifiable);
+        }
+        if index >= self.outputs.len() {
+            return Err(ModifyError::NoOutput(index));
+        }
+        self.check_signatures(Change::RemoveOutput(index))?;
+
+        let output = self.outputs.remove(index);
+        self.reindex();
+        Ok(output)
+    }
+
+    #[inline]
+    fn modifiable(&self) -> TxModifiable { self.tx_modifiable.unwrap_or_default() }
+
+    fn is_signed(&self) -> bool {
+        let flags = self.modifiable();
+        self.inputs
+            .iter()
+            .any(|input| !input.sig_commitments(flags).is_empty())
+    }
+
+    fn check_signatures(&self, change: Change) -> Result<(), ModifyError> {
+        let flags = self.modifiable();
+        for (index, input) in self.inputs.iter().enumerate() {
+            for (commitment, anyone_can_pay) in input.sig_commitments(flags) {
+                let invalidated = match change {
+                    Change::AddInput => !anyone_can_pay,
+                    Change::RemoveInput(removed) if removed == index => false,
+                    Change::RemoveInput(removed) => {
+                        !anyone_can_pay || (commitment == Commitment::Single && index > removed)
+                    }
+                    Change::AddOutput => {
+                        commitment == Commitment::All
+                            || (commitment == Commitment::Single && index == self.outputs.len())
+                    }
+                    Change::RemoveOutput(removed) => {
+                        commitment == Commitment::All
+                            || (commitment == Commitment::Single && index >= removed)
+                    }
+                };
+                if invalidated {
+                    return Err(ModifyError::SignatureInvalidated(index));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn reindex(&mut self) {
+        for (index, input) in self.inputs.iter_mut().enumerate() {
+            input.index = index;
+        }
+        for (index, output) in self.outputs.iter_mut().enumerate() {
+            output.index = index;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use bitcoin::hashes::Hash;
+    use bitcoin::{secp256k1, EcdsaSig, OutPoint, PublicKey, Script, TxOut, Txid};
+    use bitcoin_blockchain::locks::{LockHeight, LockTime, LockTimestamp};
+
+    use super::*;
+    use crate::PsbtVersion;
+
+    fn input(no: u8) -> Input {
+        Input {
+            previous_outpoint: OutPoint::new(Txid::from_inner([no; 32]), 0),
+            ..Input::default()
+        }
+    }
+
+    fn output(amount: u64) -> Output {
+        Output::new(0, TxOut {
+            value: amount,
+            script_pubkey: Script::from(vec![0x51]),
+        })
+    }
+
+    fn sign(input: &mut Input, hash_ty: EcdsaSighashType) {
+        let pubkey = PublicKey::from_str(
+            "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798",
+        )
+        .unwrap();
+        let sig = secp256k1::ecdsa::Signature::from_compact(&[1u8; 64]).unwrap();
+        input.partial_sigs.insert(pubkey, EcdsaSig { sig, hash_ty });
+    }
+
+    fn shared_psbt() -> Psbt {
+        let mut psbt = Psbt {
+            psbt_version: PsbtVersion::V2,
+            tx_version: 2,
+            ..Psbt::default()
+        };
+        assert_eq!(
+            psbt.add_input(input(1)),
+            Err(ModifyError::InputsNotModifiable)
+        );
+        assert_eq!(
+            psbt.add_output(output(1000)),
+            Err(ModifyError::OutputsNotModifiable)
+        );
+        psbt.tx_modifiable = Some(TxModifiable {
+            inputs: true,
+            outputs: true,
+            sighash_single: false,
+        });
+        psbt
+    }
+
+    #[test]
+    fn interactive_construction() {
+        let mut psbt = shared_psbt();
+        psbt.fallback_locktime = Some(LockTime::from(700_000));
+
+        assert_eq!(psbt.add_input(input(1)), Ok(0));
+        assert_eq!(
+            psbt.add_input(input(1)),
+            Err(ModifyError::DuplicateInput(input(1).previous_outpoint))
+        );
+        assert_eq!(psbt.lock_time(), Ok(LockTime::from(700_000)));
+
+        let mut locked = input(2);
+        locked.required_height_locktime = Some(LockHeight::try_from(800_000).unwrap());
+        locked.required_time_locktime = Some(LockTimestamp::try_from(1_700_000_000).unwrap());
+        assert_eq!(psbt.add_input(locked), Ok(1));
+        assert_eq!(psbt.final_locktime(), Some(LockTime::from(800_000)));
+
+        let mut time_locked = input(3);
+        time_locked.required_time_locktime = Some(LockTimestamp::try_from(1_600_000_000).unwrap());
+        assert_eq!(psbt.add_input(time_locked.clone()), Ok(2));
+        assert_eq!(psbt.final_locktime(), Some(LockTime::from(1_700_000_000)));
+
+        let mut height_locked = input(4);
+        height_locked.required_height_locktime = Some(LockHeight::try_from(810_000).unwrap());
+        assert_eq!(
+            psbt.add_input(height_locked),
+            Err(ModifyError::IncompatibleLocktime)
+        );
+        assert_eq!(psbt.inputs.len(), 3);
+
+        assert_eq!(psbt.add_output(output(1000)), Ok(0));
+        assert_eq!(psbt.add_output(output(2000)), Ok(1));
+        assert_eq!(psbt.remove_output(0).unwrap().amount, 1000);
+        assert_eq!(psbt.outputs[0].index(), 0);
+        assert_eq!(psbt.remove_output(1), Err(ModifyError::NoOutput(1)));
+
+        // Time-locked input is signed, so the locktime can't change anymore
+        sign(&mut psbt.inputs[2], EcdsaSighashType::NonePlusAnyoneCanPay);
+        assert_eq!(
+            psbt.remove_input(0).unwrap().previous_outpoint,
+            input(1).previous_outpoint
+        );
+        assert_eq!(psbt.inputs[1].index(), 1);
+        assert_eq!(psbt.remove_input(0), Err(ModifyError::LocktimeChange));
+        assert_eq!(psbt.inputs.len(), 2);
+        assert_eq!(psbt.add_input(input(5)), Ok(2));
+        assert_eq!(psbt.add_output(output(3000)), Ok(1));
+
+        assert_eq!(psbt.remove_input(1).unwrap(), {
+            let mut signed = time_locked;
+            signed.index = 1;
+            sign(&mut signed, EcdsaSighashType::NonePlusAnyoneCanPay);
+            signed
+        });
+        assert_eq!(
+            psbt.remove_input(1),
+            Ok(Input {
+                index: 1,
+                ..input(5)
+            })
+        );
+    }
+
+    #[test]
+    fn signature_protection() {
+        let mut psbt = shared_psbt();
+        psbt.add_input(input(1)).unwrap();
+        psbt.add_input(input(2)).unwrap();
+        psbt.add_output(output(1000)).unwrap();
+        psbt.add_output(output(2000)).unwrap();
+
+        sign(
+            &mut psbt.inputs[1],
+            EcdsaSighashType::SinglePlusAnyoneCanPay,
+        );
+        assert_eq!(
+            psbt.remove_output(1),
+            Err(ModifyError::SignatureInvalidated(1))
+        );
+        assert_eq!(
+            psbt.remove_output(0),
+            Err(ModifyError::SignatureInvalidated(1))
+        );
+        assert_eq!(
+            psbt.remove_input(0),
+            Err(ModifyError::SignatureInvalidated(1))
+        );
+        assert_eq!(psbt.add_input(input(3)), Ok(2));
+        assert_eq!(psbt.add_output(output(3000)), Ok(2));
+        assert_eq!(psbt.remove_output(2).unwrap().amount, 3000);
+        assert_eq!(
+            psbt.remove_input(2),
+            Ok(Input {
+                index: 2,
+                ..input(3)
+            })
+        );
+
+        sign(&mut psbt.inputs[0], EcdsaSighashType::All);
+        assert_eq!(
+            psbt.add_input(input(3)),
+            Err(ModifyError::SignatureInvalidated(0))
+        );
+        assert_eq!(
+            psbt.add_output(output(3000)),
+            Err(ModifyError::SignatureInvalidated(0))
+        );
+        assert_eq!(
+            psbt.remove_input(0),
+            Err(ModifyError::SignatureInvalidated(1))
+        );
+        assert_eq!(
+            psbt.remove_input(1),
+            Err(ModifyError::SignatureInvalidated(0))
+        );
+
+        // Finalized inputs are assumed to be signed according to the flags
+        let mut psbt = shared_psbt();
+        psbt.add_input(input(1)).unwrap();
+        psbt.inputs[0].final_script_witness = Some(bitcoin::Witness::default());
+        psbt.tx_modifiable.as_mut().unwrap().sighash_single = true;
+        assert_eq!(
+            psbt.add_output(output(1000)),
+            Err(ModifyError::SignatureInvalidated(0))
+        );
+        psbt.tx_modifiable.as_mut().unwrap().sighash_single = false;
+        assert_eq!(psbt.add_output(output(1000)), Ok(0));
+        psbt.tx_modifiable.as_mut().unwrap().outputs = false;
+        assert_eq!(
+            psbt.remove_output(0),
+            Err(ModifyError::OutputsNotModifiable)
+        );
+        assert_eq!(psbt.add_input(input(2)), Ok(1));
+    }
+}
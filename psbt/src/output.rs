@@ -11,10 +11,11 @@
 
 use std::collections::BTreeMap;
 
+use bitcoin::consensus::encode;
 use bitcoin::psbt::TapTree;
 use bitcoin::util::bip32::KeySource;
 use bitcoin::util::taproot::TapLeafHash;
-use bitcoin::{secp256k1, TxOut, XOnlyPublicKey};
+use bitcoin::{secp256k1, Script, TxOut, XOnlyPublicKey};
 use bitcoin_scripts::{PubkeyScript, RedeemScript, WitnessScript};
 #[cfg(feature = "serde")]
 use serde_with::{hex::Hex, As, Same};
@@ -22,6 +23,14 @@ use serde_with::{hex::Hex, As, Same};
 use crate::raw;
 use crate::v0::OutputV0;
 
+/// Type: Output Amount PSBT_OUT_AMOUNT = 0x03
+const PSBT_OUT_AMOUNT: u8 = 0x03;
+/// Type: Output Script PSBT_OUT_SCRIPT = 0x04
+const PSBT_OUT_SCRIPT: u8 = 0x04;
+
+/// Output key types defined by BIP370 for PSBT version 2 only
+pub(crate) const PSBT_OUT_V2_TYPES: [u8; 2] = [PSBT_OUT_AMOUNT, PSBT_OUT_SCRIPT];
+
 // TODO: Do manual serde implementation to check the deserialized values
 #[derive(Clone, Eq, PartialEq, Debug, Default)]
 #[derive(StrictEncode, StrictDecode)]
@@ -100,6 +109,30 @@ impl Output {
         }
     }
 
+    /// Constructs output from a BIP370 output map, extracting version 2 fields
+    /// from the unknown key-value pairs parsed by the version 0 decoder.
+    pub(crate) fn with_v2(index: usize, mut v0: OutputV0) -> Result<Self, encode::Error> {
+        let mut take = |type_value: u8| {
+            v0.unknown.remove(&raw::Key {
+                type_value,
+                key: vec![],
+            })
+        };
+
+        let amount = take(PSBT_OUT_AMOUNT).ok_or(encode::Error::ParseFailed(
+            "PSBT v2 output must have amount",
+        ))?;
+        let script = take(PSBT_OUT_SCRIPT).ok_or(encode::Error::ParseFailed(
+            "PSBT v2 output must have script",
+        ))?;
+
+        let txout = TxOut {
+            value: encode::deserialize(&amount)?,
+            script_pubkey: Script::from(script),
+        };
+        Ok(Output::with(index, v0, txout))
+    }
+
     #[inline]
     pub fn index(&self) -> usize { self.index }
 
@@ -135,6 +168,25 @@ impl Output {
             },
         )
     }
+
+    /// Converts output into a BIP370 output map, keeping version 2 fields
+    /// among the unknown key-value pairs for the version 0 encoder.
+    pub(crate) fn into_v2(self) -> OutputV0 {
+        let (mut v0, txout) = self.split();
+        for (type_value, value) in [
+            (PSBT_OUT_AMOUNT, encode::serialize(&txout.value)),
+            (PSBT_OUT_SCRIPT, txout.script_pubkey.into_bytes()),
+        ] {
+            v0.unknown.insert(
+                raw::Key {
+                    type_value,
+                    key: vec![],
+                },
+                value,
+            );
+        }
+        v0
+    }
 }
 
 impl From<Output> for OutputV0 {
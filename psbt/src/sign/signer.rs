@@ -34,7 +34,7 @@ use descriptors::{CompositeDescrType, DeductionError};
 use miniscript::{Miniscript, ToPublicKey};
 
 use super::SecretProvider;
-use crate::{Input, InputMatchError, Psbt};
+use crate::{Input, InputMatchError, LocktimeConflict, Psbt};
 
 /// Errors happening during whole PSBT signing process
 #[derive(Debug, Display, Error)]
@@ -95,6 +95,10 @@ pub enum SignInputError {
     #[from]
     TaprootSighashError(sighash::Error),
 
+    /// input requires locktime of a type incompatible with other inputs
+    #[from]
+    Locktime(LocktimeConflict),
+
     /// taproot key signature existing hash type `{prev_sighash_type:?}` does
     /// not match current type `{sighash_type:?}` for input
     TaprootKeySighashTypeMismatch {
@@ -146,6 +150,7 @@ impl std::error::Error for SignInputError {
             SignInputError::LegacySpentTransactionMissed => None,
             SignInputError::TaprootPrevoutsMissed => None,
             SignInputError::TaprootSighashError(err) => Some(err),
+            SignInputError::Locktime(err) => Some(err),
             SignInputError::SecpPrivkeyDerivation => None,
             SignInputError::ScriptPubkeyMismatch => None,
             SignInputError::P2cTweak => None,
@@ -208,7 +213,18 @@ impl SignAll for Psbt {
         &mut self,
         provider: &impl SecretProvider<C>,
     ) -> Result<usize, SignError> {
-        let tx = self.clone().into_unsigned_tx();
+        let tx = self.clone().into_unsigned_tx().map_err(|err| {
+            // With conflicting locktimes some of the inputs requiring locktime
+            // must lack height-based requirement; we report the first of them
+            let input_index = self
+                .inputs
+                .iter()
+                .filter(|input| input.locktime().is_some())
+                .find(|input| input.required_height_locktime.is_none())
+                .map(Input::index)
+                .unwrap_or_default();
+            SignError::with_input_no(err.into(), input_index)
+        })?;
         let mut signature_count = 0usize;
         let mut sig_hasher = SighashCache::new(&tx);
 
@@ -21,7 +21,7 @@ extern crate amplify;
 extern crate miniscript_crate as miniscript;
 extern crate strict_encoding_crate as strict_encoding;
 
-use std::collections::{BTreeMap, BTreeSet};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
 use std::convert::Infallible;
 use std::fmt::{Debug, Display, Formatter, Write};
 use std::io::{stdin, stdout, BufRead, BufReader, Write as IoWrite};
@@ -35,13 +35,19 @@ use amplify::{IoError, Wrapper};
 use bitcoin::consensus::Encodable;
 use bitcoin::psbt::serialize::Serialize;
 use bitcoin::psbt::PartiallySignedTransaction;
+use bitcoin::secp256k1::rand::thread_rng;
 use bitcoin::secp256k1::Secp256k1;
 use bitcoin::util::address;
 use bitcoin::util::bip32::{ChildNumber, ExtendedPubKey};
-use bitcoin::{consensus, Address, Network};
+use bitcoin::{consensus, Address, Network, Script, Transaction, Txid};
 use bitcoin_blockchain::locks::LockTime;
 use bitcoin_hd::DeriveError;
-use bitcoin_onchain::UtxoResolverError;
+use bitcoin_onchain::blockchain::{FeeRate, HistoryEntry, MiningStatus, Utxo};
+use bitcoin_onchain::{
+    bitcoin_core, esplora, BackendError, BitcoinNetwork, Broadcast, ResolveChainState,
+    ResolveFeeRate, ResolveHistory, ResolveTx, ResolveTxFee, ResolveUtxo, TxResolverError,
+    UtxoResolverError,
+};
 use bitcoin_scripts::address::AddressCompat;
 use bitcoin_scripts::PubkeyScript;
 use clap::Parser;
@@ -52,15 +58,21 @@ use electrum_client::ElectrumApi;
 use miniscript::psbt::PsbtExt;
 use miniscript::{MiniscriptKey, TranslatePk};
 use miniscript_crate::Translator;
+use psbt::construct::rbf::BumpError;
+use psbt::construct::select::{Candidate, SelectionParams};
+use psbt::construct::weight::{self, TapSpendPath};
 use psbt::serialize::Deserialize;
-use psbt::{construct, ProprietaryKeyDescriptor, ProprietaryKeyError, ProprietaryKeyLocation};
+use psbt::{
+    construct, LocktimeConflict, ProprietaryKeyDescriptor, ProprietaryKeyError,
+    ProprietaryKeyLocation,
+};
 use slip132::{
     DefaultResolver, FromSlip132, KeyApplication, KeyVersion, ToSlip132, VersionResolver,
 };
-use wallet::descriptors::InputDescriptor;
+use wallet::descriptors::{InputDescriptor, PolicyError, WalletPolicy};
 use wallet::hd::{DerivationAccount, SegmentIndexes, UnhardenedIndex};
-use wallet::onchain::ResolveDescriptor;
-use wallet::psbt::{Psbt, PsbtParseError};
+use wallet::onchain::ScanDescriptor;
+use wallet::psbt::{Psbt, PsbtParseError, PsbtVersion};
 
 /// Command-line arguments
 #[derive(Parser)]
@@ -88,18 +100,58 @@ pub struct Args {
     #[clap(short = 'p', global = true)]
     pub electrum_port: Option<u16>,
 
+    /// Bitcoin network: `mainnet`, `testnet`, `testnet4`, `signet`,
+    /// `regtest` or a custom signet specified as `signet:<challenge hex>`.
+    ///
+    /// By default the network is detected from the wallet descriptor keys,
+    /// which can distinguish only mainnet and testnet. The network must be
+    /// consistent with the descriptor keys.
+    #[clap(long, global = true)]
+    pub network: Option<BitcoinNetwork>,
+
     /// Use Bitcoin Core descriptor representation.
     #[clap(long = "bitcoin-core-fmt", global = true)]
     pub bitcoin_core_fmt: bool,
-    /*
-    /// Bitcoin Core backend to use. If used, overrides `electrum_server`,
-    /// which becomes unused.
+
+    /// Bitcoin Core RPC backend to use, in form of
+    /// `[http://]host[:port][/wallet/<name>]`. If used, overrides
+    /// `electrum_server`, which becomes unused.
     ///
-    /// Used only by `check`, `history`, `construct` and some forms of
-    /// `extract` command
-    #[clap(long, global = true, conflicts_with = "electrum-server")]
+    /// Without the wallet path UTXOs are looked up with `scantxoutset`;
+    /// otherwise the node wallet, which must be watching the descriptor, is
+    /// queried. Transaction lookup requires `-txindex` for transactions not
+    /// known to the node wallet. Transaction history requires the wallet path.
+    ///
+    /// Used only by `check`, `history`, `construct`, `bump-fee` and some
+    /// forms of `extract` and `finalize` command
+    #[clap(long, global = true)]
     pub bitcoin_core: Option<String>,
-     */
+
+    /// Bitcoin Core RPC cookie file used for authentication
+    #[clap(
+        long,
+        global = true,
+        requires = "bitcoin_core",
+        conflicts_with = "rpc_user"
+    )]
+    pub rpc_cookie: Option<PathBuf>,
+
+    /// Bitcoin Core RPC user name used for authentication
+    #[clap(long, global = true, requires_all = ["bitcoin_core", "rpc_password"])]
+    pub rpc_user: Option<String>,
+
+    /// Bitcoin Core RPC password used for authentication
+    #[clap(long, global = true, requires = "rpc_user")]
+    pub rpc_password: Option<String>,
+
+    /// Esplora REST backend to use, in form of `http[s]://host[:port][/path]`,
+    /// for instance `https://blockstream.info/api`. If used, overrides
+    /// `electrum_server`, which becomes unused.
+    ///
+    /// Used only by `check`, `history`, `construct`, `bump-fee` and some
+    /// forms of `extract` and `finalize` command
+    #[clap(long, global = true, conflicts_with = "bitcoin_core")]
+    pub esplora: Option<String>,
 }
 
 /// Wallet command to execute
@@ -116,11 +168,24 @@ pub enum Command {
         #[clap(long)]
         account_file: Option<PathBuf>,
 
+        /// File to export BIP-388 wallet policy for the created wallet.
+        ///
+        /// Requires all keys in the descriptor to use `/<M;N>/*` derivation.
+        #[clap(long)]
+        policy_file: Option<PathBuf>,
+
+        /// Read the descriptor file as BIP-388 wallet policy: descriptor
+        /// template with `@N/**` key placeholders followed by the list of
+        /// keys, one per line.
+        #[clap(long, conflicts_with = "account_file")]
+        policy: bool,
+
         /// Wallet output descriptor text file. Can use explicit or named
         /// tracking accounts; in the second case please provide
         /// `--account-file` parameter.
         ///
-        /// Descriptor can use taproot and miniscript.
+        /// Descriptor can use taproot and miniscript. With `--policy` flag
+        /// the file must contain BIP-388 wallet policy instead.
         descriptor_file: PathBuf,
 
         /// File to save descriptor info
@@ -133,18 +198,14 @@ pub enum Command {
         /// Path to the read-only wallet file generated with `create` command
         wallet_file: PathBuf,
 
-        /// Minimum number of addresses to look ahead
+        /// Number of consecutive unused addresses after which the lookup stops
         #[clap(short = 'n', long, default_value = "20")]
         look_ahead: u16,
 
-        /// Number of addresses to skip
+        /// Number of addresses at the start of each derivation branch whose
+        /// UTXOs are not listed
         #[clap(short, long, default_value = "0")]
         skip: u16,
-
-        /// Show addresses using regtest prefix. Works only for testnet-based
-        /// wallet descriptors.
-        #[clap(long = "regtest")]
-        regtest: bool,
     },
 
     /// Read history of operations with descriptor controlled outputs from
@@ -152,6 +213,10 @@ pub enum Command {
     History {
         /// Path to the read-only wallet file generated with `create` command
         wallet_file: PathBuf,
+
+        /// Number of consecutive unused addresses after which the lookup stops
+        #[clap(short = 'n', long, default_value = "20")]
+        look_ahead: u16,
     },
 
     /// List addresses corresponding to the given descriptor wallet
@@ -170,11 +235,6 @@ pub enum Command {
         /// Whether or not to show change addresses
         #[clap(short = 'c', long = "change")]
         show_change: bool,
-
-        /// Display address using regtest prefix. Works only for testnet-based
-        /// descriptors.
-        #[clap(long = "regtest")]
-        regtest: bool,
     },
 
     /// Construct new PSBT.
@@ -195,16 +255,21 @@ pub enum Command {
         wallet_file: PathBuf,
 
         /// List of input descriptors, specifying public keys used in
-        /// generating provided UTXOs from the account data.
+        /// generating provided UTXOs from the account data. If no inputs are
+        /// given, they are selected from the wallet UTXOs.
         #[clap(
             short,
             long = "input",
-            required = true,
             long_help = "\
 List of input descriptors, specifying public keys used in generating provided
 UTXOs from the account data. Input descriptors are matched to UTXOs in
 automatic manner.
 
+If no input descriptors are given, the wallet UTXOs are discovered by scanning
+the descriptor addresses up to the `--look-ahead` gap limit, and the inputs are
+chosen among them with coin selection. This requires the fee to be specified
+with `--fee-rate` or `--target-blocks`.
+
 Input descriptor format:
 
 `txid:vout deriv-terminal [fingerprint:tweak] [rbf|height|time] [sighashtype]`
@@ -250,6 +315,11 @@ SIGHASH_TYPE representations:
         #[clap(short, long, default_value = "0")]
         change_index: UnhardenedIndex,
 
+        /// Number of consecutive unused addresses after which the lookup of
+        /// the wallet UTXOs stops; used only when no inputs are given
+        #[clap(short = 'n', long, default_value = "20")]
+        look_ahead: u16,
+
         /// Additional proprietary keys which will be added to the constructed
         /// PSBT.
         #[clap(short = 'k', long = "proprietary-key")]
@@ -258,11 +328,79 @@ SIGHASH_TYPE representations:
         /// Destination file to save constructed PSBT
         psbt_file: PathBuf,
 
+        /// Construct PSBT version 2 (BIP370) instead of version 0 (BIP174).
+        #[clap(long)]
+        psbt_v2: bool,
+
         /// Total fee to pay to the miners, in satoshis.
         ///
         /// The fee is used in change calculation; the change address is
         /// added automatically.
-        fee: u64,
+        #[clap(required_unless_present_any = ["fee_rate", "target_blocks"])]
+        fee: Option<u64>,
+
+        /// Fee rate in satoshis per virtual byte, used instead of the total
+        /// fee. The fee is computed from the transaction size estimated using
+        /// the maximal satisfaction weight of the wallet descriptor.
+        #[clap(long, conflicts_with = "fee")]
+        fee_rate: Option<FeeRate>,
+
+        /// Use fee rate estimated by the blockchain backend for the
+        /// transaction to get confirmed within the given number of blocks,
+        /// instead of the explicit fee or fee rate.
+        #[clap(long, conflicts_with_all = ["fee", "fee_rate"])]
+        target_blocks: Option<u16>,
+
+        /// Estimate the size of taproot inputs assuming they are spent with
+        /// the most expensive script path instead of the key path.
+        #[clap(long, conflicts_with = "fee")]
+        script_path: bool,
+    },
+
+    /// Construct BIP-125 replacement for an unconfirmed transaction paying
+    /// higher fee.
+    ///
+    /// The replacement keeps all recipient outputs and takes the additional
+    /// fee from the change; if the change is not sufficient, the provided
+    /// inputs are added to the transaction.
+    BumpFee {
+        /// Wallet descriptor file
+        wallet_file: PathBuf,
+
+        /// Fee rate for the replacement, in satoshis per virtual byte
+        fee_rate: FeeRate,
+
+        /// Destination file to save the replacement PSBT
+        psbt_file: PathBuf,
+
+        /// PSBT file of the transaction which has to be replaced
+        #[clap(long, required_unless_present = "txid", conflicts_with = "txid")]
+        original: Option<PathBuf>,
+
+        /// Id of the transaction which has to be replaced; the transaction is
+        /// retrieved from the electrum server
+        #[clap(long)]
+        txid: Option<Txid>,
+
+        /// Number of addresses on each derivation branch scanned to detect
+        /// the wallet inputs and change of the replaced transaction
+        #[clap(long, default_value = "100")]
+        lookup_depth: u32,
+
+        /// Additional confirmed wallet inputs spent if the change can't cover
+        /// the fee, in the same format as for `construct` command.
+        #[clap(short, long = "input")]
+        inputs: Vec<InputDescriptor>,
+
+        /// Derivation index for change address, used only if the replaced
+        /// transaction had no change
+        #[clap(short, long, default_value = "0")]
+        change_index: UnhardenedIndex,
+
+        /// Estimate the size of taproot inputs assuming they are spent with
+        /// the most expensive script path instead of the key path.
+        #[clap(long)]
+        script_path: bool,
     },
 
     /// Try to finalize PSBT
@@ -273,9 +411,10 @@ SIGHASH_TYPE representations:
         tx_file: Option<PathBuf>,
 
         /// Publish the transaction to the network; optional argument allows
-        /// to specify some custom network (testnet, for instance).
+        /// to specify some custom network (testnet, for instance). Defaults to
+        /// the network given with `--network` option or to mainnet.
         #[clap(long)]
-        publish: Option<Option<Network>>,
+        publish: Option<Option<BitcoinNetwork>>,
 
         /// File containing fully-signed PSBT
         psbt_file: PathBuf,
@@ -299,19 +438,64 @@ SIGHASH_TYPE representations:
 }
 
 impl Args {
-    fn electrum_client(&self, network: Network) -> Result<electrum::Client, electrum::Error> {
+    /// Detects network for the wallet descriptor, which is either specified
+    /// with `--network` option or matches the descriptor keys
+    fn network(
+        &self,
+        descriptor: &miniscript::Descriptor<DerivationAccount>,
+    ) -> Result<BitcoinNetwork, Error> {
+        let key_network = descriptor.network()?;
+        match &self.network {
+            None => Ok(BitcoinNetwork::from(key_network)),
+            Some(network) if network.is_testnet() == (key_network != Network::Bitcoin) => {
+                Ok(network.clone())
+            }
+            Some(_) => Err(DeriveError::InconsistentKeyNetwork.into()),
+        }
+    }
+
+    fn backend(&self, network: &BitcoinNetwork) -> Result<Backend, Error> {
+        if let Some(url) = &self.bitcoin_core {
+            let auth = match (&self.rpc_cookie, &self.rpc_user, &self.rpc_password) {
+                (Some(cookie), _, _) => bitcoin_core::Auth::Cookie(cookie.clone()),
+                (None, Some(user), Some(password)) => {
+                    bitcoin_core::Auth::UserPass(user.clone(), password.clone())
+                }
+                _ => bitcoin_core::Auth::None,
+            };
+            eprintln!(
+                "Connecting to network {} using Bitcoin Core {}",
+                network.to_string().yellow(),
+                url.yellow()
+            );
+            return Ok(Backend::BitcoinCore(bitcoin_core::Client::new(
+                url,
+                network.into(),
+                auth,
+            )?));
+        }
+
+        if let Some(url) = &self.esplora {
+            eprintln!(
+                "Connecting to network {} using esplora {}",
+                network.to_string().yellow(),
+                url.yellow()
+            );
+            return Ok(Backend::Esplora(esplora::Client::new(url)?));
+        }
+
         let electrum_url = format!(
             "{}:{}",
             self.electrum_server,
             self.electrum_port
-                .unwrap_or_else(|| default_electrum_port(network))
+                .unwrap_or_else(|| network.electrum_port())
         );
         eprintln!(
             "Connecting to network {} using {}",
             network.to_string().yellow(),
             electrum_url.yellow()
         );
-        electrum::Client::new(&electrum_url)
+        Ok(Backend::Electrum(electrum::Client::new(&electrum_url)?))
     }
 
     pub fn exec(&self) -> Result<(), Error> {
@@ -319,41 +503,100 @@ impl Args {
             Command::Inspect { file } => self.inspect(file.as_ref()),
             Command::Create {
                 account_file,
+                policy_file,
+                policy,
+                descriptor_file,
+                output_file,
+            } => Self::create(
                 descriptor_file,
                 output_file,
-            } => Self::create(descriptor_file, output_file, account_file.as_deref()),
+                account_file.as_deref(),
+                policy_file.as_deref(),
+                *policy,
+            ),
             Command::Check {
                 wallet_file,
                 look_ahead,
                 skip,
-                regtest,
-            } => self.check(wallet_file, *look_ahead, *skip, *regtest),
-            Command::History { .. } => self.history(),
+            } => self.check(wallet_file, *look_ahead, *skip),
+            Command::History {
+                wallet_file,
+                look_ahead,
+            } => self.history(wallet_file, *look_ahead),
             Command::Address {
                 wallet_file,
                 count,
                 skip,
                 show_change,
-                regtest,
-            } => self.address(wallet_file, *count, *skip, *show_change, *regtest),
+            } => self.address(wallet_file, *count, *skip, *show_change),
             Command::Construct {
                 locktime,
                 wallet_file,
                 inputs,
                 outputs,
                 change_index,
+                look_ahead,
                 proprietary_keys,
                 psbt_file,
+                psbt_v2,
                 fee,
-            } => self.construct(
+                fee_rate,
+                target_blocks,
+                script_path,
+            } => {
+                let tap_path = if *script_path {
+                    TapSpendPath::ScriptPath
+                } else {
+                    TapSpendPath::KeyPath
+                };
+                self.construct(
+                    wallet_file,
+                    *locktime,
+                    inputs,
+                    outputs,
+                    *change_index,
+                    *look_ahead,
+                    proprietary_keys,
+                    match (fee, fee_rate, target_blocks) {
+                        (Some(fee), _, _) => FeeSpec::Absolute(*fee),
+                        (None, Some(fee_rate), _) => FeeSpec::Rate(*fee_rate, tap_path),
+                        (None, None, Some(target)) => FeeSpec::Target(*target, tap_path),
+                        (None, None, None) => {
+                            unreachable!("clap requires either fee, fee rate or target")
+                        }
+                    },
+                    if *psbt_v2 {
+                        PsbtVersion::V2
+                    } else {
+                        PsbtVersion::V0
+                    },
+                    psbt_file,
+                )
+            }
+            Command::BumpFee {
                 wallet_file,
-                *locktime,
+                fee_rate,
+                psbt_file,
+                original,
+                txid,
+                lookup_depth,
                 inputs,
-                outputs,
-                *change_index,
-                proprietary_keys,
-                *fee,
+                change_index,
+                script_path,
+            } => self.bump_fee(
+                wallet_file,
+                *fee_rate,
                 psbt_file,
+                original.as_deref(),
+                *txid,
+                *lookup_depth,
+                inputs,
+                *change_index,
+                if *script_path {
+                    TapSpendPath::ScriptPath
+                } else {
+                    TapSpendPath::KeyPath
+                },
             ),
             Command::Finalize {
                 psbt_file,
@@ -362,10 +605,11 @@ impl Args {
             } => self.finalize(
                 psbt_file,
                 tx_file.as_ref(),
-                publish
-                    .as_ref()
-                    .copied()
-                    .map(|n| n.unwrap_or(Network::Bitcoin)),
+                publish.as_ref().map(|n| {
+                    n.clone()
+                        .or_else(|| self.network.clone())
+                        .unwrap_or(BitcoinNetwork::Mainnet)
+                }),
             ),
             Command::Info { data } => self.info(data.as_str()),
             Command::Convert { file } => self.convert(file),
@@ -376,6 +620,8 @@ impl Args {
         descriptor_file: &Path,
         path: &Path,
         account_file: Option<&Path>,
+        policy_file: Option<&Path>,
+        is_policy: bool,
     ) -> Result<(), Error> {
         pub struct DerivationRefTranslator<'a> {
             account_file: Option<&'a Path>,
@@ -404,20 +650,39 @@ impl Args {
             .and_then(AccountIndex::read_file)
             .unwrap_or_default();
 
-        let descriptor_str =
-            fs::read_to_string(descriptor_file)?.replace(['\n', '\r', ' ', '\t'], "");
-        println!(
-            "Creating wallet for descriptor:\n{}",
-            descriptor_str.bright_white()
-        );
-        let descriptor = miniscript::Descriptor::<DerivationRef>::from_str(&descriptor_str)?;
-        let descriptor = descriptor.translate_pk(&mut DerivationRefTranslator {
-            account_file,
-            accounts: &accounts,
-        })?;
+        let file_str = fs::read_to_string(descriptor_file)?;
+        let descriptor = if is_policy {
+            let policy = WalletPolicy::from_str(&file_str)?;
+            println!(
+                "Creating wallet for wallet policy:\n{}",
+                policy.to_string().bright_white()
+            );
+            policy.to_descriptor()
+        } else {
+            let descriptor_str = file_str.replace(['\n', '\r', ' ', '\t'], "");
+            println!(
+                "Creating wallet for descriptor:\n{}",
+                descriptor_str.bright_white()
+            );
+            let descriptor = miniscript::Descriptor::<DerivationRef>::from_str(&descriptor_str)?;
+            descriptor.translate_pk(&mut DerivationRefTranslator {
+                account_file,
+                accounts: &accounts,
+            })?
+        };
 
         fs::write(path, descriptor.to_string())?;
 
+        if let Some(policy_file) = policy_file {
+            let policy = WalletPolicy::from_descriptor(&descriptor)?;
+            fs::write(policy_file, format!("{}\n", policy))?;
+            println!(
+                "{} in `{}`",
+                "Wallet policy exported".bright_green(),
+                policy_file.display()
+            );
+        }
+
         println!(
             "{} in `{}`\n",
             "Wallet created".bright_green(),
@@ -427,14 +692,7 @@ impl Args {
         Ok(())
     }
 
-    fn address(
-        &self,
-        path: &Path,
-        count: u16,
-        skip: u16,
-        show_change: bool,
-        regtest: bool,
-    ) -> Result<(), Error> {
+    fn address(&self, path: &Path, count: u16, skip: u16, show_change: bool) -> Result<(), Error> {
         let secp = Secp256k1::new();
 
         let descriptor_str = fs::read_to_string(path)?;
@@ -450,6 +708,7 @@ impl Args {
         if descriptor.derive_pattern_len()? != 2 {
             return Err(Error::DescriptorDerivePattern);
         }
+        let network = self.network(&descriptor)?;
         for index in skip..(skip + count) {
             let address = descriptor.address(
                 &secp,
@@ -457,7 +716,7 @@ impl Args {
                     UnhardenedIndex::from(u8::from(show_change)),
                     UnhardenedIndex::from(index),
                 ],
-                regtest,
+                Network::from(&network),
             )?;
 
             println!("{:>6} {}", format!("#{}", index).dimmed(), address);
@@ -468,15 +727,15 @@ impl Args {
         Ok(())
     }
 
-    fn check(&self, path: &Path, batch_size: u16, skip: u16, regtest: bool) -> Result<(), Error> {
+    fn check(&self, path: &Path, look_ahead: u16, skip: u16) -> Result<(), Error> {
         let secp = Secp256k1::new();
 
         let descriptor_str = fs::read_to_string(path)?;
         let descriptor: miniscript::Descriptor<DerivationAccount> =
             miniscript::Descriptor::from_str(&descriptor_str)?;
 
-        let network = descriptor.network(regtest)?;
-        let client = self.electrum_client(network)?;
+        let network = self.network(&descriptor)?;
+        let client = self.backend(&network)?;
 
         println!(
             "{}\n{}\n",
@@ -484,80 +743,56 @@ impl Args {
             descriptor.to_string_std(self.bitcoin_core_fmt)
         );
 
-        let mut total = 0u64;
-        let mut single_pat = [UnhardenedIndex::zero(); 1];
-        let mut double_pat = [UnhardenedIndex::zero(); 2];
-        let derive_pattern = match descriptor.derive_pattern_len()? {
-            1 => single_pat.as_mut_slice(),
-            2 => double_pat.as_mut_slice(),
-            _ => return Err(Error::DescriptorDerivePattern),
-        };
-        for case in 0u8..(derive_pattern.len() as u8) {
-            let mut offset = skip;
-            let mut last_count = 1usize;
-            if derive_pattern.len() > 1 {
-                if let Some(idx) = derive_pattern.first_mut() {
-                    *idx = UnhardenedIndex::from(case)
-                }
-            }
-            loop {
-                eprint!("Batch {}/{}..{}", case, offset, offset + batch_size);
-
-                let mut addr_total = 0u64;
-                let mut count = 0usize;
-                eprint!(" ... ");
-                for (index, (script, utxo_set)) in client.resolve_descriptor_utxo(
-                    &secp,
-                    &descriptor,
-                    [UnhardenedIndex::from(case)],
-                    UnhardenedIndex::from(offset),
-                    batch_size as u32,
-                )? {
-                    if utxo_set.is_empty() {
-                        continue;
-                    }
-                    count += utxo_set.len();
-
-                    let derive_term = format!("{}/{}", case, index);
-                    if let Some(address) =
-                        AddressCompat::from_script(&script.clone().into(), network.into())
-                    {
-                        println!(
-                            "\n  {} address {}:",
-                            derive_term.bright_white(),
-                            address.to_string().bright_white(),
-                        );
-                    } else {
-                        println!(
-                            "\n  {} no-address script {}:",
-                            derive_term.bright_white(),
-                            script
-                        );
-                    }
+        eprint!("Scanning wallet addresses ... ");
+        let scan = client.scan_descriptor(&secp, &descriptor, look_ahead as u32)?;
+        eprintln!("{}", "done".green());
 
-                    for utxo in utxo_set {
-                        println!(
-                            "{:>10} @ {} - {}",
-                            utxo.amount().to_string().bright_yellow(),
-                            utxo.outpoint(),
-                            utxo.mined()
-                        );
-                        addr_total += utxo.amount().to_sat();
-                    }
-                }
+        let mut used = scan
+            .scripts
+            .iter()
+            .filter(|(_, (terminal, utxo_set))| {
+                !utxo_set.is_empty()
+                    && terminal
+                        .last()
+                        .map_or(true, |index| index.first_index() >= skip as u32)
+            })
+            .collect::<Vec<_>>();
+        used.sort_by_key(|(_, (terminal, _))| terminal);
 
-                offset += batch_size;
-                total += addr_total;
+        let mut total = 0u64;
+        for (script, (terminal, utxo_set)) in used {
+            let derive_term = terminal
+                .iter()
+                .map(UnhardenedIndex::to_string)
+                .collect::<Vec<_>>()
+                .join("/");
+            if let Some(address) =
+                AddressCompat::from_script(&script.clone().into(), Network::from(&network).into())
+            {
+                println!(
+                    "\n  {} address {}:",
+                    derive_term.bright_white(),
+                    address.to_string().bright_white(),
+                );
+            } else {
+                println!(
+                    "\n  {} no-address script {}:",
+                    derive_term.bright_white(),
+                    script
+                );
+            }
 
-                if count == 0 {
-                    eprintln!("empty");
-                }
-                if last_count == 0 && count == 0 {
-                    break;
-                }
-                last_count = count;
+            for utxo in utxo_set {
+                println!(
+                    "{:>10} @ {} - {}",
+                    utxo.amount().to_string().bright_yellow(),
+                    utxo.outpoint(),
+                    utxo.mined()
+                );
+                total += utxo.amount().to_sat();
             }
         }
+        println!();
 
         println!(
             "Total {} sats\n",
@@ -567,7 +802,96 @@ impl Args {
         Ok(())
     }
 
-    fn history(&self) -> Result<(), Error> { todo!() }
+    fn history(&self, path: &Path, look_ahead: u16) -> Result<(), Error> {
+        let secp = Secp256k1::new();
+
+        let descriptor_str = fs::read_to_string(path)?;
+        let descriptor: miniscript::Descriptor<DerivationAccount> =
+            miniscript::Descriptor::from_str(&descriptor_str)?;
+
+        let network = self.network(&descriptor)?;
+        let client = self.backend(&network)?;
+
+        println!(
+            "{}\n{}\n",
+            "\nWallet descriptor:".bright_white(),
+            descriptor.to_string_std(self.bitcoin_core_fmt)
+        );
+
+        if !matches!(descriptor.derive_pattern_len()?, 1 | 2) {
+            return Err(Error::DescriptorDerivePattern);
+        }
+
+        eprint!("Scanning wallet addresses ... ");
+        let scan = client.scan_descriptor(&secp, &descriptor, look_ahead as u32)?;
+        let wallet_scripts = &scan.scripts;
+        let entries = client
+            .resolve_history(wallet_scripts.keys())?
+            .into_iter()
+            .flatten()
+            .map(|entry| (*entry.txid(), entry))
+            .collect::<BTreeMap<_, _>>();
+        eprintln!("{} transaction(s)", entries.len());
+
+        let txs = client.transactions(&entries.keys().copied().collect())?;
+        let wallet_prevout = |txin: &bitcoin::TxIn| {
+            txs.get(&txin.previous_output.txid)
+                .and_then(|tx| tx.output.get(txin.previous_output.vout as usize))
+                .filter(|txout| wallet_scripts.contains_key(&txout.script_pubkey))
+        };
+
+        // Fees can be computed only for transactions spending wallet funds, for
+        // which we need all previous transactions
+        let prev_txids = txs
+            .values()
+            .filter(|tx| tx.input.iter().any(|txin| wallet_prevout(txin).is_some()))
+            .flat_map(|tx| tx.input.iter().map(|txin| txin.previous_output.txid))
+            .filter(|txid| !txs.contains_key(txid))
+            .collect::<BTreeSet<_>>();
+        let prev_txs = client.transactions(&prev_txids)?;
+
+        let records = history_records(entries.into_values(), &txs, &prev_txs, |script| {
+            wallet_scripts.contains_key(script)
+        });
+
+        println!("\n{}", "Transaction history:".bright_white());
+        let mut balance = 0i64;
+        for record in records {
+            balance = record.balance;
+            let kind = match record.kind {
+                TransferKind::Incoming => "incoming".bright_green(),
+                TransferKind::SelfTransfer => "self-transfer".bright_blue(),
+                TransferKind::Outgoing => "outgoing".bright_red(),
+            };
+            let entry = record.entry;
+            let mined = match entry.time_height() {
+                Some(block) => format!("{} @ {}", block.block_height(), block.timestamp()),
+                None => entry.mined().to_string(),
+            };
+            println!(
+                "\n  {} {} {}",
+                entry.txid().to_string().bright_white(),
+                kind,
+                mined
+            );
+            println!(
+                "{:>14} sats, fee {}, balance {} sats",
+                format!("{:+}", record.net).bright_yellow(),
+                record
+                    .fee
+                    .map(|fee| format!("{} sats", fee))
+                    .unwrap_or_else(|| s!("n/a")),
+                balance
+            );
+        }
+
+        println!(
+            "\nBalance {} sats\n",
+            balance.to_string().bright_yellow().underline()
+        );
+
+        Ok(())
+    }
 
     fn info(&self, data: &str) -> Result<(), Error> {
         let xpub = ExtendedPubKey::from_slip132_str(data)?;
@@ -618,22 +942,18 @@ impl Args {
         inputs: &[InputDescriptor],
         outputs: &[AddressAmount],
         change_index: UnhardenedIndex,
+        look_ahead: u16,
         proprietary_keys: &[ProprietaryKeyDescriptor],
-        fee: u64,
+        fee: FeeSpec,
+        psbt_version: PsbtVersion,
         psbt_path: &Path,
     ) -> Result<(), Error> {
         let descriptor_str = fs::read_to_string(wallet_path)?;
         let descriptor: miniscript::Descriptor<DerivationAccount> =
             miniscript::Descriptor::from_str(&descriptor_str)?;
 
-        let network = descriptor.network(false)?;
-        let electrum_url = format!(
-            "{}:{}",
-            self.electrum_server,
-            self.electrum_port
-                .unwrap_or_else(|| default_electrum_port(network))
-        );
-        let client = electrum::Client::new(&electrum_url)?;
+        let network = self.network(&descriptor)?;
+        let client = self.backend(&network)?;
 
         println!(
             "{}\n{}\n",
@@ -641,21 +961,6 @@ impl Args {
             descriptor
         );
 
-        eprint!(
-            "Re-scanning network {} using {} ... ",
-            network.to_string().yellow(),
-            electrum_url.yellow()
-        );
-
-        let txid_set: BTreeSet<_> = inputs.iter().map(|input| input.outpoint.txid).collect();
-        let tx_map = client
-            .batch_transaction_get(&txid_set)?
-            .into_iter()
-            .map(|tx| (tx.txid(), tx))
-            .collect::<BTreeMap<_, _>>();
-
-        eprintln!("{}", "done\n".green());
-
         let outputs = outputs
             .iter()
             .map(|a| {
@@ -666,7 +971,101 @@ impl Args {
             })
             .collect::<Vec<_>>();
 
-        let mut psbt = Psbt::construct(&descriptor, inputs, &outputs, change_index, fee, &tx_map)?;
+        let fee = match fee {
+            FeeSpec::Target(target, tap_path) => {
+                let fee_rate = client
+                    .resolve_fee_rate(target)?
+                    .ok_or(Error::FeeEstimateUnavailable(target))?;
+                println!(
+                    "{} {} for confirmation within {} blocks\n",
+                    "Estimated fee rate:".bright_white(),
+                    fee_rate,
+                    target
+                );
+                FeeSpec::Rate(fee_rate, tap_path)
+            }
+            fee => fee,
+        };
+
+        let mut psbt = if inputs.is_empty() {
+            let FeeSpec::Rate(fee_rate, tap_path) = fee else {
+                return Err(Error::FeeRateRequired);
+            };
+
+            eprint!("Scanning wallet UTXOs ... ");
+
+            let secp = Secp256k1::new();
+            let scan = client.scan_descriptor(&secp, &descriptor, look_ahead as u32)?;
+            let tip_height = client.resolve_height()?;
+            let candidates = Candidate::with_utxo(
+                &descriptor,
+                scan.utxo().map(|(_, utxo)| utxo),
+                tip_height as u64,
+                tap_path,
+            )?;
+
+            eprintln!("{}", "done\n".green());
+
+            let params = SelectionParams::with_descriptor(
+                &descriptor,
+                &outputs,
+                change_index,
+                fee_rate,
+                tap_path,
+            )?;
+            let construction = Psbt::construct_with_selection(
+                &descriptor,
+                &candidates,
+                &outputs,
+                change_index,
+                &params,
+                &mut thread_rng(),
+                &client,
+            )?;
+            println!(
+                "{} {} out of {} spendable UTXOs, using {}\n{} {} sats\n",
+                "Selected inputs:".bright_white(),
+                construction.selection.inputs.len(),
+                candidates.len(),
+                construction.selection.algorithm,
+                "Fee:".bright_white(),
+                construction.selection.fee,
+            );
+            construction.psbt
+        } else {
+            eprint!("Retrieving spent transactions ... ");
+
+            let txid_set: BTreeSet<_> = inputs.iter().map(|input| input.outpoint.txid).collect();
+            let tx_map = client.transactions(&txid_set)?;
+
+            eprintln!("{}", "done\n".green());
+
+            match fee {
+                FeeSpec::Absolute(fee) => {
+                    Psbt::construct(&descriptor, inputs, &outputs, change_index, fee, &tx_map)?
+                }
+                FeeSpec::Rate(fee_rate, tap_path) => {
+                    let construction = Psbt::construct_with_fee_rate(
+                        &descriptor,
+                        inputs,
+                        &outputs,
+                        change_index,
+                        fee_rate,
+                        tap_path,
+                        &tx_map,
+                    )?;
+                    println!(
+                        "{} {} sats for estimated {} vbytes\n",
+                        "Fee:".bright_white(),
+                        construction.fee,
+                        construction.vsize
+                    );
+                    construction.psbt
+                }
+                FeeSpec::Target(..) => unreachable!("fee rate is already estimated"),
+            }
+        }
+        .into_version(psbt_version)?;
         psbt.fallback_locktime = Some(lock_time);
 
         for key in proprietary_keys {
@@ -703,11 +1102,103 @@ impl Args {
         Ok(())
     }
 
+    #[allow(clippy::too_many_arguments)]
+    fn bump_fee(
+        &self,
+        wallet_path: &Path,
+        fee_rate: FeeRate,
+        psbt_path: &Path,
+        original: Option<&Path>,
+        txid: Option<Txid>,
+        lookup_depth: u32,
+        inputs: &[InputDescriptor],
+        change_index: UnhardenedIndex,
+        tap_path: TapSpendPath,
+    ) -> Result<(), Error> {
+        let descriptor_str = fs::read_to_string(wallet_path)?;
+        let descriptor: miniscript::Descriptor<DerivationAccount> =
+            miniscript::Descriptor::from_str(&descriptor_str)?;
+
+        let network = self.network(&descriptor)?;
+        let client = self.backend(&network)?;
+
+        println!(
+            "{}\n{}\n",
+            "\nWallet descriptor:".bright_white(),
+            descriptor
+        );
+
+        let txid_set: BTreeSet<_> = inputs.iter().map(|input| input.outpoint.txid).collect();
+        let tx_map = client.transactions(&txid_set)?;
+
+        let weight = weight::input_weight(&descriptor, tap_path)?;
+        let candidates = inputs
+            .iter()
+            .map(|input| {
+                let outpoint = input.outpoint;
+                let amount = tx_map
+                    .get(&outpoint.txid)
+                    .and_then(|tx| tx.output.get(outpoint.vout as usize))
+                    .ok_or(construct::Error::OutputUnknown(
+                        outpoint.txid,
+                        outpoint.vout,
+                    ))?
+                    .value;
+                Ok(Candidate {
+                    input: input.clone(),
+                    amount,
+                    weight,
+                })
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+
+        let bump = match (original, txid) {
+            (Some(path), _) => {
+                let data = fs::read(path)?;
+                let psbt = Psbt::deserialize(&data).map_err(Error::psbt_from_consensus)?;
+                psbt.bump_fee(
+                    &descriptor,
+                    fee_rate,
+                    candidates,
+                    change_index,
+                    tap_path,
+                    &tx_map,
+                )?
+            }
+            (None, Some(txid)) => Psbt::bump_tx_fee(
+                txid,
+                &descriptor,
+                lookup_depth,
+                fee_rate,
+                candidates,
+                change_index,
+                tap_path,
+                &client,
+            )?,
+            (None, None) => unreachable!("clap requires either original PSBT or txid"),
+        };
+
+        println!(
+            "{} {} sats\n{} {} sats for estimated {} vbytes\n",
+            "Original fee:".bright_white(),
+            bump.original_fee,
+            "Fee:".bright_white(),
+            bump.fee,
+            bump.vsize
+        );
+
+        fs::write(psbt_path, bump.psbt.serialize())?;
+
+        println!("{} {}\n", "PSBT:".bright_white(), bump.psbt);
+
+        Ok(())
+    }
+
     fn finalize(
         &self,
         psbt_path: &Path,
         tx_path: Option<&PathBuf>,
-        publish: Option<Network>,
+        publish: Option<BitcoinNetwork>,
     ) -> Result<(), Error> {
         let secp = Secp256k1::new();
 
@@ -727,8 +1218,8 @@ impl Args {
         }
 
         if let Some(network) = publish {
-            let client = self.electrum_client(network)?;
-            client.transaction_broadcast(&tx)?;
+            let client = self.backend(&network)?;
+            client.broadcast(&tx)?;
             eprintln!(
                 "{} {} {}\n",
                 "Transaction".bright_yellow(),
@@ -763,11 +1254,130 @@ impl Args {
     }
 }
 
-fn default_electrum_port(network: Network) -> u16 {
-    match network {
-        Network::Bitcoin => 50001,
-        Network::Testnet => 60001,
-        Network::Signet | Network::Regtest => 60601,
+/// Blockchain backend used for retrieving transactions and UTXOs
+#[allow(clippy::large_enum_variant)]
+pub enum Backend {
+    Electrum(electrum::Client),
+    BitcoinCore(bitcoin_core::Client),
+    Esplora(esplora::Client),
+}
+
+impl Backend {
+    pub fn transactions(
+        &self,
+        txids: &BTreeSet<Txid>,
+    ) -> Result<BTreeMap<Txid, Transaction>, Error> {
+        match self {
+            Backend::Electrum(client) => Ok(client
+                .batch_transaction_get(txids)?
+                .into_iter()
+                .map(|tx| (tx.txid(), tx))
+                .collect()),
+            Backend::BitcoinCore(client) => txids
+                .iter()
+                .map(|txid| {
+                    // Node wallet provides its transactions without `-txindex`
+                    let tx = match client.is_wallet() {
+                        true => client
+                            .get_wallet_transaction(*txid)
+                            .or_else(|_| client.get_raw_transaction(*txid))?,
+                        false => client.get_raw_transaction(*txid)?,
+                    };
+                    Ok((*txid, tx))
+                })
+                .collect(),
+            Backend::Esplora(client) => txids
+                .iter()
+                .map(|txid| {
+                    let tx = client.tx(*txid)?.ok_or(Error::UnknownTx(*txid))?;
+                    Ok((*txid, tx))
+                })
+                .collect(),
+        }
+    }
+}
+
+impl ResolveFeeRate for Backend {
+    fn resolve_fee_rate(&self, target: u16) -> Result<Option<FeeRate>, BackendError> {
+        match self {
+            Backend::Electrum(client) => client.resolve_fee_rate(target),
+            Backend::BitcoinCore(client) => client.resolve_fee_rate(target),
+            Backend::Esplora(client) => client.resolve_fee_rate(target),
+        }
+    }
+}
+
+impl Broadcast for Backend {
+    fn broadcast(&self, tx: &Transaction) -> Result<Txid, BackendError> {
+        match self {
+            Backend::Electrum(client) => client.broadcast(tx),
+            Backend::BitcoinCore(client) => client.broadcast(tx),
+            Backend::Esplora(client) => Broadcast::broadcast(client, tx),
+        }
+    }
+}
+
+impl ResolveTx for Backend {
+    fn resolve_tx(&self, txid: Txid) -> Result<Transaction, TxResolverError> {
+        match self {
+            Backend::Electrum(client) => client.resolve_tx(txid),
+            Backend::BitcoinCore(client) => client.resolve_tx(txid),
+            Backend::Esplora(client) => client.resolve_tx(txid),
+        }
+    }
+}
+
+impl ResolveTxFee for Backend {
+    fn resolve_tx_fee(&self, txid: Txid) -> Result<Option<(Transaction, u64)>, TxResolverError> {
+        match self {
+            Backend::Electrum(client) => client.resolve_tx_fee(txid),
+            Backend::BitcoinCore(client) => client.resolve_tx_fee(txid),
+            Backend::Esplora(client) => client.resolve_tx_fee(txid),
+        }
+    }
+}
+
+impl ResolveHistory for Backend {
+    fn resolve_history<'script>(
+        &self,
+        scripts: impl IntoIterator<Item = &'script Script> + Clone,
+    ) -> Result<Vec<HashSet<HistoryEntry>>, UtxoResolverError> {
+        match self {
+            Backend::Electrum(client) => client.resolve_history(scripts),
+            Backend::BitcoinCore(client) => client.resolve_history(scripts),
+            Backend::Esplora(client) => client.resolve_history(scripts),
+        }
+    }
+}
+
+impl ResolveChainState for Backend {
+    fn resolve_height(&self) -> Result<u32, BackendError> {
+        match self {
+            Backend::Electrum(client) => client.resolve_height(),
+            Backend::BitcoinCore(client) => client.resolve_height(),
+            Backend::Esplora(client) => client.resolve_height(),
+        }
+    }
+
+    fn resolve_tx_status(&self, txid: Txid) -> Result<MiningStatus, TxResolverError> {
+        match self {
+            Backend::Electrum(client) => client.resolve_tx_status(txid),
+            Backend::BitcoinCore(client) => client.resolve_tx_status(txid),
+            Backend::Esplora(client) => client.resolve_tx_status(txid),
+        }
+    }
+}
+
+impl ResolveUtxo for Backend {
+    fn resolve_utxo<'script>(
+        &self,
+        scripts: impl IntoIterator<Item = &'script Script> + Clone,
+    ) -> Result<Vec<HashSet<Utxo>>, UtxoResolverError> {
+        match self {
+            Backend::Electrum(client) => client.resolve_utxo(scripts),
+            Backend::BitcoinCore(client) => client.resolve_utxo(scripts),
+            Backend::Esplora(client) => client.resolve_utxo(scripts),
+        }
     }
 }
 
@@ -818,6 +1428,129 @@ impl FromStr for AddressAmount {
     }
 }
 
+/// Direction in which a wallet transaction moves funds
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub enum TransferKind {
+    /// Transaction does not spend wallet funds
+    Incoming,
+
+    /// Transaction spends wallet funds to external scripts
+    Outgoing,
+
+    /// Transaction spends wallet funds to wallet scripts only
+    SelfTransfer,
+}
+
+/// Wallet transaction together with its effect on the wallet balance
+#[derive(Clone, Eq, PartialEq, Hash, Debug)]
+pub struct HistoryRecord {
+    /// Transaction history entry
+    pub entry: HistoryEntry,
+
+    /// Direction of the transfer
+    pub kind: TransferKind,
+
+    /// Change of the wallet balance, in satoshis
+    pub net: i64,
+
+    /// Transaction fee, in satoshis. Known only for transactions spending
+    /// wallet funds, when all their previous outputs are known.
+    pub fee: Option<u64>,
+
+    /// Wallet balance after the transaction, in satoshis
+    pub balance: i64,
+}
+
+/// Computes wallet balance changes for history `entries` in the order of
+/// their mining. All history transactions must be present in `txs`, while
+/// `prev_txs` must provide other transactions spent by them for the fees to
+/// be known.
+fn history_records(
+    entries: impl IntoIterator<Item = HistoryEntry>,
+    txs: &BTreeMap<Txid, Transaction>,
+    prev_txs: &BTreeMap<Txid, Transaction>,
+    is_wallet_script: impl Fn(&Script) -> bool,
+) -> Vec<HistoryRecord> {
+    let prevout = |txin: &bitcoin::TxIn| {
+        txs.get(&txin.previous_output.txid)
+            .or_else(|| prev_txs.get(&txin.previous_output.txid))
+            .and_then(|tx| tx.output.get(txin.previous_output.vout as usize))
+    };
+
+    let mut entries = entries.into_iter().collect::<Vec<_>>();
+    entries.sort_by_key(|entry| match entry.mined() {
+        MiningStatus::Blockchain(height) => (*height, *entry.txid()),
+        _ => (u64::MAX, *entry.txid()),
+    });
+
+    let mut balance = 0i64;
+    entries
+        .into_iter()
+        .map(|entry| {
+            let tx = &txs[entry.txid()];
+            let debit = tx
+                .input
+                .iter()
+                .filter_map(prevout)
+                .filter(|txout| is_wallet_script(&txout.script_pubkey))
+                .map(|txout| txout.value)
+                .sum::<u64>();
+            let credit = tx
+                .output
+                .iter()
+                .filter(|txout| is_wallet_script(&txout.script_pubkey))
+                .map(|txout| txout.value)
+                .sum::<u64>();
+            let fee = if debit > 0 {
+                tx.input
+                    .iter()
+                    .map(|txin| prevout(txin).map(|txout| txout.value))
+                    .sum::<Option<u64>>()
+                    .and_then(|input| {
+                        input.checked_sub(tx.output.iter().map(|txout| txout.value).sum())
+                    })
+            } else {
+                None
+            };
+            let net = credit as i64 - debit as i64;
+            balance += net;
+
+            let kind = if debit == 0 {
+                TransferKind::Incoming
+            } else if tx
+                .output
+                .iter()
+                .all(|txout| is_wallet_script(&txout.script_pubkey))
+            {
+                TransferKind::SelfTransfer
+            } else {
+                TransferKind::Outgoing
+            };
+            HistoryRecord {
+                entry,
+                kind,
+                net,
+                fee,
+                balance,
+            }
+        })
+        .collect()
+}
+
+/// Way the transaction fee is specified for PSBT construction
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum FeeSpec {
+    /// Absolute fee amount in satoshis
+    Absolute(u64),
+
+    /// Fee rate applied to the estimated transaction size
+    Rate(FeeRate, TapSpendPath),
+
+    /// Fee rate estimated by the blockchain backend for the confirmation
+    /// within the given number of blocks
+    Target(u16, TapSpendPath),
+}
+
 #[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug, Display, From)]
 #[display(inner)]
 #[allow(clippy::large_enum_variant)]
@@ -939,18 +1672,36 @@ pub enum Error {
     #[from]
     Derive(DeriveError),
 
+    #[from]
+    Policy(PolicyError),
+
     #[from]
     ResolveUtxo(UtxoResolverError),
 
+    #[from]
+    Backend(BackendError),
+
     #[from]
     Electrum(electrum::Error),
 
+    #[from]
+    BitcoinCore(bitcoin_core::Error),
+
+    #[from]
+    Esplora(esplora::Error),
+
     #[from]
     Yaml(serde_yaml::Error),
 
     #[from]
     PsbtConstruction(construct::Error),
 
+    #[from]
+    PsbtBump(BumpError),
+
+    #[from]
+    PsbtLocktime(LocktimeConflict),
+
     /// can't finalize PSBT data due to following problem(s):
     ///
     /// {0}
@@ -958,6 +1709,11 @@ pub enum Error {
     #[from]
     PsbtFinalization(VecDisplay<miniscript::psbt::Error, true, '-', '\n'>),
 
+    /// fee rate estimate for the confirmation within {0} blocks is not
+    /// available from the blockchain backend
+    #[display(doc_comments)]
+    FeeEstimateUnavailable(u16),
+
     /// unrecognized number of wildcards in the descriptor derive pattern
     #[display(doc_comments)]
     DescriptorDerivePattern,
@@ -980,6 +1736,15 @@ pub enum Error {
     #[from]
     #[display(doc_comments)]
     PsbtProprietaryKey(ProprietaryKeyError),
+
+    /// transaction {0} is not known to the blockchain backend
+    #[display(doc_comments)]
+    UnknownTx(Txid),
+
+    /// automatic selection of transaction inputs requires fee rate or
+    /// confirmation target instead of the absolute fee
+    #[display(doc_comments)]
+    FeeRateRequired,
 }
 
 impl Error {
@@ -1051,3 +1816,113 @@ fn main() {
         eprintln!("{}: {}\n", "Error".bright_red(), err);
     }
 }
+
+#[cfg(test)]
+mod test {
+    use bitcoin::hashes::Hash;
+    use bitcoin::{OutPoint, PackedLockTime, TxIn, TxOut};
+    use bitcoin_onchain::MemoryChain;
+
+    use super::*;
+
+    fn script(no: u8) -> Script {
+        Script::new_v0_p2wpkh(&bitcoin::WPubkeyHash::from_inner([no; 20]))
+    }
+
+    fn spend(inputs: &[OutPoint], outputs: &[(u8, u64)]) -> Transaction {
+        Transaction {
+            version: 2,
+            lock_time: PackedLockTime::ZERO,
+            input: inputs
+                .iter()
+                .map(|outpoint| TxIn {
+                    previous_output: *outpoint,
+                    ..default!()
+                })
+                .collect(),
+            output: outputs
+                .iter()
+                .map(|(no, value)| TxOut {
+                    value: *value,
+                    script_pubkey: script(*no),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn history_balance() {
+        // Scripts 1 to 3 belong to the wallet, others are external
+        let wallet = [script(1), script(2), script(3)];
+        let is_wallet = |script: &Script| wallet.contains(script);
+
+        let mut chain = MemoryChain::new(Network::Regtest);
+        chain.generate(101, &script(10));
+        let coinbase = chain.block(1).unwrap().txdata[0].txid();
+        let subsidy = 50 * 100_000_000;
+
+        let incoming = spend(&[OutPoint::new(coinbase, 0)], &[
+            (1, 100_000),
+            (11, subsidy - 101_000),
+        ]);
+        let incoming_txid = chain.send(incoming).unwrap();
+        chain.mine(&script(10));
+        let outgoing = spend(&[OutPoint::new(incoming_txid, 0)], &[
+            (12, 60_000),
+            (2, 39_000),
+        ]);
+        let outgoing_txid = chain.send(outgoing).unwrap();
+        chain.mine(&script(10));
+        let transfer = spend(&[OutPoint::new(outgoing_txid, 1)], &[(3, 38_500)]);
+        let transfer_txid = chain.send(transfer).unwrap();
+
+        let entries = chain
+            .resolve_history(&wallet)
+            .unwrap()
+            .into_iter()
+            .flatten()
+            .map(|entry| (*entry.txid(), entry))
+            .collect::<BTreeMap<_, _>>();
+        assert_eq!(entries.len(), 3);
+        let txs = entries
+            .keys()
+            .map(|txid| (*txid, chain.resolve_tx(*txid).unwrap()))
+            .collect::<BTreeMap<_, _>>();
+
+        let records = history_records(entries.into_values(), &txs, &bmap! {}, is_wallet)
+            .into_iter()
+            .map(|record| {
+                (
+                    *record.entry.txid(),
+                    record.kind,
+                    record.net,
+                    record.fee,
+                    record.balance,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(records, vec![
+            (
+                incoming_txid,
+                TransferKind::Incoming,
+                100_000,
+                None,
+                100_000
+            ),
+            (
+                outgoing_txid,
+                TransferKind::Outgoing,
+                -61_000,
+                Some(1_000),
+                39_000
+            ),
+            (
+                transfer_txid,
+                TransferKind::SelfTransfer,
+                -500,
+                Some(500),
+                38_500
+            ),
+        ]);
+    }
+}
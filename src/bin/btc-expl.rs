@@ -19,18 +19,27 @@ extern crate clap;
 #[macro_use]
 extern crate amplify;
 
-use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::{fs, io};
 
 use amplify::hex::ToHex;
 use amplify::IoError;
+use bitcoin::secp256k1::Secp256k1;
 use bitcoin::util::address::WitnessVersion;
 use bitcoin::util::taproot::LeafVersion;
-use bitcoin::{consensus, Address, EcdsaSig, LockTime, Network, PublicKey, Script, Txid};
+use bitcoin::{
+    consensus, Address, EcdsaSig, LockTime, Network, PublicKey, Script, Transaction, Txid,
+};
 use bitcoin_blockchain::locks::SeqNo;
+use bitcoin_hd::{DerivationAccount, DeriveError};
+use bitcoin_onchain::blockchain::MiningStatus;
+use bitcoin_onchain::{esplora, BitcoinNetwork};
 use bitcoin_scripts::address::{AddressCompat, AddressFormat};
 use bitcoin_scripts::TaprootWitness;
 use clap::Parser;
 use colored::Colorize;
+use descriptors::ScriptIndex;
 use electrum_client as electrum;
 use electrum_client::ElectrumApi;
 use miniscript_crate::{Legacy, Miniscript, Segwitv0, Tap};
@@ -61,10 +70,16 @@ pub struct Args {
     #[clap(short = 'p', global = true)]
     pub electrum_port: Option<u16>,
 
-    /// Publish the transaction to the network; optional argument allows
-    /// to specify some custom network (testnet, for instance).
+    /// Esplora server to use, in form of `http[s]://host[:port][/path]`, for
+    /// instance `https://blockstream.info/api`. If used, overrides
+    /// `electrum_server`, which becomes unused.
+    #[clap(long, global = true)]
+    pub esplora: Option<String>,
+
+    /// Bitcoin network: `mainnet`, `testnet`, `testnet4`, `signet`,
+    /// `regtest` or a custom signet specified as `signet:<challenge hex>`.
     #[clap(short, long, global = true, default_value = "bitcoin")]
-    network: Network,
+    network: BitcoinNetwork,
 }
 
 /// Wallet command to execute
@@ -75,44 +90,84 @@ pub enum Command {
     Tx {
         /// Txid to lookup.
         txid: Txid,
-    },
-}
 
-fn default_electrum_port(network: Network) -> u16 {
-    match network {
-        Network::Bitcoin => 50001,
-        Network::Testnet => 60001,
-        Network::Signet | Network::Regtest => 60601,
-    }
+        /// Path to the read-only wallet file generated with `btc-cold create`
+        /// command. If provided, inputs and outputs controlled by the wallet
+        /// are marked.
+        #[clap(short, long)]
+        wallet: Option<PathBuf>,
+
+        /// Number of addresses on each of the wallet derivation branches which
+        /// are checked for ownership
+        #[clap(long, default_value = "100")]
+        look_ahead: u32,
+    },
 }
 
 const SATS_IN_BTC: u64 = 100_000_000;
 
 impl Args {
-    fn electrum_client(&self) -> Result<electrum::Client, electrum::Error> {
+    fn backend(&self) -> Result<Backend, Error> {
+        if let Some(url) = &self.esplora {
+            eprintln!(
+                "Connecting to network {} using esplora {}",
+                self.network.to_string().yellow(),
+                url.yellow()
+            );
+            return Ok(Backend::Esplora(esplora::Client::new(url)?));
+        }
+
         let electrum_url = format!(
             "{}:{}",
             self.electrum_server,
             self.electrum_port
-                .unwrap_or_else(|| default_electrum_port(self.network))
+                .unwrap_or_else(|| self.network.electrum_port())
         );
         eprintln!(
             "Connecting to network {} using {}",
             self.network.to_string().yellow(),
             electrum_url.yellow()
         );
-        electrum::Client::new(&electrum_url)
+        Ok(Backend::Electrum(electrum::Client::new(&electrum_url)?))
     }
 
     pub fn exec(self) -> Result<(), Error> {
         match &self.command {
-            Command::Tx { txid } => self.tx(txid),
+            Command::Tx {
+                txid,
+                wallet,
+                look_ahead,
+            } => self.tx(txid, wallet.as_deref(), *look_ahead),
         }
     }
 
-    fn tx(&self, txid: &Txid) -> Result<(), Error> {
-        let electrum = self.electrum_client()?;
-        let tx = electrum.transaction_get(txid)?;
+    fn tx(&self, txid: &Txid, wallet: Option<&Path>, look_ahead: u32) -> Result<(), Error> {
+        let wallet = match wallet {
+            Some(path) => {
+                let descriptor_str = fs::read_to_string(path)?;
+                let descriptor = miniscript_crate::Descriptor::<DerivationAccount>::from_str(
+                    descriptor_str.trim(),
+                )?;
+                Some(ScriptIndex::new(
+                    &Secp256k1::verification_only(),
+                    descriptor,
+                    0..look_ahead,
+                )?)
+            }
+            None => None,
+        };
+        let print_origin = |script: &Script| {
+            if let Some(origin) = wallet.as_ref().and_then(|wallet| wallet.get(script)) {
+                println!(
+                    "  {} {}",
+                    "controlled by the wallet at".bright_green(),
+                    origin
+                );
+            }
+        };
+
+        let backend = self.backend()?;
+        let tx = backend.transaction(txid)?;
 
         println!("\nTransaction {}", txid.to_string().bright_white());
         println!("Version {:#x}", tx.version);
@@ -124,8 +179,8 @@ impl Args {
         let mut witness_size = 0usize;
         let mut total_in = 0u64;
         let mut total_out = 0u64;
-        let prev_txs = electrum
-            .batch_transaction_get(tx.input.iter().map(|txin| &txin.previous_output.txid))?;
+        let prev_txs =
+            backend.transactions(tx.input.iter().map(|txin| &txin.previous_output.txid))?;
 
         println!();
         for (vin, (prev_tx, txin)) in prev_txs.into_iter().zip(tx.input).enumerate() {
@@ -141,6 +196,7 @@ impl Args {
 
             let seq = SeqNo::from_consensus(txin.sequence.to_consensus_u32());
             println!("  sequence value is {seq}");
+            print_origin(&prevout.script_pubkey);
 
             total_in += prevout.value;
             let btc = prevout.value / SATS_IN_BTC;
@@ -153,7 +209,7 @@ impl Args {
             );
             let prev_addr = AddressCompat::from_script(
                 &prevout.script_pubkey.clone().into(),
-                self.network.into(),
+                Network::from(&self.network).into(),
             );
             match (prev_addr, prevout.script_pubkey.witness_version()) {
                 (Some(addr), None) => {
@@ -352,9 +408,10 @@ impl Args {
                     .bright_yellow()
             );
             println!("  locked with {}", txout.script_pubkey);
+            print_origin(&txout.script_pubkey);
             let addr_compat = AddressCompat::from_script(
                 &txout.script_pubkey.clone().into(),
-                self.network.into(),
+                Network::from(&self.network).into(),
             );
             if let Some(addr) = addr_compat {
                 println!("  addr({addr})");
@@ -386,23 +443,41 @@ impl Args {
         );
         println!();
 
-        if let Ok(info) = electrum.transaction_get_merkle(txid, 0) {
-            if info.block_height == 0 {
-                println!("Transaction is not mined yet and exists in mempool");
-            } else {
-                println!("Mined at height {}", info.block_height);
-                println!("  Block position is {}", info.pos);
-                println!("  Transaction inclusion Merkle path proof:");
-                for node in info.merkle {
-                    println!("    {}", node.to_hex());
+        match &backend {
+            Backend::Electrum(electrum) => {
+                if let Ok(info) = electrum.transaction_get_merkle(txid, 0) {
+                    if info.block_height == 0 {
+                        println!("Transaction is not mined yet and exists in mempool");
+                    } else {
+                        println!("Mined at height {}", info.block_height);
+                        println!("  Block position is {}", info.pos);
+                        println!("  Transaction inclusion Merkle path proof:");
+                        for node in info.merkle {
+                            println!("    {}", node.to_hex());
+                        }
+                    }
+                } else {
+                    eprintln!(
+                        "{}: the used electrum backend doesn't provide mining info by a txid
+  use esplora-powered backends to get addition info about the transaction",
+                        "Warning".bright_yellow()
+                    );
                 }
             }
-        } else {
-            eprintln!(
-                "{}: the used electrum backend doesn't provide mining info by a txid
-  use esplora-powered backends to get addition info about the transaction",
-                "Warning".bright_yellow()
-            );
+            Backend::Esplora(esplora) => match esplora.tx_status(*txid)? {
+                (MiningStatus::Blockchain(height), Some(block)) => {
+                    println!("Mined at height {height}");
+                    println!("  Block hash is {}", block.block_hash());
+                    println!("  Block timestamp is {}", block.timestamp());
+                }
+                (MiningStatus::Mempool, _) => {
+                    println!("Transaction is not mined yet and exists in mempool")
+                }
+                _ => eprintln!(
+                    "{}: mining status of the transaction is unknown",
+                    "Warning".bright_yellow()
+                ),
+            },
         }
         println!();
 
@@ -421,6 +496,47 @@ pub enum Error {
 
     #[from]
     Electrum(electrum::Error),
+
+    #[from]
+    Esplora(esplora::Error),
+
+    #[from]
+    Miniscript(miniscript_crate::Error),
+
+    #[from]
+    Derive(DeriveError),
+
+    #[display("transaction {0} is not known to the backend")]
+    UnknownTx(Txid),
+}
+
+/// Blockchain backend used for retrieving transactions
+#[allow(clippy::large_enum_variant)]
+pub enum Backend {
+    Electrum(electrum::Client),
+    Esplora(esplora::Client),
+}
+
+impl Backend {
+    pub fn transaction(&self, txid: &Txid) -> Result<Transaction, Error> {
+        match self {
+            Backend::Electrum(client) => Ok(client.transaction_get(txid)?),
+            Backend::Esplora(client) => client.tx(*txid)?.ok_or(Error::UnknownTx(*txid)),
+        }
+    }
+
+    pub fn transactions<'txid>(
+        &self,
+        txids: impl IntoIterator<Item = &'txid Txid> + Clone,
+    ) -> Result<Vec<Transaction>, Error> {
+        match self {
+            Backend::Electrum(client) => Ok(client.batch_transaction_get(txids)?),
+            Backend::Esplora(_) => txids
+                .into_iter()
+                .map(|txid| self.transaction(txid))
+                .collect(),
+        }
+    }
 }
 
 fn main() {
@@ -23,16 +23,18 @@ extern crate bitcoin_hwi as hwi;
 extern crate miniscript_crate as miniscript;
 extern crate strict_encoding_crate as strict_encoding;
 
-use std::io::Write;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::{fs, io};
 
 use aes::cipher::generic_array::GenericArray;
-use aes::cipher::{BlockDecrypt, BlockEncrypt, KeyInit};
+use aes::cipher::{BlockDecrypt, KeyInit};
 use aes::{Aes256, Block};
+use aes_gcm::aead::{Aead, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
 use amplify::hex::ToHex;
 use amplify::IoError;
+use argon2::Argon2;
 use bip39::Mnemonic;
 use bitcoin::consensus::{self, Decodable, Encodable};
 use bitcoin::hashes::{sha256, Hash};
@@ -51,8 +53,10 @@ use psbt::serialize::{Deserialize, Serialize};
 use psbt::sign::{MemoryKeyProvider, MemorySigningAccount, SignAll, SignError};
 use psbt::Psbt;
 use slip132::{KeyApplication, ToSlip132};
+use wallet::hd::bip85::{Bip85, Bip85Error};
 use wallet::hd::standards::DerivationBlockchain;
-use wallet::hd::{Bip43, HardenedIndex};
+use wallet::hd::{slip39, Bip43, HardenedIndex};
+use zeroize::Zeroizing;
 
 /// Global bitcoin networks having bitcoin-consensus-compatible transactions.
 /// This does not include on-premise networks like regtest or custom signet.
@@ -82,7 +86,8 @@ impl From<Network> for DerivationBlockchain {
     fn from(network: Network) -> Self {
         match network {
             Network::Bitcoin => DerivationBlockchain::Bitcoin,
-            Network::Testnet3 | Network::Signet => DerivationBlockchain::Testnet,
+            Network::Testnet3 => DerivationBlockchain::Testnet,
+            Network::Signet => DerivationBlockchain::Signet,
         }
     }
 }
@@ -134,23 +139,212 @@ impl SeedType {
     }
 }
 
-fn decode(source: impl AsRef<[u8]>, password: &str) -> Vec<u8> {
-    let key = sha256::Hash::hash(password.as_bytes());
-    let key = GenericArray::from_slice(key.as_inner());
-    let cipher = Aes256::new(key);
+/// Magic bytes starting encrypted seed and signing account files
+const CONTAINER_MAGIC: [u8; 6] = *b"BTCHOT";
+/// Current version of the encrypted container format
+const CONTAINER_VERSION: u8 = 1;
+const CONTAINER_SALT_LEN: usize = 16;
+const CONTAINER_NONCE_LEN: usize = 12;
+/// Header consists of magic, version, payload type, three argon2 parameters,
+/// salt and nonce
+const CONTAINER_HEADER_LEN: usize =
+    CONTAINER_MAGIC.len() + 2 + 3 * 4 + CONTAINER_SALT_LEN + CONTAINER_NONCE_LEN;
+/// Maximal argon2 memory cost (in KiB) accepted from a container header
+const ARGON2_MAX_M_COST: u32 = 4 * 1024 * 1024;
+/// Maximal argon2 number of iterations accepted from a container header
+const ARGON2_MAX_T_COST: u32 = 64;
+/// Maximal argon2 parallelism accepted from a container header
+const ARGON2_MAX_P_COST: u32 = 64;
+
+/// Type of secret data stored in an encrypted container
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug, Display)]
+#[repr(u8)]
+pub enum ContainerPayload {
+    /// Seed entropy
+    #[display("seed")]
+    Seed = 0,
+
+    /// Signing account
+    #[display("signing account")]
+    Account = 1,
+}
 
-    let mut source = source.as_ref().to_vec();
-    if source.len() % 16 != 0 {
-        panic!("data length for encoding must be proportional to 16")
+impl ContainerPayload {
+    fn with_byte(byte: u8) -> Result<Self, ContainerError> {
+        match byte {
+            0 => Ok(ContainerPayload::Seed),
+            1 => Ok(ContainerPayload::Account),
+            unknown => Err(ContainerError::UnknownPayload(unknown)),
+        }
     }
-    for chunk in source.chunks_mut(16) {
-        let block = Block::from_mut_slice(chunk);
-        cipher.decrypt_block(block);
+}
+
+/// Errors working with encrypted seed and signing account files
+#[derive(Clone, Eq, PartialEq, Debug, Display, Error)]
+#[display(doc_comments)]
+pub enum ContainerError {
+    /// the file is not an encrypted container; if it was created with an
+    /// earlier version of btc-hot please convert it with `migrate` command
+    LegacyFormat,
+
+    /// unsupported version {0} of the encrypted container
+    UnsupportedVersion(u8),
+
+    /// encrypted container data are truncated
+    Truncated,
+
+    /// unknown type {0} of the encrypted container data
+    UnknownPayload(u8),
+
+    /// the file contains {found} while {expected} is required
+    PayloadMismatch {
+        /// Type of the data required by the operation
+        expected: ContainerPayload,
+        /// Type of the data stored in the file
+        found: ContainerPayload,
+    },
+
+    /// file is encrypted and requires a password
+    PasswordRequired,
+
+    /// invalid password or corrupted file
+    Decryption,
+
+    /// unable to encrypt data
+    Encryption,
+
+    /// password key derivation failure: {0}
+    Kdf(String),
+
+    /// container requires excessive password key derivation resources
+    /// (memory {m_cost} KiB, {t_cost} iterations, {p_cost} lanes)
+    KdfLimits {
+        /// Argon2 memory cost, in KiB
+        m_cost: u32,
+        /// Argon2 number of iterations
+        t_cost: u32,
+        /// Argon2 degree of parallelism
+        p_cost: u32,
+    },
+}
+
+fn container_key(
+    password: &str,
+    salt: &[u8],
+    params: argon2::Params,
+) -> Result<Zeroizing<[u8; 32]>, ContainerError> {
+    let mut key = Zeroizing::new([0u8; 32]);
+    Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params)
+        .hash_password_into(password.as_bytes(), salt, &mut *key)
+        .map_err(|err| ContainerError::Kdf(err.to_string()))?;
+    Ok(key)
+}
+
+/// Encrypts data with AES-256-GCM using key derived from the password with
+/// Argon2id and a random salt. All header data, including KDF parameters, are
+/// authenticated as associated data.
+fn seal(
+    payload: ContainerPayload,
+    data: impl AsRef<[u8]>,
+    password: &str,
+) -> Result<Vec<u8>, ContainerError> {
+    let params = argon2::Params::default();
+    let mut salt = [0u8; CONTAINER_SALT_LEN];
+    let mut nonce = [0u8; CONTAINER_NONCE_LEN];
+    rand::thread_rng().fill_bytes(&mut salt);
+    rand::thread_rng().fill_bytes(&mut nonce);
+
+    let mut container = Vec::with_capacity(CONTAINER_HEADER_LEN + data.as_ref().len() + 16);
+    container.extend(CONTAINER_MAGIC);
+    container.push(CONTAINER_VERSION);
+    container.push(payload as u8);
+    container.extend(params.m_cost().to_le_bytes());
+    container.extend(params.t_cost().to_le_bytes());
+    container.extend(params.p_cost().to_le_bytes());
+    container.extend(salt);
+    container.extend(nonce);
+
+    let key = container_key(password, &salt, params)?;
+    let cipher = Aes256Gcm::new(GenericArray::from_slice(&*key));
+    let ciphertext = cipher
+        .encrypt(Nonce::from_slice(&nonce), Payload {
+            msg: data.as_ref(),
+            aad: &container,
+        })
+        .map_err(|_| ContainerError::Encryption)?;
+    container.extend(ciphertext);
+    Ok(container)
+}
+
+/// Detects type of the data stored in the container without decrypting it.
+fn container_payload(container: impl AsRef<[u8]>) -> Result<ContainerPayload, ContainerError> {
+    let container = container.as_ref();
+    if !container.starts_with(&CONTAINER_MAGIC) {
+        return Err(ContainerError::LegacyFormat);
     }
-    source
+    if container.len() < CONTAINER_HEADER_LEN {
+        return Err(ContainerError::Truncated);
+    }
+    let version = container[CONTAINER_MAGIC.len()];
+    if version != CONTAINER_VERSION {
+        return Err(ContainerError::UnsupportedVersion(version));
+    }
+    ContainerPayload::with_byte(container[CONTAINER_MAGIC.len() + 1])
 }
 
-fn encode(source: impl AsRef<[u8]>, password: &str) -> Vec<u8> {
+/// Decrypts data previously encrypted with [`seal`], checking that they have
+/// the expected type.
+///
+/// Since KDF parameters are read from the header before it gets authenticated,
+/// they are checked against the maximal allowed values first.
+fn unseal(
+    container: impl AsRef<[u8]>,
+    expected: ContainerPayload,
+    password: &str,
+) -> Result<Zeroizing<Vec<u8>>, ContainerError> {
+    let container = container.as_ref();
+    let found = container_payload(container)?;
+    if found != expected {
+        return Err(ContainerError::PayloadMismatch { expected, found });
+    }
+    let (header, ciphertext) = container.split_at(CONTAINER_HEADER_LEN);
+    let header_data = &header[CONTAINER_MAGIC.len() + 2..];
+
+    let mut params = header_data
+        .chunks_exact(4)
+        .take(3)
+        .map(|chunk| u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]));
+    let (m_cost, t_cost, p_cost) = (
+        params.next().expect("header length is checked"),
+        params.next().expect("header length is checked"),
+        params.next().expect("header length is checked"),
+    );
+    if m_cost > ARGON2_MAX_M_COST || t_cost > ARGON2_MAX_T_COST || p_cost > ARGON2_MAX_P_COST {
+        return Err(ContainerError::KdfLimits {
+            m_cost,
+            t_cost,
+            p_cost,
+        });
+    }
+    let params = argon2::Params::new(m_cost, t_cost, p_cost, None)
+        .map_err(|err| ContainerError::Kdf(err.to_string()))?;
+    let (salt, nonce) = header_data[12..].split_at(CONTAINER_SALT_LEN);
+
+    let key = container_key(password, salt, params)?;
+    let cipher = Aes256Gcm::new(GenericArray::from_slice(&*key));
+    cipher
+        .decrypt(Nonce::from_slice(nonce), Payload {
+            msg: ciphertext,
+            aad: header,
+        })
+        .map(Zeroizing::new)
+        .map_err(|_| ContainerError::Decryption)
+}
+
+/// Decrypts files created by btc-hot before introduction of encrypted
+/// containers (AES-256 in ECB mode with SHA256 of the password used as a key).
+/// Used only for migrating old files.
+fn decode_legacy(source: impl AsRef<[u8]>, password: &str) -> Vec<u8> {
     let key = sha256::Hash::hash(password.as_bytes());
     let key = GenericArray::from_slice(key.as_inner());
     let cipher = Aes256::new(key);
@@ -161,7 +355,7 @@ fn encode(source: impl AsRef<[u8]>, password: &str) -> Vec<u8> {
     }
     for chunk in source.chunks_mut(16) {
         let block = Block::from_mut_slice(chunk);
-        cipher.encrypt_block(block);
+        cipher.decrypt_block(block);
     }
     source
 }
@@ -174,43 +368,130 @@ fn get_password(password_arg: Option<String>, prompt: &str) -> Result<String, st
     }
 }
 
-struct Seed(Box<[u8]>);
+/// Seed entropy together with the information on how the master key is
+/// derived from it.
+struct Seed {
+    entropy: Box<[u8]>,
+    /// BIP-39 passphrase. If present, the master key is derived from the
+    /// BIP-39 seed computed from the mnemonic and the passphrase, matching
+    /// other BIP-39 wallets. Seeds created by earlier versions of btc-hot have
+    /// no passphrase and use raw entropy as a BIP-32 seed.
+    passphrase: Option<String>,
+}
+
+/// Seed data use raw entropy as BIP-32 seed
+const SEED_TAG_RAW: u8 = 0;
+/// Seed data are BIP-39 entropy followed by the passphrase
+const SEED_TAG_BIP39: u8 = 1;
 
 impl Seed {
     pub fn with(seed_type: SeedType) -> Seed {
         let mut entropy = vec![0u8; seed_type.byte_len()];
         rand::thread_rng().fill_bytes(&mut entropy);
-        Seed(Box::from(entropy))
+        Seed {
+            entropy: Box::from(entropy),
+            passphrase: Some(empty!()),
+        }
     }
 
-    pub fn read<P>(file: P, password: &str) -> io::Result<Seed>
+    /// Restores seed from a BIP-39 mnemonic, checking its wordlist and
+    /// checksum.
+    pub fn with_mnemonic(mnemonic: &str, passphrase: impl ToString) -> Result<Seed, bip39::Error> {
+        let mnemonic = Mnemonic::parse(mnemonic)?;
+        Ok(Seed {
+            entropy: Box::from(mnemonic.to_entropy()),
+            passphrase: Some(passphrase.to_string()),
+        })
+    }
+
+    /// Constructs seed from BIP-39 entropy, checking its length.
+    pub fn with_entropy(
+        entropy: impl AsRef<[u8]>,
+        passphrase: impl ToString,
+    ) -> Result<Seed, bip39::Error> {
+        Mnemonic::from_entropy(entropy.as_ref())?;
+        Ok(Seed {
+            entropy: Box::from(entropy.as_ref()),
+            passphrase: Some(passphrase.to_string()),
+        })
+    }
+
+    /// Constructs seed from the entropy stored by btc-hot versions predating
+    /// BIP-39 support.
+    fn with_legacy(entropy: impl AsRef<[u8]>) -> Seed {
+        Seed {
+            entropy: Box::from(entropy.as_ref()),
+            passphrase: None,
+        }
+    }
+
+    pub fn read<P>(file: P, password: &str) -> Result<Seed, Error>
     where
         P: AsRef<Path>,
     {
         let data = fs::read(file)?;
-        Ok(Seed(Box::from(decode(data, password))))
+        let data = unseal(data, ContainerPayload::Seed, password)?;
+        let (tag, data) = data.split_first().ok_or(ContainerError::Truncated)?;
+        let (len, data) = data.split_first().ok_or(ContainerError::Truncated)?;
+        let len = *len as usize;
+        if data.len() < len {
+            return Err(ContainerError::Truncated.into());
+        }
+        let (entropy, passphrase) = data.split_at(len);
+        match *tag {
+            SEED_TAG_RAW if passphrase.is_empty() => Ok(Seed::with_legacy(entropy)),
+            SEED_TAG_BIP39 if Mnemonic::from_entropy(entropy).is_err() => {
+                Err(Error::SeedData("invalid BIP-39 entropy length"))
+            }
+            SEED_TAG_BIP39 => Ok(Seed {
+                entropy: Box::from(entropy),
+                passphrase: Some(
+                    String::from_utf8(passphrase.to_vec())
+                        .map_err(|_| Error::SeedData("non-UTF8 BIP-39 passphrase"))?,
+                ),
+            }),
+            _ => Err(Error::SeedData("unknown seed data format")),
+        }
     }
 
-    pub fn write<P>(&self, file: P, password: &str) -> io::Result<()>
+    pub fn write<P>(&self, file: P, password: &str) -> Result<(), Error>
     where
         P: AsRef<Path>,
     {
-        fs::write(file, encode(&self.0, password))
+        let mut data = Zeroizing::new(vec![
+            if self.passphrase.is_some() {
+                SEED_TAG_BIP39
+            } else {
+                SEED_TAG_RAW
+            },
+            self.entropy.len() as u8,
+        ]);
+        data.extend(&*self.entropy);
+        if let Some(ref passphrase) = self.passphrase {
+            data.extend(passphrase.as_bytes());
+        }
+        fs::write(file, seal(ContainerPayload::Seed, &*data, password)?)?;
+        Ok(())
     }
 
     #[inline]
-    pub fn as_entropy(&self) -> &[u8] { &self.0 }
+    pub fn as_entropy(&self) -> &[u8] { &self.entropy }
 
     #[inline]
     pub fn master_xpriv(&self, testnet: bool) -> Result<ExtendedPrivKey, bip32::Error> {
-        ExtendedPrivKey::new_master(
-            if testnet {
-                bitcoin::Network::Testnet
-            } else {
-                bitcoin::Network::Bitcoin
-            },
-            self.as_entropy(),
-        )
+        let network = if testnet {
+            bitcoin::Network::Testnet
+        } else {
+            bitcoin::Network::Bitcoin
+        };
+        match self.passphrase {
+            Some(ref passphrase) => {
+                let mnemonic = Mnemonic::from_entropy(self.as_entropy())
+                    .expect("BIP-39 seed entropy is validated on construction");
+                ExtendedPrivKey::new_master(network, &mnemonic.to_seed(passphrase))
+            }
+            None => ExtendedPrivKey::new_master(network, self.as_entropy()),
+        }
     }
 }
 
@@ -219,16 +500,23 @@ trait SecretIo {
         secp: &Secp256k1<C>,
         reader: impl io::Read,
         password: Option<&str>,
-    ) -> Result<Self, consensus::encode::Error>
+    ) -> Result<Self, Error>
     where
         C: Signing,
         Self: Sized;
 
-    fn write(
-        &self,
-        writer: impl io::Write,
+    /// Reads data in a format used before introduction of encrypted
+    /// containers.
+    fn read_legacy<C>(
+        secp: &Secp256k1<C>,
+        reader: impl io::Read,
         password: Option<&str>,
-    ) -> Result<(), consensus::encode::Error>;
+    ) -> Result<Self, Error>
+    where
+        C: Signing,
+        Self: Sized;
+
+    fn write(&self, writer: impl io::Write, password: Option<&str>) -> Result<(), Error>;
 }
 
 impl SecretIo for MemorySigningAccount {
@@ -236,7 +524,33 @@ impl SecretIo for MemorySigningAccount {
         secp: &Secp256k1<C>,
         mut reader: impl io::Read,
         password: Option<&str>,
-    ) -> Result<Self, consensus::encode::Error>
+    ) -> Result<Self, Error>
+    where
+        C: Signing,
+    {
+        let mut data = vec![];
+        reader.read_to_end(&mut data)?;
+        match password {
+            _ if !data.starts_with(&CONTAINER_MAGIC) && password.is_some() => {
+                Err(ContainerError::LegacyFormat.into())
+            }
+            None if data.starts_with(&CONTAINER_MAGIC) => {
+                Err(ContainerError::PasswordRequired.into())
+            }
+            Some(password) => Self::read_legacy(
+                secp,
+                &unseal(data, ContainerPayload::Account, password)?[..],
+                None,
+            ),
+            None => Self::read_legacy(secp, &data[..], None),
+        }
+    }
+
+    fn read_legacy<C>(
+        secp: &Secp256k1<C>,
+        mut reader: impl io::Read,
+        password: Option<&str>,
+    ) -> Result<Self, Error>
     where
         C: Signing,
     {
@@ -253,7 +567,7 @@ impl SecretIo for MemorySigningAccount {
         let mut slice = [0u8; 80];
         reader.read_exact(&mut slice)?;
         if let Some(password) = password {
-            let data = decode(slice, password);
+            let data = decode_legacy(slice, password);
             slice.copy_from_slice(&data);
         }
         let account_xpriv = ExtendedPrivKey::decode(&slice[..78]).map_err(|_| {
@@ -268,28 +582,27 @@ impl SecretIo for MemorySigningAccount {
         ))
     }
 
-    fn write(
-        &self,
-        mut writer: impl io::Write,
-        password: Option<&str>,
-    ) -> Result<(), consensus::encode::Error> {
-        writer.write_all(self.master_id())?;
+    fn write(&self, mut writer: impl io::Write, password: Option<&str>) -> Result<(), Error> {
+        let mut data = Zeroizing::new(self.master_id().to_vec());
 
         let len = self.derivation().len() as u64;
-        len.consensus_encode(&mut writer)?;
+        len.consensus_encode(&mut *data)?;
         for child in self.derivation() {
             let index = u32::from(*child);
-            index.consensus_encode(&mut writer)?;
+            index.consensus_encode(&mut *data)?;
         }
 
-        let mut data = self.account_xpriv().encode().to_vec();
-        data.resize(80, 0);
-        rand::thread_rng().fill_bytes(&mut data[78..]);
-        if let Some(password) = password {
-            data = encode(data, password);
-        }
+        let mut xpriv = Zeroizing::new(self.account_xpriv().encode().to_vec());
+        xpriv.resize(80, 0);
+        rand::thread_rng().fill_bytes(&mut xpriv[78..]);
+        data.extend(&*xpriv);
 
-        writer.write_all(&data)?;
+        match password {
+            Some(password) => {
+                writer.write_all(&seal(ContainerPayload::Account, &*data, password)?)?
+            }
+            None => writer.write_all(&data)?,
+        }
 
         Ok(())
     }
@@ -315,6 +628,49 @@ pub struct Args {
     pub command: Command,
 }
 
+/// BIP-85 application to derive entropy for
+#[derive(Subcommand)]
+#[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
+pub enum Bip85Command {
+    /// BIP-39 mnemonic
+    Mnemonic {
+        /// Number of words: 12, 15, 18, 21 or 24
+        #[clap(short, long, default_value = "12")]
+        words: u8,
+
+        /// BIP-85 language code: 0 - English, 1 - Japanese, 2 - Korean,
+        /// 3 - Spanish, 4 - Chinese (simplified), 5 - Chinese (traditional),
+        /// 6 - French, 7 - Italian, 8 - Czech
+        #[clap(short, long, default_value = "0")]
+        language: u32,
+    },
+
+    /// Private key in WIF format for an HD-seed wallet
+    Wif,
+
+    /// Extended private key
+    Xprv,
+
+    /// Raw entropy in hexadecimal encoding
+    Hex {
+        /// Number of bytes, from 16 to 64
+        #[clap(short, long, default_value = "32")]
+        bytes: u8,
+    },
+
+    /// Password
+    Password {
+        /// Password length; from 20 to 86 characters for base64 passwords
+        /// and from 10 to 80 characters for base85 passwords
+        #[clap(short, long, default_value = "21")]
+        length: u8,
+
+        /// Use base85 encoding instead of base64
+        #[clap(long)]
+        base85: bool,
+    },
+}
+
 /// Wallet command to execute
 #[derive(Subcommand)]
 #[derive(Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
@@ -329,6 +685,81 @@ pub enum Command {
         seed_password: Option<String>,
     },
 
+    /// Restore seed from a BIP-39 mnemonic and save it as an encoded file
+    Restore {
+        /// File to save restored seed data and extended master key
+        output_file: PathBuf,
+
+        /// BIP-39 mnemonic words. If not provided, they will be asked for
+        /// interactively.
+        #[clap(short, long)]
+        mnemonic: Option<String>,
+
+        /// Optional BIP-39 passphrase (the "25th word"). If not provided, it
+        /// will be asked for interactively; leave empty if none.
+        #[clap(long)]
+        passphrase: Option<String>,
+
+        /// Seed password
+        #[clap(short = 'p', long)]
+        seed_password: Option<String>,
+    },
+
+    /// Split seed into SLIP-39 mnemonic shares organized in groups
+    Split {
+        /// Seed file to split
+        seed_file: PathBuf,
+
+        /// Seed password
+        #[clap(short = 'p', long)]
+        seed_password: Option<String>,
+
+        /// Number of groups required to recover the seed
+        #[clap(short = 't', long, default_value = "1")]
+        group_threshold: u8,
+
+        /// Group of shares in form of `M-of-N`, where `M` is the number of
+        /// shares required to recover the group and `N` is the total number
+        /// of the shares in the group. Can be repeated to define multiple
+        /// groups.
+        #[clap(short, long = "group", required = true)]
+        groups: Vec<slip39::GroupSpec>,
+
+        /// Optional SLIP-39 passphrase used to encrypt the shares
+        #[clap(long)]
+        share_passphrase: Option<String>,
+    },
+
+    /// Recover seed from SLIP-39 mnemonic shares and save it as an encoded file
+    Recover {
+        /// File to save recovered seed data and extended master key
+        output_file: PathBuf,
+
+        /// File containing SLIP-39 shares, one per line. If not provided, the
+        /// shares will be asked for interactively.
+        #[clap(short, long)]
+        shares_file: Option<PathBuf>,
+
+        /// Optional SLIP-39 passphrase used to encrypt the shares
+        #[clap(long)]
+        share_passphrase: Option<String>,
+
+        /// Use recovered secret directly as a BIP-32 seed, as SLIP-39 wallets
+        /// and seeds created by earlier versions of btc-hot do, instead of
+        /// using it as BIP-39 entropy.
+        #[clap(long)]
+        raw: bool,
+
+        /// BIP-39 passphrase of the seed; ignored with `--raw`. If not
+        /// provided, it will be asked for interactively; leave empty if none.
+        #[clap(long)]
+        passphrase: Option<String>,
+
+        /// Seed password
+        #[clap(short = 'p', long)]
+        seed_password: Option<String>,
+    },
+
     /// List connected hardware devices and provide extended key information for
     /// some specific or all known derivation schemata.
     DeviceKeys {
@@ -445,6 +876,46 @@ pub enum Command {
         password: Option<String>,
     },
 
+    /// Derive deterministic child entropy from the seed or signing account
+    /// according to BIP-85
+    Bip85 {
+        /// Seed or signing account file to derive entropy from
+        file: PathBuf,
+
+        /// Seed or account password
+        #[clap(short, long)]
+        password: Option<String>,
+
+        /// Produce WIF keys and extended private keys for testnet
+        #[clap(long)]
+        testnet: bool,
+
+        /// Child index
+        #[clap(short, long, default_value = "0")]
+        index: u32,
+
+        /// Application to derive entropy for
+        #[clap(subcommand)]
+        application: Bip85Command,
+    },
+
+    /// Convert seed or signing account file created by an earlier version of
+    /// btc-hot into the current encrypted file format.
+    ///
+    /// The original file is kept untouched; the new file uses the same
+    /// password.
+    Migrate {
+        /// Seed or signing account file in the legacy format
+        file: PathBuf,
+
+        /// Seed or account password
+        #[clap(short, long)]
+        password: Option<String>,
+
+        /// Output file for storing converted data
+        output_file: PathBuf,
+    },
+
     /// Sign PSBT with the provided account keys
     Sign {
         /// Add signature with known keys to the aggregated Schnorr signatures
@@ -471,6 +942,40 @@ impl Args {
                 output_file,
                 seed_password,
             } => self.seed(output_file, seed_password),
+            Command::Restore {
+                output_file,
+                mnemonic,
+                passphrase,
+                seed_password,
+            } => self.restore(output_file, mnemonic, passphrase, seed_password),
+            Command::Split {
+                seed_file,
+                seed_password,
+                group_threshold,
+                groups,
+                share_passphrase,
+            } => self.split(
+                seed_file,
+                seed_password,
+                *group_threshold,
+                groups,
+                share_passphrase,
+            ),
+            Command::Recover {
+                output_file,
+                shares_file,
+                share_passphrase,
+                raw,
+                passphrase,
+                seed_password,
+            } => self.recover(
+                output_file,
+                shares_file.as_deref(),
+                share_passphrase,
+                *raw,
+                passphrase,
+                seed_password,
+            ),
             Command::DeviceKeys {
                 account,
                 mainnet: _,
@@ -505,6 +1010,18 @@ impl Args {
                 )
             }
             Command::Info { file, password } => self.info(file, password),
+            Command::Bip85 {
+                file,
+                password,
+                testnet,
+                index,
+                application,
+            } => self.bip85(file, password, *testnet, *index, application),
+            Command::Migrate {
+                file,
+                password,
+                output_file,
+            } => self.migrate(file, password, output_file),
             Command::Sign {
                 musig,
                 psbt_file,
@@ -531,6 +1048,139 @@ impl Args {
         Ok(())
     }
 
+    fn restore(
+        &self,
+        output_file: &Path,
+        mnemonic: &Option<String>,
+        passphrase: &Option<String>,
+        seed_password: &Option<String>,
+    ) -> Result<(), Error> {
+        let mnemonic = get_password(mnemonic.clone(), "Mnemonic")?;
+        let passphrase = get_password(
+            passphrase.clone(),
+            "BIP-39 passphrase (leave empty if none)",
+        )?;
+        let seed = Seed::with_mnemonic(&mnemonic, passphrase)?;
+        let seed_password = get_password(seed_password.clone(), "Seed password")?;
+        seed.write(output_file, &seed_password)?;
+
+        let secp = Secp256k1::new();
+        self.info_seed(&secp, seed);
+
+        Ok(())
+    }
+
+    fn split(
+        &self,
+        seed_file: &Path,
+        seed_password: &Option<String>,
+        group_threshold: u8,
+        groups: &[slip39::GroupSpec],
+        share_passphrase: &Option<String>,
+    ) -> Result<(), Error> {
+        let seed_password = get_password(seed_password.clone(), "Seed password")?;
+        let seed = Seed::read(seed_file, &seed_password)?;
+
+        let shares = slip39::split(
+            seed.as_entropy(),
+            share_passphrase.as_deref().unwrap_or_default(),
+            group_threshold,
+            groups,
+            0,
+            &mut rand::thread_rng(),
+        )?;
+
+        println!(
+            "\n{} {} {}",
+            "Seed split into".bright_white(),
+            groups.len(),
+            format!(
+                "groups, {} of which are required for recovery:",
+                group_threshold
+            )
+            .bright_white()
+        );
+        for (group_no, (spec, shares)) in groups.iter().zip(shares).enumerate() {
+            println!(
+                "\n{} {} ({}):",
+                "Group".bright_white(),
+                group_no + 1,
+                spec.to_string().bright_green()
+            );
+            for (share_no, share) in shares.into_iter().enumerate() {
+                println!(
+                    "{:>4}. {}",
+                    share_no + 1,
+                    share.to_string().black().dimmed()
+                );
+            }
+        }
+        if seed.passphrase.is_some() {
+            println!(
+                "\n{} seed uses BIP-39 key derivation; recover it with the same BIP-39 \
+                 passphrase\n",
+                "Note:".bright_yellow()
+            );
+        } else {
+            println!(
+                "\n{} seed uses raw key derivation; recover it with `--raw` flag\n",
+                "Note:".bright_yellow()
+            );
+        }
+
+        Ok(())
+    }
+
+    fn recover(
+        &self,
+        output_file: &Path,
+        shares_file: Option<&Path>,
+        share_passphrase: &Option<String>,
+        raw: bool,
+        passphrase: &Option<String>,
+        seed_password: &Option<String>,
+    ) -> Result<(), Error> {
+        let shares = match shares_file {
+            Some(path) => fs::read_to_string(path)?
+                .lines()
+                .filter(|line| !line.trim().is_empty())
+                .map(slip39::Share::from_str)
+                .collect::<Result<Vec<_>, _>>()?,
+            None => {
+                let mut shares = vec![];
+                loop {
+                    let line = rpassword::prompt_password(format!(
+                        "Share #{} (leave empty to finish): ",
+                        shares.len() + 1
+                    ))?;
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    shares.push(slip39::Share::from_str(&line)?);
+                }
+                shares
+            }
+        };
+
+        let entropy = slip39::combine(&shares, share_passphrase.as_deref().unwrap_or_default())?;
+        let seed = if raw {
+            Seed::with_legacy(entropy)
+        } else {
+            let passphrase = get_password(
+                passphrase.clone(),
+                "BIP-39 passphrase (leave empty if none)",
+            )?;
+            Seed::with_entropy(entropy, passphrase)?
+        };
+        let seed_password = get_password(seed_password.clone(), "Seed password")?;
+        seed.write(output_file, &seed_password)?;
+
+        let secp = Secp256k1::new();
+        self.info_seed(&secp, seed);
+
+        Ok(())
+    }
+
     fn devices(
         &self,
         account: HardenedIndex,
@@ -868,31 +1518,124 @@ impl Args {
 
     fn info(&self, path: &Path, password: &Option<String>) -> Result<(), Error> {
         let secp = Secp256k1::new();
-        let file = fs::File::open(path)?;
+        let data = fs::read(path)?;
 
         let password = get_password(password.clone(), "Password")?;
+        let account_password = if password.is_empty() {
+            None
+        } else {
+            Some(password.as_str())
+        };
 
-        {
-            let password = if password.is_empty() {
-                None
-            } else {
-                Some(password.clone())
-            };
-            if let Ok(account) = MemorySigningAccount::read(&secp, file, password.as_deref()) {
-                self.info_account(account);
-                return Ok(());
+        // Unencrypted signing account files have no container header
+        match container_payload(&data) {
+            Ok(ContainerPayload::Seed) => self.info_seed(&secp, Seed::read(path, &password)?),
+            Ok(ContainerPayload::Account) | Err(ContainerError::LegacyFormat) => self.info_account(
+                MemorySigningAccount::read(&secp, &data[..], account_password)?,
+            ),
+            Err(err) => return Err(err.into()),
+        }
+
+        Ok(())
+    }
+
+    fn bip85(
+        &self,
+        path: &Path,
+        password: &Option<String>,
+        testnet: bool,
+        index: u32,
+        application: &Bip85Command,
+    ) -> Result<(), Error> {
+        let secp = Secp256k1::new();
+        let data = fs::read(path)?;
+
+        let password = get_password(password.clone(), "Password")?;
+        let account_password = if password.is_empty() {
+            None
+        } else {
+            Some(password.as_str())
+        };
+        let mut xpriv = match container_payload(&data) {
+            Ok(ContainerPayload::Seed) => Seed::read(path, &password)?.master_xpriv(testnet)?,
+            Ok(ContainerPayload::Account) | Err(ContainerError::LegacyFormat) => {
+                *MemorySigningAccount::read(&secp, &data[..], account_password)?.account_xpriv()
+            }
+            Err(err) => return Err(err.into()),
+        };
+        xpriv.network = if testnet {
+            bitcoin::Network::Testnet
+        } else {
+            bitcoin::Network::Bitcoin
+        };
+
+        let (name, value) = match application {
+            Bip85Command::Mnemonic { words, language } => (
+                "Mnemonic:",
+                xpriv
+                    .bip85_mnemonic(&secp, *language, *words, index)?
+                    .to_string(),
+            ),
+            Bip85Command::Wif => ("Private key:", xpriv.bip85_wif(&secp, index)?.to_wif()),
+            Bip85Command::Xprv => ("Xpriv:", xpriv.bip85_xprv(&secp, index)?.to_string()),
+            Bip85Command::Hex { bytes } => {
+                ("Entropy:", xpriv.bip85_hex(&secp, *bytes, index)?.to_hex())
             }
+            Bip85Command::Password { length, base85 } => (
+                "Password:",
+                xpriv.bip85_password(&secp, *length, *base85, index)?,
+            ),
+        };
+        println!("\n{:-18} {}\n", name.bright_white(), value);
+
+        Ok(())
+    }
+
+    fn migrate(
+        &self,
+        path: &Path,
+        password: &Option<String>,
+        output_file: &Path,
+    ) -> Result<(), Error> {
+        let secp = Secp256k1::new();
+
+        let data = fs::read(path)?;
+        if data.starts_with(&CONTAINER_MAGIC) {
+            eprintln!(
+                "{} `{}` already uses the current file format",
+                "Warning:".bright_yellow(),
+                path.display()
+            );
+            return Ok(());
         }
 
-        if let Ok(seed) = Seed::read(path, &password) {
+        let password = get_password(password.clone(), "Password")?;
+
+        // Legacy seed files contain just encrypted entropy, while signing
+        // account files always exceed that size
+        if data.len() <= SeedType::Bit256.byte_len() {
+            if data.is_empty() || data.len() % 16 != 0 {
+                return Err(ContainerError::Truncated.into());
+            }
+            let seed = Seed::with_legacy(decode_legacy(data, &password));
+            seed.write(output_file, &password)?;
             self.info_seed(&secp, seed);
-            return Ok(());
+        } else {
+            let password = if password.is_empty() {
+                None
+            } else {
+                Some(password)
+            };
+            let account = MemorySigningAccount::read_legacy(&secp, &data[..], password.as_deref())?;
+            let file = fs::File::create(output_file)?;
+            account.write(file, password.as_deref())?;
+            self.info_account(account);
         }
 
-        eprintln!(
-            "{} can't detect file format for `{}`",
-            "Error:".bright_red(),
-            path.display()
+        println!(
+            "{} in `{}`\n",
+            "Converted file saved".bright_green(),
+            output_file.display()
         );
 
         Ok(())
@@ -949,6 +1692,18 @@ pub enum Error {
     #[from]
     Encoding(consensus::encode::Error),
 
+    #[from]
+    Container(ContainerError),
+
+    #[from]
+    Slip39(slip39::Slip39Error),
+
+    #[from]
+    Bip85(Bip85Error),
+
+    #[display("invalid seed data: {0}")]
+    SeedData(&'static str),
+
     #[from]
     Signing(SignError),
 
@@ -963,3 +1718,296 @@ fn main() {
         eprintln!("{}: {}\n", "Error".bright_red(), err);
     }
 }
+
+#[cfg(test)]
+mod test {
+    use aes::cipher::BlockEncrypt;
+    use amplify::hex::FromHex;
+
+    use super::*;
+
+    const PASSWORD: &str = "correct horse battery staple";
+
+    fn temp_file(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("btc-hot-{}-{}", std::process::id(), name))
+    }
+
+    /// Encrypts data in the format used before introduction of encrypted
+    /// containers
+    fn encode_legacy(data: &[u8], password: &str) -> Vec<u8> {
+        let key = sha256::Hash::hash(password.as_bytes());
+        let cipher = Aes256::new(GenericArray::from_slice(key.as_inner()));
+        let mut data = data.to_vec();
+        for chunk in data.chunks_mut(16) {
+            cipher.encrypt_block(Block::from_mut_slice(chunk));
+        }
+        data
+    }
+
+    fn migrate(legacy: &[u8], password: &str, name: &str) -> PathBuf {
+        let (file, output_file) = (temp_file(name), temp_file(&format!("{}.migrated", name)));
+        fs::write(&file, legacy).unwrap();
+        Args::parse_from([
+            "btc-hot",
+            "migrate",
+            "--password",
+            password,
+            file.to_str().unwrap(),
+            output_file.to_str().unwrap(),
+        ])
+        .exec()
+        .unwrap();
+        fs::remove_file(file).unwrap();
+        output_file
+    }
+
+    #[test]
+    fn container_roundtrip() {
+        let container = seal(ContainerPayload::Seed, b"secret data", PASSWORD).unwrap();
+        assert!(container.starts_with(&CONTAINER_MAGIC));
+        assert_eq!(
+            &unseal(&container, ContainerPayload::Seed, PASSWORD).unwrap()[..],
+            b"secret data"
+        );
+        assert_eq!(
+            unseal(&container, ContainerPayload::Seed, "wrong password"),
+            Err(ContainerError::Decryption)
+        );
+        assert_eq!(container_payload(&container), Ok(ContainerPayload::Seed));
+        assert_eq!(
+            unseal(&container, ContainerPayload::Account, PASSWORD),
+            Err(ContainerError::PayloadMismatch {
+                expected: ContainerPayload::Account,
+                found: ContainerPayload::Seed
+            })
+        );
+    }
+
+    #[test]
+    fn seed_roundtrip() {
+        let file = temp_file("seed-roundtrip");
+        let seed = Seed::with_entropy([0x7Fu8; 16], "TREZOR").unwrap();
+        seed.write(&file, PASSWORD).unwrap();
+        let restored = Seed::read(&file, PASSWORD).unwrap();
+        assert_eq!(restored.as_entropy(), seed.as_entropy());
+        assert_eq!(restored.passphrase.as_deref(), Some("TREZOR"));
+
+        // Seeds migrated from the files predating encrypted containers use raw
+        // entropy as BIP-32 seed
+        let entropy = [0x80u8; 32];
+        Seed::with_legacy(entropy).write(&file, PASSWORD).unwrap();
+        let legacy = Seed::read(&file, PASSWORD).unwrap();
+        fs::remove_file(file).unwrap();
+        assert_eq!(legacy.as_entropy(), &entropy);
+        assert_eq!(legacy.passphrase, None);
+        assert_eq!(
+            legacy.master_xpriv(false).unwrap(),
+            ExtendedPrivKey::new_master(bitcoin::Network::Bitcoin, &entropy).unwrap()
+        );
+    }
+
+    #[test]
+    fn bip85_file_types() {
+        let bip85 = |file: &Path, password: &str| {
+            Args::parse_from([
+                "btc-hot",
+                "bip85",
+                "--password",
+                password,
+                file.to_str().unwrap(),
+                "hex",
+            ])
+            .exec()
+        };
+
+        let secp = Secp256k1::new();
+        let seed_file = temp_file("bip85-seed");
+        let seed = Seed::with_entropy([0x7Fu8; 16], "").unwrap();
+        seed.write(&seed_file, PASSWORD).unwrap();
+        assert!(bip85(&seed_file, PASSWORD).is_ok());
+        assert!(matches!(
+            bip85(&seed_file, "wrong password"),
+            Err(Error::Container(ContainerError::Decryption))
+        ));
+
+        let account_file = temp_file("bip85-account");
+        let master = seed.master_xpriv(false).unwrap();
+        let account = MemorySigningAccount::with(
+            &secp,
+            ExtendedPubKey::from_priv(&secp, &master).identifier(),
+            DerivationPath::master(),
+            master,
+        );
+        account
+            .write(fs::File::create(&account_file).unwrap(), Some(PASSWORD))
+            .unwrap();
+        assert!(bip85(&account_file, PASSWORD).is_ok());
+        assert!(matches!(
+            bip85(&account_file, "wrong password"),
+            Err(Error::Container(ContainerError::Decryption))
+        ));
+        assert!(matches!(
+            Seed::read(&account_file, PASSWORD),
+            Err(Error::Container(ContainerError::PayloadMismatch {
+                expected: ContainerPayload::Seed,
+                found: ContainerPayload::Account
+            }))
+        ));
+
+        fs::remove_file(seed_file).unwrap();
+        fs::remove_file(account_file).unwrap();
+    }
+
+    #[test]
+    fn bip39_vectors() {
+        // Official BIP-39 test vectors, all using "TREZOR" passphrase
+        for (entropy, xpriv) in [
+            (
+                "00000000000000000000000000000000",
+                "xprv9s21ZrQH143K3h3fDYiay8mocZ3afhfULfb5GX8kCBdno77K4HiA15Tg23wpbeF1pLfs1c5SPmYHrEpTuuRhxMwvKDwqdKiGJS9XFKzUsAF",
+            ),
+            (
+                "7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f7f",
+                "xprv9s21ZrQH143K2gA81bYFHqU68xz1cX2APaSq5tt6MFSLeXnCKV1RVUJt9FWNTbrrryem4ZckN8k4Ls1H6nwdvDTvnV7zEXs2HgPezuVccsq",
+            ),
+            (
+                "80808080808080808080808080808080",
+                "xprv9s21ZrQH143K2shfP28KM3nr5Ap1SXjz8gc2rAqqMEynmjt6o1qboCDpxckqXavCwdnYds6yBHZGKHv7ef2eTXy461PXUjBFQg6PrwY4Gzq",
+            ),
+            (
+                "ffffffffffffffffffffffffffffffff",
+                "xprv9s21ZrQH143K2V4oox4M8Zmhi2Fjx5XK4Lf7GKRvPSgydU3mjZuKGCTg7UPiBUD7ydVPvSLtg9hjp7MQTYsW67rZHAXeccqYqrsx8LcXnyd",
+            ),
+        ] {
+            let entropy = Vec::<u8>::from_hex(entropy).unwrap();
+            let seed = Seed::with_entropy(&entropy, "TREZOR").unwrap();
+            assert_eq!(seed.master_xpriv(false).unwrap().to_string(), xpriv);
+
+            let mnemonic = Mnemonic::from_entropy(&entropy).unwrap().to_string();
+            let seed = Seed::with_mnemonic(&mnemonic, "TREZOR").unwrap();
+            assert_eq!(seed.master_xpriv(false).unwrap().to_string(), xpriv);
+        }
+    }
+
+    #[test]
+    fn container_truncated() {
+        let container = seal(ContainerPayload::Seed, b"secret data", PASSWORD).unwrap();
+        assert_eq!(
+            unseal(
+                &container[..CONTAINER_HEADER_LEN - 1],
+                ContainerPayload::Seed,
+                PASSWORD
+            ),
+            Err(ContainerError::Truncated)
+        );
+        assert_eq!(
+            unseal(
+                &container[..CONTAINER_HEADER_LEN + 4],
+                ContainerPayload::Seed,
+                PASSWORD
+            ),
+            Err(ContainerError::Decryption)
+        );
+        assert_eq!(
+            unseal(&container[1..], ContainerPayload::Seed, PASSWORD),
+            Err(ContainerError::LegacyFormat)
+        );
+    }
+
+    #[test]
+    fn container_tampered() {
+        let container = seal(ContainerPayload::Seed, b"secret data", PASSWORD).unwrap();
+        let tampered = |pos: usize, data: &[u8]| {
+            let mut container = container.clone();
+            container[pos..pos + data.len()].copy_from_slice(data);
+            unseal(container, ContainerPayload::Seed, PASSWORD)
+        };
+        let (version, payload) = (CONTAINER_MAGIC.len(), CONTAINER_MAGIC.len() + 1);
+        let m_cost = payload + 1;
+        let (t_cost, p_cost, salt) = (m_cost + 4, m_cost + 8, m_cost + 12);
+
+        assert_eq!(
+            tampered(version, &[0xFF]),
+            Err(ContainerError::UnsupportedVersion(0xFF))
+        );
+        assert_eq!(
+            tampered(payload, &[0xFF]),
+            Err(ContainerError::UnknownPayload(0xFF))
+        );
+        // Payload type is authenticated together with the rest of the header
+        let mut relabeled = container.clone();
+        relabeled[payload] = ContainerPayload::Account as u8;
+        assert_eq!(
+            unseal(relabeled, ContainerPayload::Account, PASSWORD),
+            Err(ContainerError::Decryption)
+        );
+        assert_eq!(
+            tampered(t_cost, &1u32.to_le_bytes()),
+            Err(ContainerError::Decryption)
+        );
+        assert_eq!(tampered(salt, &[0u8; 4]), Err(ContainerError::Decryption));
+        assert!(matches!(
+            tampered(m_cost, &u32::MAX.to_le_bytes()),
+            Err(ContainerError::KdfLimits {
+                m_cost: u32::MAX,
+                ..
+            })
+        ));
+        assert!(matches!(
+            tampered(t_cost, &u32::MAX.to_le_bytes()),
+            Err(ContainerError::KdfLimits { .. })
+        ));
+        assert!(matches!(
+            tampered(p_cost, &u32::MAX.to_le_bytes()),
+            Err(ContainerError::KdfLimits { .. })
+        ));
+    }
+
+    #[test]
+    fn migrate_legacy_seed() {
+        let entropy = [0xA5u8; 32];
+        let output_file = migrate(&encode_legacy(&entropy, PASSWORD), PASSWORD, "seed");
+
+        let seed = Seed::read(&output_file, PASSWORD).unwrap();
+        fs::remove_file(output_file).unwrap();
+        assert_eq!(seed.as_entropy(), &entropy);
+        assert_eq!(seed.passphrase, None);
+        assert_eq!(
+            seed.master_xpriv(false).unwrap(),
+            ExtendedPrivKey::new_master(bitcoin::Network::Bitcoin, &entropy).unwrap()
+        );
+    }
+
+    #[test]
+    fn migrate_legacy_account() {
+        let secp = Secp256k1::new();
+        let master = ExtendedPrivKey::new_master(bitcoin::Network::Bitcoin, &[7u8; 32]).unwrap();
+        let derivation = DerivationPath::from_str("m/84'/0'/0'").unwrap();
+        let account_xpriv = master.derive_priv(&secp, &derivation).unwrap();
+
+        let mut legacy = ExtendedPubKey::from_priv(&secp, &master)
+            .identifier()
+            .to_vec();
+        (derivation.len() as u64)
+            .consensus_encode(&mut legacy)
+            .unwrap();
+        for child in &derivation {
+            u32::from(*child).consensus_encode(&mut legacy).unwrap();
+        }
+        let mut xpriv = account_xpriv.encode().to_vec();
+        xpriv.resize(80, 0);
+        legacy.extend(encode_legacy(&xpriv, PASSWORD));
+
+        let output_file = migrate(&legacy, PASSWORD, "account");
+        let account = MemorySigningAccount::read(
+            &secp,
+            fs::File::open(&output_file).unwrap(),
+            Some(PASSWORD),
+        )
+        .unwrap();
+        fs::remove_file(output_file).unwrap();
+        assert_eq!(account.account_xpriv(), &account_xpriv);
+        assert_eq!(account.derivation(), &derivation);
+        assert_eq!(account.master_fingerprint(), master.fingerprint(&secp));
+    }
+}